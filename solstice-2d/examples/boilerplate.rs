@@ -37,10 +37,9 @@ pub trait Example: Sized {
             .build_windowed(wb, &event_loop)
             .unwrap();
         let window = unsafe { window.make_current().unwrap() };
-        let glow_ctx = unsafe {
-            solstice::glow::Context::from_loader_function(|name| window.get_proc_address(name))
+        let mut context = unsafe {
+            solstice::Context::from_loader_function(|name| window.get_proc_address(name))
         };
-        let mut context = solstice::Context::new(glow_ctx);
         context.set_viewport(0, 0, width as _, height as _);
         let gfx = solstice_2d::Graphics::new(&mut context, width as _, height as _).unwrap();
 
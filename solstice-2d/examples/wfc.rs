@@ -224,10 +224,8 @@ fn main() {
         .build_windowed(wb, &event_loop)
         .unwrap();
     let window = unsafe { window.make_current().unwrap() };
-    let glow_ctx = unsafe {
-        solstice::glow::Context::from_loader_function(|name| window.get_proc_address(name))
-    };
-    let mut context = solstice::Context::new(glow_ctx);
+    let mut context =
+        unsafe { solstice::Context::from_loader_function(|name| window.get_proc_address(name)) };
     let mut d2 = solstice_2d::Graphics::new(&mut context, width as _, height as _).unwrap();
 
     let image_settings = solstice::image::Settings {
@@ -79,6 +79,32 @@ impl Text {
         })
     }
 
+    /// Sets how the glyph quads grow when more text is queued than they have room for.
+    pub fn set_growth_policy(&mut self, growth_policy: solstice::buffer::GrowthPolicy) {
+        self.quad_batch.set_growth_policy(growth_policy)
+    }
+
+    /// Gives the glyph atlas mipmaps, which are regenerated once after any update that adds
+    /// glyphs to it rather than after every glyph. Off by default.
+    pub fn set_mipmaps(&mut self, ctx: &mut Context, mipmaps: bool) {
+        let mut info = self.font_texture.get_texture_info();
+        let mut filter = info.filter();
+        filter.set_mipmap(if mipmaps {
+            FilterMode::Linear
+        } else {
+            FilterMode::None
+        });
+        info.set_filter(filter);
+        info.set_mipmaps(mipmaps);
+        self.font_texture.set_texture_info(info);
+        ctx.set_texture_filter(
+            self.font_texture.get_texture_key(),
+            self.font_texture.get_texture_type(),
+            filter,
+        );
+        self.font_texture.generate_mipmaps(ctx);
+    }
+
     pub fn add_font(&mut self, font_data: FontVec) -> FontId {
         self.glyph_brush.add_font(font_data.0)
     }
@@ -182,11 +208,15 @@ impl Text {
             }
         };
 
+        let mut uploaded = false;
         loop {
             let update_texture = |rect: glyph_brush::Rectangle<u32>, data: &[u8]| {
+                uploaded = true;
                 let mut info = font_texture.get_texture_info();
                 info.set_width(rect.width());
                 info.set_height(rect.height());
+                // the mipmaps are regenerated once after all of the glyphs are in
+                info.set_mipmaps(false);
                 ctx.set_texture_sub_data(
                     font_texture.get_texture_key(),
                     info,
@@ -226,5 +256,8 @@ impl Text {
                 },
             }
         }
+        if uploaded {
+            font_texture.generate_mipmaps(ctx);
+        }
     }
 }
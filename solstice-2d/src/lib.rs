@@ -8,8 +8,9 @@ pub use shared::*;
 pub use solstice;
 
 use solstice::{
+    buffer::{BufferType, StreamBuffer},
     image::Image,
-    mesh::{MappedIndexedMesh, MappedVertexMesh},
+    mesh::{IndexedMesh, VertexMesh},
     texture::Texture,
     viewport::Viewport,
     Context,
@@ -51,11 +52,13 @@ impl std::ops::Drop for GraphicsLock<'_, '_> {
     }
 }
 
+// Geometry that's drawn from data is streamed through these so that draws don't have to wait on
+// the ones before them to finish reading.
 struct GeometryBuffers {
-    mesh3d: MappedIndexedMesh<Vertex3D, u32>,
-    mesh3d_unindexed: MappedVertexMesh<Vertex3D>,
-    mesh2d: MappedIndexedMesh<Vertex2D, u32>,
-    mesh2d_unindexed: MappedVertexMesh<Vertex2D>,
+    vertices: StreamBuffer,
+    indices: StreamBuffer,
+    // indices offset by where their vertices landed in `vertices`
+    rebased_indices: Vec<u32>,
 }
 
 #[derive(Debug, Default)]
@@ -64,6 +67,9 @@ pub struct Config {
     pub height: f32,
     pub line_capacity: usize,
     pub mesh_capacity: usize,
+    /// How the line and text buffers grow when a frame needs more than their capacity. Mesh data
+    /// is streamed and only grows when a single draw needs more than `mesh_capacity`.
+    pub growth_policy: solstice::buffer::GrowthPolicy,
 }
 
 pub struct Graphics {
@@ -87,30 +93,36 @@ impl Graphics {
                 height,
                 line_capacity: 10_000,
                 mesh_capacity: 10_000,
+                growth_policy: solstice::buffer::GrowthPolicy::Double,
             },
         )
     }
 
     pub fn with_config(ctx: &mut Context, config: &Config) -> Result<Self, GraphicsError> {
-        let mesh2d = MappedIndexedMesh::new(ctx, config.mesh_capacity, config.mesh_capacity)?;
-        let mesh2d_unindexed = MappedVertexMesh::new(ctx, config.mesh_capacity)?;
-        let mesh3d = MappedIndexedMesh::new(ctx, config.mesh_capacity, config.mesh_capacity)?;
-        let mesh3d_unindexed = MappedVertexMesh::new(ctx, config.mesh_capacity)?;
-        let line_workspace = LineWorkspace::with_capacity(ctx, config.line_capacity)?;
+        let vertex_size = std::mem::size_of::<Vertex2D>().max(std::mem::size_of::<Vertex3D>());
+        let vertices =
+            StreamBuffer::new(ctx, config.mesh_capacity * vertex_size, BufferType::Vertex)?;
+        let indices = StreamBuffer::new(
+            ctx,
+            config.mesh_capacity * std::mem::size_of::<u32>(),
+            BufferType::Index,
+        )?;
+        let mut line_workspace = LineWorkspace::with_capacity(ctx, config.line_capacity)?;
+        line_workspace.set_growth_policy(config.growth_policy);
         let default_shader = Shader::new(ctx)?;
         let default_texture = create_default_texture(ctx)?;
 
-        let text_workspace = text::Text::new(ctx)?;
+        let mut text_workspace = text::Text::new(ctx)?;
+        text_workspace.set_growth_policy(config.growth_policy);
         let text_shader = Shader::with((text::DEFAULT_VERT, text::DEFAULT_FRAG), ctx)?;
 
         let viewport = Viewport::new(0, 0, config.width as _, config.height as _);
 
         Ok(Self {
             meshes: GeometryBuffers {
-                mesh3d,
-                mesh3d_unindexed,
-                mesh2d,
-                mesh2d_unindexed,
+                vertices,
+                indices,
+                rebased_indices: Vec::new(),
             },
             line_workspace,
             default_shader,
@@ -200,15 +212,11 @@ impl Graphics {
                                 Some(texture) => shader.bind_texture(texture),
                             }
                             shader.activate(ctx);
-                            ctx.set_viewport(
-                                viewport.x() as _,
-                                viewport.y() as _,
-                                viewport.width() as _,
-                                viewport.height() as _,
-                            );
 
                             let settings = solstice::PipelineSettings {
+                                viewport,
                                 depth_state: None,
+                                blend_state: Some(solstice::BlendState::alpha()),
                                 scissor_state,
                                 framebuffer: target.as_ref().map(|c| &c.inner),
                                 ..solstice::PipelineSettings::default()
@@ -234,14 +242,9 @@ impl Graphics {
                             }
                             shader.activate(ctx);
 
-                            ctx.set_viewport(
-                                viewport.x(),
-                                viewport.y(),
-                                viewport.width(),
-                                viewport.height(),
-                            );
-
                             let settings = solstice::PipelineSettings {
+                                viewport,
+                                blend_state: Some(solstice::BlendState::alpha()),
                                 scissor_state,
                                 framebuffer: target.as_ref().map(|c| &c.inner),
                                 ..solstice::PipelineSettings::default()
@@ -305,18 +308,14 @@ impl Graphics {
                         None
                     };
 
-                    ctx.set_viewport(
-                        viewport.x(),
-                        viewport.y(),
-                        viewport.width(),
-                        viewport.height(),
-                    );
                     solstice::Renderer::draw(
                         ctx,
                         &shader,
                         &geometry,
                         solstice::PipelineSettings {
+                            viewport,
                             depth_state,
+                            blend_state: Some(solstice::BlendState::alpha()),
                             framebuffer: target.as_ref().map(|c| &c.inner),
                             scissor_state,
                             ..solstice::PipelineSettings::default()
@@ -379,18 +378,14 @@ impl Graphics {
 
                     let geometry = self.text_workspace.geometry(ctx);
 
-                    ctx.set_viewport(
-                        viewport.x(),
-                        viewport.y(),
-                        viewport.width(),
-                        viewport.height(),
-                    );
                     solstice::Renderer::draw(
                         ctx,
                         shader,
                         &geometry,
                         solstice::PipelineSettings {
+                            viewport,
                             depth_state: None,
+                            blend_state: Some(solstice::BlendState::alpha()),
                             scissor_state,
                             framebuffer: target.as_ref().map(|c| &c.inner),
                             ..solstice::PipelineSettings::default()
@@ -409,6 +404,8 @@ impl Graphics {
                 }
             }
         }
+        // anything dropped while building the draw list can go now that it's been drawn
+        ctx.delete_dropped_resources();
     }
 }
 
@@ -550,45 +547,10 @@ trait WriteAndDrawBuffer {
         S: solstice::shader::Shader;
 }
 
-impl WriteAndDrawBuffer for &Geometry<'_, Vertex2D> {
-    fn draw<S>(
-        self,
-        meshes: &mut GeometryBuffers,
-        ctx: &mut Context,
-        shader: &S,
-        settings: solstice::PipelineSettings,
-    ) where
-        S: solstice::shader::Shader,
-    {
-        match &self.indices {
-            None => {
-                meshes.mesh2d_unindexed.set_vertices(&self.vertices, 0);
-                let mesh = meshes.mesh2d_unindexed.unmap(ctx);
-                let geometry = solstice::Geometry {
-                    mesh,
-                    draw_range: 0..self.vertices.len(),
-                    draw_mode: solstice::DrawMode::Triangles,
-                    instance_count: 1,
-                };
-                solstice::Renderer::draw(ctx, shader, &geometry, settings);
-            }
-            Some(indices) => {
-                meshes.mesh2d.set_vertices(&self.vertices, 0);
-                meshes.mesh2d.set_indices(&indices, 0);
-                let mesh = meshes.mesh2d.unmap(ctx);
-                let geometry = solstice::Geometry {
-                    mesh,
-                    draw_range: 0..indices.len(),
-                    draw_mode: solstice::DrawMode::Triangles,
-                    instance_count: 1,
-                };
-                solstice::Renderer::draw(ctx, shader, &geometry, settings);
-            }
-        }
-    }
-}
-
-impl WriteAndDrawBuffer for &Geometry<'_, Vertex3D> {
+impl<V> WriteAndDrawBuffer for &Geometry<'_, V>
+where
+    V: solstice::vertex::Vertex + bytemuck::Pod,
+{
     fn draw<S>(
         self,
         meshes: &mut GeometryBuffers,
@@ -598,25 +560,29 @@ impl WriteAndDrawBuffer for &Geometry<'_, Vertex3D> {
     ) where
         S: solstice::shader::Shader,
     {
+        let first = meshes.vertices.write(ctx, &self.vertices);
+        let vbo = meshes.vertices.inner().clone();
         match &self.indices {
             None => {
-                meshes.mesh3d_unindexed.set_vertices(&self.vertices, 0);
-                let mesh = meshes.mesh3d_unindexed.unmap(ctx);
+                let mesh = VertexMesh::<V>::with_buffer(vbo);
                 let geometry = solstice::Geometry {
-                    mesh,
-                    draw_range: 0..self.vertices.len(),
+                    mesh: &mesh,
+                    draw_range: first..(first + self.vertices.len()),
                     draw_mode: solstice::DrawMode::Triangles,
                     instance_count: 1,
                 };
                 solstice::Renderer::draw(ctx, shader, &geometry, settings);
             }
             Some(indices) => {
-                meshes.mesh3d.set_vertices(&self.vertices, 0);
-                meshes.mesh3d.set_indices(&indices, 0);
-                let mesh = meshes.mesh3d.unmap(ctx);
+                meshes.rebased_indices.clear();
+                meshes
+                    .rebased_indices
+                    .extend(indices.iter().map(|index| index + first as u32));
+                let start = meshes.indices.write(ctx, &meshes.rebased_indices);
+                let mesh = IndexedMesh::<V, u32>::with_buffers(vbo, meshes.indices.inner().clone());
                 let geometry = solstice::Geometry {
-                    mesh,
-                    draw_range: 0..indices.len(),
+                    mesh: &mesh,
+                    draw_range: start..(start + indices.len()),
                     draw_mode: solstice::DrawMode::Triangles,
                     instance_count: 1,
                 };
@@ -1073,3 +1039,337 @@ pub fn create_default_texture(gl: &mut solstice::Context) -> ImageResult {
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solstice::backend::{recording::Command, RecordingBackend};
+
+    #[test]
+    fn process() {
+        let backend = RecordingBackend::new();
+        let mut ctx = Context::with_backend(backend.clone());
+        let mut gfx = Graphics::new(&mut ctx, 720., 480.).unwrap();
+
+        let mut dl = DrawList::default();
+        dl.clear([0., 0., 0., 1.]);
+        dl.draw_with_color(Rectangle::new(0., 0., 100., 100.), [1., 0., 0., 1.]);
+        let start = backend.recording().commands.len();
+        gfx.process(&mut ctx, &dl);
+
+        let recording = backend.recording();
+        let commands = &recording.commands[start..];
+        assert_eq!(commands.len(), 2);
+        match &commands[0] {
+            Command::Clear { state, .. } => assert_eq!(state.clear_color, [0., 0., 0., 1.]),
+            command => panic!("expected a clear, got {:?}", command),
+        }
+        let draw = match &commands[1] {
+            Command::Draw(draw) => draw,
+            command => panic!("expected a draw, got {:?}", command),
+        };
+        assert_eq!(draw.count, 6);
+        assert_eq!(draw.state.viewport, [0, 0, 720, 480]);
+        assert_eq!(
+            draw.uniforms.get("uColor"),
+            Some(&solstice::shader::RawUniformValue::Vec4(
+                [1., 0., 0., 1.].into()
+            ))
+        );
+    }
+
+    #[test]
+    fn uniform_arrays() {
+        use solstice::shader::RawUniformValue;
+
+        const SHADER: &str = r#"
+uniform float uWeights[3];
+
+vec4 effect(vec4 color, Image texture, vec2 st, vec2 screen_coords) {
+    return color * uWeights[2];
+}"#;
+
+        let backend = RecordingBackend::new();
+        let mut ctx = Context::with_backend(backend.clone());
+        let mut gfx = Graphics::new(&mut ctx, 720., 480.).unwrap();
+        let mut shader = Shader::with(SHADER, &mut ctx).unwrap();
+        shader.send_uniform("uWeights", vec![1f32, 2., 3.]);
+
+        let mut dl = DrawList::default();
+        dl.set_shader(Some(shader));
+        dl.draw(Rectangle::new(0., 0., 100., 100.));
+        let start = backend.recording().commands.len();
+        gfx.process(&mut ctx, &dl);
+
+        let recording = backend.recording();
+        let draw = recording.commands[start..]
+            .iter()
+            .find_map(|command| match command {
+                Command::Draw(draw) => Some(draw),
+                _ => None,
+            })
+            .unwrap();
+        for (i, weight) in [1., 2., 3.].iter().enumerate() {
+            assert_eq!(
+                draw.uniforms.get(&format!("uWeights[{}]", i)),
+                Some(&RawUniformValue::Float(*weight))
+            );
+        }
+    }
+
+    #[test]
+    fn streamed_geometry() {
+        let backend = RecordingBackend::new();
+        let mut ctx = Context::with_backend(backend.clone());
+        let mut gfx = Graphics::new(&mut ctx, 720., 480.).unwrap();
+
+        let mut dl = DrawList::default();
+        for i in 0..3 {
+            dl.draw(Rectangle::new(i as f32 * 10., 0., 10., 10.));
+        }
+        let start = backend.recording().commands.len();
+        gfx.process(&mut ctx, &dl);
+        gfx.process(&mut ctx, &dl);
+
+        let recording = backend.recording();
+        let draws = recording.commands[start..]
+            .iter()
+            .filter_map(|command| match command {
+                Command::Draw(draw) => Some(draw),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(draws.len(), 6);
+        // every draw gets its own slice of the same buffers, across frames too
+        let index_size = std::mem::size_of::<u32>() as i32;
+        for (i, draw) in draws.iter().enumerate() {
+            assert_eq!(draw.count, 6);
+            assert_eq!(draw.first, i as i32 * 6 * index_size);
+            assert_eq!(
+                draw.state.vertex_attributes[&0].buffer,
+                draws[0].state.vertex_attributes[&0].buffer
+            );
+        }
+
+        let index_buffer = draws[0].state.buffers[&solstice::glow::ELEMENT_ARRAY_BUFFER];
+        let indices: &[u32] = bytemuck::cast_slice(&recording.buffers[index_buffer].data);
+        assert_eq!(
+            &indices[..6],
+            &indices[6..12].iter().map(|i| i - 4).collect::<Vec<_>>()[..]
+        );
+    }
+
+    #[test]
+    fn growing_buffers() {
+        let backend = RecordingBackend::new();
+        let mut ctx = Context::with_backend(backend.clone());
+        let config = Config {
+            width: 720.,
+            height: 480.,
+            line_capacity: 4,
+            mesh_capacity: 4,
+            growth_policy: solstice::buffer::GrowthPolicy::Double,
+        };
+        let mut gfx = Graphics::with_config(&mut ctx, &config).unwrap();
+
+        let mut dl = DrawList::default();
+        dl.draw(Circle {
+            x: 0.,
+            y: 0.,
+            radius: 10.,
+            segments: 100,
+        });
+        dl.line_2d(
+            (0..100)
+                .map(|i| LineVertex {
+                    position: [i as f32, 0., 0.],
+                    width: 1.,
+                    color: [1.; 4],
+                })
+                .collect::<Vec<_>>(),
+        );
+        let start = backend.recording().commands.len();
+        gfx.process(&mut ctx, &dl);
+
+        let recording = backend.recording();
+        let draws = recording.commands[start..]
+            .iter()
+            .filter_map(|command| match command {
+                Command::Draw(draw) => Some(draw),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(draws.len(), 2);
+        assert!(draws[0].count > 100);
+        assert_eq!(draws[1].instance_count, 99);
+    }
+
+    #[test]
+    fn software_rectangle() {
+        let mut dl = DrawList::default();
+        dl.clear([0., 0., 1., 1.]);
+        dl.draw_with_color(Rectangle::new(2., 2., 4., 4.), [1., 0., 0., 1.]);
+        let pixels = render_to_rgba(&dl, 8, 8).unwrap();
+
+        let pixel = |x: usize, y: usize| &pixels[(y * 8 + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(2, 2), [255, 0, 0, 255]);
+        assert_eq!(pixel(5, 5), [255, 0, 0, 255]);
+        assert_eq!(pixel(6, 6), [0, 0, 255, 255]);
+
+        let mut expected = vec![0; pixels.len()];
+        for (index, pixel) in expected.chunks_mut(4).enumerate() {
+            let (x, y) = (index % 8, index / 8);
+            let inside = (2..6).contains(&x) && (2..6).contains(&y);
+            pixel.copy_from_slice(if inside {
+                &[255, 0, 0, 255]
+            } else {
+                &[0, 0, 255, 255]
+            });
+        }
+        assert!(diff_rgba(&expected, &pixels, 0).is_match());
+    }
+
+    #[test]
+    fn software_line() {
+        let vertex = |x, y| LineVertex {
+            position: [x, y, 0.],
+            width: 2.,
+            color: [0., 1., 0., 1.],
+        };
+        let mut dl = DrawList::default();
+        dl.clear([0., 0., 0., 1.]);
+        dl.line_2d(vec![vertex(0., 4.), vertex(8., 4.)]);
+        let pixels = render_to_rgba(&dl, 8, 8).unwrap();
+
+        let pixel = |x: usize, y: usize| &pixels[(y * 8 + x) * 4..][..4];
+        for x in 0..8 {
+            assert_eq!(pixel(x, 0), [0, 0, 0, 255]);
+            assert_eq!(pixel(x, 3), [0, 255, 0, 255]);
+            assert_eq!(pixel(x, 4), [0, 255, 0, 255]);
+            assert_eq!(pixel(x, 7), [0, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn software_msaa_canvas() {
+        let mut renderer = SoftwareRenderer::new(8, 8).unwrap();
+        let canvas = Canvas::with_settings(
+            renderer.ctx_mut(),
+            solstice::canvas::Settings {
+                width: 8,
+                height: 8,
+                msaa: 4,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(canvas.inner.msaa(), 4);
+
+        let mut dl = DrawList::default();
+        dl.set_canvas(Some(canvas.clone()));
+        dl.clear([0., 0., 1., 1.]);
+        dl.draw_with_color(Rectangle::new(2., 2., 4., 4.), [1., 0., 0., 1.]);
+        dl.set_canvas(None);
+        dl.clear([0., 0., 0., 1.]);
+        dl.image(Rectangle::new(0., 0., 8., 8.), &canvas);
+        let pixels = renderer.render(&dl);
+
+        let pixel = |x: usize, y: usize| &pixels[(y * 8 + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(3, 4), [255, 0, 0, 255]);
+        assert_eq!(pixel(7, 7), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn software_text() {
+        let font = include_bytes!("../examples/resources/DejaVuSans.ttf").to_vec();
+        let mut renderer = SoftwareRenderer::new(32, 32).unwrap();
+        let font = renderer
+            .gfx_mut()
+            .add_font(std::convert::TryInto::try_into(font).unwrap());
+
+        let mut dl = DrawList::default();
+        dl.clear([0., 0., 0., 1.]);
+        dl.set_color([1., 1., 1., 1.]);
+        dl.print("I", font, 32., Rectangle::new(0., 0., 32., 32.));
+        let pixels = renderer.render(&dl);
+
+        let lit = pixels.chunks(4).filter(|pixel| pixel[0] > 128).count();
+        assert!(lit > 0 && lit < 32 * 32 / 2, "{} pixels lit", lit);
+    }
+
+    #[test]
+    fn file_shader() {
+        use solstice::shader::{RawUniformValue, Shader as _};
+
+        const SHADER: &str = r#"
+uniform float uScale;
+
+vec4 effect(vec4 color, Image texture, vec2 st, vec2 screen_coords) {
+    return color * uScale;
+}"#;
+
+        let path = std::env::temp_dir().join(format!("solstice-2d-{}.glsl", std::process::id()));
+        // bumps the modification time since writes can land within the file system's resolution
+        let write = |src: &str, seconds: u64| {
+            std::fs::write(&path, src).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(
+                    std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds),
+                )
+                .unwrap();
+        };
+
+        let backend = RecordingBackend::new();
+        let mut ctx = Context::with_backend(backend.clone());
+        let mut gfx = Graphics::new(&mut ctx, 720., 480.).unwrap();
+        write(SHADER, 1);
+        let mut shader = FileShader::new(&mut ctx, &path).unwrap();
+        shader.shader_mut().send_uniform("uScale", 2f32);
+        assert!(!shader.reload(&mut ctx).unwrap());
+        let first = shader.shader().handle();
+
+        write(&SHADER.replace("color * uScale", "color * uScale * 0.5"), 2);
+        assert!(shader.reload(&mut ctx).unwrap());
+        let second = shader.shader().handle();
+        assert_ne!(first, second);
+
+        // compile errors keep the previous program around
+        write("#error oops", 3);
+        match shader.reload(&mut ctx) {
+            Err(ShaderError::GraphicsError(_)) => (),
+            result => panic!("expected a compile error, got {:?}", result),
+        }
+        assert_eq!(shader.shader().handle(), second);
+        assert!(!shader.reload(&mut ctx).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        match shader.reload(&mut ctx) {
+            Err(ShaderError::FileError(error_path, _)) => assert_eq!(error_path, path),
+            result => panic!("expected a file error, got {:?}", result),
+        }
+
+        let mut dl = DrawList::default();
+        dl.set_shader(Some(shader.shader().clone()));
+        dl.draw(Rectangle::new(0., 0., 100., 100.));
+        let start = backend.recording().commands.len();
+        gfx.process(&mut ctx, &dl);
+
+        let recording = backend.recording();
+        let draw = recording.commands[start..]
+            .iter()
+            .find_map(|command| match command {
+                Command::Draw(draw) => Some(draw),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(draw.state.program, Some(second));
+        assert_eq!(
+            draw.uniforms.get("uScale"),
+            Some(&RawUniformValue::Float(2.))
+        );
+    }
+}
@@ -1,12 +1,16 @@
 mod color;
+mod file_shader;
 mod lines;
 mod noise_texture;
 mod shader;
+mod software;
 
 pub use color::*;
+pub use file_shader::*;
 pub use lines::*;
 pub use noise_texture::*;
 pub use shader::*;
+pub use software::*;
 
 #[derive(Debug)]
 pub enum GraphicsError {
@@ -0,0 +1,91 @@
+use super::{Shader, ShaderError};
+use solstice::Context;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// A `Shader` whose source is read from files so it can be edited while the app is running.
+///
+/// `reload` polls the modification times of the files and recompiles the shader when they
+/// change. A source that fails to compile leaves the previous program in place and uniforms
+/// sent with `send_uniform` carry over to the new one.
+#[derive(Debug)]
+pub struct FileShader {
+    shader: Shader,
+    vertex: PathBuf,
+    fragment: PathBuf,
+    modified: [Option<SystemTime>; 2],
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn read(path: &Path) -> Result<String, ShaderError> {
+    std::fs::read_to_string(path).map_err(|err| ShaderError::FileError(path.to_owned(), err))
+}
+
+impl FileShader {
+    /// Loads a shader whose `pos` and `effect` functions are both in the file at `path`.
+    pub fn new<P: AsRef<Path>>(ctx: &mut Context, path: P) -> Result<Self, ShaderError> {
+        Self::with_stages(ctx, path.as_ref(), path.as_ref())
+    }
+
+    /// Loads a shader with its `pos` function in the file at `vertex` and its `effect` function
+    /// in the file at `fragment`.
+    pub fn with_stages<V, F>(ctx: &mut Context, vertex: V, fragment: F) -> Result<Self, ShaderError>
+    where
+        V: AsRef<Path>,
+        F: AsRef<Path>,
+    {
+        let vertex = vertex.as_ref().to_owned();
+        let fragment = fragment.as_ref().to_owned();
+        let modified = [modified(&vertex), modified(&fragment)];
+        let vertex_src = read(&vertex)?;
+        let fragment_src = read(&fragment)?;
+        let shader = Shader::with((vertex_src.as_str(), fragment_src.as_str()), ctx)?;
+        Ok(Self {
+            shader,
+            vertex,
+            fragment,
+            modified,
+        })
+    }
+
+    /// Recompiles the shader if its files have been modified since they were last read. Returns
+    /// whether the shader was recompiled.
+    ///
+    /// Errors leave the previous program in place. The files aren't read again until they next
+    /// change so it's fine to call this every frame.
+    pub fn reload(&mut self, ctx: &mut Context) -> Result<bool, ShaderError> {
+        let modified = [modified(&self.vertex), modified(&self.fragment)];
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+        let vertex = read(&self.vertex)?;
+        let fragment = read(&self.fragment)?;
+        self.shader
+            .recompile((vertex.as_str(), fragment.as_str()), ctx)?;
+        Ok(true)
+    }
+
+    pub fn shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    pub fn shader_mut(&mut self) -> &mut Shader {
+        &mut self.shader
+    }
+
+    pub fn vertex_path(&self) -> &Path {
+        &self.vertex
+    }
+
+    pub fn fragment_path(&self) -> &Path {
+        &self.fragment
+    }
+}
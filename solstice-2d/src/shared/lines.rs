@@ -6,7 +6,7 @@ use solstice::{
     Context,
 };
 
-const SHADER_SRC: &str = include_str!("lines.glsl");
+pub(super) const SHADER_SRC: &str = include_str!("lines.glsl");
 
 #[repr(C)]
 #[derive(bytemuck::Zeroable, bytemuck::Pod, Vertex, Copy, Clone, Debug)]
@@ -185,8 +185,20 @@ impl LineWorkspace {
         })
     }
 
+    pub fn set_growth_policy(&mut self, growth_policy: solstice::buffer::GrowthPolicy) {
+        self.positions.set_growth_policy(growth_policy)
+    }
+
     pub fn can_buffer(&self, verts: &[LineVertex]) -> bool {
-        self.offset + verts.len() < self.positions.get_vertices().len()
+        let size = std::mem::size_of::<LineVertex>();
+        let capacity = self.positions.get_vertices().len() * size;
+        let required = (self.offset + verts.len()) * size;
+        required <= capacity
+            || self
+                .positions
+                .growth_policy()
+                .grow(capacity, required)
+                .is_some()
     }
 
     pub fn add_points(&mut self, verts: &[LineVertex]) {
@@ -27,6 +27,7 @@ pub enum Projection {
 pub enum ShaderError {
     GraphicsError(solstice::GraphicsError),
     UniformNotFound(String),
+    FileError(std::path::PathBuf, std::io::Error),
 }
 
 impl std::fmt::Display for ShaderError {
@@ -99,13 +100,13 @@ pub struct Shader {
     other_uniforms: std::collections::HashMap<String, solstice::shader::RawUniformValue>,
 }
 
-const DEFAULT_VERT: &str = r#"
+pub(super) const DEFAULT_VERT: &str = r#"
 vec4 pos(mat4 transform_projection, vec4 vertex_position) {
     return transform_projection * vertex_position;
 }
 "#;
 
-const DEFAULT_FRAG: &str = r#"
+pub(super) const DEFAULT_FRAG: &str = r#"
 vec4 effect(vec4 color, Image texture, vec2 texture_coords, vec2 screen_coords) {
     return Texel(texture, texture_coords) * color;
 }
@@ -118,7 +119,7 @@ fn get_location(
     shader
         .get_uniform_by_name(name)
         .ok_or_else(|| ShaderError::UniformNotFound(name.to_owned()))
-        .map(|uniform| uniform.location.clone())
+        .map(|uniform| uniform.location)
 }
 
 fn shader_src(src: ShaderSource) -> String {
@@ -274,6 +275,27 @@ impl Shader {
         })
     }
 
+    /// Compiles `src` and swaps it in for the current program, keeping the textures, transforms,
+    /// color and uniforms that have been set. The current program is kept if compilation fails.
+    pub fn recompile<'a, S>(&mut self, src: S, ctx: &mut Context) -> Result<(), ShaderError>
+    where
+        S: Into<ShaderSource<'a>>,
+    {
+        let mut shader = Self::with(src, ctx)?;
+        shader.projection_cache = self.projection_cache;
+        shader.view_cache = self.view_cache;
+        shader.model_cache = self.model_cache;
+        shader.color_cache = self.color_cache;
+        shader.resolution_cache = self.resolution_cache;
+        for (texture, previous) in shader.textures.iter_mut().zip(self.textures.iter()) {
+            texture.ty = previous.ty;
+            texture.key = previous.key;
+        }
+        shader.other_uniforms = std::mem::take(&mut self.other_uniforms);
+        *self = shader;
+        Ok(())
+    }
+
     pub fn set_viewport(
         &mut self,
         projection: Projection,
@@ -394,7 +416,13 @@ impl Shader {
         S: AsRef<str>,
         V: std::convert::TryInto<solstice::shader::RawUniformValue>,
     {
-        if let Some(uniform) = self.inner.get_uniform_by_name(name.as_ref()) {
+        // arrays are reflected one element at a time so their name is that of the first element
+        let name = name.as_ref();
+        let uniform = self
+            .inner
+            .get_uniform_by_name(name)
+            .or_else(|| self.inner.get_uniform_by_name(&format!("{}[0]", name)));
+        if let Some(uniform) = uniform {
             if let Some(data) = value.try_into().ok() {
                 self.other_uniforms.insert(uniform.name.clone(), data);
             }
@@ -465,6 +493,10 @@ impl solstice::shader::Shader for Shader {
     fn uniforms(&self) -> &[Uniform] {
         self.inner.uniforms()
     }
+
+    fn uniform_blocks(&self) -> &[solstice::shader::UniformBlock] {
+        self.inner.uniform_blocks()
+    }
 }
 
 fn ortho(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> [[f32; 4]; 4] {
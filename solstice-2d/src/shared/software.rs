@@ -0,0 +1,216 @@
+use crate::{text, DrawList, Graphics, GraphicsError};
+use solstice::{
+    backend::software::{Fragment, SoftwareProgram, Uniforms, Varyings, VertexInput},
+    backend::SoftwareBackend,
+    Context, PixelFormat,
+};
+
+pub use solstice::backend::software::{diff_rgba, ImageDiff};
+
+type Matrix4 = nalgebra::Matrix4<f32>;
+type Vector4 = nalgebra::Vector4<f32>;
+
+fn transform(uniforms: &Uniforms) -> Matrix4 {
+    let projection: Matrix4 = uniforms.mat4("uProjection").into();
+    let view: Matrix4 = uniforms.mat4("uView").into();
+    let model: Matrix4 = uniforms.mat4("uModel").into();
+    projection * view * model
+}
+
+fn multiply(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2], a[3] * b[3]]
+}
+
+fn vertex_color_and_uv(input: &VertexInput, varyings: &mut Varyings) {
+    varyings[..4].copy_from_slice(&input.get("color"));
+    varyings[4..6].copy_from_slice(&input.get("uv")[..2]);
+}
+
+fn uv(fragment: &Fragment) -> [f32; 2] {
+    [fragment.varyings[4], fragment.varyings[5]]
+}
+
+fn color(fragment: &Fragment, uniforms: &Uniforms) -> [f32; 4] {
+    let mut color = [0.; 4];
+    color.copy_from_slice(&fragment.varyings[..4]);
+    multiply(uniforms.vec4("uColor"), color)
+}
+
+// `shader_src` wrapped around the default `pos` and `effect`.
+struct DefaultProgram;
+
+impl SoftwareProgram for DefaultProgram {
+    fn vertex(
+        &self,
+        input: &VertexInput,
+        uniforms: &Uniforms,
+        varyings: &mut Varyings,
+    ) -> [f32; 4] {
+        vertex_color_and_uv(input, varyings);
+        (transform(uniforms) * Vector4::from(input.get("position"))).into()
+    }
+
+    fn fragment(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<[f32; 4]> {
+        let texel = uniforms.sample("tex0", uv(fragment));
+        Some(multiply(texel, color(fragment, uniforms)))
+    }
+}
+
+// `text::DEFAULT_FRAG` only takes coverage from the glyph atlas.
+struct TextProgram;
+
+impl SoftwareProgram for TextProgram {
+    fn vertex(
+        &self,
+        input: &VertexInput,
+        uniforms: &Uniforms,
+        varyings: &mut Varyings,
+    ) -> [f32; 4] {
+        DefaultProgram.vertex(input, uniforms, varyings)
+    }
+
+    fn fragment(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<[f32; 4]> {
+        let mut color = color(fragment, uniforms);
+        color[3] *= uniforms.sample("tex0", uv(fragment))[3];
+        Some(color)
+    }
+}
+
+// A port of `lines.glsl`.
+struct LinesProgram;
+
+impl SoftwareProgram for LinesProgram {
+    fn vertex(
+        &self,
+        input: &VertexInput,
+        uniforms: &Uniforms,
+        varyings: &mut Varyings,
+    ) -> [f32; 4] {
+        use nalgebra::{Vector2, Vector3};
+
+        let point = Vector4::from(input.get("point"));
+        let mix = |a: Vector4, b: Vector4| a + (b - a) * point.z;
+        let line_color = mix(input.get("color1").into(), input.get("color2").into());
+        varyings[..4].copy_from_slice(line_color.as_slice());
+        varyings[4..6].copy_from_slice(&input.get("uv")[..2]);
+
+        let transform = transform(uniforms);
+        let position = |name| Vector3::from_row_slice(&input.get(name)[..3]).push(1.);
+        let clip0 = transform * position("position1");
+        let clip1 = transform * position("position2");
+        let resolution = Vector2::from_row_slice(&uniforms.vec4("uResolution")[..2]);
+        let to_screen = |clip: Vector4| {
+            (clip.xy() / clip.w * 0.5)
+                .add_scalar(0.5)
+                .component_mul(&resolution)
+        };
+        let (screen0, screen1) = (to_screen(clip0), to_screen(clip1));
+        let x_basis = (screen1 - screen0).normalize();
+        let y_basis = Vector2::new(-x_basis.y, x_basis.x);
+        let offset = point.x * x_basis + point.y * y_basis;
+        let pt0 = screen0 + input.get("width1")[0] * offset;
+        let pt1 = screen1 + input.get("width2")[0] * offset;
+        let pt = pt0 + (pt1 - pt0) * point.z;
+        let clip = mix(clip0, clip1);
+        let xy = (pt.component_div(&resolution) * 2.).add_scalar(-1.) * clip.w;
+        [xy.x, xy.y, clip.z, clip.w]
+    }
+
+    fn fragment(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<[f32; 4]> {
+        let mut line_color = [0.; 4];
+        line_color.copy_from_slice(&fragment.varyings[..4]);
+        let color = multiply(line_color, uniforms.vec4("uColor"));
+        Some(multiply(color, uniforms.sample("tex0", uv(fragment))))
+    }
+}
+
+/// A `SoftwareBackend` that can run the shaders built into this crate. Custom shaders need to
+/// have a `SoftwareProgram` registered before the backend is given to a `Context`.
+pub fn software_backend(width: u32, height: u32) -> SoftwareBackend {
+    let mut backend = SoftwareBackend::new(width, height);
+    backend.register_program(
+        |vertex, _| vertex.contains(super::lines::SHADER_SRC),
+        LinesProgram,
+    );
+    backend.register_program(
+        |vertex, fragment| {
+            vertex.contains(text::DEFAULT_VERT) && fragment.contains(text::DEFAULT_FRAG)
+        },
+        TextProgram,
+    );
+    backend.register_program(
+        |vertex, fragment| {
+            vertex.contains(super::shader::DEFAULT_VERT)
+                && fragment.contains(super::shader::DEFAULT_FRAG)
+        },
+        DefaultProgram,
+    );
+    backend
+}
+
+/// Renders `DrawList`s on the CPU. Useful for comparing output against known good images.
+pub struct SoftwareRenderer {
+    ctx: Context,
+    gfx: Graphics,
+    width: u32,
+    height: u32,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u32, height: u32) -> Result<Self, GraphicsError> {
+        Self::with_backend(software_backend(width, height), width, height)
+    }
+
+    pub fn with_backend(
+        backend: SoftwareBackend,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, GraphicsError> {
+        let mut ctx = Context::with_backend(backend);
+        let gfx = Graphics::new(&mut ctx, width as _, height as _)?;
+        Ok(Self {
+            ctx,
+            gfx,
+            width,
+            height,
+        })
+    }
+
+    /// For creating the resources that draw lists refer to, like canvases and images.
+    pub fn ctx_mut(&mut self) -> &mut Context {
+        &mut self.ctx
+    }
+
+    pub fn gfx_mut(&mut self) -> &mut Graphics {
+        &mut self.gfx
+    }
+
+    /// Returns tightly packed RGBA8 pixels with the top row first.
+    pub fn render(&mut self, draw_list: &DrawList) -> Vec<u8> {
+        self.gfx.process(&mut self.ctx, draw_list);
+        self.ctx
+            .bind_framebuffer(solstice::canvas::Target::All, None);
+
+        let row_length = self.width as usize * 4;
+        let mut pixels = vec![0; row_length * self.height as usize];
+        self.ctx.read_pixels(
+            0,
+            0,
+            self.width as _,
+            self.height as _,
+            PixelFormat::RGBA8,
+            &mut pixels,
+        );
+        // GL rows start at the bottom
+        pixels.chunks(row_length).rev().flatten().copied().collect()
+    }
+}
+
+/// Renders `draw_list` with a new `SoftwareRenderer`.
+pub fn render_to_rgba(
+    draw_list: &DrawList,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, GraphicsError> {
+    Ok(SoftwareRenderer::new(width, height)?.render(draw_list))
+}
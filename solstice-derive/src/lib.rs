@@ -166,3 +166,55 @@ pub fn derive_vertex(item: TokenStream) -> TokenStream {
         Data::Enum(_) | Data::Union(_) => panic!("only structs are supported"),
     }
 }
+
+#[proc_macro_derive(Std140)]
+pub fn derive_std140(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+
+    let fields = match input.data {
+        Data::Struct(s) => match s.fields {
+            Fields::Named(fields) => fields.named,
+            Fields::Unnamed(_) | Fields::Unit => panic!("only named fields are supported"),
+        },
+        Data::Enum(_) | Data::Union(_) => panic!("only structs are supported"),
+    };
+
+    let field_idents = fields
+        .iter()
+        .map(|field| field.ident.as_ref().unwrap())
+        .collect::<Vec<_>>();
+    let field_types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+
+    let ident = input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    TokenStream::from(quote! {
+        impl #impl_generics ::solstice::shader::Std140 for #ident #ty_generics #where_clause {
+            const ALIGNMENT: usize = ::solstice::shader::std140_struct_alignment(&[
+                #(<#field_types as ::solstice::shader::Std140>::ALIGNMENT),*
+            ]);
+            const SIZE: usize = {
+                let offset = 0usize;
+                #(
+                    let offset = ::solstice::shader::std140_align(
+                        offset,
+                        <#field_types as ::solstice::shader::Std140>::ALIGNMENT,
+                    ) + <#field_types as ::solstice::shader::Std140>::SIZE;
+                )*
+                ::solstice::shader::std140_align(offset, Self::ALIGNMENT)
+            };
+
+            fn write_std140(&self, out: &mut [u8]) {
+                let offset = 0usize;
+                #(
+                    let offset = ::solstice::shader::std140_align(
+                        offset,
+                        <#field_types as ::solstice::shader::Std140>::ALIGNMENT,
+                    );
+                    ::solstice::shader::Std140::write_std140(&self.#field_idents, &mut out[offset..]);
+                    let offset = offset + <#field_types as ::solstice::shader::Std140>::SIZE;
+                )*
+                let _ = offset;
+            }
+        }
+    })
+}
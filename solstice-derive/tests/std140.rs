@@ -0,0 +1,66 @@
+use solstice::shader::Std140 as _;
+use solstice_derive::Std140;
+
+#[derive(Std140)]
+struct Light {
+    position: mint::Vector3<f32>,
+    intensity: f32,
+    color: mint::Vector3<f32>,
+}
+
+#[derive(Std140)]
+struct Scene {
+    time: f32,
+    resolution: mint::Vector2<f32>,
+    view: mint::ColumnMatrix3<f32>,
+    weights: [f32; 2],
+    lights: [Light; 2],
+    enabled: bool,
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..(offset + 4)]);
+    f32::from_ne_bytes(value)
+}
+
+#[test]
+fn derive_std140_layout() {
+    // the float after a vec3 fills its padding
+    assert_eq!(Light::ALIGNMENT, 16);
+    assert_eq!(Light::SIZE, 32);
+
+    // time: 0, resolution: 8, view: 16, weights: 64, lights: 96, enabled: 160
+    assert_eq!(Scene::ALIGNMENT, 16);
+    assert_eq!(Scene::SIZE, 176);
+
+    let light = |x: f32| Light {
+        position: [x, x, x].into(),
+        intensity: x + 0.5,
+        color: [x; 3].into(),
+    };
+    let scene = Scene {
+        time: 1.,
+        resolution: [2., 3.].into(),
+        view: [[4.; 3], [5.; 3], [6.; 3]].into(),
+        weights: [7., 8.],
+        lights: [light(9.), light(10.)],
+        enabled: true,
+    };
+    let bytes = scene.to_std140();
+    assert_eq!(bytes.len(), Scene::SIZE);
+    assert_eq!(read_f32(&bytes, 0), 1.);
+    assert_eq!(read_f32(&bytes, 8), 2.);
+    assert_eq!(read_f32(&bytes, 12), 3.);
+    assert_eq!(read_f32(&bytes, 16), 4.);
+    assert_eq!(read_f32(&bytes, 28), 0.);
+    assert_eq!(read_f32(&bytes, 32), 5.);
+    assert_eq!(read_f32(&bytes, 48), 6.);
+    assert_eq!(read_f32(&bytes, 64), 7.);
+    assert_eq!(read_f32(&bytes, 80), 8.);
+    assert_eq!(read_f32(&bytes, 96), 9.);
+    assert_eq!(read_f32(&bytes, 108), 9.5);
+    assert_eq!(read_f32(&bytes, 112), 9.);
+    assert_eq!(read_f32(&bytes, 128), 10.);
+    assert_eq!(&bytes[160..164], &1u32.to_ne_bytes());
+}
@@ -0,0 +1,1532 @@
+use super::{glsl, Backend, DebugCallback};
+use crate::{
+    gl,
+    shader::{self, ShaderError},
+    BufferKey, FramebufferKey, GraphicsError, QueryKey, RenderbufferKey, ShaderKey, TextureKey,
+};
+use glow::HasContext;
+use slotmap::{SecondaryMap, SlotMap};
+#[cfg(not(target_arch = "wasm32"))]
+use std::os::raw::c_void;
+
+type GLContext = glow::Context;
+
+type GLBuffer = <GLContext as HasContext>::Buffer;
+type GLProgram = <GLContext as HasContext>::Program;
+type GLTexture = <GLContext as HasContext>::Texture;
+type GLFramebuffer = <GLContext as HasContext>::Framebuffer;
+type GLRenderbuffer = <GLContext as HasContext>::Renderbuffer;
+type GLQuery = <GLContext as HasContext>::Query;
+type GLVertexArray = <GLContext as HasContext>::VertexArray;
+type GLUniformLocation = <GLContext as HasContext>::UniformLocation;
+
+struct Program {
+    handle: GLProgram,
+    attributes: Vec<shader::Attribute>,
+    uniforms: Vec<shader::Uniform>,
+    uniform_blocks: Vec<shader::UniformBlock>,
+}
+
+// glow doesn't wrap the program binary functions
+#[cfg(not(target_arch = "wasm32"))]
+type GetProgramIv = unsafe extern "system" fn(u32, u32, *mut i32);
+#[cfg(not(target_arch = "wasm32"))]
+type GetProgramBinary = unsafe extern "system" fn(u32, i32, *mut i32, *mut u32, *mut c_void);
+#[cfg(not(target_arch = "wasm32"))]
+type ProgramBinary = unsafe extern "system" fn(u32, u32, *const c_void, i32);
+#[cfg(not(target_arch = "wasm32"))]
+type ProgramParameteri = unsafe extern "system" fn(u32, u32, i32);
+
+#[cfg(not(target_arch = "wasm32"))]
+struct ProgramBinaryFunctions {
+    get_program_iv: GetProgramIv,
+    get_program_binary: GetProgramBinary,
+    program_binary: ProgramBinary,
+    program_parameter_i32: ProgramParameteri,
+}
+
+// nor the compressed texture functions
+#[cfg(not(target_arch = "wasm32"))]
+type CompressedTexImage2D =
+    unsafe extern "system" fn(u32, i32, u32, i32, i32, i32, i32, *const c_void);
+#[cfg(not(target_arch = "wasm32"))]
+type CompressedTexSubImage2D =
+    unsafe extern "system" fn(u32, i32, i32, i32, i32, i32, u32, i32, *const c_void);
+#[cfg(not(target_arch = "wasm32"))]
+type CompressedTexImage3D =
+    unsafe extern "system" fn(u32, i32, u32, i32, i32, i32, i32, i32, *const c_void);
+#[cfg(not(target_arch = "wasm32"))]
+type CompressedTexSubImage3D =
+    unsafe extern "system" fn(u32, i32, i32, i32, i32, i32, i32, i32, u32, i32, *const c_void);
+
+// nor the 64 bit query results, which are core in GL 3.3 and need EXT_disjoint_timer_query on GLES
+#[cfg(not(target_arch = "wasm32"))]
+type GetQueryObjectui64v = unsafe extern "system" fn(u32, u32, *mut u64);
+
+#[cfg(not(target_arch = "wasm32"))]
+struct CompressedTextureFunctions {
+    tex_image_2d: CompressedTexImage2D,
+    tex_sub_image_2d: CompressedTexSubImage2D,
+    // only in GL 3 and GLES 3
+    tex_image_3d: Option<CompressedTexImage3D>,
+    tex_sub_image_3d: Option<CompressedTexSubImage3D>,
+}
+
+/// The default backend, built on top of `glow`.
+///
+/// glow has no compressed texture functions on the web, so there this backend can't upload
+/// compressed textures even if the WebGL context advertises the extensions for them.
+pub struct GlowBackend {
+    ctx: GLContext,
+    buffers: SecondaryMap<BufferKey, GLBuffer>,
+    programs: SecondaryMap<ShaderKey, Program>,
+    textures: SecondaryMap<TextureKey, GLTexture>,
+    framebuffers: SecondaryMap<FramebufferKey, GLFramebuffer>,
+    renderbuffers: SecondaryMap<RenderbufferKey, GLRenderbuffer>,
+    queries: SecondaryMap<QueryKey, GLQuery>,
+    uniform_locations: SlotMap<shader::UniformLocation, GLUniformLocation>,
+    vertex_array: Option<GLVertexArray>,
+    #[cfg(not(target_arch = "wasm32"))]
+    program_binary: Option<ProgramBinaryFunctions>,
+    #[cfg(not(target_arch = "wasm32"))]
+    compressed_textures: Option<CompressedTextureFunctions>,
+    #[cfg(not(target_arch = "wasm32"))]
+    get_query_object_u64: Option<GetQueryObjectui64v>,
+    #[cfg(target_arch = "wasm32")]
+    supported_extensions: Option<Vec<String>>,
+}
+
+impl GlowBackend {
+    pub fn new(ctx: GLContext) -> Self {
+        let vertex_array = unsafe {
+            let vertex_array = ctx.create_vertex_array().ok();
+            ctx.bind_vertex_array(vertex_array);
+            vertex_array
+        };
+
+        Self {
+            ctx,
+            buffers: SecondaryMap::new(),
+            programs: SecondaryMap::new(),
+            textures: SecondaryMap::new(),
+            framebuffers: SecondaryMap::new(),
+            renderbuffers: SecondaryMap::new(),
+            queries: SecondaryMap::new(),
+            uniform_locations: SlotMap::with_key(),
+            vertex_array,
+            #[cfg(not(target_arch = "wasm32"))]
+            program_binary: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            compressed_textures: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            get_query_object_u64: None,
+            #[cfg(target_arch = "wasm32")]
+            supported_extensions: None,
+        }
+    }
+
+    /// Wraps a WebGL context. Unlike `new` this keeps the list of extensions the context
+    /// supports, which glow can't query.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_webgl1_context(context: web_sys::WebGlRenderingContext) -> Self {
+        let extensions = context.get_supported_extensions();
+        let mut backend = Self::new(GLContext::from_webgl1_context(context));
+        backend.supported_extensions =
+            extensions.map(|names| names.iter().filter_map(|name| name.as_string()).collect());
+        backend
+    }
+
+    /// Wraps a WebGL 2 context. Unlike `new` this keeps the list of extensions the context
+    /// supports, which glow can't query.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_webgl2_context(context: web_sys::WebGl2RenderingContext) -> Self {
+        let extensions = context.get_supported_extensions();
+        let mut backend = Self::new(GLContext::from_webgl2_context(context));
+        backend.supported_extensions =
+            extensions.map(|names| names.iter().filter_map(|name| name.as_string()).collect());
+        backend
+    }
+
+    /// Creates the `glow::Context` from `loader` and loads the GL functions glow doesn't
+    /// provide with it, for program binaries, compressed textures and 64 bit query results,
+    /// which `new` can't do.
+    ///
+    /// # Safety
+    ///
+    /// The same as `glow::Context::from_loader_function`: `loader` has to return valid function
+    /// pointers for the current GL context.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn from_loader_function<F>(mut loader: F) -> Self
+    where
+        F: FnMut(&str) -> *const c_void,
+    {
+        let mut backend = Self::new(GLContext::from_loader_function(&mut loader));
+        if !backend.load_program_binary_functions(&mut loader) {
+            log::debug!("program binaries aren't supported by this driver");
+        }
+        if !backend.load_compressed_texture_functions(&mut loader) {
+            log::debug!("compressed textures aren't supported by this driver");
+        }
+        if !backend.load_query_functions(&mut loader) {
+            log::debug!("64 bit query results aren't supported by this driver");
+        }
+        backend
+    }
+
+    /// Loads `glGetProgramBinary`, `glProgramBinary` and `glProgramParameteri`, which glow
+    /// doesn't provide, with the same loader the `glow::Context` was created from.
+    /// `from_loader_function` already does this. They're needed for
+    /// `Context::set_program_cache` and are available from GL 4.1, GLES 3 or with
+    /// `ARB_get_program_binary`. Returns whether they could be loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_program_binary_functions<F>(&mut self, mut loader: F) -> bool
+    where
+        F: FnMut(&str) -> *const c_void,
+    {
+        let get_program_iv = loader("glGetProgramiv");
+        let get_program_binary = loader("glGetProgramBinary");
+        let program_binary = loader("glProgramBinary");
+        let program_parameter_i32 = loader("glProgramParameteri");
+        self.program_binary = if get_program_iv.is_null()
+            || get_program_binary.is_null()
+            || program_binary.is_null()
+            || program_parameter_i32.is_null()
+        {
+            None
+        } else {
+            unsafe {
+                Some(ProgramBinaryFunctions {
+                    get_program_iv: std::mem::transmute::<*const c_void, GetProgramIv>(
+                        get_program_iv,
+                    ),
+                    get_program_binary: std::mem::transmute::<*const c_void, GetProgramBinary>(
+                        get_program_binary,
+                    ),
+                    program_binary: std::mem::transmute::<*const c_void, ProgramBinary>(
+                        program_binary,
+                    ),
+                    program_parameter_i32: std::mem::transmute::<*const c_void, ProgramParameteri>(
+                        program_parameter_i32,
+                    ),
+                })
+            }
+        };
+        self.program_binary.is_some()
+    }
+
+    /// Loads the `glCompressedTex*Image*` functions, which glow doesn't provide either, with the
+    /// same loader the `glow::Context` was created from. `from_loader_function` already does
+    /// this. Block-compressed pixel formats can't be uploaded without them. Returns whether they
+    /// could be loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_compressed_texture_functions<F>(&mut self, mut loader: F) -> bool
+    where
+        F: FnMut(&str) -> *const c_void,
+    {
+        let tex_image_2d = loader("glCompressedTexImage2D");
+        let tex_sub_image_2d = loader("glCompressedTexSubImage2D");
+        let tex_image_3d = loader("glCompressedTexImage3D");
+        let tex_sub_image_3d = loader("glCompressedTexSubImage3D");
+        self.compressed_textures = if tex_image_2d.is_null() || tex_sub_image_2d.is_null() {
+            None
+        } else {
+            unsafe {
+                Some(CompressedTextureFunctions {
+                    tex_image_2d: std::mem::transmute::<*const c_void, CompressedTexImage2D>(
+                        tex_image_2d,
+                    ),
+                    tex_sub_image_2d: std::mem::transmute::<*const c_void, CompressedTexSubImage2D>(
+                        tex_sub_image_2d,
+                    ),
+                    tex_image_3d: if tex_image_3d.is_null() {
+                        None
+                    } else {
+                        Some(std::mem::transmute::<*const c_void, CompressedTexImage3D>(
+                            tex_image_3d,
+                        ))
+                    },
+                    tex_sub_image_3d: if tex_sub_image_3d.is_null() {
+                        None
+                    } else {
+                        Some(
+                            std::mem::transmute::<*const c_void, CompressedTexSubImage3D>(
+                                tex_sub_image_3d,
+                            ),
+                        )
+                    },
+                })
+            }
+        };
+        self.compressed_textures.is_some()
+    }
+
+    /// Loads `glGetQueryObjectui64v`, which glow doesn't provide either, with the same loader
+    /// the `glow::Context` was created from. `from_loader_function` already does this. Without
+    /// it query results are read as 32 bits, so timers wrap after about 4.29 seconds. Returns
+    /// whether it could be loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_query_functions<F>(&mut self, mut loader: F) -> bool
+    where
+        F: FnMut(&str) -> *const c_void,
+    {
+        let get_query_object_u64 = Some(loader("glGetQueryObjectui64v"))
+            .filter(|function| !function.is_null())
+            .unwrap_or_else(|| loader("glGetQueryObjectui64vEXT"));
+        self.get_query_object_u64 = if get_query_object_u64.is_null() {
+            None
+        } else {
+            unsafe {
+                Some(std::mem::transmute::<*const c_void, GetQueryObjectui64v>(
+                    get_query_object_u64,
+                ))
+            }
+        };
+        self.get_query_object_u64.is_some()
+    }
+
+    fn insert_program(
+        &mut self,
+        program: ShaderKey,
+        handle: GLProgram,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) {
+        let attributes = self.get_program_attributes(handle);
+        let uniforms = self.get_program_uniforms(handle);
+        // glow can't query the layout of a block so it's worked out from the source instead
+        let declarations = glsl::declarations(vertex_source)
+            .into_iter()
+            .chain(glsl::declarations(fragment_source))
+            .collect::<Vec<_>>();
+        let uniform_blocks = glsl::uniform_blocks(&declarations)
+            .into_iter()
+            .filter_map(|mut block| {
+                let index = unsafe { self.ctx.get_uniform_block_index(handle, &block.name) }?;
+                block.index = index;
+                Some(block)
+            })
+            .collect();
+        self.programs.insert(
+            program,
+            Program {
+                handle,
+                attributes,
+                uniforms,
+                uniform_blocks,
+            },
+        );
+    }
+
+    fn get_program_attributes(&self, program: GLProgram) -> Vec<shader::Attribute> {
+        let count = unsafe { self.ctx.get_active_attributes(program) };
+        let mut attributes = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            unsafe {
+                let glow::ActiveAttribute { name, size, atype } =
+                    self.ctx.get_active_attribute(program, index).unwrap();
+                if let Some(location) = self.ctx.get_attrib_location(program, name.as_str()) {
+                    // specifically this is for gl_InstanceID
+                    attributes.push(shader::Attribute {
+                        name,
+                        size,
+                        atype: gl::attribute::from_gl(atype),
+                        location,
+                    });
+                }
+            }
+        }
+        attributes.sort_by(|a, b| a.location.partial_cmp(&b.location).unwrap());
+        attributes
+    }
+
+    fn get_program_uniforms(&mut self, program: GLProgram) -> Vec<shader::Uniform> {
+        unsafe fn get_initial_uniform_data(
+            gl: &glow::Context,
+            utype: u32,
+            program: GLProgram,
+            location: &GLUniformLocation,
+        ) -> Option<shader::RawUniformValue> {
+            use shader::RawUniformValue;
+            macro_rules! get_uniform_data {
+                (f32, 1, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
+                    let mut data = [0.; 1];
+                    $gl.get_uniform_f32($program, $location, &mut data);
+                    RawUniformValue::$uni_ty(data[0].into())
+                }};
+                (i32, 1, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
+                    let mut data = [0; 1];
+                    $gl.get_uniform_i32($program, $location, &mut data);
+                    RawUniformValue::$uni_ty(data[0].into())
+                }};
+                (f32, $data_size:expr, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
+                    let mut data = [0.; $data_size];
+                    $gl.get_uniform_f32($program, $location, &mut data);
+                    RawUniformValue::$uni_ty(data.into())
+                }};
+                (i32, $data_size:expr, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
+                    let mut data = [0; $data_size];
+                    $gl.get_uniform_i32($program, $location, &mut data);
+                    RawUniformValue::$uni_ty(data.into())
+                }};
+                // there's no glGetUniformuiv in glow so the bits are read back as signed
+                (u32, $data_size:expr, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
+                    let mut data = [0; $data_size];
+                    $gl.get_uniform_i32($program, $location, &mut data);
+                    RawUniformValue::$uni_ty(data.map(|v| v as u32).into())
+                }};
+            }
+
+            let data = match utype {
+                glow::FLOAT => get_uniform_data!(f32, 1, Float, gl, program, location),
+                glow::FLOAT_VEC2 => get_uniform_data!(f32, 2, Vec2, gl, program, location),
+                glow::FLOAT_VEC3 => get_uniform_data!(f32, 3, Vec3, gl, program, location),
+                glow::FLOAT_VEC4 => get_uniform_data!(f32, 4, Vec4, gl, program, location),
+                glow::FLOAT_MAT2 => get_uniform_data!(f32, 4, Mat2, gl, program, location),
+                glow::FLOAT_MAT3 => get_uniform_data!(f32, 9, Mat3, gl, program, location),
+                glow::FLOAT_MAT4 => get_uniform_data!(f32, 16, Mat4, gl, program, location),
+                glow::INT => get_uniform_data!(i32, 1, SignedInt, gl, program, location),
+                utype if gl::uniform::is_sampler(utype) => {
+                    get_uniform_data!(i32, 1, SignedInt, gl, program, location)
+                }
+                glow::INT_VEC2 | glow::BOOL_VEC2 => {
+                    get_uniform_data!(i32, 2, IntVec2, gl, program, location)
+                }
+                glow::INT_VEC3 | glow::BOOL_VEC3 => {
+                    get_uniform_data!(i32, 3, IntVec3, gl, program, location)
+                }
+                glow::INT_VEC4 | glow::BOOL_VEC4 => {
+                    get_uniform_data!(i32, 4, IntVec4, gl, program, location)
+                }
+                glow::BOOL => {
+                    let mut data = [0; 1];
+                    gl.get_uniform_i32(program, location, &mut data);
+                    RawUniformValue::Bool(data[0] != 0)
+                }
+                glow::UNSIGNED_INT => {
+                    let mut data = [0; 1];
+                    gl.get_uniform_i32(program, location, &mut data);
+                    RawUniformValue::UnsignedInt(data[0] as u32)
+                }
+                glow::UNSIGNED_INT_VEC2 => {
+                    get_uniform_data!(u32, 2, UnsignedIntVec2, gl, program, location)
+                }
+                glow::UNSIGNED_INT_VEC3 => {
+                    get_uniform_data!(u32, 3, UnsignedIntVec3, gl, program, location)
+                }
+                glow::UNSIGNED_INT_VEC4 => {
+                    get_uniform_data!(u32, 4, UnsignedIntVec4, gl, program, location)
+                }
+                _ => return None,
+            };
+            Some(data)
+        }
+
+        use shader::Uniform;
+        let gl = &self.ctx;
+        let locations = &mut self.uniform_locations;
+        let count = unsafe { gl.get_active_uniforms(program) };
+        let mut uniforms = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            unsafe {
+                let glow::ActiveUniform { name, size, utype } =
+                    gl.get_active_uniform(program, index).unwrap();
+                if size > 1 {
+                    let name = name.trim_end_matches("[0]");
+                    uniforms.extend((0..size).filter_map(|i| {
+                        let name = format!("{}[{}]", name, i);
+                        let location = gl.get_uniform_location(program, name.as_str())?;
+                        let initial_data = get_initial_uniform_data(gl, utype, program, &location)?;
+                        let location = locations.insert(location);
+                        Some(Uniform {
+                            name,
+                            size: 1,
+                            utype,
+                            location,
+                            initial_data,
+                        })
+                    }));
+                } else if let Some(location) = gl.get_uniform_location(program, name.as_str()) {
+                    let initial_data = match get_initial_uniform_data(gl, utype, program, &location)
+                    {
+                        Some(initial_data) => initial_data,
+                        None => {
+                            log::warn!("skipping uniform {} of unknown type {:#x}", name, utype);
+                            continue;
+                        }
+                    };
+                    let location = locations.insert(location);
+                    uniforms.push(Uniform {
+                        name,
+                        size,
+                        utype,
+                        location,
+                        initial_data,
+                    });
+                }
+            }
+        }
+        uniforms
+    }
+}
+
+// Lays out an array of vectors or matrices as the flat list of components GL expects.
+fn flatten<T: Copy, const N: usize>(values: &[impl AsRef<[T; N]>]) -> Vec<T> {
+    values
+        .iter()
+        .flat_map(|value| value.as_ref().iter().copied())
+        .collect()
+}
+
+impl Backend for GlowBackend {
+    fn get_parameter_i32(&self, parameter: u32) -> i32 {
+        unsafe { self.ctx.get_parameter_i32(parameter) }
+    }
+
+    fn get_parameter_string(&self, parameter: u32) -> String {
+        unsafe { self.ctx.get_parameter_string(parameter) }
+    }
+
+    fn get_parameter_indexed_string(&self, parameter: u32, index: u32) -> String {
+        unsafe { self.ctx.get_parameter_indexed_string(parameter, index) }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn supported_extensions(&self) -> Option<Vec<String>> {
+        self.supported_extensions.clone()
+    }
+
+    fn get_error(&self) -> u32 {
+        unsafe { self.ctx.get_error() }
+    }
+
+    fn supports_debug(&self) -> bool {
+        self.ctx.supports_debug()
+    }
+
+    fn push_debug_group(&self, message: &str) {
+        unsafe {
+            self.ctx
+                .push_debug_group(glow::DEBUG_SOURCE_APPLICATION, 0, message)
+        }
+    }
+
+    fn pop_debug_group(&self) {
+        unsafe { self.ctx.pop_debug_group() }
+    }
+
+    fn debug_message_callback(&self, callback: DebugCallback) {
+        unsafe {
+            self.ctx.enable(glow::DEBUG_OUTPUT);
+            self.ctx.debug_message_callback(callback);
+        }
+    }
+
+    fn enable(&self, capability: u32) {
+        unsafe { self.ctx.enable(capability) }
+    }
+
+    fn disable(&self, capability: u32) {
+        unsafe { self.ctx.disable(capability) }
+    }
+
+    fn pixel_store_i32(&self, parameter: u32, value: i32) {
+        unsafe { self.ctx.pixel_store_i32(parameter, value) }
+    }
+
+    fn depth_func(&self, function: u32) {
+        unsafe { self.ctx.depth_func(function) }
+    }
+
+    fn depth_mask(&self, write_mask: bool) {
+        unsafe { self.ctx.depth_mask(write_mask) }
+    }
+
+    fn depth_range_f32(&self, near: f32, far: f32) {
+        unsafe { self.ctx.depth_range_f32(near, far) }
+    }
+
+    fn blend_equation_separate(&self, rgb: u32, alpha: u32) {
+        unsafe { self.ctx.blend_equation_separate(rgb, alpha) }
+    }
+
+    fn blend_func_separate(&self, src_rgb: u32, dst_rgb: u32, src_alpha: u32, dst_alpha: u32) {
+        unsafe {
+            self.ctx
+                .blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha)
+        }
+    }
+
+    fn blend_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        unsafe { self.ctx.blend_color(red, green, blue, alpha) }
+    }
+
+    fn stencil_func(&self, function: u32, reference: i32, mask: u32) {
+        unsafe { self.ctx.stencil_func(function, reference, mask) }
+    }
+
+    fn stencil_op_separate(&self, face: u32, fail: u32, depth_fail: u32, pass: u32) {
+        unsafe { self.ctx.stencil_op_separate(face, fail, depth_fail, pass) }
+    }
+
+    fn stencil_mask(&self, mask: u32) {
+        unsafe { self.ctx.stencil_mask(mask) }
+    }
+
+    fn cull_face(&self, face: u32) {
+        unsafe { self.ctx.cull_face(face) }
+    }
+
+    fn front_face(&self, winding: u32) {
+        unsafe { self.ctx.front_face(winding) }
+    }
+
+    fn polygon_offset(&self, factor: f32, units: f32) {
+        unsafe { self.ctx.polygon_offset(factor, units) }
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        unsafe { self.ctx.viewport(x, y, width, height) }
+    }
+
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+        unsafe { self.ctx.scissor(x, y, width, height) }
+    }
+
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        unsafe { self.ctx.clear_color(red, green, blue, alpha) }
+    }
+
+    fn clear_depth_f32(&self, depth: f32) {
+        unsafe { self.ctx.clear_depth_f32(depth) }
+    }
+
+    fn clear_stencil(&self, stencil: i32) {
+        unsafe { self.ctx.clear_stencil(stencil) }
+    }
+
+    fn clear(&self, mask: u32) {
+        unsafe { self.ctx.clear(mask) }
+    }
+
+    fn clear_buffer_f32_slice(&self, target: u32, draw_buffer: u32, values: &[f32]) {
+        let mut values = values.to_vec();
+        unsafe {
+            self.ctx
+                .clear_buffer_f32_slice(target, draw_buffer, &mut values)
+        }
+    }
+
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        data: &mut [u8],
+    ) {
+        unsafe {
+            self.ctx.read_pixels(
+                x,
+                y,
+                width,
+                height,
+                format,
+                ty,
+                glow::PixelPackData::Slice(data),
+            )
+        }
+    }
+
+    fn create_buffer(&mut self, buffer: BufferKey) -> Result<(), GraphicsError> {
+        let handle = unsafe {
+            self.ctx
+                .create_buffer()
+                .map_err(|_| GraphicsError::BufferError)?
+        };
+        self.buffers.insert(buffer, handle);
+        Ok(())
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferKey) {
+        if let Some(handle) = self.buffers.remove(buffer) {
+            unsafe { self.ctx.delete_buffer(handle) }
+        }
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferKey>) {
+        let handle = buffer.and_then(|buffer| self.buffers.get(buffer).copied());
+        unsafe { self.ctx.bind_buffer(target, handle) }
+    }
+
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32) {
+        unsafe { self.ctx.buffer_data_size(target, size, usage) }
+    }
+
+    fn buffer_data_u8_slice(&self, target: u32, data: &[u8], usage: u32) {
+        unsafe { self.ctx.buffer_data_u8_slice(target, data, usage) }
+    }
+
+    fn buffer_sub_data_u8_slice(&self, target: u32, offset: i32, data: &[u8]) {
+        unsafe { self.ctx.buffer_sub_data_u8_slice(target, offset, data) }
+    }
+
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferKey>) {
+        let handle = buffer.and_then(|buffer| self.buffers.get(buffer).copied());
+        unsafe { self.ctx.bind_buffer_base(target, index, handle) }
+    }
+
+    fn copy_buffer_sub_data(
+        &self,
+        src_target: u32,
+        dst_target: u32,
+        src_offset: i32,
+        dst_offset: i32,
+        size: i32,
+    ) {
+        unsafe {
+            self.ctx
+                .copy_buffer_sub_data(src_target, dst_target, src_offset, dst_offset, size)
+        }
+    }
+
+    fn create_program(
+        &mut self,
+        program: ShaderKey,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<(), ShaderError> {
+        let handle = unsafe {
+            let gl = &self.ctx;
+            let vertex = gl
+                .create_shader(glow::VERTEX_SHADER)
+                .map_err(|_| ShaderError::ResourceCreationError)?;
+            gl.shader_source(vertex, vertex_source);
+            gl.compile_shader(vertex);
+            if !gl.get_shader_compile_status(vertex) {
+                let err = Err(ShaderError::VertexCompileError(
+                    gl.get_shader_info_log(vertex),
+                ));
+                gl.delete_shader(vertex);
+                return err;
+            }
+            let fragment = gl
+                .create_shader(glow::FRAGMENT_SHADER)
+                .expect("Failed to create Fragment shader.");
+            gl.shader_source(fragment, fragment_source);
+            gl.compile_shader(fragment);
+            if !gl.get_shader_compile_status(fragment) {
+                let err = Err(ShaderError::FragmentCompileError(
+                    gl.get_shader_info_log(fragment),
+                ));
+                gl.delete_shader(fragment);
+                return err;
+            }
+            let program = gl.create_program().expect("Failed to create program.");
+            gl.attach_shader(program, vertex);
+            gl.attach_shader(program, fragment);
+            // without the hint some drivers don't keep a binary around to hand out later
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(functions) = &self.program_binary {
+                (functions.program_parameter_i32)(
+                    program,
+                    glow::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                    glow::TRUE as i32,
+                );
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                let err = Err(ShaderError::LinkError(gl.get_program_info_log(program)));
+                gl.delete_program(program);
+                return err;
+            }
+
+            program
+        };
+        self.insert_program(program, handle, vertex_source, fragment_source);
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn supports_program_binaries(&self) -> bool {
+        self.program_binary.is_some()
+            && unsafe { self.ctx.get_parameter_i32(glow::NUM_PROGRAM_BINARY_FORMATS) } > 0
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn get_program_binary(&self, program: ShaderKey) -> Option<shader::ProgramBinary> {
+        let functions = self.program_binary.as_ref()?;
+        let handle = self.programs.get(program)?.handle;
+        unsafe {
+            let mut length = 0;
+            (functions.get_program_iv)(handle, glow::PROGRAM_BINARY_LENGTH, &mut length);
+            if length <= 0 {
+                return None;
+            }
+            let mut data = vec![0u8; length as usize];
+            let mut format = 0;
+            let mut written = 0;
+            (functions.get_program_binary)(
+                handle,
+                length,
+                &mut written,
+                &mut format,
+                data.as_mut_ptr() as *mut _,
+            );
+            data.truncate(written.max(0) as usize);
+            Some(shader::ProgramBinary { format, data })
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn create_program_from_binary(
+        &mut self,
+        program: ShaderKey,
+        binary: &shader::ProgramBinary,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<(), ShaderError> {
+        let functions = self.program_binary.as_ref().ok_or_else(|| {
+            ShaderError::LinkError("program binary functions haven't been loaded".to_owned())
+        })?;
+        let handle = unsafe {
+            let gl = &self.ctx;
+            let handle = gl
+                .create_program()
+                .map_err(|_| ShaderError::ResourceCreationError)?;
+            (functions.program_binary)(
+                handle,
+                binary.format,
+                binary.data.as_ptr() as *const _,
+                binary.data.len() as i32,
+            );
+            if !gl.get_program_link_status(handle) {
+                let err = Err(ShaderError::LinkError(gl.get_program_info_log(handle)));
+                gl.delete_program(handle);
+                return err;
+            }
+            handle
+        };
+        self.insert_program(program, handle, vertex_source, fragment_source);
+        Ok(())
+    }
+
+    fn delete_program(&mut self, program: ShaderKey) {
+        if let Some(program) = self.programs.remove(program) {
+            for uniform in program.uniforms {
+                self.uniform_locations.remove(uniform.location);
+            }
+            unsafe { self.ctx.delete_program(program.handle) }
+        }
+    }
+
+    fn use_program(&self, program: Option<ShaderKey>) {
+        let handle = program.and_then(|program| self.programs.get(program).map(|p| p.handle));
+        unsafe { self.ctx.use_program(handle) }
+    }
+
+    fn get_program_attributes(&self, program: ShaderKey) -> Vec<shader::Attribute> {
+        self.programs
+            .get(program)
+            .map(|program| program.attributes.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_program_uniforms(&self, program: ShaderKey) -> Vec<shader::Uniform> {
+        self.programs
+            .get(program)
+            .map(|program| program.uniforms.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_program_uniform_blocks(&self, program: ShaderKey) -> Vec<shader::UniformBlock> {
+        self.programs
+            .get(program)
+            .map(|program| program.uniform_blocks.clone())
+            .unwrap_or_default()
+    }
+
+    fn uniform_block_binding(&self, program: ShaderKey, index: u32, binding: u32) {
+        if let Some(program) = self.programs.get(program) {
+            unsafe {
+                self.ctx
+                    .uniform_block_binding(program.handle, index, binding)
+            }
+        }
+    }
+
+    fn set_uniform(&self, location: shader::UniformLocation, data: &shader::RawUniformValue) {
+        use shader::RawUniformValue;
+        let location = self.uniform_locations.get(location);
+        unsafe {
+            match data {
+                RawUniformValue::SignedInt(data) => self.ctx.uniform_1_i32(location, *data),
+                RawUniformValue::UnsignedInt(data) => self.ctx.uniform_1_u32(location, *data),
+                RawUniformValue::Bool(data) => self.ctx.uniform_1_i32(location, *data as i32),
+                RawUniformValue::Float(data) => self.ctx.uniform_1_f32(location, *data),
+                RawUniformValue::Mat2(data) => self.ctx.uniform_matrix_2_f32_slice(
+                    location,
+                    false,
+                    &AsRef::<[f32; 4]>::as_ref(data)[..],
+                ),
+                RawUniformValue::Mat3(data) => self.ctx.uniform_matrix_3_f32_slice(
+                    location,
+                    false,
+                    &AsRef::<[f32; 9]>::as_ref(data)[..],
+                ),
+                RawUniformValue::Mat4(data) => self.ctx.uniform_matrix_4_f32_slice(
+                    location,
+                    false,
+                    &AsRef::<[f32; 16]>::as_ref(data)[..],
+                ),
+                RawUniformValue::Vec2(data) => {
+                    self.ctx.uniform_2_f32_slice(location, data.as_ref())
+                }
+                RawUniformValue::Vec3(data) => {
+                    self.ctx.uniform_3_f32_slice(location, data.as_ref())
+                }
+                RawUniformValue::Vec4(data) => {
+                    self.ctx.uniform_4_f32_slice(location, data.as_ref())
+                }
+                RawUniformValue::IntVec2(data) => {
+                    self.ctx.uniform_2_i32_slice(location, data.as_ref())
+                }
+                RawUniformValue::IntVec3(data) => {
+                    self.ctx.uniform_3_i32_slice(location, data.as_ref())
+                }
+                RawUniformValue::IntVec4(data) => {
+                    self.ctx.uniform_4_i32_slice(location, data.as_ref())
+                }
+                RawUniformValue::UnsignedIntVec2(data) => {
+                    self.ctx.uniform_2_u32_slice(location, data.as_ref())
+                }
+                RawUniformValue::UnsignedIntVec3(data) => {
+                    self.ctx.uniform_3_u32_slice(location, data.as_ref())
+                }
+                RawUniformValue::UnsignedIntVec4(data) => {
+                    self.ctx.uniform_4_u32_slice(location, data.as_ref())
+                }
+                RawUniformValue::SignedIntArray(data) => {
+                    self.ctx.uniform_1_i32_slice(location, data)
+                }
+                RawUniformValue::UnsignedIntArray(data) => {
+                    self.ctx.uniform_1_u32_slice(location, data)
+                }
+                RawUniformValue::BoolArray(data) => {
+                    let data = data.iter().map(|v| *v as i32).collect::<Vec<_>>();
+                    self.ctx.uniform_1_i32_slice(location, &data)
+                }
+                RawUniformValue::FloatArray(data) => self.ctx.uniform_1_f32_slice(location, data),
+                RawUniformValue::Mat2Array(data) => {
+                    self.ctx
+                        .uniform_matrix_2_f32_slice(location, false, &flatten::<_, 4>(data))
+                }
+                RawUniformValue::Mat3Array(data) => {
+                    self.ctx
+                        .uniform_matrix_3_f32_slice(location, false, &flatten::<_, 9>(data))
+                }
+                RawUniformValue::Mat4Array(data) => {
+                    self.ctx
+                        .uniform_matrix_4_f32_slice(location, false, &flatten::<_, 16>(data))
+                }
+                RawUniformValue::Vec2Array(data) => {
+                    self.ctx.uniform_2_f32_slice(location, &flatten(data))
+                }
+                RawUniformValue::Vec3Array(data) => {
+                    self.ctx.uniform_3_f32_slice(location, &flatten(data))
+                }
+                RawUniformValue::Vec4Array(data) => {
+                    self.ctx.uniform_4_f32_slice(location, &flatten(data))
+                }
+                RawUniformValue::IntVec2Array(data) => {
+                    self.ctx.uniform_2_i32_slice(location, &flatten(data))
+                }
+                RawUniformValue::IntVec3Array(data) => {
+                    self.ctx.uniform_3_i32_slice(location, &flatten(data))
+                }
+                RawUniformValue::IntVec4Array(data) => {
+                    self.ctx.uniform_4_i32_slice(location, &flatten(data))
+                }
+                RawUniformValue::UnsignedIntVec2Array(data) => {
+                    self.ctx.uniform_2_u32_slice(location, &flatten(data))
+                }
+                RawUniformValue::UnsignedIntVec3Array(data) => {
+                    self.ctx.uniform_3_u32_slice(location, &flatten(data))
+                }
+                RawUniformValue::UnsignedIntVec4Array(data) => {
+                    self.ctx.uniform_4_u32_slice(location, &flatten(data))
+                }
+            }
+        }
+    }
+
+    fn create_texture(&mut self, texture: TextureKey) -> Result<(), GraphicsError> {
+        let handle = unsafe {
+            self.ctx
+                .create_texture()
+                .map_err(|_| GraphicsError::TextureError)?
+        };
+        self.textures.insert(texture, handle);
+        Ok(())
+    }
+
+    fn delete_texture(&mut self, texture: TextureKey) {
+        if let Some(handle) = self.textures.remove(texture) {
+            unsafe { self.ctx.delete_texture(handle) }
+        }
+    }
+
+    fn active_texture(&self, unit: u32) {
+        unsafe { self.ctx.active_texture(unit) }
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<TextureKey>) {
+        let handle = texture.and_then(|texture| self.textures.get(texture).copied());
+        unsafe { self.ctx.bind_texture(target, handle) }
+    }
+
+    fn tex_parameter_i32(&self, target: u32, parameter: u32, value: i32) {
+        unsafe { self.ctx.tex_parameter_i32(target, parameter, value) }
+    }
+
+    fn tex_parameter_f32(&self, target: u32, parameter: u32, value: f32) {
+        unsafe { self.ctx.tex_parameter_f32(target, parameter, value) }
+    }
+
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    ) {
+        unsafe {
+            self.ctx.tex_image_2d(
+                target,
+                level,
+                internal_format,
+                width,
+                height,
+                border,
+                format,
+                ty,
+                pixels,
+            )
+        }
+    }
+
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    ) {
+        unsafe {
+            self.ctx.tex_sub_image_2d(
+                target,
+                level,
+                x_offset,
+                y_offset,
+                width,
+                height,
+                format,
+                ty,
+                glow::PixelUnpackData::Slice(pixels),
+            )
+        }
+    }
+
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    ) {
+        unsafe {
+            self.ctx.tex_image_3d(
+                target,
+                level,
+                internal_format,
+                width,
+                height,
+                depth,
+                border,
+                format,
+                ty,
+                pixels,
+            )
+        }
+    }
+
+    fn tex_sub_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        z_offset: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    ) {
+        unsafe {
+            self.ctx.tex_sub_image_3d(
+                target,
+                level,
+                x_offset,
+                y_offset,
+                z_offset,
+                width,
+                height,
+                depth,
+                format,
+                ty,
+                glow::PixelUnpackData::Slice(pixels),
+            )
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn tex_image_2d_with_html_image(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        format: u32,
+        ty: u32,
+        image: &web_sys::HtmlImageElement,
+    ) {
+        unsafe {
+            self.ctx
+                .tex_image_2d_with_html_image(target, level, internal_format, format, ty, image)
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn supports_compressed_textures(&self) -> bool {
+        self.compressed_textures.is_some()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn compressed_tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        border: i32,
+        data: &[u8],
+    ) {
+        match self.compressed_textures.as_ref() {
+            Some(functions) => unsafe {
+                (functions.tex_image_2d)(
+                    target,
+                    level,
+                    internal_format,
+                    width,
+                    height,
+                    border,
+                    data.len() as i32,
+                    data.as_ptr() as *const _,
+                )
+            },
+            None => log::warn!("compressed texture functions haven't been loaded"),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn compressed_tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data: &[u8],
+    ) {
+        match self.compressed_textures.as_ref() {
+            Some(functions) => unsafe {
+                (functions.tex_sub_image_2d)(
+                    target,
+                    level,
+                    x_offset,
+                    y_offset,
+                    width,
+                    height,
+                    format,
+                    data.len() as i32,
+                    data.as_ptr() as *const _,
+                )
+            },
+            None => log::warn!("compressed texture functions haven't been loaded"),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn compressed_tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        border: i32,
+        data: &[u8],
+    ) {
+        match self
+            .compressed_textures
+            .as_ref()
+            .and_then(|functions| functions.tex_image_3d)
+        {
+            Some(tex_image_3d) => unsafe {
+                tex_image_3d(
+                    target,
+                    level,
+                    internal_format,
+                    width,
+                    height,
+                    depth,
+                    border,
+                    data.len() as i32,
+                    data.as_ptr() as *const _,
+                )
+            },
+            None => log::warn!("glCompressedTexImage3D hasn't been loaded"),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn compressed_tex_sub_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        z_offset: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        format: u32,
+        data: &[u8],
+    ) {
+        match self
+            .compressed_textures
+            .as_ref()
+            .and_then(|functions| functions.tex_sub_image_3d)
+        {
+            Some(tex_sub_image_3d) => unsafe {
+                tex_sub_image_3d(
+                    target,
+                    level,
+                    x_offset,
+                    y_offset,
+                    z_offset,
+                    width,
+                    height,
+                    depth,
+                    format,
+                    data.len() as i32,
+                    data.as_ptr() as *const _,
+                )
+            },
+            None => log::warn!("glCompressedTexSubImage3D hasn't been loaded"),
+        }
+    }
+
+    fn generate_mipmap(&self, target: u32) {
+        unsafe { self.ctx.generate_mipmap(target) }
+    }
+
+    fn create_framebuffer(&mut self, framebuffer: FramebufferKey) -> Result<(), GraphicsError> {
+        let handle = unsafe {
+            self.ctx
+                .create_framebuffer()
+                .map_err(|_| GraphicsError::FramebufferError)?
+        };
+        self.framebuffers.insert(framebuffer, handle);
+        Ok(())
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferKey) {
+        if let Some(handle) = self.framebuffers.remove(framebuffer) {
+            unsafe { self.ctx.delete_framebuffer(handle) }
+        }
+    }
+
+    fn bind_framebuffer(&self, target: u32, framebuffer: Option<FramebufferKey>) {
+        let handle =
+            framebuffer.and_then(|framebuffer| self.framebuffers.get(framebuffer).copied());
+        unsafe { self.ctx.bind_framebuffer(target, handle) }
+    }
+
+    fn check_framebuffer_status(&self, target: u32) -> u32 {
+        unsafe { self.ctx.check_framebuffer_status(target) }
+    }
+
+    fn draw_buffers(&self, buffers: &[u32]) {
+        unsafe { self.ctx.draw_buffers(buffers) }
+    }
+
+    fn read_buffer(&self, source: u32) {
+        unsafe { self.ctx.read_buffer(source) }
+    }
+
+    fn framebuffer_texture_2d(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture_target: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+    ) {
+        let handle = texture.and_then(|texture| self.textures.get(texture).copied());
+        unsafe {
+            self.ctx
+                .framebuffer_texture_2d(target, attachment, texture_target, handle, level)
+        }
+    }
+
+    fn framebuffer_texture_layer(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+        layer: i32,
+    ) {
+        let handle = texture.and_then(|texture| self.textures.get(texture).copied());
+        unsafe {
+            self.ctx
+                .framebuffer_texture_layer(target, attachment, handle, level, layer)
+        }
+    }
+
+    fn blit_framebuffer(
+        &self,
+        src_x0: i32,
+        src_y0: i32,
+        src_x1: i32,
+        src_y1: i32,
+        dst_x0: i32,
+        dst_y0: i32,
+        dst_x1: i32,
+        dst_y1: i32,
+        mask: u32,
+        filter: u32,
+    ) {
+        unsafe {
+            self.ctx.blit_framebuffer(
+                src_x0, src_y0, src_x1, src_y1, dst_x0, dst_y0, dst_x1, dst_y1, mask, filter,
+            )
+        }
+    }
+
+    fn create_renderbuffer(&mut self, renderbuffer: RenderbufferKey) -> Result<(), GraphicsError> {
+        let handle = unsafe {
+            self.ctx
+                .create_renderbuffer()
+                .map_err(|_| GraphicsError::RenderbufferError)?
+        };
+        self.renderbuffers.insert(renderbuffer, handle);
+        Ok(())
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: RenderbufferKey) {
+        if let Some(handle) = self.renderbuffers.remove(renderbuffer) {
+            unsafe { self.ctx.delete_renderbuffer(handle) }
+        }
+    }
+
+    fn bind_renderbuffer(&self, target: u32, renderbuffer: Option<RenderbufferKey>) {
+        let handle =
+            renderbuffer.and_then(|renderbuffer| self.renderbuffers.get(renderbuffer).copied());
+        unsafe { self.ctx.bind_renderbuffer(target, handle) }
+    }
+
+    fn renderbuffer_storage(&self, target: u32, internal_format: u32, width: i32, height: i32) {
+        unsafe {
+            self.ctx
+                .renderbuffer_storage(target, internal_format, width, height)
+        }
+    }
+
+    fn renderbuffer_storage_multisample(
+        &self,
+        target: u32,
+        samples: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    ) {
+        unsafe {
+            self.ctx.renderbuffer_storage_multisample(
+                target,
+                samples,
+                internal_format,
+                width,
+                height,
+            )
+        }
+    }
+
+    fn framebuffer_renderbuffer(
+        &self,
+        target: u32,
+        attachment: u32,
+        renderbuffer_target: u32,
+        renderbuffer: Option<RenderbufferKey>,
+    ) {
+        let handle =
+            renderbuffer.and_then(|renderbuffer| self.renderbuffers.get(renderbuffer).copied());
+        unsafe {
+            self.ctx
+                .framebuffer_renderbuffer(target, attachment, renderbuffer_target, handle)
+        }
+    }
+
+    fn create_query(&mut self, query: QueryKey) -> Result<(), GraphicsError> {
+        let handle = unsafe {
+            self.ctx
+                .create_query()
+                .map_err(|_| GraphicsError::QueryError)?
+        };
+        self.queries.insert(query, handle);
+        Ok(())
+    }
+
+    fn delete_query(&mut self, query: QueryKey) {
+        if let Some(handle) = self.queries.remove(query) {
+            unsafe { self.ctx.delete_query(handle) }
+        }
+    }
+
+    fn begin_query(&self, target: u32, query: QueryKey) {
+        if let Some(handle) = self.queries.get(query) {
+            unsafe { self.ctx.begin_query(target, *handle) }
+        }
+    }
+
+    fn end_query(&self, target: u32) {
+        unsafe { self.ctx.end_query(target) }
+    }
+
+    fn get_query_parameter_u32(&self, query: QueryKey, parameter: u32) -> u32 {
+        match self.queries.get(query) {
+            Some(handle) => unsafe { self.ctx.get_query_parameter_u32(*handle, parameter) },
+            None => 0,
+        }
+    }
+
+    // WebGL reads results as doubles, which glow truncates to 32 bits
+    #[cfg(not(target_arch = "wasm32"))]
+    fn get_query_parameter_u64(&self, query: QueryKey, parameter: u32) -> u64 {
+        match (self.queries.get(query), self.get_query_object_u64) {
+            (Some(handle), Some(get_query_object_u64)) => unsafe {
+                let mut result = 0;
+                get_query_object_u64(*handle, parameter, &mut result);
+                result
+            },
+            _ => self.get_query_parameter_u32(query, parameter) as u64,
+        }
+    }
+
+    fn restore_vertex_array(&self) {
+        unsafe { self.ctx.bind_vertex_array(self.vertex_array) }
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        unsafe { self.ctx.enable_vertex_attrib_array(index) }
+    }
+
+    fn disable_vertex_attrib_array(&self, index: u32) {
+        unsafe { self.ctx.disable_vertex_attrib_array(index) }
+    }
+
+    fn vertex_attrib_divisor(&self, index: u32, divisor: u32) {
+        unsafe { self.ctx.vertex_attrib_divisor(index, divisor) }
+    }
+
+    fn vertex_attrib_pointer_f32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: i32,
+    ) {
+        unsafe {
+            self.ctx
+                .vertex_attrib_pointer_f32(index, size, data_type, normalized, stride, offset)
+        }
+    }
+
+    fn vertex_attrib_pointer_i32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        stride: i32,
+        offset: i32,
+    ) {
+        unsafe {
+            self.ctx
+                .vertex_attrib_pointer_i32(index, size, data_type, stride, offset)
+        }
+    }
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        unsafe { self.ctx.draw_arrays(mode, first, count) }
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, element_type: u32, offset: i32) {
+        unsafe { self.ctx.draw_elements(mode, count, element_type, offset) }
+    }
+
+    fn draw_arrays_instanced(&self, mode: u32, first: i32, count: i32, instance_count: i32) {
+        unsafe {
+            self.ctx
+                .draw_arrays_instanced(mode, first, count, instance_count)
+        }
+    }
+
+    fn draw_elements_instanced(
+        &self,
+        mode: u32,
+        count: i32,
+        element_type: u32,
+        offset: i32,
+        instance_count: i32,
+    ) {
+        unsafe {
+            self.ctx
+                .draw_elements_instanced(mode, count, element_type, offset, instance_count)
+        }
+    }
+}
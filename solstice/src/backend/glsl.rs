@@ -0,0 +1,560 @@
+// Just enough of a GLSL front end to find the top level `in`, `attribute` and `uniform`
+// declarations of a shader, along with the members of `uniform` blocks. It runs the
+// conditional parts of the preprocessor and expands object-like macros but otherwise trusts
+// that the source is valid.
+
+use crate::shader;
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Storage {
+    Input,
+    Uniform,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Declaration {
+    pub storage: Storage,
+    pub ty: String,
+    pub name: String,
+    pub array_length: Option<usize>,
+    pub location: Option<u32>,
+    /// The uniform block this is a member of.
+    pub block: Option<String>,
+}
+
+/// The messages of the `#error` directives that aren't excluded by the preprocessor. Drivers
+/// fail compilation when they come across one.
+pub fn errors(source: &str) -> Vec<String> {
+    let mut preprocessor = Preprocessor::default();
+    preprocessor.run(&strip_comments(source));
+    preprocessor.errors
+}
+
+pub fn declarations(source: &str) -> Vec<Declaration> {
+    let mut preprocessor = Preprocessor::default();
+    let source = preprocessor.run(&strip_comments(source));
+    let tokens = tokenize(&source)
+        .into_iter()
+        .map(|token| preprocessor.expand(token))
+        .collect::<Vec<_>>();
+
+    let mut declarations = vec![];
+    let mut statement = vec![];
+    let mut block = None;
+    let mut member = vec![];
+    let mut depth = 0usize;
+    for token in tokens {
+        match token.as_str() {
+            "{" => {
+                if depth == 0 && statement.iter().any(|token| token == "uniform") {
+                    block = statement.last().cloned();
+                }
+                depth += 1;
+            }
+            "}" => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    // either a function body or an interface block, whose instance name we skip
+                    statement.clear();
+                    block = None;
+                }
+            }
+            ";" if depth == 0 => {
+                parse_declaration(&statement, &mut declarations);
+                statement.clear();
+            }
+            ";" if depth == 1 && block.is_some() => {
+                let tokens = member.iter().map(String::as_str).collect::<Vec<_>>();
+                for (ty, name, array_length) in declarators(&tokens) {
+                    declarations.push(Declaration {
+                        storage: Storage::Uniform,
+                        ty,
+                        name,
+                        array_length,
+                        location: None,
+                        block: block.clone(),
+                    });
+                }
+                member.clear();
+            }
+            _ if depth == 0 => statement.push(token),
+            _ if depth == 1 && block.is_some() => member.push(token),
+            _ => (),
+        }
+    }
+    declarations
+}
+
+const QUALIFIERS: &[&str] = &[
+    "highp",
+    "mediump",
+    "lowp",
+    "flat",
+    "smooth",
+    "noperspective",
+    "centroid",
+    "invariant",
+    "const",
+];
+
+// Splits `type name[length], other, ...` into its declarators.
+fn declarators(tokens: &[&str]) -> Vec<(String, String, Option<usize>)> {
+    let mut tokens = tokens
+        .iter()
+        .filter(|token| !QUALIFIERS.contains(token))
+        .copied();
+    let ty = match tokens.next() {
+        Some(ty) => ty.to_owned(),
+        None => return vec![],
+    };
+    let rest = tokens.collect::<Vec<_>>();
+    rest.split(|token| *token == ",")
+        .filter_map(|declarator| {
+            let (name, array) = declarator.split_first()?;
+            let array_length = match array {
+                ["[", length, "]"] => length.parse().ok(),
+                _ => None,
+            };
+            Some((ty.clone(), (*name).to_owned(), array_length))
+        })
+        .collect()
+}
+
+fn parse_declaration(tokens: &[String], declarations: &mut Vec<Declaration>) {
+    let mut tokens = tokens.iter().map(String::as_str).peekable();
+    let mut location = None;
+    if tokens.peek() == Some(&"layout") {
+        tokens.next();
+        let mut layout = vec![];
+        for token in tokens.by_ref() {
+            if token == ")" {
+                break;
+            }
+            layout.push(token);
+        }
+        location = layout
+            .windows(3)
+            .find(|window| window[0] == "location" && window[1] == "=")
+            .and_then(|window| window[2].parse().ok());
+    }
+
+    let mut storage = None;
+    let mut rest = vec![];
+    for token in tokens {
+        match token {
+            "in" | "attribute" => storage = Some(Storage::Input),
+            "uniform" => storage = Some(Storage::Uniform),
+            "out" | "varying" | "precision" => return,
+            _ => rest.push(token),
+        }
+    }
+
+    let storage = match storage {
+        Some(storage) => storage,
+        None => return,
+    };
+    for (ty, name, array_length) in declarators(&rest) {
+        declarations.push(Declaration {
+            storage,
+            ty,
+            name,
+            array_length,
+            location,
+            block: None,
+        });
+    }
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push('\n');
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                output.push(' ');
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_alphanumeric() || c == '_' || c == '.' {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' || c == '.' {
+                    token.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(token);
+        } else {
+            chars.next();
+            let mut token = c.to_string();
+            if let Some(&next) = chars.peek() {
+                let pair = [c, next].iter().collect::<String>();
+                if ["&&", "||", "==", "!=", "<=", ">="].contains(&pair.as_str()) {
+                    chars.next();
+                    token = pair;
+                }
+            }
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    defines: HashMap<String, String>,
+    errors: Vec<String>,
+}
+
+impl Preprocessor {
+    fn run(&mut self, source: &str) -> String {
+        // each entry is (currently emitting, some branch has been taken, parent is emitting)
+        let mut conditions: Vec<(bool, bool, bool)> = vec![];
+        let mut output = String::with_capacity(source.len());
+        for line in source.lines() {
+            let active = !matches!(conditions.last(), Some((false, _, _)));
+            let trimmed = line.trim_start();
+            if !trimmed.starts_with('#') {
+                if active {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+                continue;
+            }
+
+            let directive = trimmed[1..].trim_start();
+            let (name, rest) = match directive.find(char::is_whitespace) {
+                Some(index) => (&directive[..index], directive[index..].trim()),
+                None => (directive, ""),
+            };
+            match name {
+                "ifdef" | "ifndef" | "if" => {
+                    let value = active
+                        && match name {
+                            "ifdef" => self.defines.contains_key(rest),
+                            "ifndef" => !self.defines.contains_key(rest),
+                            _ => self.evaluate(rest) != 0,
+                        };
+                    conditions.push((value, value, active));
+                }
+                "elif" => {
+                    if let Some((emitting, taken, parent)) = conditions.last_mut() {
+                        *emitting = *parent && !*taken && self.evaluate(rest) != 0;
+                        *taken |= *emitting;
+                    }
+                }
+                "else" => {
+                    if let Some((emitting, taken, parent)) = conditions.last_mut() {
+                        *emitting = *parent && !*taken;
+                        *taken = true;
+                    }
+                }
+                "endif" => {
+                    conditions.pop();
+                }
+                _ if !active => (),
+                "version" => {
+                    let mut parts = rest.split_whitespace();
+                    let version = parts.next().unwrap_or("100");
+                    let es = version == "100" || parts.next() == Some("es");
+                    self.defines.insert("__VERSION__".into(), version.into());
+                    if es {
+                        self.defines.insert("GL_ES".into(), "1".into());
+                    }
+                }
+                "define" => {
+                    let name_end = rest
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len());
+                    let (name, value) = rest.split_at(name_end);
+                    // function-like macros are only tracked as being defined
+                    let value = if value.starts_with('(') { "" } else { value };
+                    self.defines.insert(name.into(), value.trim().into());
+                }
+                "undef" => {
+                    self.defines.remove(rest);
+                }
+                "error" => self.errors.push(rest.to_owned()),
+                _ => (),
+            }
+        }
+        output
+    }
+
+    fn expand(&self, mut token: String) -> String {
+        // the depth limit guards against self-referential macros
+        for _ in 0..8 {
+            match self.defines.get(&token) {
+                Some(value) if !value.is_empty() && !value.contains(char::is_whitespace) => {
+                    token = value.clone();
+                }
+                _ => break,
+            }
+        }
+        token
+    }
+
+    fn evaluate(&self, expression: &str) -> i64 {
+        let tokens = tokenize(expression);
+        let mut parser = Expression {
+            tokens: &tokens,
+            position: 0,
+            preprocessor: self,
+        };
+        parser.or()
+    }
+
+    fn value_of(&self, identifier: &str) -> i64 {
+        let value = self.expand(identifier.to_owned());
+        value.parse().unwrap_or(0)
+    }
+}
+
+struct Expression<'a> {
+    tokens: &'a [String],
+    position: usize,
+    preprocessor: &'a Preprocessor,
+}
+
+impl Expression<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.position).map(String::as_str);
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> i64 {
+        let mut lhs = self.and();
+        while self.peek() == Some("||") {
+            self.next();
+            let rhs = self.and();
+            lhs = (lhs != 0 || rhs != 0) as i64;
+        }
+        lhs
+    }
+
+    fn and(&mut self) -> i64 {
+        let mut lhs = self.comparison();
+        while self.peek() == Some("&&") {
+            self.next();
+            let rhs = self.comparison();
+            lhs = (lhs != 0 && rhs != 0) as i64;
+        }
+        lhs
+    }
+
+    fn comparison(&mut self) -> i64 {
+        let lhs = self.unary();
+        let op = match self.peek() {
+            Some(op) if ["==", "!=", "<", "<=", ">", ">="].contains(&op) => op.to_owned(),
+            _ => return lhs,
+        };
+        self.next();
+        let rhs = self.unary();
+        let result = match op.as_str() {
+            "==" => lhs == rhs,
+            "!=" => lhs != rhs,
+            "<" => lhs < rhs,
+            "<=" => lhs <= rhs,
+            ">" => lhs > rhs,
+            _ => lhs >= rhs,
+        };
+        result as i64
+    }
+
+    fn unary(&mut self) -> i64 {
+        match self.next() {
+            Some("!") => (self.unary() == 0) as i64,
+            Some("(") => {
+                let value = self.or();
+                self.next();
+                value
+            }
+            Some("defined") => {
+                let parenthesized = self.peek() == Some("(");
+                if parenthesized {
+                    self.next();
+                }
+                let name = self.next().unwrap_or_default().to_owned();
+                if parenthesized {
+                    self.next();
+                }
+                self.preprocessor.defines.contains_key(&name) as i64
+            }
+            Some(token) => {
+                let token = token.to_owned();
+                token
+                    .parse()
+                    .unwrap_or_else(|_| self.preprocessor.value_of(&token))
+            }
+            None => 0,
+        }
+    }
+}
+
+/// The GL enum that reflection reports for a GLSL type.
+pub fn type_to_gl(ty: &str) -> Option<u32> {
+    let gl = match ty {
+        "float" => glow::FLOAT,
+        "vec2" => glow::FLOAT_VEC2,
+        "vec3" => glow::FLOAT_VEC3,
+        "vec4" => glow::FLOAT_VEC4,
+        "mat2" => glow::FLOAT_MAT2,
+        "mat3" => glow::FLOAT_MAT3,
+        "mat4" => glow::FLOAT_MAT4,
+        "int" => glow::INT,
+        "ivec2" => glow::INT_VEC2,
+        "ivec3" => glow::INT_VEC3,
+        "ivec4" => glow::INT_VEC4,
+        "uint" => glow::UNSIGNED_INT,
+        "uvec2" => glow::UNSIGNED_INT_VEC2,
+        "uvec3" => glow::UNSIGNED_INT_VEC3,
+        "uvec4" => glow::UNSIGNED_INT_VEC4,
+        "bool" => glow::BOOL,
+        "bvec2" => glow::BOOL_VEC2,
+        "bvec3" => glow::BOOL_VEC3,
+        "bvec4" => glow::BOOL_VEC4,
+        "sampler2D" => glow::SAMPLER_2D,
+        "sampler3D" => glow::SAMPLER_3D,
+        "sampler2DArray" => glow::SAMPLER_2D_ARRAY,
+        "samplerCube" => glow::SAMPLER_CUBE,
+        "sampler2DShadow" => glow::SAMPLER_2D_SHADOW,
+        "sampler2DArrayShadow" => glow::SAMPLER_2D_ARRAY_SHADOW,
+        "samplerCubeShadow" => glow::SAMPLER_CUBE_SHADOW,
+        "isampler2D" => glow::INT_SAMPLER_2D,
+        "isampler3D" => glow::INT_SAMPLER_3D,
+        "isampler2DArray" => glow::INT_SAMPLER_2D_ARRAY,
+        "isamplerCube" => glow::INT_SAMPLER_CUBE,
+        "usampler2D" => glow::UNSIGNED_INT_SAMPLER_2D,
+        "usampler3D" => glow::UNSIGNED_INT_SAMPLER_3D,
+        "usampler2DArray" => glow::UNSIGNED_INT_SAMPLER_2D_ARRAY,
+        "usamplerCube" => glow::UNSIGNED_INT_SAMPLER_CUBE,
+        _ => return None,
+    };
+    Some(gl)
+}
+
+// The base alignment and size in bytes of a type under the std140 rules.
+fn std140_layout(utype: u32) -> Option<(usize, usize)> {
+    let layout = match utype {
+        glow::FLOAT | glow::INT | glow::UNSIGNED_INT | glow::BOOL => (4, 4),
+        glow::FLOAT_VEC2 | glow::INT_VEC2 | glow::UNSIGNED_INT_VEC2 | glow::BOOL_VEC2 => (8, 8),
+        glow::FLOAT_VEC3 | glow::INT_VEC3 | glow::UNSIGNED_INT_VEC3 | glow::BOOL_VEC3 => (16, 12),
+        glow::FLOAT_VEC4 | glow::INT_VEC4 | glow::UNSIGNED_INT_VEC4 | glow::BOOL_VEC4 => (16, 16),
+        glow::FLOAT_MAT2 => (16, 32),
+        glow::FLOAT_MAT3 => (16, 48),
+        glow::FLOAT_MAT4 => (16, 64),
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// Lays out the uniform blocks in `declarations` with the std140 rules. Blocks are indexed in
+/// the order they're first declared and blocks with members we can't lay out are skipped.
+pub fn uniform_blocks<'a, I>(declarations: I) -> Vec<shader::UniformBlock>
+where
+    I: IntoIterator<Item = &'a Declaration>,
+{
+    use shader::{std140_align, UniformBlock, UniformBlockMember};
+
+    let mut blocks: Vec<(UniformBlock, bool)> = vec![];
+    for declaration in declarations {
+        let name = match &declaration.block {
+            Some(name) => name,
+            None => continue,
+        };
+        let index = match blocks.iter().position(|(block, _)| &block.name == name) {
+            Some(index) => index,
+            None => {
+                let block = UniformBlock {
+                    name: name.clone(),
+                    index: blocks.len() as u32,
+                    size: 0,
+                    members: vec![],
+                };
+                blocks.push((block, true));
+                blocks.len() - 1
+            }
+        };
+        let (block, valid) = &mut blocks[index];
+        // the same block declared in another stage
+        if block
+            .members
+            .iter()
+            .any(|member| member.name == declaration.name)
+        {
+            continue;
+        }
+        let (utype, (alignment, size)) = match type_to_gl(&declaration.ty)
+            .and_then(|utype| std140_layout(utype).map(|layout| (utype, layout)))
+        {
+            Some(layout) => layout,
+            None => {
+                *valid = false;
+                continue;
+            }
+        };
+        let (alignment, size) = match declaration.array_length {
+            Some(length) => {
+                let alignment = std140_align(alignment, 16);
+                (alignment, std140_align(size, alignment) * length)
+            }
+            None => (alignment, size),
+        };
+        let offset = std140_align(block.size, alignment);
+        block.members.push(UniformBlockMember {
+            name: declaration.name.clone(),
+            size: declaration.array_length.unwrap_or(1) as i32,
+            utype,
+            offset,
+        });
+        block.size = offset + size;
+    }
+
+    blocks
+        .into_iter()
+        .filter(|(_, valid)| *valid)
+        .enumerate()
+        .map(|(index, (mut block, _))| {
+            block.index = index as u32;
+            block.size = std140_align(block.size, 16);
+            block
+        })
+        .collect()
+}
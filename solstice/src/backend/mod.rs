@@ -0,0 +1,372 @@
+//! The layer between `Context` and the graphics API.
+//!
+//! `Context` does all of the bookkeeping and state caching and only calls into a backend to do
+//! the actual work. The interface deliberately mirrors OpenGL: enums are passed as their GL
+//! values and resources are referred to by the keys `Context` hands out. Backends map those keys
+//! to whatever handles they use internally.
+
+mod glow;
+mod glsl;
+pub mod recording;
+pub mod software;
+
+pub use self::glow::GlowBackend;
+pub use recording::RecordingBackend;
+pub use software::SoftwareBackend;
+
+use crate::{
+    shader::{self, ShaderError},
+    BufferKey, FramebufferKey, GraphicsError, QueryKey, RenderbufferKey, ShaderKey, TextureKey,
+};
+
+pub type DebugCallback<'a> = Box<dyn FnMut(u32, u32, u32, u32, &str) + 'a>;
+
+#[allow(clippy::too_many_arguments)]
+pub trait Backend {
+    fn get_parameter_i32(&self, parameter: u32) -> i32;
+    fn get_parameter_string(&self, parameter: u32) -> String;
+    /// Only available from GL 3 and GLES 3. Used to list extensions with `NUM_EXTENSIONS`.
+    fn get_parameter_indexed_string(&self, parameter: u32, index: u32) -> String;
+    /// The extensions the driver supports where they can't be listed with the parameters above,
+    /// like WebGL's `getSupportedExtensions`.
+    fn supported_extensions(&self) -> Option<Vec<String>> {
+        None
+    }
+    /// Returns and clears the oldest error flag, `NO_ERROR` if there isn't one.
+    fn get_error(&self) -> u32;
+
+    fn supports_debug(&self) -> bool {
+        false
+    }
+    fn push_debug_group(&self, _message: &str) {}
+    fn pop_debug_group(&self) {}
+    fn debug_message_callback(&self, _callback: DebugCallback) {}
+
+    fn enable(&self, capability: u32);
+    fn disable(&self, capability: u32);
+    fn pixel_store_i32(&self, parameter: u32, value: i32);
+    fn depth_func(&self, function: u32);
+    fn depth_mask(&self, write_mask: bool);
+    fn depth_range_f32(&self, near: f32, far: f32);
+    fn blend_equation_separate(&self, rgb: u32, alpha: u32);
+    fn blend_func_separate(&self, src_rgb: u32, dst_rgb: u32, src_alpha: u32, dst_alpha: u32);
+    fn blend_color(&self, red: f32, green: f32, blue: f32, alpha: f32);
+    fn stencil_func(&self, function: u32, reference: i32, mask: u32);
+    fn stencil_op_separate(&self, face: u32, fail: u32, depth_fail: u32, pass: u32);
+    fn stencil_mask(&self, mask: u32);
+    fn cull_face(&self, face: u32);
+    fn front_face(&self, winding: u32);
+    fn polygon_offset(&self, factor: f32, units: f32);
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32);
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32);
+    fn clear_depth_f32(&self, depth: f32);
+    fn clear_stencil(&self, stencil: i32);
+    fn clear(&self, mask: u32);
+    /// Clears a single draw buffer of the bound draw framebuffer.
+    fn clear_buffer_f32_slice(&self, target: u32, draw_buffer: u32, values: &[f32]);
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        data: &mut [u8],
+    );
+
+    fn create_buffer(&mut self, buffer: BufferKey) -> Result<(), GraphicsError>;
+    fn delete_buffer(&mut self, buffer: BufferKey);
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferKey>);
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32);
+    fn buffer_data_u8_slice(&self, target: u32, data: &[u8], usage: u32);
+    fn buffer_sub_data_u8_slice(&self, target: u32, offset: i32, data: &[u8]);
+    /// Binds `buffer` to the indexed binding point `index` of `target` as well as to `target`
+    /// itself.
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferKey>);
+    fn copy_buffer_sub_data(
+        &self,
+        src_target: u32,
+        dst_target: u32,
+        src_offset: i32,
+        dst_offset: i32,
+        size: i32,
+    );
+
+    /// Compiles and links a program. Reflection happens here too so that the results of
+    /// `get_program_attributes` and `get_program_uniforms` can be cached.
+    fn create_program(
+        &mut self,
+        program: ShaderKey,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<(), ShaderError>;
+    fn delete_program(&mut self, program: ShaderKey);
+    fn use_program(&self, program: Option<ShaderKey>);
+    fn get_program_attributes(&self, program: ShaderKey) -> Vec<shader::Attribute>;
+    /// Arrays are expected to be split into one `Uniform` per element.
+    fn get_program_uniforms(&self, program: ShaderKey) -> Vec<shader::Uniform>;
+    fn set_uniform(&self, location: shader::UniformLocation, data: &shader::RawUniformValue);
+    fn get_program_uniform_blocks(&self, program: ShaderKey) -> Vec<shader::UniformBlock>;
+    fn uniform_block_binding(&self, program: ShaderKey, index: u32, binding: u32);
+    /// Whether linked programs can be saved with `get_program_binary` and loaded again with
+    /// `create_program_from_binary`.
+    fn supports_program_binaries(&self) -> bool {
+        false
+    }
+    fn get_program_binary(&self, _program: ShaderKey) -> Option<shader::ProgramBinary> {
+        None
+    }
+    /// Like `create_program` but loads a binary from `get_program_binary` instead of compiling.
+    /// The sources it was built from are passed along for reflection. Drivers reject binaries
+    /// after updates so this is expected to fail now and then.
+    fn create_program_from_binary(
+        &mut self,
+        _program: ShaderKey,
+        _binary: &shader::ProgramBinary,
+        _vertex_source: &str,
+        _fragment_source: &str,
+    ) -> Result<(), ShaderError> {
+        Err(ShaderError::LinkError(
+            "program binaries aren't supported".to_owned(),
+        ))
+    }
+
+    fn create_texture(&mut self, texture: TextureKey) -> Result<(), GraphicsError>;
+    fn delete_texture(&mut self, texture: TextureKey);
+    fn active_texture(&self, unit: u32);
+    fn bind_texture(&self, target: u32, texture: Option<TextureKey>);
+    fn tex_parameter_i32(&self, target: u32, parameter: u32, value: i32);
+    fn tex_parameter_f32(&self, target: u32, parameter: u32, value: f32);
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    );
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    );
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    );
+    fn tex_sub_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        z_offset: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    );
+    #[cfg(target_arch = "wasm32")]
+    fn tex_image_2d_with_html_image(
+        &self,
+        _target: u32,
+        _level: i32,
+        _internal_format: i32,
+        _format: u32,
+        _ty: u32,
+        _image: &web_sys::HtmlImageElement,
+    ) {
+        log::warn!("this backend can't upload HTML images");
+    }
+    /// Whether block-compressed data can be uploaded with the `compressed_tex_*` functions. Which
+    /// formats are accepted still depends on the version and extensions.
+    fn supports_compressed_textures(&self) -> bool {
+        false
+    }
+    /// `data` holds whole blocks in the layout of `internal_format`.
+    fn compressed_tex_image_2d(
+        &self,
+        _target: u32,
+        _level: i32,
+        _internal_format: u32,
+        _width: i32,
+        _height: i32,
+        _border: i32,
+        _data: &[u8],
+    ) {
+        log::warn!("this backend can't upload compressed textures");
+    }
+    fn compressed_tex_sub_image_2d(
+        &self,
+        _target: u32,
+        _level: i32,
+        _x_offset: i32,
+        _y_offset: i32,
+        _width: i32,
+        _height: i32,
+        _format: u32,
+        _data: &[u8],
+    ) {
+        log::warn!("this backend can't upload compressed textures");
+    }
+    fn compressed_tex_image_3d(
+        &self,
+        _target: u32,
+        _level: i32,
+        _internal_format: u32,
+        _width: i32,
+        _height: i32,
+        _depth: i32,
+        _border: i32,
+        _data: &[u8],
+    ) {
+        log::warn!("this backend can't upload compressed textures");
+    }
+    fn compressed_tex_sub_image_3d(
+        &self,
+        _target: u32,
+        _level: i32,
+        _x_offset: i32,
+        _y_offset: i32,
+        _z_offset: i32,
+        _width: i32,
+        _height: i32,
+        _depth: i32,
+        _format: u32,
+        _data: &[u8],
+    ) {
+        log::warn!("this backend can't upload compressed textures");
+    }
+    fn generate_mipmap(&self, target: u32);
+
+    fn create_framebuffer(&mut self, framebuffer: FramebufferKey) -> Result<(), GraphicsError>;
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferKey);
+    fn bind_framebuffer(&self, target: u32, framebuffer: Option<FramebufferKey>);
+    fn check_framebuffer_status(&self, target: u32) -> u32;
+    fn draw_buffers(&self, buffers: &[u32]);
+    fn read_buffer(&self, source: u32);
+    fn framebuffer_texture_2d(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture_target: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+    );
+    /// Attaches a single layer of an array or volume texture.
+    fn framebuffer_texture_layer(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+        layer: i32,
+    );
+    /// Copies from the bound read framebuffer to the bound draw framebuffer, resolving
+    /// multisampled buffers along the way.
+    fn blit_framebuffer(
+        &self,
+        src_x0: i32,
+        src_y0: i32,
+        src_x1: i32,
+        src_y1: i32,
+        dst_x0: i32,
+        dst_y0: i32,
+        dst_x1: i32,
+        dst_y1: i32,
+        mask: u32,
+        filter: u32,
+    );
+
+    fn create_renderbuffer(&mut self, renderbuffer: RenderbufferKey) -> Result<(), GraphicsError>;
+    fn delete_renderbuffer(&mut self, renderbuffer: RenderbufferKey);
+    fn bind_renderbuffer(&self, target: u32, renderbuffer: Option<RenderbufferKey>);
+    fn renderbuffer_storage(&self, target: u32, internal_format: u32, width: i32, height: i32);
+    fn renderbuffer_storage_multisample(
+        &self,
+        target: u32,
+        samples: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    );
+    fn framebuffer_renderbuffer(
+        &self,
+        target: u32,
+        attachment: u32,
+        renderbuffer_target: u32,
+        renderbuffer: Option<RenderbufferKey>,
+    );
+
+    fn create_query(&mut self, query: QueryKey) -> Result<(), GraphicsError>;
+    fn delete_query(&mut self, query: QueryKey);
+    fn begin_query(&self, target: u32, query: QueryKey);
+    fn end_query(&self, target: u32);
+    /// Either `QUERY_RESULT_AVAILABLE` or `QUERY_RESULT`, which blocks until it's available.
+    fn get_query_parameter_u32(&self, query: QueryKey, parameter: u32) -> u32;
+    /// Like `get_query_parameter_u32` but for results that don't fit in 32 bits, like timers
+    /// running longer than about 4.29 seconds. Backends that can't read 64 bits truncate.
+    fn get_query_parameter_u64(&self, query: QueryKey, parameter: u32) -> u64 {
+        self.get_query_parameter_u32(query, parameter) as u64
+    }
+
+    /// Rebinds the vertex array object the backend draws with, if it uses one, after something
+    /// else has bound another.
+    fn restore_vertex_array(&self) {}
+    fn enable_vertex_attrib_array(&self, index: u32);
+    fn disable_vertex_attrib_array(&self, index: u32);
+    fn vertex_attrib_divisor(&self, index: u32, divisor: u32);
+    fn vertex_attrib_pointer_f32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: i32,
+    );
+    fn vertex_attrib_pointer_i32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        stride: i32,
+        offset: i32,
+    );
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32);
+    fn draw_elements(&self, mode: u32, count: i32, element_type: u32, offset: i32);
+    fn draw_arrays_instanced(&self, mode: u32, first: i32, count: i32, instance_count: i32);
+    fn draw_elements_instanced(
+        &self,
+        mode: u32,
+        count: i32,
+        element_type: u32,
+        offset: i32,
+        instance_count: i32,
+    );
+}
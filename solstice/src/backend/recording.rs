@@ -0,0 +1,1930 @@
+use super::{glsl, Backend};
+use crate::{
+    gl,
+    shader::{self, RawUniformValue, ShaderError},
+    BufferKey, FramebufferKey, GraphicsError, QueryKey, RenderbufferKey, ShaderKey, TextureKey,
+};
+use slotmap::{SecondaryMap, SlotMap};
+use std::{
+    cell::{Ref, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BufferRecord {
+    pub data: Vec<u8>,
+    pub usage: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgramRecord {
+    pub vertex_source: String,
+    pub fragment_source: String,
+    pub attributes: Vec<shader::Attribute>,
+    pub uniforms: Vec<shader::Uniform>,
+    /// The last value sent to each uniform, by name.
+    pub uniform_values: HashMap<String, RawUniformValue>,
+    pub uniform_blocks: Vec<shader::UniformBlock>,
+    /// The binding point of each uniform block, by block index. Blocks start at binding 0.
+    pub uniform_block_bindings: HashMap<u32, u32>,
+    /// Whether the program was loaded from a binary rather than compiled.
+    pub from_binary: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextureRecord {
+    pub target: Option<u32>,
+    pub width: i32,
+    pub height: i32,
+    /// Layers for array textures and faces for cube maps.
+    pub depth: i32,
+    pub internal_format: i32,
+    pub format: u32,
+    pub ty: u32,
+    /// Level 0 only, every layer one after another. Sized by whatever the uploads implied.
+    /// Compressed textures keep their blocks as they were uploaded.
+    pub data: Vec<u8>,
+    pub parameters: HashMap<u32, i32>,
+    pub float_parameters: HashMap<u32, f32>,
+    pub mipmap_generations: usize,
+    /// Levels past 0 that were uploaded rather than generated, starting with level 1. Laid out
+    /// like `data`.
+    pub mipmaps: Vec<Vec<u8>>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FramebufferAttachment {
+    Texture {
+        target: u32,
+        texture: TextureKey,
+        level: i32,
+        /// The layer of an array or volume texture, zero for everything else.
+        layer: i32,
+    },
+    Renderbuffer(RenderbufferKey),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FramebufferRecord {
+    pub attachments: HashMap<u32, FramebufferAttachment>,
+    /// The attachment each fragment output is written to, `NONE` for unused outputs.
+    pub draw_buffers: Vec<u32>,
+    pub read_buffer: u32,
+}
+
+impl Default for FramebufferRecord {
+    fn default() -> Self {
+        Self {
+            attachments: Default::default(),
+            draw_buffers: vec![glow::COLOR_ATTACHMENT0],
+            read_buffer: glow::COLOR_ATTACHMENT0,
+        }
+    }
+}
+
+impl FramebufferRecord {
+    /// The attachment that fragment output `index` is written to.
+    pub fn draw_buffer(&self, index: usize) -> Option<u32> {
+        self.draw_buffers
+            .get(index)
+            .copied()
+            .filter(|buffer| *buffer != glow::NONE)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RenderbufferRecord {
+    pub internal_format: u32,
+    pub width: i32,
+    pub height: i32,
+    /// Zero for single sampled storage.
+    pub samples: i32,
+}
+
+/// There's no GPU to measure so occlusion queries count the vertices drawn while they're active
+/// as samples and timer queries always report zero.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct QueryRecord {
+    /// The target of the last `begin_query`.
+    pub target: Option<u32>,
+    pub active: bool,
+    pub vertices: u32,
+    /// Whether the result is ready. Set when the query ends unless results are being held back.
+    pub available: bool,
+}
+
+impl QueryRecord {
+    fn result(&self) -> u32 {
+        match self.target {
+            Some(glow::SAMPLES_PASSED) => self.vertices,
+            Some(glow::ANY_SAMPLES_PASSED) | Some(glow::ANY_SAMPLES_PASSED_CONSERVATIVE) => {
+                (self.vertices > 0) as u32
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct VertexAttribute {
+    pub enabled: bool,
+    pub buffer: Option<BufferKey>,
+    pub size: i32,
+    pub data_type: u32,
+    pub normalized: bool,
+    pub integer: bool,
+    pub stride: i32,
+    pub offset: i32,
+    pub divisor: u32,
+}
+
+/// Everything that would be global GL state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundState {
+    pub program: Option<ShaderKey>,
+    pub buffers: HashMap<u32, BufferKey>,
+    /// Keyed by target and binding point, like the uniform buffer bindings.
+    pub indexed_buffers: HashMap<(u32, u32), BufferKey>,
+    pub draw_framebuffer: Option<FramebufferKey>,
+    pub read_framebuffer: Option<FramebufferKey>,
+    pub renderbuffer: Option<RenderbufferKey>,
+    pub active_texture: u32,
+    /// Keyed by texture unit index and target.
+    pub textures: HashMap<(u32, u32), TextureKey>,
+    pub vertex_attributes: BTreeMap<u32, VertexAttribute>,
+    pub capabilities: HashSet<u32>,
+    pub pixel_store: HashMap<u32, i32>,
+    pub viewport: [i32; 4],
+    pub scissor: [i32; 4],
+    pub depth_func: u32,
+    pub depth_mask: bool,
+    pub depth_range: (f32, f32),
+    pub blend_equation: (u32, u32),
+    pub blend_func: [u32; 4],
+    pub blend_color: [f32; 4],
+    pub stencil_func: (u32, i32, u32),
+    pub stencil_op_front: [u32; 3],
+    pub stencil_op_back: [u32; 3],
+    pub stencil_mask: u32,
+    pub cull_face: u32,
+    pub front_face: u32,
+    pub polygon_offset: (f32, f32),
+    pub clear_color: [f32; 4],
+    pub clear_depth: f32,
+    pub clear_stencil: i32,
+}
+
+impl Default for BoundState {
+    fn default() -> Self {
+        Self {
+            program: None,
+            buffers: Default::default(),
+            indexed_buffers: Default::default(),
+            draw_framebuffer: None,
+            read_framebuffer: None,
+            renderbuffer: None,
+            active_texture: 0,
+            textures: Default::default(),
+            vertex_attributes: Default::default(),
+            capabilities: Default::default(),
+            pixel_store: Default::default(),
+            viewport: [0; 4],
+            scissor: [0; 4],
+            depth_func: glow::LESS,
+            depth_mask: true,
+            depth_range: (0., 1.),
+            blend_equation: (glow::FUNC_ADD, glow::FUNC_ADD),
+            blend_func: [glow::ONE, glow::ZERO, glow::ONE, glow::ZERO],
+            blend_color: [0.; 4],
+            stencil_func: (glow::ALWAYS, 0, !0),
+            stencil_op_front: [glow::KEEP; 3],
+            stencil_op_back: [glow::KEEP; 3],
+            stencil_mask: !0,
+            cull_face: glow::BACK,
+            front_face: glow::CCW,
+            polygon_offset: (0., 0.),
+            clear_color: [0.; 4],
+            clear_depth: 1.,
+            clear_stencil: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawCall {
+    pub mode: u32,
+    /// The first vertex for array draws or the byte offset into the index buffer.
+    pub first: i32,
+    pub count: i32,
+    /// Set for indexed draws.
+    pub element_type: Option<u32>,
+    pub instance_count: i32,
+    /// The uniform values of the bound program at the time of the draw.
+    pub uniforms: HashMap<String, RawUniformValue>,
+    pub state: BoundState,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Clear {
+        mask: u32,
+        state: BoundState,
+    },
+    Draw(DrawCall),
+    /// A `glClearBuffer` of a single color draw buffer.
+    ClearBuffer {
+        draw_buffer: u32,
+        color: [f32; 4],
+        state: BoundState,
+    },
+    Blit {
+        src: [i32; 4],
+        dst: [i32; 4],
+        mask: u32,
+        filter: u32,
+        state: BoundState,
+    },
+}
+
+/// What a `RecordingBackend` has seen so far.
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub buffers: SecondaryMap<BufferKey, BufferRecord>,
+    pub programs: SecondaryMap<ShaderKey, ProgramRecord>,
+    pub textures: SecondaryMap<TextureKey, TextureRecord>,
+    pub framebuffers: SecondaryMap<FramebufferKey, FramebufferRecord>,
+    pub renderbuffers: SecondaryMap<RenderbufferKey, RenderbufferRecord>,
+    pub queries: SecondaryMap<QueryKey, QueryRecord>,
+    pub state: BoundState,
+    pub commands: Vec<Command>,
+    /// The number of fixed-function state calls made so far: enables and disables along with
+    /// the depth, blend, stencil, culling, polygon offset, viewport and scissor state.
+    pub state_calls: usize,
+    errors: Vec<u32>,
+    hold_query_results: bool,
+    extensions: Vec<String>,
+    version: Option<String>,
+    uniform_locations: SlotMap<shader::UniformLocation, (ShaderKey, String)>,
+}
+
+impl Recording {
+    pub fn draws(&self) -> impl Iterator<Item = &DrawCall> {
+        self.commands.iter().filter_map(|command| match command {
+            Command::Draw(draw) => Some(draw),
+            Command::Clear { .. } | Command::ClearBuffer { .. } | Command::Blit { .. } => None,
+        })
+    }
+
+    fn version(&self) -> crate::GLVersion {
+        self.version
+            .as_deref()
+            .unwrap_or("3.3")
+            .parse()
+            .unwrap_or_default()
+    }
+
+    fn bound_buffer_mut(&mut self, target: u32) -> Option<&mut BufferRecord> {
+        let key = *self.state.buffers.get(&target)?;
+        self.buffers.get_mut(key)
+    }
+
+    fn bound_texture_mut(&mut self, target: u32) -> Option<&mut TextureRecord> {
+        let key = *self
+            .state
+            .textures
+            .get(&(self.state.active_texture, target))?;
+        self.textures.get_mut(key)
+    }
+
+    fn bound_framebuffer_mut(&mut self, target: u32) -> Option<&mut FramebufferRecord> {
+        let key = match target {
+            glow::READ_FRAMEBUFFER => self.state.read_framebuffer,
+            _ => self.state.draw_framebuffer,
+        }?;
+        self.framebuffers.get_mut(key)
+    }
+
+    fn draw(
+        &mut self,
+        mode: u32,
+        first: i32,
+        count: i32,
+        element_type: Option<u32>,
+        instances: i32,
+    ) {
+        let uniforms = self
+            .state
+            .program
+            .and_then(|program| self.programs.get(program))
+            .map(|program| program.uniform_values.clone())
+            .unwrap_or_default();
+        for query in self.queries.values_mut().filter(|query| query.active) {
+            query.vertices += (count * instances.max(1)) as u32;
+        }
+        self.commands.push(Command::Draw(DrawCall {
+            mode,
+            first,
+            count,
+            element_type,
+            instance_count: instances,
+            uniforms,
+            state: self.state.clone(),
+        }));
+    }
+
+    fn vertex_attribute_mut(&mut self, index: u32) -> &mut VertexAttribute {
+        self.state.vertex_attributes.entry(index).or_default()
+    }
+}
+
+/// A backend that doesn't draw anything. It keeps track of the objects that get created, the
+/// state that gets bound and the clears and draws that get issued so that they can be inspected
+/// without a GPU.
+///
+/// Clones share the same recording so keep one around before handing the backend to `Context`.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingBackend {
+    recording: Rc<RefCell<Recording>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn recording(&self) -> Ref<'_, Recording> {
+        self.recording.borrow()
+    }
+
+    /// Queues up an error for `get_error` to report, like a driver would after a failed call.
+    pub fn raise_error(&self, error: u32) {
+        self.record(|r| r.errors.push(error))
+    }
+
+    /// The extensions to advertise. `Context` only asks when it's created so this has to be set
+    /// before the backend is handed over.
+    pub fn set_extensions(&self, extensions: &[&str]) {
+        self.record(|r| r.extensions = extensions.iter().map(|name| name.to_string()).collect())
+    }
+
+    /// The version string to report, `3.3 (recording)` by default. Like the extensions this has
+    /// to be set before the backend is handed over.
+    pub fn set_version(&self, version: &str) {
+        self.record(|r| r.version = Some(version.to_owned()))
+    }
+
+    /// Makes the results of queries that end from now on unavailable until this is called again
+    /// with `false`, like a GPU that's a few frames behind.
+    pub fn hold_query_results(&self, hold: bool) {
+        self.record(|r| {
+            r.hold_query_results = hold;
+            if !hold {
+                for query in r.queries.values_mut().filter(|query| !query.active) {
+                    query.available = query.target.is_some();
+                }
+            }
+        })
+    }
+
+    pub(super) fn record<T, F: FnOnce(&mut Recording) -> T>(&self, f: F) -> T {
+        f(&mut self.recording.borrow_mut())
+    }
+
+    fn record_state<T, F: FnOnce(&mut BoundState) -> T>(&self, f: F) -> T {
+        self.record(|r| {
+            r.state_calls += 1;
+            f(&mut r.state)
+        })
+    }
+
+    // levels past 0 are replaced whole, or a face at a time for cube maps
+    fn record_mipmap(&self, target: u32, level: i32, size: usize, pixels: Option<&[u8]>) {
+        let (target, face) = face_target(target);
+        let faces = if target == glow::TEXTURE_CUBE_MAP {
+            6
+        } else {
+            1
+        };
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                let index = level as usize - 1;
+                if texture.mipmaps.len() <= index {
+                    texture.mipmaps.resize(index + 1, vec![]);
+                }
+                let mipmap = &mut texture.mipmaps[index];
+                if mipmap.len() != size * faces {
+                    *mipmap = vec![0; size * faces];
+                }
+                let start = face as usize * size;
+                let pixels = pixels.unwrap_or(&[]);
+                let len = pixels.len().min(size);
+                mipmap[start..(start + len)].copy_from_slice(&pixels[..len]);
+            }
+        })
+    }
+}
+
+impl TextureRecord {
+    fn layer_size(&self) -> usize {
+        match compressed_block(self.internal_format) {
+            Some((block_width, block_height, block_size)) => {
+                let blocks_wide = blocks(self.width, block_width) as usize;
+                blocks_wide * blocks(self.height, block_height) as usize * block_size
+            }
+            None => {
+                self.width as usize * self.height as usize * bytes_per_pixel(self.format, self.ty)
+            }
+        }
+    }
+
+    fn write(&mut self, offset: [i32; 3], size: [i32; 3], stride: usize, pixels: &[u8]) {
+        let texture_size = [self.width, self.height];
+        self.write_region(offset, size, texture_size, stride, pixels)
+    }
+
+    // compressed data is written a row of blocks at a time
+    fn write_blocks(&mut self, offset: [i32; 3], size: [i32; 3], pixels: &[u8]) {
+        if let Some((block_width, block_height, block_size)) =
+            compressed_block(self.internal_format)
+        {
+            let [x_offset, y_offset, z_offset] = offset;
+            let [width, height, depth] = size;
+            self.write_region(
+                [x_offset / block_width, y_offset / block_height, z_offset],
+                [
+                    blocks(width, block_width),
+                    blocks(height, block_height),
+                    depth,
+                ],
+                [
+                    blocks(self.width, block_width),
+                    blocks(self.height, block_height),
+                ],
+                block_size,
+                pixels,
+            )
+        }
+    }
+
+    fn write_region(
+        &mut self,
+        offset: [i32; 3],
+        size: [i32; 3],
+        texture_size: [i32; 2],
+        stride: usize,
+        pixels: &[u8],
+    ) {
+        let [x_offset, y_offset, z_offset] = offset;
+        let [width, height, depth] = size;
+        let row_length = width as usize * stride;
+        let texture_row_length = texture_size[0] as usize * stride;
+        let layer_size = texture_row_length * texture_size[1] as usize;
+        let rows = pixels
+            .chunks(row_length)
+            .take(height as usize * depth as usize);
+        for (row, src) in rows.enumerate() {
+            let (layer, row) = (row / height as usize, row % height as usize);
+            let start = (z_offset as usize + layer) * layer_size
+                + (y_offset as usize + row) * texture_row_length
+                + x_offset as usize * stride;
+            if let Some(dst) = self.data.get_mut(start..(start + src.len())) {
+                dst.copy_from_slice(src);
+            }
+        }
+    }
+}
+
+// the block dimensions and bytes per block of compressed internal formats
+fn compressed_block(internal_format: i32) -> Option<(i32, i32, usize)> {
+    let format = gl::pixel_format::compressed_from_gl(internal_format as u32)?;
+    let (width, height) = gl::pixel_format::block_dimensions(format);
+    Some((width as i32, height as i32, gl::pixel_format::size(format)))
+}
+
+fn blocks(pixels: i32, block_size: i32) -> i32 {
+    (pixels + block_size - 1) / block_size
+}
+
+/// Cube map faces are uploaded through their own targets but bound as `TEXTURE_CUBE_MAP`.
+fn face_target(target: u32) -> (u32, i32) {
+    match target {
+        glow::TEXTURE_CUBE_MAP_POSITIVE_X..=glow::TEXTURE_CUBE_MAP_NEGATIVE_Z => (
+            glow::TEXTURE_CUBE_MAP,
+            (target - glow::TEXTURE_CUBE_MAP_POSITIVE_X) as i32,
+        ),
+        target => (target, 0),
+    }
+}
+
+pub(super) fn bytes_per_pixel(format: u32, ty: u32) -> usize {
+    let components = match format {
+        glow::RG | glow::RG_INTEGER | glow::LUMINANCE_ALPHA => 2,
+        glow::RGB | glow::RGB_INTEGER => 3,
+        glow::RGBA | glow::RGBA_INTEGER => 4,
+        _ => 1,
+    };
+    match ty {
+        glow::UNSIGNED_SHORT | glow::SHORT | glow::HALF_FLOAT => components * 2,
+        glow::FLOAT | glow::INT | glow::UNSIGNED_INT => components * 4,
+        glow::UNSIGNED_INT_24_8 => 4,
+        glow::FLOAT_32_UNSIGNED_INT_24_8_REV => 8,
+        _ => components,
+    }
+}
+
+/// The format of the program binaries the recording backend hands out, which are just the
+/// sources they were compiled from.
+pub const PROGRAM_BINARY_FORMAT: u32 = 0x5245_4300;
+
+fn program_binary(vertex_source: &str, fragment_source: &str) -> Vec<u8> {
+    [vertex_source, fragment_source].join("\0").into_bytes()
+}
+
+fn zeroed_uniform(utype: u32) -> Option<RawUniformValue> {
+    let value = match utype {
+        glow::FLOAT => RawUniformValue::Float(0.),
+        glow::FLOAT_VEC2 => RawUniformValue::Vec2([0.; 2].into()),
+        glow::FLOAT_VEC3 => RawUniformValue::Vec3([0.; 3].into()),
+        glow::FLOAT_VEC4 => RawUniformValue::Vec4([0.; 4].into()),
+        glow::FLOAT_MAT2 => RawUniformValue::Mat2([0.; 4].into()),
+        glow::FLOAT_MAT3 => RawUniformValue::Mat3([0.; 9].into()),
+        glow::FLOAT_MAT4 => RawUniformValue::Mat4([0.; 16].into()),
+        glow::INT_VEC2 | glow::BOOL_VEC2 => RawUniformValue::IntVec2([0; 2].into()),
+        glow::INT_VEC3 | glow::BOOL_VEC3 => RawUniformValue::IntVec3([0; 3].into()),
+        glow::INT_VEC4 | glow::BOOL_VEC4 => RawUniformValue::IntVec4([0; 4].into()),
+        glow::UNSIGNED_INT => RawUniformValue::UnsignedInt(0),
+        glow::UNSIGNED_INT_VEC2 => RawUniformValue::UnsignedIntVec2([0; 2].into()),
+        glow::UNSIGNED_INT_VEC3 => RawUniformValue::UnsignedIntVec3([0; 3].into()),
+        glow::UNSIGNED_INT_VEC4 => RawUniformValue::UnsignedIntVec4([0; 4].into()),
+        glow::BOOL => RawUniformValue::Bool(false),
+        glow::INT => RawUniformValue::SignedInt(0),
+        utype if gl::uniform::is_sampler(utype) => RawUniformValue::SignedInt(0),
+        _ => return None,
+    };
+    Some(value)
+}
+
+// Splits `name[index]` into its parts.
+fn array_element(name: &str) -> Option<(&str, usize)> {
+    let (base, index) = name.strip_suffix(']')?.rsplit_once('[')?;
+    Some((base, index.parse().ok()?))
+}
+
+impl Backend for RecordingBackend {
+    fn get_parameter_i32(&self, parameter: u32) -> i32 {
+        match parameter {
+            glow::NUM_EXTENSIONS => self.record(|r| r.extensions.len() as i32),
+            glow::MAX_VERTEX_ATTRIBS => 16,
+            glow::MAX_COMBINED_TEXTURE_IMAGE_UNITS => 16,
+            glow::MAX_TEXTURE_SIZE | glow::MAX_RENDERBUFFER_SIZE => 4096,
+            glow::MAX_SAMPLES => 4,
+            glow::MAX_UNIFORM_BUFFER_BINDINGS => {
+                self.record(|r| if r.version().major >= 3 { 36 } else { 0 })
+            }
+            _ => 0,
+        }
+    }
+
+    fn get_parameter_string(&self, parameter: u32) -> String {
+        match parameter {
+            glow::VERSION => self.record(|r| {
+                r.version
+                    .clone()
+                    .unwrap_or_else(|| "3.3 (recording)".to_owned())
+            }),
+            glow::EXTENSIONS => self.record(|r| r.extensions.join(" ")),
+            _ => "solstice recording backend".to_owned(),
+        }
+    }
+
+    fn get_parameter_indexed_string(&self, parameter: u32, index: u32) -> String {
+        match parameter {
+            glow::EXTENSIONS => self.record(|r| {
+                r.extensions
+                    .get(index as usize)
+                    .cloned()
+                    .unwrap_or_default()
+            }),
+            _ => String::new(),
+        }
+    }
+
+    fn get_error(&self) -> u32 {
+        self.record(|r| {
+            if r.errors.is_empty() {
+                glow::NO_ERROR
+            } else {
+                r.errors.remove(0)
+            }
+        })
+    }
+
+    fn enable(&self, capability: u32) {
+        self.record_state(|state| state.capabilities.insert(capability));
+    }
+
+    fn disable(&self, capability: u32) {
+        self.record_state(|state| state.capabilities.remove(&capability));
+    }
+
+    fn pixel_store_i32(&self, parameter: u32, value: i32) {
+        self.record(|r| r.state.pixel_store.insert(parameter, value));
+    }
+
+    fn depth_func(&self, function: u32) {
+        self.record_state(|state| state.depth_func = function)
+    }
+
+    fn depth_mask(&self, write_mask: bool) {
+        self.record_state(|state| state.depth_mask = write_mask)
+    }
+
+    fn depth_range_f32(&self, near: f32, far: f32) {
+        self.record_state(|state| state.depth_range = (near, far))
+    }
+
+    fn blend_equation_separate(&self, rgb: u32, alpha: u32) {
+        self.record_state(|state| state.blend_equation = (rgb, alpha))
+    }
+
+    fn blend_func_separate(&self, src_rgb: u32, dst_rgb: u32, src_alpha: u32, dst_alpha: u32) {
+        self.record_state(|state| state.blend_func = [src_rgb, dst_rgb, src_alpha, dst_alpha])
+    }
+
+    fn blend_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.record_state(|state| state.blend_color = [red, green, blue, alpha])
+    }
+
+    fn stencil_func(&self, function: u32, reference: i32, mask: u32) {
+        self.record_state(|state| state.stencil_func = (function, reference, mask))
+    }
+
+    fn stencil_op_separate(&self, face: u32, fail: u32, depth_fail: u32, pass: u32) {
+        self.record_state(|state| {
+            let ops = [fail, depth_fail, pass];
+            if face != glow::BACK {
+                state.stencil_op_front = ops;
+            }
+            if face != glow::FRONT {
+                state.stencil_op_back = ops;
+            }
+        })
+    }
+
+    fn stencil_mask(&self, mask: u32) {
+        self.record_state(|state| state.stencil_mask = mask)
+    }
+
+    fn cull_face(&self, face: u32) {
+        self.record_state(|state| state.cull_face = face)
+    }
+
+    fn front_face(&self, winding: u32) {
+        self.record_state(|state| state.front_face = winding)
+    }
+
+    fn polygon_offset(&self, factor: f32, units: f32) {
+        self.record_state(|state| state.polygon_offset = (factor, units))
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.record_state(|state| state.viewport = [x, y, width, height])
+    }
+
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.record_state(|state| state.scissor = [x, y, width, height])
+    }
+
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.record(|r| r.state.clear_color = [red, green, blue, alpha])
+    }
+
+    fn clear_depth_f32(&self, depth: f32) {
+        self.record(|r| r.state.clear_depth = depth)
+    }
+
+    fn clear_stencil(&self, stencil: i32) {
+        self.record(|r| r.state.clear_stencil = stencil)
+    }
+
+    fn clear(&self, mask: u32) {
+        self.record(|r| {
+            let state = r.state.clone();
+            r.commands.push(Command::Clear { mask, state })
+        })
+    }
+
+    fn clear_buffer_f32_slice(&self, _target: u32, draw_buffer: u32, values: &[f32]) {
+        let mut color = [0.; 4];
+        for (dst, src) in color.iter_mut().zip(values) {
+            *dst = *src;
+        }
+        self.record(|r| {
+            let state = r.state.clone();
+            r.commands.push(Command::ClearBuffer {
+                draw_buffer,
+                color,
+                state,
+            })
+        })
+    }
+
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        data: &mut [u8],
+    ) {
+        data.iter_mut().for_each(|byte| *byte = 0);
+        // nothing is ever rendered so only what was uploaded to a texture attachment can be read
+        let recording = self.recording();
+        let attachment = recording
+            .state
+            .read_framebuffer
+            .and_then(|framebuffer| recording.framebuffers.get(framebuffer))
+            .and_then(|framebuffer| framebuffer.attachments.get(&framebuffer.read_buffer));
+        let (target, texture, level, layer) = match attachment {
+            Some(FramebufferAttachment::Texture {
+                target,
+                texture,
+                level,
+                layer,
+            }) => (*target, *texture, *level, *layer),
+            _ => return,
+        };
+        let texture = match recording.textures.get(texture) {
+            Some(texture) => texture,
+            None => return,
+        };
+        let pixels = match level {
+            0 => Some(&texture.data),
+            level => texture.mipmaps.get(level as usize - 1),
+        };
+        let stride = bytes_per_pixel(format, ty);
+        let pixels = match pixels {
+            Some(pixels) if stride == bytes_per_pixel(texture.format, texture.ty) => pixels,
+            _ => return,
+        };
+        let level_width = (texture.width >> level).max(1) as usize;
+        let level_height = (texture.height >> level).max(1) as usize;
+        let layer = match target {
+            glow::TEXTURE_CUBE_MAP_POSITIVE_X..=glow::TEXTURE_CUBE_MAP_NEGATIVE_Z => {
+                target - glow::TEXTURE_CUBE_MAP_POSITIVE_X
+            }
+            _ => layer as u32,
+        } as usize;
+        let layer_offset = layer * level_width * level_height * stride;
+        let row_length = width.max(0) as usize * stride;
+        for (row, dst) in data
+            .chunks_mut(row_length)
+            .take(height.max(0) as usize)
+            .enumerate()
+        {
+            let src_y = y + row as i32;
+            if src_y < 0 || src_y as usize >= level_height {
+                continue;
+            }
+            for (column, pixel) in dst.chunks_exact_mut(stride).enumerate() {
+                let src_x = x + column as i32;
+                if src_x < 0 || src_x as usize >= level_width {
+                    continue;
+                }
+                let index = layer_offset + (src_y as usize * level_width + src_x as usize) * stride;
+                if let Some(src) = pixels.get(index..index + stride) {
+                    pixel.copy_from_slice(src);
+                }
+            }
+        }
+    }
+
+    fn create_buffer(&mut self, buffer: BufferKey) -> Result<(), GraphicsError> {
+        self.recording
+            .borrow_mut()
+            .buffers
+            .insert(buffer, Default::default());
+        Ok(())
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferKey) {
+        self.record(|r| {
+            r.buffers.remove(buffer);
+            r.state.buffers.retain(|_, bound| *bound != buffer);
+        })
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferKey>) {
+        self.record(|r| {
+            // GLES 2 and WebGL 1 only have the vertex and index targets
+            if r.version().major < 3
+                && target != glow::ARRAY_BUFFER
+                && target != glow::ELEMENT_ARRAY_BUFFER
+            {
+                r.errors.push(glow::INVALID_ENUM);
+                return;
+            }
+            match buffer {
+                Some(buffer) => r.state.buffers.insert(target, buffer),
+                None => r.state.buffers.remove(&target),
+            };
+        });
+    }
+
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32) {
+        self.record(|r| {
+            if let Some(buffer) = r.bound_buffer_mut(target) {
+                buffer.data = vec![0; size as usize];
+                buffer.usage = usage;
+            }
+        })
+    }
+
+    fn buffer_data_u8_slice(&self, target: u32, data: &[u8], usage: u32) {
+        self.record(|r| {
+            if let Some(buffer) = r.bound_buffer_mut(target) {
+                buffer.data = data.to_vec();
+                buffer.usage = usage;
+            }
+        })
+    }
+
+    fn buffer_sub_data_u8_slice(&self, target: u32, offset: i32, data: &[u8]) {
+        self.record(|r| {
+            if let Some(buffer) = r.bound_buffer_mut(target) {
+                let offset = offset as usize;
+                let end = (offset + data.len()).min(buffer.data.len());
+                if offset < end {
+                    buffer.data[offset..end].copy_from_slice(&data[..(end - offset)]);
+                }
+            }
+        })
+    }
+
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferKey>) {
+        self.record(|r| match buffer {
+            Some(buffer) => {
+                r.state.buffers.insert(target, buffer);
+                r.state.indexed_buffers.insert((target, index), buffer);
+            }
+            None => {
+                r.state.buffers.remove(&target);
+                r.state.indexed_buffers.remove(&(target, index));
+            }
+        })
+    }
+
+    fn copy_buffer_sub_data(
+        &self,
+        src_target: u32,
+        dst_target: u32,
+        src_offset: i32,
+        dst_offset: i32,
+        size: i32,
+    ) {
+        self.record(|r| {
+            let (src_offset, dst_offset) = (src_offset as usize, dst_offset as usize);
+            let data = match r.bound_buffer_mut(src_target) {
+                Some(buffer) => buffer
+                    .data
+                    .get(src_offset..(src_offset + size as usize))
+                    .map(<[u8]>::to_vec),
+                None => None,
+            };
+            if let (Some(data), Some(buffer)) = (data, r.bound_buffer_mut(dst_target)) {
+                if let Some(dst) = buffer.data.get_mut(dst_offset..(dst_offset + data.len())) {
+                    dst.copy_from_slice(&data);
+                }
+            }
+        })
+    }
+
+    fn create_program(
+        &mut self,
+        program: ShaderKey,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<(), ShaderError> {
+        if let Some(error) = glsl::errors(vertex_source).into_iter().next() {
+            return Err(ShaderError::VertexCompileError(error));
+        }
+        if let Some(error) = glsl::errors(fragment_source).into_iter().next() {
+            return Err(ShaderError::FragmentCompileError(error));
+        }
+        let vertex = glsl::declarations(vertex_source);
+        let fragment = glsl::declarations(fragment_source);
+
+        let mut attributes = vec![];
+        let mut used_locations = vertex
+            .iter()
+            .filter_map(|declaration| declaration.location)
+            .collect::<HashSet<_>>();
+        let mut next_location = 0;
+        for declaration in vertex
+            .iter()
+            .filter(|declaration| declaration.storage == glsl::Storage::Input)
+        {
+            let atype = match glsl::type_to_gl(&declaration.ty).and_then(gl::attribute::try_from_gl)
+            {
+                Some(atype) => atype,
+                None => continue,
+            };
+            let location = declaration.location.unwrap_or_else(|| {
+                while used_locations.contains(&next_location) {
+                    next_location += 1;
+                }
+                used_locations.insert(next_location);
+                next_location
+            });
+            attributes.push(shader::Attribute {
+                name: declaration.name.clone(),
+                size: 1,
+                atype,
+                location,
+            });
+        }
+        attributes.sort_by_key(|attribute| attribute.location);
+
+        let mut recording = self.recording.borrow_mut();
+        let mut uniforms: Vec<shader::Uniform> = vec![];
+        for declaration in vertex.iter().chain(fragment.iter()) {
+            if declaration.storage != glsl::Storage::Uniform || declaration.block.is_some() {
+                continue;
+            }
+            let (utype, initial_data) = match glsl::type_to_gl(&declaration.ty)
+                .and_then(|utype| zeroed_uniform(utype).map(|value| (utype, value)))
+            {
+                Some(uniform) => uniform,
+                None => continue,
+            };
+            let names = match declaration.array_length {
+                Some(length) => (0..length)
+                    .map(|i| format!("{}[{}]", declaration.name, i))
+                    .collect(),
+                None => vec![declaration.name.clone()],
+            };
+            for name in names {
+                if uniforms.iter().any(|uniform| uniform.name == name) {
+                    continue;
+                }
+                let location = recording.uniform_locations.insert((program, name.clone()));
+                uniforms.push(shader::Uniform {
+                    name,
+                    size: 1,
+                    utype,
+                    location,
+                    initial_data: initial_data.clone(),
+                });
+            }
+        }
+
+        recording.programs.insert(
+            program,
+            ProgramRecord {
+                vertex_source: vertex_source.to_owned(),
+                fragment_source: fragment_source.to_owned(),
+                attributes,
+                uniforms,
+                uniform_values: Default::default(),
+                uniform_blocks: glsl::uniform_blocks(vertex.iter().chain(fragment.iter())),
+                uniform_block_bindings: Default::default(),
+                from_binary: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn supports_program_binaries(&self) -> bool {
+        true
+    }
+
+    fn get_program_binary(&self, program: ShaderKey) -> Option<shader::ProgramBinary> {
+        self.record(|r| {
+            let record = r.programs.get(program)?;
+            Some(shader::ProgramBinary {
+                format: PROGRAM_BINARY_FORMAT,
+                data: program_binary(&record.vertex_source, &record.fragment_source),
+            })
+        })
+    }
+
+    fn create_program_from_binary(
+        &mut self,
+        program: ShaderKey,
+        binary: &shader::ProgramBinary,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<(), ShaderError> {
+        if binary.format != PROGRAM_BINARY_FORMAT
+            || binary.data != program_binary(vertex_source, fragment_source)
+        {
+            return Err(ShaderError::LinkError("invalid program binary".to_owned()));
+        }
+        self.create_program(program, vertex_source, fragment_source)?;
+        self.record(|r| {
+            if let Some(record) = r.programs.get_mut(program) {
+                record.from_binary = true;
+            }
+        });
+        Ok(())
+    }
+
+    fn delete_program(&mut self, program: ShaderKey) {
+        let mut recording = self.recording.borrow_mut();
+        if let Some(record) = recording.programs.remove(program) {
+            for uniform in record.uniforms {
+                recording.uniform_locations.remove(uniform.location);
+            }
+        }
+        if recording.state.program == Some(program) {
+            recording.state.program = None;
+        }
+    }
+
+    fn use_program(&self, program: Option<ShaderKey>) {
+        self.record(|r| r.state.program = program)
+    }
+
+    fn get_program_attributes(&self, program: ShaderKey) -> Vec<shader::Attribute> {
+        self.recording()
+            .programs
+            .get(program)
+            .map(|program| program.attributes.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_program_uniforms(&self, program: ShaderKey) -> Vec<shader::Uniform> {
+        self.recording()
+            .programs
+            .get(program)
+            .map(|program| program.uniforms.clone())
+            .unwrap_or_default()
+    }
+
+    fn set_uniform(&self, location: shader::UniformLocation, data: &RawUniformValue) {
+        self.record(|r| {
+            if let Some((program, name)) = r.uniform_locations.get(location).cloned() {
+                if let Some(program) = r.programs.get_mut(program) {
+                    match array_element(&name) {
+                        // arrays spill over into the elements after this one
+                        Some((base, first)) => {
+                            for (i, element) in data.elements().into_iter().enumerate() {
+                                let name = format!("{}[{}]", base, first + i);
+                                if !program.uniforms.iter().any(|uniform| uniform.name == name) {
+                                    break;
+                                }
+                                program.uniform_values.insert(name, element);
+                            }
+                        }
+                        None => {
+                            program.uniform_values.insert(name, data.clone());
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn get_program_uniform_blocks(&self, program: ShaderKey) -> Vec<shader::UniformBlock> {
+        self.recording()
+            .programs
+            .get(program)
+            .map(|program| program.uniform_blocks.clone())
+            .unwrap_or_default()
+    }
+
+    fn uniform_block_binding(&self, program: ShaderKey, index: u32, binding: u32) {
+        self.record(|r| {
+            if let Some(program) = r.programs.get_mut(program) {
+                program.uniform_block_bindings.insert(index, binding);
+            }
+        })
+    }
+
+    fn create_texture(&mut self, texture: TextureKey) -> Result<(), GraphicsError> {
+        self.recording
+            .borrow_mut()
+            .textures
+            .insert(texture, Default::default());
+        Ok(())
+    }
+
+    fn delete_texture(&mut self, texture: TextureKey) {
+        self.record(|r| {
+            r.textures.remove(texture);
+            r.state.textures.retain(|_, bound| *bound != texture);
+        })
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.record(|r| r.state.active_texture = unit - glow::TEXTURE0)
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<TextureKey>) {
+        self.record(|r| {
+            let unit = r.state.active_texture;
+            match texture {
+                Some(texture) => {
+                    if let Some(record) = r.textures.get_mut(texture) {
+                        record.target.get_or_insert(target);
+                    }
+                    r.state.textures.insert((unit, target), texture)
+                }
+                None => r.state.textures.remove(&(unit, target)),
+            };
+        })
+    }
+
+    fn tex_parameter_i32(&self, target: u32, parameter: u32, value: i32) {
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.parameters.insert(parameter, value);
+            }
+        })
+    }
+
+    fn tex_parameter_f32(&self, target: u32, parameter: u32, value: f32) {
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.float_parameters.insert(parameter, value);
+            }
+        })
+    }
+
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        _border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    ) {
+        if level != 0 {
+            let size = width as usize * height as usize * bytes_per_pixel(format, ty);
+            return self.record_mipmap(target, level, size, pixels);
+        }
+        let (target, face) = face_target(target);
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.width = width;
+                texture.height = height;
+                texture.internal_format = internal_format;
+                texture.format = format;
+                texture.ty = ty;
+                if target == glow::TEXTURE_CUBE_MAP {
+                    let layer_size = texture.layer_size();
+                    if texture.depth != 6 || texture.data.len() != layer_size * 6 {
+                        texture.depth = 6;
+                        texture.data = vec![0; layer_size * 6];
+                    }
+                    let stride = bytes_per_pixel(format, ty);
+                    match pixels {
+                        Some(pixels) => {
+                            texture.write([0, 0, face], [width, height, 1], stride, pixels)
+                        }
+                        None => texture.data[face as usize * layer_size..][..layer_size].fill(0),
+                    }
+                } else {
+                    texture.depth = 1;
+                    texture.data = match pixels {
+                        Some(pixels) => pixels.to_vec(),
+                        None => vec![0; texture.layer_size()],
+                    };
+                }
+            }
+        })
+    }
+
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    ) {
+        let (target, face) = face_target(target);
+        self.tex_sub_image_3d(
+            target, level, x_offset, y_offset, face, width, height, 1, format, ty, pixels,
+        )
+    }
+
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        _border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    ) {
+        if level != 0 {
+            let size =
+                width as usize * height as usize * depth as usize * bytes_per_pixel(format, ty);
+            return self.record_mipmap(target, level, size, pixels);
+        }
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.width = width;
+                texture.height = height;
+                texture.depth = depth;
+                texture.internal_format = internal_format;
+                texture.format = format;
+                texture.ty = ty;
+                texture.data = match pixels {
+                    Some(pixels) => pixels.to_vec(),
+                    None => vec![0; texture.layer_size() * depth as usize],
+                };
+            }
+        })
+    }
+
+    fn tex_sub_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        z_offset: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    ) {
+        if level != 0 {
+            return;
+        }
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                let offset = [x_offset, y_offset, z_offset];
+                let size = [width, height, depth];
+                texture.write(offset, size, bytes_per_pixel(format, ty), pixels);
+            }
+        })
+    }
+
+    fn supports_compressed_textures(&self) -> bool {
+        true
+    }
+
+    fn compressed_tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        _border: i32,
+        data: &[u8],
+    ) {
+        if level != 0 {
+            return self.record_mipmap(target, level, data.len(), Some(data));
+        }
+        let (target, face) = face_target(target);
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.width = width;
+                texture.height = height;
+                texture.internal_format = internal_format as i32;
+                texture.format = internal_format;
+                texture.ty = 0;
+                if target == glow::TEXTURE_CUBE_MAP {
+                    let layer_size = texture.layer_size();
+                    if texture.depth != 6 || texture.data.len() != layer_size * 6 {
+                        texture.depth = 6;
+                        texture.data = vec![0; layer_size * 6];
+                    }
+                    texture.write_blocks([0, 0, face], [width, height, 1], data);
+                } else {
+                    texture.depth = 1;
+                    texture.data = data.to_vec();
+                }
+            }
+        })
+    }
+
+    fn compressed_tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        data: &[u8],
+    ) {
+        let (target, face) = face_target(target);
+        self.compressed_tex_sub_image_3d(
+            target, level, x_offset, y_offset, face, width, height, 1, format, data,
+        )
+    }
+
+    fn compressed_tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        _border: i32,
+        data: &[u8],
+    ) {
+        if level != 0 {
+            return self.record_mipmap(target, level, data.len(), Some(data));
+        }
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.width = width;
+                texture.height = height;
+                texture.depth = depth;
+                texture.internal_format = internal_format as i32;
+                texture.format = internal_format;
+                texture.ty = 0;
+                texture.data = data.to_vec();
+            }
+        })
+    }
+
+    fn compressed_tex_sub_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        z_offset: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        _format: u32,
+        data: &[u8],
+    ) {
+        if level != 0 {
+            return;
+        }
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                let offset = [x_offset, y_offset, z_offset];
+                texture.write_blocks(offset, [width, height, depth], data);
+            }
+        })
+    }
+
+    fn generate_mipmap(&self, target: u32) {
+        self.record(|r| {
+            if let Some(texture) = r.bound_texture_mut(target) {
+                texture.mipmap_generations += 1;
+            }
+        })
+    }
+
+    fn create_framebuffer(&mut self, framebuffer: FramebufferKey) -> Result<(), GraphicsError> {
+        self.recording
+            .borrow_mut()
+            .framebuffers
+            .insert(framebuffer, Default::default());
+        Ok(())
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferKey) {
+        let recording = &mut *self.recording.borrow_mut();
+        recording.framebuffers.remove(framebuffer);
+        for bound in [
+            &mut recording.state.draw_framebuffer,
+            &mut recording.state.read_framebuffer,
+        ] {
+            if *bound == Some(framebuffer) {
+                *bound = None;
+            }
+        }
+    }
+
+    fn bind_framebuffer(&self, target: u32, framebuffer: Option<FramebufferKey>) {
+        self.record(|r| {
+            if target != glow::READ_FRAMEBUFFER {
+                r.state.draw_framebuffer = framebuffer;
+            }
+            if target != glow::DRAW_FRAMEBUFFER {
+                r.state.read_framebuffer = framebuffer;
+            }
+        })
+    }
+
+    fn check_framebuffer_status(&self, target: u32) -> u32 {
+        let mut recording = self.recording.borrow_mut();
+        let attachments = match recording.bound_framebuffer_mut(target) {
+            Some(framebuffer) if framebuffer.attachments.is_empty() => {
+                return glow::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT
+            }
+            Some(framebuffer) => framebuffer.attachments.clone(),
+            None => return glow::FRAMEBUFFER_COMPLETE,
+        };
+        // color attachments have to be color renderable and vice versa
+        for (attachment, attached) in attachments {
+            let format = match attached {
+                FramebufferAttachment::Texture { texture, .. } => recording
+                    .textures
+                    .get(texture)
+                    .map(|texture| texture.format),
+                FramebufferAttachment::Renderbuffer(renderbuffer) => recording
+                    .renderbuffers
+                    .get(renderbuffer)
+                    .map(|renderbuffer| renderbuffer.internal_format),
+            };
+            let is_depth_or_stencil = matches!(
+                format,
+                Some(glow::DEPTH_COMPONENT)
+                    | Some(glow::DEPTH_STENCIL)
+                    | Some(glow::STENCIL_INDEX8)
+                    | Some(glow::DEPTH_COMPONENT16)
+                    | Some(glow::DEPTH_COMPONENT24)
+                    | Some(glow::DEPTH_COMPONENT32F)
+                    | Some(glow::DEPTH24_STENCIL8)
+                    | Some(glow::DEPTH32F_STENCIL8)
+            );
+            let is_color =
+                (glow::COLOR_ATTACHMENT0..=glow::COLOR_ATTACHMENT31).contains(&attachment);
+            if is_color == is_depth_or_stencil {
+                return glow::FRAMEBUFFER_INCOMPLETE_ATTACHMENT;
+            }
+        }
+        glow::FRAMEBUFFER_COMPLETE
+    }
+
+    fn draw_buffers(&self, buffers: &[u32]) {
+        self.record(|r| {
+            if let Some(framebuffer) = r.bound_framebuffer_mut(glow::DRAW_FRAMEBUFFER) {
+                framebuffer.draw_buffers = buffers.to_vec();
+            }
+        })
+    }
+
+    fn read_buffer(&self, source: u32) {
+        self.record(|r| {
+            if let Some(framebuffer) = r.bound_framebuffer_mut(glow::READ_FRAMEBUFFER) {
+                framebuffer.read_buffer = source;
+            }
+        })
+    }
+
+    fn framebuffer_texture_2d(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture_target: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+    ) {
+        self.record(|r| {
+            if let Some(framebuffer) = r.bound_framebuffer_mut(target) {
+                match texture {
+                    Some(texture) => framebuffer.attachments.insert(
+                        attachment,
+                        FramebufferAttachment::Texture {
+                            target: texture_target,
+                            texture,
+                            level,
+                            layer: 0,
+                        },
+                    ),
+                    None => framebuffer.attachments.remove(&attachment),
+                };
+            }
+        })
+    }
+
+    fn framebuffer_texture_layer(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+        layer: i32,
+    ) {
+        self.record(|r| {
+            let texture_target = texture
+                .and_then(|texture| r.textures.get(texture))
+                .and_then(|texture| texture.target);
+            if let Some(framebuffer) = r.bound_framebuffer_mut(target) {
+                match (texture, texture_target) {
+                    (Some(texture), Some(texture_target)) => framebuffer.attachments.insert(
+                        attachment,
+                        FramebufferAttachment::Texture {
+                            target: texture_target,
+                            texture,
+                            level,
+                            layer,
+                        },
+                    ),
+                    _ => framebuffer.attachments.remove(&attachment),
+                };
+            }
+        })
+    }
+
+    fn create_query(&mut self, query: QueryKey) -> Result<(), GraphicsError> {
+        self.record(|r| r.queries.insert(query, Default::default()));
+        Ok(())
+    }
+
+    fn delete_query(&mut self, query: QueryKey) {
+        self.record(|r| r.queries.remove(query));
+    }
+
+    fn begin_query(&self, target: u32, query: QueryKey) {
+        self.record(|r| {
+            if let Some(query) = r.queries.get_mut(query) {
+                *query = QueryRecord {
+                    target: Some(target),
+                    active: true,
+                    vertices: 0,
+                    available: false,
+                };
+            }
+        })
+    }
+
+    fn end_query(&self, target: u32) {
+        self.record(|r| {
+            let available = !r.hold_query_results;
+            for query in r.queries.values_mut() {
+                if query.active && query.target == Some(target) {
+                    query.active = false;
+                    query.available = available;
+                }
+            }
+        })
+    }
+
+    fn get_query_parameter_u32(&self, query: QueryKey, parameter: u32) -> u32 {
+        let recording = self.recording();
+        let query = match recording.queries.get(query) {
+            Some(query) => query,
+            None => return 0,
+        };
+        match parameter {
+            glow::QUERY_RESULT_AVAILABLE => query.available as u32,
+            glow::QUERY_RESULT => query.result(),
+            _ => 0,
+        }
+    }
+
+    fn blit_framebuffer(
+        &self,
+        src_x0: i32,
+        src_y0: i32,
+        src_x1: i32,
+        src_y1: i32,
+        dst_x0: i32,
+        dst_y0: i32,
+        dst_x1: i32,
+        dst_y1: i32,
+        mask: u32,
+        filter: u32,
+    ) {
+        self.record(|r| {
+            let state = r.state.clone();
+            r.commands.push(Command::Blit {
+                src: [src_x0, src_y0, src_x1, src_y1],
+                dst: [dst_x0, dst_y0, dst_x1, dst_y1],
+                mask,
+                filter,
+                state,
+            })
+        })
+    }
+
+    fn create_renderbuffer(&mut self, renderbuffer: RenderbufferKey) -> Result<(), GraphicsError> {
+        self.recording
+            .borrow_mut()
+            .renderbuffers
+            .insert(renderbuffer, Default::default());
+        Ok(())
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: RenderbufferKey) {
+        let mut recording = self.recording.borrow_mut();
+        recording.renderbuffers.remove(renderbuffer);
+        if recording.state.renderbuffer == Some(renderbuffer) {
+            recording.state.renderbuffer = None;
+        }
+    }
+
+    fn bind_renderbuffer(&self, _target: u32, renderbuffer: Option<RenderbufferKey>) {
+        self.record(|r| r.state.renderbuffer = renderbuffer)
+    }
+
+    fn renderbuffer_storage(&self, _target: u32, internal_format: u32, width: i32, height: i32) {
+        self.record(|r| {
+            let bound = r.state.renderbuffer;
+            if let Some(renderbuffer) = bound.and_then(|key| r.renderbuffers.get_mut(key)) {
+                *renderbuffer = RenderbufferRecord {
+                    internal_format,
+                    width,
+                    height,
+                    samples: 0,
+                };
+            }
+        })
+    }
+
+    fn renderbuffer_storage_multisample(
+        &self,
+        _target: u32,
+        samples: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    ) {
+        self.record(|r| {
+            let bound = r.state.renderbuffer;
+            if let Some(renderbuffer) = bound.and_then(|key| r.renderbuffers.get_mut(key)) {
+                *renderbuffer = RenderbufferRecord {
+                    internal_format,
+                    width,
+                    height,
+                    samples,
+                };
+            }
+        })
+    }
+
+    fn framebuffer_renderbuffer(
+        &self,
+        target: u32,
+        attachment: u32,
+        _renderbuffer_target: u32,
+        renderbuffer: Option<RenderbufferKey>,
+    ) {
+        self.record(|r| {
+            if let Some(framebuffer) = r.bound_framebuffer_mut(target) {
+                match renderbuffer {
+                    Some(renderbuffer) => framebuffer.attachments.insert(
+                        attachment,
+                        FramebufferAttachment::Renderbuffer(renderbuffer),
+                    ),
+                    None => framebuffer.attachments.remove(&attachment),
+                };
+            }
+        })
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        self.record(|r| r.vertex_attribute_mut(index).enabled = true)
+    }
+
+    fn disable_vertex_attrib_array(&self, index: u32) {
+        self.record(|r| r.vertex_attribute_mut(index).enabled = false)
+    }
+
+    fn vertex_attrib_divisor(&self, index: u32, divisor: u32) {
+        self.record(|r| r.vertex_attribute_mut(index).divisor = divisor)
+    }
+
+    fn vertex_attrib_pointer_f32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: i32,
+    ) {
+        self.record(|r| {
+            let buffer = r.state.buffers.get(&glow::ARRAY_BUFFER).copied();
+            let attribute = r.vertex_attribute_mut(index);
+            attribute.buffer = buffer;
+            attribute.size = size;
+            attribute.data_type = data_type;
+            attribute.normalized = normalized;
+            attribute.integer = false;
+            attribute.stride = stride;
+            attribute.offset = offset;
+        })
+    }
+
+    fn vertex_attrib_pointer_i32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        stride: i32,
+        offset: i32,
+    ) {
+        self.record(|r| {
+            let buffer = r.state.buffers.get(&glow::ARRAY_BUFFER).copied();
+            let attribute = r.vertex_attribute_mut(index);
+            attribute.buffer = buffer;
+            attribute.size = size;
+            attribute.data_type = data_type;
+            attribute.normalized = false;
+            attribute.integer = true;
+            attribute.stride = stride;
+            attribute.offset = offset;
+        })
+    }
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        self.record(|r| r.draw(mode, first, count, None, 1))
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, element_type: u32, offset: i32) {
+        self.record(|r| r.draw(mode, offset, count, Some(element_type), 1))
+    }
+
+    fn draw_arrays_instanced(&self, mode: u32, first: i32, count: i32, instance_count: i32) {
+        self.record(|r| r.draw(mode, first, count, None, instance_count))
+    }
+
+    fn draw_elements_instanced(
+        &self,
+        mode: u32,
+        count: i32,
+        element_type: u32,
+        offset: i32,
+        instance_count: i32,
+    ) {
+        self.record(|r| r.draw(mode, offset, count, Some(element_type), instance_count))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    //! Fixtures shared by the tests that run on the recording backend.
+
+    use super::RecordingBackend;
+    use crate::{
+        vertex::{AttributeType, Vertex, VertexFormat},
+        Context,
+    };
+
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
+    pub struct TestVertex {
+        pub position: [f32; 2],
+    }
+
+    unsafe impl bytemuck::Zeroable for TestVertex {}
+    unsafe impl bytemuck::Pod for TestVertex {}
+
+    impl Vertex for TestVertex {
+        fn build_bindings() -> &'static [VertexFormat] {
+            &[VertexFormat {
+                name: "position",
+                offset: 0,
+                atype: AttributeType::F32F32,
+                normalize: false,
+            }]
+        }
+    }
+
+    pub const SRC: &str = r#"
+uniform vec4 color;
+uniform float weights[2];
+
+#ifdef VERTEX
+attribute vec2 position;
+
+void main() {
+    gl_Position = vec4(position, 0., 1.);
+}
+#endif
+
+#ifdef FRAGMENT
+void main() {
+    fragColor = color * weights[1];
+}
+#endif"#;
+
+    pub fn context() -> (Context, RecordingBackend) {
+        let backend = RecordingBackend::new();
+        (Context::with_backend(backend.clone()), backend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::*, *};
+    use crate::shader::DynamicShader;
+
+    #[test]
+    fn reflection() {
+        let (mut ctx, _backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+
+        let attribute = shader.get_attribute_by_name("position").unwrap();
+        assert_eq!(attribute.location, 0);
+        assert_eq!(attribute.atype, gl::attribute::from_gl(glow::FLOAT_VEC2));
+
+        assert!(shader.get_uniform_by_name("color").is_some());
+        assert!(shader.get_uniform_by_name("weights[0]").is_some());
+        assert!(shader.get_uniform_by_name("weights[1]").is_some());
+        assert!(shader.get_uniform_by_name("weights").is_none());
+    }
+
+    #[test]
+    fn uniform_types() {
+        use crate::shader::RawUniformValue;
+
+        const TYPES: &str = r#"
+uniform uint count;
+uniform uvec2 size;
+uniform bool enabled;
+uniform bvec3 mask;
+uniform mat4 bones[3];
+uniform sampler2D layers[2];
+uniform usampler2D lookup;
+
+#ifdef VERTEX
+attribute vec2 position;
+
+void main() {
+    gl_Position = bones[2] * vec4(position, 0., 1.);
+}
+#endif
+
+#ifdef FRAGMENT
+void main() {
+    fragColor = texture2D(layers[1], vec2(0.));
+}
+#endif"#;
+
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(TYPES, TYPES);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+
+        let uniform = |name: &str| shader.get_uniform_by_name(name).unwrap();
+        assert_eq!(uniform("count").utype, glow::UNSIGNED_INT);
+        assert_eq!(
+            uniform("count").initial_data,
+            RawUniformValue::UnsignedInt(0)
+        );
+        assert_eq!(
+            uniform("size").initial_data,
+            RawUniformValue::UnsignedIntVec2([0; 2].into())
+        );
+        assert_eq!(
+            uniform("enabled").initial_data,
+            RawUniformValue::Bool(false)
+        );
+        assert_eq!(uniform("mask").utype, glow::BOOL_VEC3);
+        assert_eq!(
+            uniform("mask").initial_data,
+            RawUniformValue::IntVec3([0; 3].into())
+        );
+        assert_eq!(uniform("layers[1]").utype, glow::SAMPLER_2D);
+        assert_eq!(uniform("lookup").utype, glow::UNSIGNED_INT_SAMPLER_2D);
+        assert_eq!(
+            uniform("lookup").initial_data,
+            RawUniformValue::SignedInt(0)
+        );
+
+        let identity: mint::ColumnMatrix4<f32> = [
+            1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+        ]
+        .into();
+        let scale: mint::ColumnMatrix4<f32> = [
+            2., 0., 0., 0., 0., 2., 0., 0., 0., 0., 2., 0., 0., 0., 0., 1.,
+        ]
+        .into();
+        ctx.use_shader(Some(&shader));
+        ctx.set_uniform_by_location(&uniform("count").location, &7u32.into());
+        ctx.set_uniform_by_location(&uniform("enabled").location, &true.into());
+        // the array spills over from the element it's sent to and stops at the end
+        ctx.set_uniform_by_location(
+            &uniform("bones[1]").location,
+            &vec![identity, scale, scale].into(),
+        );
+        ctx.set_uniform_by_location(&uniform("layers[0]").location, &[3, 4][..].into());
+
+        let recording = backend.recording();
+        let values = &recording.programs[shader.handle()].uniform_values;
+        assert_eq!(values["count"], RawUniformValue::UnsignedInt(7));
+        assert_eq!(values["enabled"], RawUniformValue::Bool(true));
+        assert!(!values.contains_key("bones[0]"));
+        assert_eq!(values["bones[1]"], RawUniformValue::Mat4(identity));
+        assert_eq!(values["bones[2]"], RawUniformValue::Mat4(scale));
+        assert!(!values.contains_key("bones[3]"));
+        assert_eq!(values["layers[0]"], RawUniformValue::SignedInt(3));
+        assert_eq!(values["layers[1]"], RawUniformValue::SignedInt(4));
+    }
+}
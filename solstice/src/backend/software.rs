@@ -0,0 +1,2016 @@
+use super::{
+    recording::{self, FramebufferAttachment, Recording, RecordingBackend, TextureRecord},
+    Backend,
+};
+use crate::{
+    shader::{self, RawUniformValue, ShaderError},
+    BufferKey, FramebufferKey, GraphicsError, QueryKey, RenderbufferKey, ShaderKey, TextureKey,
+};
+use slotmap::SecondaryMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+pub const MAX_VARYINGS: usize = 16;
+
+/// Values written by the vertex stage and interpolated, perspective correct, across primitives.
+pub type Varyings = [f32; MAX_VARYINGS];
+
+/// The attributes of a single vertex.
+pub struct VertexInput<'a> {
+    attributes: &'a [shader::Attribute],
+    values: &'a [[f32; 4]],
+}
+
+impl VertexInput<'_> {
+    /// Missing components are filled in the same way GL does, with `(0, 0, 0, 1)`.
+    pub fn get(&self, name: &str) -> [f32; 4] {
+        self.attributes
+            .iter()
+            .position(|attribute| attribute.name == name)
+            .map_or([0., 0., 0., 1.], |index| self.values[index])
+    }
+}
+
+pub struct Fragment {
+    /// Equivalent to `gl_FragCoord`.
+    pub coord: [f32; 4],
+    pub front_facing: bool,
+    pub varyings: Varyings,
+}
+
+/// The uniform values of the program being drawn with. Anything that hasn't been set reads as
+/// zero.
+pub struct Uniforms<'a> {
+    values: &'a HashMap<String, RawUniformValue>,
+    recording: &'a Recording,
+}
+
+impl Uniforms<'_> {
+    pub fn get(&self, name: &str) -> Option<&RawUniformValue> {
+        self.values.get(name)
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        match self.get(name) {
+            Some(RawUniformValue::Float(v)) => *v,
+            _ => 0.,
+        }
+    }
+
+    pub fn vec2(&self, name: &str) -> [f32; 2] {
+        match self.get(name) {
+            Some(RawUniformValue::Vec2(v)) => (*v).into(),
+            _ => [0.; 2],
+        }
+    }
+
+    pub fn vec3(&self, name: &str) -> [f32; 3] {
+        match self.get(name) {
+            Some(RawUniformValue::Vec3(v)) => (*v).into(),
+            _ => [0.; 3],
+        }
+    }
+
+    pub fn vec4(&self, name: &str) -> [f32; 4] {
+        match self.get(name) {
+            Some(RawUniformValue::Vec4(v)) => (*v).into(),
+            _ => [0.; 4],
+        }
+    }
+
+    pub fn mat4(&self, name: &str) -> mint::ColumnMatrix4<f32> {
+        match self.get(name) {
+            Some(RawUniformValue::Mat4(v)) => *v,
+            _ => [0.; 16].into(),
+        }
+    }
+
+    /// Samples the 2D texture bound to the unit the named sampler refers to. There are no
+    /// derivatives to pick between minification and magnification so the magnification filter
+    /// is always used and only the base level is read.
+    pub fn sample(&self, sampler: &str, uv: [f32; 2]) -> [f32; 4] {
+        let unit = match self.get(sampler) {
+            Some(RawUniformValue::SignedInt(unit)) => *unit as u32,
+            _ => 0,
+        };
+        self.recording
+            .state
+            .textures
+            .get(&(unit, glow::TEXTURE_2D))
+            .and_then(|texture| self.recording.textures.get(*texture))
+            .map_or([0., 0., 0., 1.], |texture| sample(texture, uv))
+    }
+}
+
+/// A stand-in for a compiled GLSL program.
+pub trait SoftwareProgram {
+    /// Returns the clip space position of the vertex.
+    fn vertex(&self, input: &VertexInput, uniforms: &Uniforms, varyings: &mut Varyings)
+        -> [f32; 4];
+
+    /// Returns the color of the fragment or `None` to discard it.
+    fn fragment(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<[f32; 4]>;
+}
+
+type Matcher = Box<dyn Fn(&str, &str) -> bool>;
+
+#[derive(Default)]
+struct Surface {
+    width: usize,
+    height: usize,
+    color: Vec<u8>,
+    depth: Vec<f32>,
+    stencil: Vec<u8>,
+}
+
+impl Surface {
+    fn new(width: usize, height: usize, color: bool) -> Self {
+        let pixels = width * height;
+        Self {
+            width,
+            height,
+            color: if color { vec![0; pixels * 4] } else { vec![] },
+            depth: vec![1.; pixels],
+            stencil: vec![0; pixels],
+        }
+    }
+}
+
+// Where the planes of a `Target` were taken from so they can be put back.
+enum Binding {
+    Default,
+    Framebuffer {
+        color: Option<ColorAttachment>,
+        depth: Option<RenderbufferKey>,
+        stencil: Option<RenderbufferKey>,
+    },
+}
+
+#[derive(Copy, Clone)]
+enum ColorAttachment {
+    Texture(TextureKey),
+    Renderbuffer(RenderbufferKey),
+}
+
+struct Target {
+    width: usize,
+    height: usize,
+    color: Option<Vec<u8>>,
+    depth: Option<Vec<f32>>,
+    stencil: Option<Vec<u8>>,
+}
+
+/// A backend that rasterizes on the CPU into RGBA8 color and 32-bit float depth buffers with an
+/// 8-bit stencil buffer.
+///
+/// GLSL isn't executed. Instead each program is matched against the `SoftwareProgram`s that
+/// have been registered and draws with a program that didn't match anything are skipped. Only
+/// triangle primitives are rasterized and only 2D textures can be sampled. Depth and stencil
+/// buffers are only used when they're renderbuffers.
+pub struct SoftwareBackend {
+    inner: RecordingBackend,
+    available: Vec<(Matcher, Rc<dyn SoftwareProgram>)>,
+    programs: SecondaryMap<ShaderKey, Rc<dyn SoftwareProgram>>,
+    default_framebuffer: RefCell<Surface>,
+    renderbuffers: RefCell<SecondaryMap<RenderbufferKey, Surface>>,
+}
+
+impl SoftwareBackend {
+    /// `width` and `height` are the dimensions of the default framebuffer.
+    pub fn new(width: u32, height: u32) -> Self {
+        let inner = RecordingBackend::new();
+        inner.viewport(0, 0, width as _, height as _);
+        inner.scissor(0, 0, width as _, height as _);
+        Self {
+            inner,
+            available: vec![],
+            programs: SecondaryMap::new(),
+            default_framebuffer: RefCell::new(Surface::new(width as _, height as _, true)),
+            renderbuffers: Default::default(),
+        }
+    }
+
+    /// Programs created after this whose vertex and fragment sources satisfy `matches` are
+    /// executed with `program`. Earlier registrations take precedence.
+    pub fn register_program<F, P>(&mut self, matches: F, program: P)
+    where
+        F: Fn(&str, &str) -> bool + 'static,
+        P: SoftwareProgram + 'static,
+    {
+        self.available.push((Box::new(matches), Rc::new(program)));
+    }
+
+    // The color attachment fragment outputs are written to, by index. `None` where an output
+    // isn't written anywhere.
+    fn draw_attachments(&self, framebuffer: Option<FramebufferKey>) -> Vec<Option<u32>> {
+        let recording = self.inner.recording();
+        match framebuffer.and_then(|framebuffer| recording.framebuffers.get(framebuffer)) {
+            Some(framebuffer) => (0..framebuffer.draw_buffers.len())
+                .map(|index| framebuffer.draw_buffer(index))
+                .collect(),
+            None => vec![Some(glow::BACK)],
+        }
+    }
+
+    fn read_attachment(&self, framebuffer: Option<FramebufferKey>) -> Option<u32> {
+        let recording = self.inner.recording();
+        match framebuffer.and_then(|framebuffer| recording.framebuffers.get(framebuffer)) {
+            Some(framebuffer) => {
+                Some(framebuffer.read_buffer).filter(|buffer| *buffer != glow::NONE)
+            }
+            None => Some(glow::BACK),
+        }
+    }
+
+    /// `color_attachment` picks which of the framebuffer's color attachments, if any, is used.
+    /// The default framebuffer only has the one.
+    fn take_target(
+        &self,
+        framebuffer: Option<FramebufferKey>,
+        color_attachment: Option<u32>,
+    ) -> (Binding, Target) {
+        let framebuffer = match framebuffer {
+            None => {
+                let mut surface = self.default_framebuffer.borrow_mut();
+                let target = Target {
+                    width: surface.width,
+                    height: surface.height,
+                    color: Some(std::mem::take(&mut surface.color)),
+                    depth: Some(std::mem::take(&mut surface.depth)),
+                    stencil: Some(std::mem::take(&mut surface.stencil)),
+                };
+                return (Binding::Default, target);
+            }
+            Some(framebuffer) => framebuffer,
+        };
+
+        self.inner.record(|r| {
+            let attachments = r
+                .framebuffers
+                .get(framebuffer)
+                .map(|framebuffer| framebuffer.attachments.clone())
+                .unwrap_or_default();
+            let renderbuffer = |attachment| match attachments.get(&attachment) {
+                Some(FramebufferAttachment::Renderbuffer(renderbuffer)) => Some(*renderbuffer),
+                _ => None,
+            };
+            let color = match color_attachment.and_then(|attachment| attachments.get(&attachment)) {
+                Some(FramebufferAttachment::Texture { texture, level, .. }) if *level == 0 => {
+                    Some(ColorAttachment::Texture(*texture))
+                }
+                Some(FramebufferAttachment::Renderbuffer(renderbuffer)) => {
+                    Some(ColorAttachment::Renderbuffer(*renderbuffer))
+                }
+                _ => None,
+            };
+            let depth = renderbuffer(glow::DEPTH_STENCIL_ATTACHMENT)
+                .or_else(|| renderbuffer(glow::DEPTH_ATTACHMENT));
+            let stencil = renderbuffer(glow::DEPTH_STENCIL_ATTACHMENT)
+                .or_else(|| renderbuffer(glow::STENCIL_ATTACHMENT));
+
+            let mut target = Target {
+                width: 0,
+                height: 0,
+                color: None,
+                depth: None,
+                stencil: None,
+            };
+            let mut renderbuffers = self.renderbuffers.borrow_mut();
+            match color {
+                Some(ColorAttachment::Texture(texture)) => {
+                    if let Some(texture) = r.textures.get_mut(texture) {
+                        target.width = texture.width as usize;
+                        target.height = texture.height as usize;
+                        if texture.format == glow::RGBA && texture.ty == glow::UNSIGNED_BYTE {
+                            target.color = Some(std::mem::take(&mut texture.data));
+                        } else {
+                            log::warn!("only RGBA8 color attachments can be rendered to");
+                        }
+                    }
+                }
+                Some(ColorAttachment::Renderbuffer(renderbuffer)) => {
+                    if let Some(surface) = renderbuffers.get_mut(renderbuffer) {
+                        target.width = surface.width;
+                        target.height = surface.height;
+                        target.color = Some(std::mem::take(&mut surface.color));
+                    }
+                }
+                None => (),
+            }
+            if let Some(surface) = depth.and_then(|depth| renderbuffers.get_mut(depth)) {
+                if color.is_none() {
+                    target.width = surface.width;
+                    target.height = surface.height;
+                }
+                target.depth = Some(std::mem::take(&mut surface.depth));
+            }
+            if let Some(surface) = stencil.and_then(|stencil| renderbuffers.get_mut(stencil)) {
+                target.stencil = Some(std::mem::take(&mut surface.stencil));
+            }
+            let size = target.width * target.height;
+            if target.color.as_ref().map(Vec::len) != Some(size * 4) {
+                target.color = None;
+            }
+            if target.depth.as_ref().map(Vec::len) != Some(size) {
+                target.depth = None;
+            }
+            if target.stencil.as_ref().map(Vec::len) != Some(size) {
+                target.stencil = None;
+            }
+
+            let binding = Binding::Framebuffer {
+                color,
+                depth,
+                stencil,
+            };
+            (binding, target)
+        })
+    }
+
+    fn restore_target(&self, binding: Binding, target: Target) {
+        match binding {
+            Binding::Default => {
+                let mut surface = self.default_framebuffer.borrow_mut();
+                surface.color = target.color.unwrap_or_default();
+                surface.depth = target.depth.unwrap_or_default();
+                surface.stencil = target.stencil.unwrap_or_default();
+            }
+            Binding::Framebuffer {
+                color,
+                depth,
+                stencil,
+            } => {
+                let mut renderbuffers = self.renderbuffers.borrow_mut();
+                match (color, target.color) {
+                    (Some(ColorAttachment::Texture(key)), Some(data)) => self.inner.record(|r| {
+                        if let Some(texture) = r.textures.get_mut(key) {
+                            texture.data = data;
+                        }
+                    }),
+                    (Some(ColorAttachment::Renderbuffer(key)), Some(data)) => {
+                        if let Some(surface) = renderbuffers.get_mut(key) {
+                            surface.color = data;
+                        }
+                    }
+                    _ => (),
+                }
+                if let (Some(key), Some(data)) = (depth, target.depth) {
+                    if let Some(surface) = renderbuffers.get_mut(key) {
+                        surface.depth = data;
+                    }
+                }
+                if let (Some(key), Some(data)) = (stencil, target.stencil) {
+                    if let Some(surface) = renderbuffers.get_mut(key) {
+                        surface.stencil = data;
+                    }
+                }
+            }
+        }
+    }
+
+    fn clear_target(
+        &self,
+        framebuffer: Option<FramebufferKey>,
+        color_attachment: Option<u32>,
+        mask: u32,
+        color: [u8; 4],
+    ) {
+        let (binding, mut target) = self.take_target(framebuffer, color_attachment);
+        {
+            let recording = self.inner.recording();
+            let state = &recording.state;
+            let (x0, y0, x1, y1) = bounds(state, &target);
+            let depth = state.clear_depth.clamp(0., 1.);
+            let write_mask = state.stencil_mask as u8;
+            let stencil = state.clear_stencil as u8 & write_mask;
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let index = y * target.width + x;
+                    if mask & glow::COLOR_BUFFER_BIT != 0 {
+                        if let Some(buffer) = target.color.as_mut() {
+                            buffer[index * 4..index * 4 + 4].copy_from_slice(&color);
+                        }
+                    }
+                    if mask & glow::DEPTH_BUFFER_BIT != 0 && state.depth_mask {
+                        if let Some(buffer) = target.depth.as_mut() {
+                            buffer[index] = depth;
+                        }
+                    }
+                    if mask & glow::STENCIL_BUFFER_BIT != 0 {
+                        if let Some(buffer) = target.stencil.as_mut() {
+                            buffer[index] = (buffer[index] & !write_mask) | stencil;
+                        }
+                    }
+                }
+            }
+        }
+        self.restore_target(binding, target);
+    }
+
+    fn draw(&self, mode: u32, first: i32, count: i32, element_type: Option<u32>, instances: i32) {
+        let framebuffer = self.inner.recording().state.draw_framebuffer;
+        let program = self
+            .inner
+            .recording()
+            .state
+            .program
+            .and_then(|program| self.programs.get(program).cloned());
+        let program = match program {
+            Some(program) => program,
+            None => {
+                log::warn!("skipping a draw with a program that has no software equivalent");
+                return;
+            }
+        };
+
+        let color_attachment = self
+            .draw_attachments(framebuffer)
+            .first()
+            .copied()
+            .flatten();
+        let (binding, mut target) = self.take_target(framebuffer, color_attachment);
+        {
+            let recording = self.inner.recording();
+            let mut rasterizer = Rasterizer {
+                recording: &recording,
+                program: program.as_ref(),
+                target: &mut target,
+            };
+            rasterizer.draw(mode, first, count, element_type, instances);
+        }
+        self.restore_target(binding, target);
+    }
+}
+
+impl Backend for SoftwareBackend {
+    fn get_parameter_i32(&self, parameter: u32) -> i32 {
+        self.inner.get_parameter_i32(parameter)
+    }
+
+    fn get_parameter_string(&self, parameter: u32) -> String {
+        match parameter {
+            glow::VERSION => "3.3 (software)".to_owned(),
+            _ => "solstice software backend".to_owned(),
+        }
+    }
+
+    fn get_parameter_indexed_string(&self, parameter: u32, index: u32) -> String {
+        self.inner.get_parameter_indexed_string(parameter, index)
+    }
+
+    fn get_error(&self) -> u32 {
+        self.inner.get_error()
+    }
+
+    fn enable(&self, capability: u32) {
+        self.inner.enable(capability)
+    }
+
+    fn disable(&self, capability: u32) {
+        self.inner.disable(capability)
+    }
+
+    fn pixel_store_i32(&self, parameter: u32, value: i32) {
+        self.inner.pixel_store_i32(parameter, value)
+    }
+
+    fn depth_func(&self, function: u32) {
+        self.inner.depth_func(function)
+    }
+
+    fn depth_mask(&self, write_mask: bool) {
+        self.inner.depth_mask(write_mask)
+    }
+
+    fn depth_range_f32(&self, near: f32, far: f32) {
+        self.inner.depth_range_f32(near, far)
+    }
+
+    fn blend_equation_separate(&self, rgb: u32, alpha: u32) {
+        self.inner.blend_equation_separate(rgb, alpha)
+    }
+
+    fn blend_func_separate(&self, src_rgb: u32, dst_rgb: u32, src_alpha: u32, dst_alpha: u32) {
+        self.inner
+            .blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha)
+    }
+
+    fn blend_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.inner.blend_color(red, green, blue, alpha)
+    }
+
+    fn stencil_func(&self, function: u32, reference: i32, mask: u32) {
+        self.inner.stencil_func(function, reference, mask)
+    }
+
+    fn stencil_op_separate(&self, face: u32, fail: u32, depth_fail: u32, pass: u32) {
+        self.inner.stencil_op_separate(face, fail, depth_fail, pass)
+    }
+
+    fn stencil_mask(&self, mask: u32) {
+        self.inner.stencil_mask(mask)
+    }
+
+    fn cull_face(&self, face: u32) {
+        self.inner.cull_face(face)
+    }
+
+    fn front_face(&self, winding: u32) {
+        self.inner.front_face(winding)
+    }
+
+    fn polygon_offset(&self, factor: f32, units: f32) {
+        self.inner.polygon_offset(factor, units)
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.inner.viewport(x, y, width, height)
+    }
+
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.inner.scissor(x, y, width, height)
+    }
+
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.inner.clear_color(red, green, blue, alpha)
+    }
+
+    fn clear_depth_f32(&self, depth: f32) {
+        self.inner.clear_depth_f32(depth)
+    }
+
+    fn clear_stencil(&self, stencil: i32) {
+        self.inner.clear_stencil(stencil)
+    }
+
+    fn clear(&self, mask: u32) {
+        let framebuffer = self.inner.recording().state.draw_framebuffer;
+        let color = self.inner.recording().state.clear_color.map(to_unorm8);
+        let mut draw_buffers = self.draw_attachments(framebuffer);
+        if draw_buffers.is_empty() {
+            draw_buffers.push(None);
+        }
+        // depth and stencil only need clearing once
+        let mut mask = mask;
+        for attachment in draw_buffers {
+            self.clear_target(framebuffer, attachment, mask, color);
+            mask &= glow::COLOR_BUFFER_BIT;
+        }
+    }
+
+    fn clear_buffer_f32_slice(&self, target: u32, draw_buffer: u32, values: &[f32]) {
+        self.inner
+            .clear_buffer_f32_slice(target, draw_buffer, values);
+        if target != glow::COLOR {
+            log::warn!("only color buffers can be cleared individually");
+            return;
+        }
+        let framebuffer = self.inner.recording().state.draw_framebuffer;
+        let attachment = self
+            .draw_attachments(framebuffer)
+            .get(draw_buffer as usize)
+            .copied()
+            .flatten();
+        if attachment.is_some() {
+            let mut color = [0; 4];
+            for (dst, src) in color.iter_mut().zip(values) {
+                *dst = to_unorm8(*src);
+            }
+            self.clear_target(framebuffer, attachment, glow::COLOR_BUFFER_BIT, color);
+        }
+    }
+
+    fn read_pixels(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        data: &mut [u8],
+    ) {
+        let framebuffer = self.inner.recording().state.read_framebuffer;
+        // texture attachments are rendered into in place so they can be read like any other
+        // texture, whatever their format or level
+        let reads_texture = {
+            let recording = self.inner.recording();
+            let attachment = framebuffer
+                .and_then(|framebuffer| recording.framebuffers.get(framebuffer))
+                .and_then(|framebuffer| framebuffer.attachments.get(&framebuffer.read_buffer));
+            matches!(attachment, Some(FramebufferAttachment::Texture { .. }))
+        };
+        if reads_texture {
+            return self
+                .inner
+                .read_pixels(x, y, width, height, format, ty, data);
+        }
+
+        data.iter_mut().for_each(|byte| *byte = 0);
+        if format != glow::RGBA || ty != glow::UNSIGNED_BYTE {
+            log::warn!("only RGBA8 pixels can be read");
+            return;
+        }
+
+        let (binding, target) = self.take_target(framebuffer, self.read_attachment(framebuffer));
+        if let Some(color) = target.color.as_ref() {
+            let row_length = width.max(0) as usize * 4;
+            for (row, dst) in data
+                .chunks_mut(row_length)
+                .take(height as usize)
+                .enumerate()
+            {
+                let src_y = y + row as i32;
+                if src_y < 0 || src_y as usize >= target.height {
+                    continue;
+                }
+                for (column, pixel) in dst.chunks_exact_mut(4).enumerate() {
+                    let src_x = x + column as i32;
+                    if src_x < 0 || src_x as usize >= target.width {
+                        continue;
+                    }
+                    let index = (src_y as usize * target.width + src_x as usize) * 4;
+                    pixel.copy_from_slice(&color[index..index + 4]);
+                }
+            }
+        }
+        self.restore_target(binding, target);
+    }
+
+    fn create_buffer(&mut self, buffer: BufferKey) -> Result<(), GraphicsError> {
+        self.inner.create_buffer(buffer)
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferKey) {
+        self.inner.delete_buffer(buffer)
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<BufferKey>) {
+        self.inner.bind_buffer(target, buffer)
+    }
+
+    fn buffer_data_size(&self, target: u32, size: i32, usage: u32) {
+        self.inner.buffer_data_size(target, size, usage)
+    }
+
+    fn buffer_data_u8_slice(&self, target: u32, data: &[u8], usage: u32) {
+        self.inner.buffer_data_u8_slice(target, data, usage)
+    }
+
+    fn buffer_sub_data_u8_slice(&self, target: u32, offset: i32, data: &[u8]) {
+        self.inner.buffer_sub_data_u8_slice(target, offset, data)
+    }
+
+    fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<BufferKey>) {
+        self.inner.bind_buffer_base(target, index, buffer)
+    }
+
+    fn copy_buffer_sub_data(
+        &self,
+        src_target: u32,
+        dst_target: u32,
+        src_offset: i32,
+        dst_offset: i32,
+        size: i32,
+    ) {
+        self.inner
+            .copy_buffer_sub_data(src_target, dst_target, src_offset, dst_offset, size)
+    }
+
+    fn create_program(
+        &mut self,
+        program: ShaderKey,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<(), ShaderError> {
+        self.inner
+            .create_program(program, vertex_source, fragment_source)?;
+        let software = self
+            .available
+            .iter()
+            .find(|(matches, _)| matches(vertex_source, fragment_source));
+        match software {
+            Some((_, software)) => {
+                self.programs.insert(program, software.clone());
+            }
+            None => log::warn!("no software equivalent was registered for a program"),
+        }
+        Ok(())
+    }
+
+    fn delete_program(&mut self, program: ShaderKey) {
+        self.programs.remove(program);
+        self.inner.delete_program(program)
+    }
+
+    fn use_program(&self, program: Option<ShaderKey>) {
+        self.inner.use_program(program)
+    }
+
+    fn get_program_attributes(&self, program: ShaderKey) -> Vec<shader::Attribute> {
+        self.inner.get_program_attributes(program)
+    }
+
+    fn get_program_uniforms(&self, program: ShaderKey) -> Vec<shader::Uniform> {
+        self.inner.get_program_uniforms(program)
+    }
+
+    fn set_uniform(&self, location: shader::UniformLocation, data: &RawUniformValue) {
+        self.inner.set_uniform(location, data)
+    }
+
+    fn get_program_uniform_blocks(&self, program: ShaderKey) -> Vec<shader::UniformBlock> {
+        self.inner.get_program_uniform_blocks(program)
+    }
+
+    fn uniform_block_binding(&self, program: ShaderKey, index: u32, binding: u32) {
+        self.inner.uniform_block_binding(program, index, binding)
+    }
+
+    fn create_texture(&mut self, texture: TextureKey) -> Result<(), GraphicsError> {
+        self.inner.create_texture(texture)
+    }
+
+    fn delete_texture(&mut self, texture: TextureKey) {
+        self.inner.delete_texture(texture)
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.inner.active_texture(unit)
+    }
+
+    fn bind_texture(&self, target: u32, texture: Option<TextureKey>) {
+        self.inner.bind_texture(target, texture)
+    }
+
+    fn tex_parameter_i32(&self, target: u32, parameter: u32, value: i32) {
+        self.inner.tex_parameter_i32(target, parameter, value)
+    }
+
+    fn tex_parameter_f32(&self, target: u32, parameter: u32, value: f32) {
+        self.inner.tex_parameter_f32(target, parameter, value)
+    }
+
+    fn tex_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    ) {
+        self.inner.tex_image_2d(
+            target,
+            level,
+            internal_format,
+            width,
+            height,
+            border,
+            format,
+            ty,
+            pixels,
+        )
+    }
+
+    fn tex_sub_image_2d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    ) {
+        self.inner.tex_sub_image_2d(
+            target, level, x_offset, y_offset, width, height, format, ty, pixels,
+        )
+    }
+
+    fn tex_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        internal_format: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        border: i32,
+        format: u32,
+        ty: u32,
+        pixels: Option<&[u8]>,
+    ) {
+        self.inner.tex_image_3d(
+            target,
+            level,
+            internal_format,
+            width,
+            height,
+            depth,
+            border,
+            format,
+            ty,
+            pixels,
+        )
+    }
+
+    fn tex_sub_image_3d(
+        &self,
+        target: u32,
+        level: i32,
+        x_offset: i32,
+        y_offset: i32,
+        z_offset: i32,
+        width: i32,
+        height: i32,
+        depth: i32,
+        format: u32,
+        ty: u32,
+        pixels: &[u8],
+    ) {
+        self.inner.tex_sub_image_3d(
+            target, level, x_offset, y_offset, z_offset, width, height, depth, format, ty, pixels,
+        )
+    }
+
+    fn generate_mipmap(&self, target: u32) {
+        self.inner.generate_mipmap(target)
+    }
+
+    fn create_framebuffer(&mut self, framebuffer: FramebufferKey) -> Result<(), GraphicsError> {
+        self.inner.create_framebuffer(framebuffer)
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: FramebufferKey) {
+        self.inner.delete_framebuffer(framebuffer)
+    }
+
+    fn bind_framebuffer(&self, target: u32, framebuffer: Option<FramebufferKey>) {
+        self.inner.bind_framebuffer(target, framebuffer)
+    }
+
+    fn check_framebuffer_status(&self, target: u32) -> u32 {
+        self.inner.check_framebuffer_status(target)
+    }
+
+    fn draw_buffers(&self, buffers: &[u32]) {
+        self.inner.draw_buffers(buffers)
+    }
+
+    fn read_buffer(&self, source: u32) {
+        self.inner.read_buffer(source)
+    }
+
+    fn framebuffer_texture_2d(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture_target: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+    ) {
+        self.inner
+            .framebuffer_texture_2d(target, attachment, texture_target, texture, level)
+    }
+
+    fn framebuffer_texture_layer(
+        &self,
+        target: u32,
+        attachment: u32,
+        texture: Option<TextureKey>,
+        level: i32,
+        layer: i32,
+    ) {
+        self.inner
+            .framebuffer_texture_layer(target, attachment, texture, level, layer)
+    }
+
+    fn blit_framebuffer(
+        &self,
+        src_x0: i32,
+        src_y0: i32,
+        src_x1: i32,
+        src_y1: i32,
+        dst_x0: i32,
+        dst_y0: i32,
+        dst_x1: i32,
+        dst_y1: i32,
+        mask: u32,
+        filter: u32,
+    ) {
+        self.inner.blit_framebuffer(
+            src_x0, src_y0, src_x1, src_y1, dst_x0, dst_y0, dst_x1, dst_y1, mask, filter,
+        );
+        let (read, draw) = {
+            let recording = self.inner.recording();
+            (
+                recording.state.read_framebuffer,
+                recording.state.draw_framebuffer,
+            )
+        };
+        if read == draw {
+            log::warn!("can't blit a framebuffer to itself");
+            return;
+        }
+
+        let (read_binding, src) = self.take_target(read, self.read_attachment(read));
+        let mut draw_buffers = self.draw_attachments(draw);
+        if draw_buffers.is_empty() {
+            draw_buffers.push(None);
+        }
+        // every draw buffer gets a copy of the color but depth and stencil are only copied once
+        let mut mask = mask;
+        for attachment in draw_buffers {
+            let (draw_binding, mut dst) = self.take_target(draw, attachment);
+            {
+                let recording = self.inner.recording();
+                let (x0, y0, x1, y1) = bounds(&recording.state, &dst);
+                let scale_x = (src_x1 - src_x0) as f32 / (dst_x1 - dst_x0) as f32;
+                let scale_y = (src_y1 - src_y0) as f32 / (dst_y1 - dst_y0) as f32;
+                let (min_x, max_x) = (dst_x0.min(dst_x1), dst_x0.max(dst_x1));
+                let (min_y, max_y) = (dst_y0.min(dst_y1), dst_y0.max(dst_y1));
+                for y in (y0 as i32).max(min_y)..(y1 as i32).min(max_y) {
+                    let sy = src_y0 as f32 + (y - dst_y0) as f32 * scale_y + 0.5 * scale_y;
+                    let sy = sy.floor() as i32;
+                    if sy < 0 || sy as usize >= src.height {
+                        continue;
+                    }
+                    for x in (x0 as i32).max(min_x)..(x1 as i32).min(max_x) {
+                        let sx = src_x0 as f32 + (x - dst_x0) as f32 * scale_x + 0.5 * scale_x;
+                        let sx = sx.floor() as i32;
+                        if sx < 0 || sx as usize >= src.width {
+                            continue;
+                        }
+                        let from = sy as usize * src.width + sx as usize;
+                        let to = y as usize * dst.width + x as usize;
+                        if mask & glow::COLOR_BUFFER_BIT != 0 {
+                            if let (Some(src), Some(dst)) = (src.color.as_ref(), dst.color.as_mut())
+                            {
+                                dst[to * 4..to * 4 + 4]
+                                    .copy_from_slice(&src[from * 4..from * 4 + 4]);
+                            }
+                        }
+                        if mask & glow::DEPTH_BUFFER_BIT != 0 {
+                            if let (Some(src), Some(dst)) = (src.depth.as_ref(), dst.depth.as_mut())
+                            {
+                                dst[to] = src[from];
+                            }
+                        }
+                        if mask & glow::STENCIL_BUFFER_BIT != 0 {
+                            if let (Some(src), Some(dst)) =
+                                (src.stencil.as_ref(), dst.stencil.as_mut())
+                            {
+                                dst[to] = src[from];
+                            }
+                        }
+                    }
+                }
+            }
+            self.restore_target(draw_binding, dst);
+            mask &= glow::COLOR_BUFFER_BIT;
+        }
+        self.restore_target(read_binding, src);
+    }
+
+    fn create_renderbuffer(&mut self, renderbuffer: RenderbufferKey) -> Result<(), GraphicsError> {
+        self.inner.create_renderbuffer(renderbuffer)?;
+        self.renderbuffers
+            .get_mut()
+            .insert(renderbuffer, Surface::default());
+        Ok(())
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: RenderbufferKey) {
+        self.renderbuffers.get_mut().remove(renderbuffer);
+        self.inner.delete_renderbuffer(renderbuffer)
+    }
+
+    fn bind_renderbuffer(&self, target: u32, renderbuffer: Option<RenderbufferKey>) {
+        self.inner.bind_renderbuffer(target, renderbuffer)
+    }
+
+    fn renderbuffer_storage(&self, target: u32, internal_format: u32, width: i32, height: i32) {
+        self.inner
+            .renderbuffer_storage(target, internal_format, width, height);
+        let bound = self.inner.recording().state.renderbuffer;
+        let mut renderbuffers = self.renderbuffers.borrow_mut();
+        if let Some(surface) = bound.and_then(|key| renderbuffers.get_mut(key)) {
+            let color = internal_format == glow::RGBA8;
+            *surface = Surface::new(width as _, height as _, color);
+        }
+    }
+
+    /// There's no multisampling, every sample is taken at the pixel center.
+    fn renderbuffer_storage_multisample(
+        &self,
+        target: u32,
+        samples: i32,
+        internal_format: u32,
+        width: i32,
+        height: i32,
+    ) {
+        self.inner.renderbuffer_storage_multisample(
+            target,
+            samples,
+            internal_format,
+            width,
+            height,
+        );
+        let bound = self.inner.recording().state.renderbuffer;
+        let mut renderbuffers = self.renderbuffers.borrow_mut();
+        if let Some(surface) = bound.and_then(|key| renderbuffers.get_mut(key)) {
+            let color = internal_format == glow::RGBA8;
+            *surface = Surface::new(width as _, height as _, color);
+        }
+    }
+
+    fn framebuffer_renderbuffer(
+        &self,
+        target: u32,
+        attachment: u32,
+        renderbuffer_target: u32,
+        renderbuffer: Option<RenderbufferKey>,
+    ) {
+        self.inner
+            .framebuffer_renderbuffer(target, attachment, renderbuffer_target, renderbuffer)
+    }
+
+    fn create_query(&mut self, query: QueryKey) -> Result<(), GraphicsError> {
+        self.inner.create_query(query)
+    }
+
+    fn delete_query(&mut self, query: QueryKey) {
+        self.inner.delete_query(query)
+    }
+
+    fn begin_query(&self, target: u32, query: QueryKey) {
+        self.inner.begin_query(target, query)
+    }
+
+    fn end_query(&self, target: u32) {
+        self.inner.end_query(target)
+    }
+
+    fn get_query_parameter_u32(&self, query: QueryKey, parameter: u32) -> u32 {
+        self.inner.get_query_parameter_u32(query, parameter)
+    }
+
+    fn get_query_parameter_u64(&self, query: QueryKey, parameter: u32) -> u64 {
+        self.inner.get_query_parameter_u64(query, parameter)
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        self.inner.enable_vertex_attrib_array(index)
+    }
+
+    fn disable_vertex_attrib_array(&self, index: u32) {
+        self.inner.disable_vertex_attrib_array(index)
+    }
+
+    fn vertex_attrib_divisor(&self, index: u32, divisor: u32) {
+        self.inner.vertex_attrib_divisor(index, divisor)
+    }
+
+    fn vertex_attrib_pointer_f32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        normalized: bool,
+        stride: i32,
+        offset: i32,
+    ) {
+        self.inner
+            .vertex_attrib_pointer_f32(index, size, data_type, normalized, stride, offset)
+    }
+
+    fn vertex_attrib_pointer_i32(
+        &self,
+        index: u32,
+        size: i32,
+        data_type: u32,
+        stride: i32,
+        offset: i32,
+    ) {
+        self.inner
+            .vertex_attrib_pointer_i32(index, size, data_type, stride, offset)
+    }
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        self.draw(mode, first, count, None, 1)
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, element_type: u32, offset: i32) {
+        self.draw(mode, offset, count, Some(element_type), 1)
+    }
+
+    fn draw_arrays_instanced(&self, mode: u32, first: i32, count: i32, instance_count: i32) {
+        self.draw(mode, first, count, None, instance_count)
+    }
+
+    fn draw_elements_instanced(
+        &self,
+        mode: u32,
+        count: i32,
+        element_type: u32,
+        offset: i32,
+        instance_count: i32,
+    ) {
+        self.draw(mode, offset, count, Some(element_type), instance_count)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ClipVertex {
+    position: [f32; 4],
+    varyings: Varyings,
+}
+
+#[derive(Copy, Clone)]
+struct WindowVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    inv_w: f32,
+    varyings: Varyings,
+}
+
+struct Rasterizer<'a> {
+    recording: &'a Recording,
+    program: &'a dyn SoftwareProgram,
+    target: &'a mut Target,
+}
+
+impl Rasterizer<'_> {
+    fn draw(
+        &mut self,
+        mode: u32,
+        first: i32,
+        count: i32,
+        element_type: Option<u32>,
+        instances: i32,
+    ) {
+        let recording = self.recording;
+        let state = &recording.state;
+        let indices = match element_type {
+            Some(element_type) => {
+                let buffer = state
+                    .buffers
+                    .get(&glow::ELEMENT_ARRAY_BUFFER)
+                    .and_then(|buffer| recording.buffers.get(*buffer));
+                match buffer {
+                    Some(buffer) => read_indices(&buffer.data, element_type, first, count),
+                    None => return,
+                }
+            }
+            None => (first.max(0) as u32..(first + count).max(0) as u32).collect(),
+        };
+        let triangles = match assemble(mode, indices.len()) {
+            Some(triangles) => triangles,
+            None => {
+                log::warn!("only triangle primitives can be rasterized");
+                return;
+            }
+        };
+
+        let program_record = match state.program.and_then(|p| recording.programs.get(p)) {
+            Some(program) => program,
+            None => return,
+        };
+        let uniforms = Uniforms {
+            values: &program_record.uniform_values,
+            recording,
+        };
+        let attributes = &program_record.attributes;
+        let mut values = vec![[0., 0., 0., 1.]; attributes.len()];
+
+        for instance in 0..instances.max(0) as u32 {
+            let vertices = indices
+                .iter()
+                .map(|&vertex| {
+                    for (value, attribute) in values.iter_mut().zip(attributes.iter()) {
+                        *value = state
+                            .vertex_attributes
+                            .get(&attribute.location)
+                            .map_or([0., 0., 0., 1.], |attribute| {
+                                fetch(recording, attribute, vertex, instance)
+                            });
+                    }
+                    let input = VertexInput {
+                        attributes,
+                        values: &values,
+                    };
+                    let mut varyings = [0.; MAX_VARYINGS];
+                    let position = self.program.vertex(&input, &uniforms, &mut varyings);
+                    ClipVertex { position, varyings }
+                })
+                .collect::<Vec<_>>();
+
+            for [a, b, c] in triangles.iter() {
+                let polygon = clip_near(&[vertices[*a], vertices[*b], vertices[*c]]);
+                let window = polygon
+                    .iter()
+                    .map(|vertex| self.to_window(vertex))
+                    .collect::<Vec<_>>();
+                for i in 1..window.len().saturating_sub(1) {
+                    self.triangle(&uniforms, [&window[0], &window[i], &window[i + 1]]);
+                }
+            }
+        }
+    }
+
+    fn to_window(&self, vertex: &ClipVertex) -> WindowVertex {
+        let state = &self.recording.state;
+        let [x, y, z, w] = vertex.position;
+        let [vx, vy, vw, vh] = state.viewport;
+        let (near, far) = state.depth_range;
+        let inv_w = 1. / w;
+        let mut varyings = vertex.varyings;
+        varyings.iter_mut().for_each(|v| *v *= inv_w);
+        WindowVertex {
+            x: vx as f32 + (x * inv_w + 1.) * vw as f32 / 2.,
+            y: vy as f32 + (y * inv_w + 1.) * vh as f32 / 2.,
+            z: near + (far - near) * (z * inv_w + 1.) / 2.,
+            inv_w,
+            varyings,
+        }
+    }
+
+    fn triangle(&mut self, uniforms: &Uniforms, vertices: [&WindowVertex; 3]) {
+        let state = &self.recording.state;
+        let [v0, v1, v2] = vertices;
+        let area = edge(v0, v1, v2.x, v2.y);
+        if area == 0. || !area.is_finite() {
+            return;
+        }
+
+        let front_facing = (area > 0.) == (state.front_face == glow::CCW);
+        if state.capabilities.contains(&glow::CULL_FACE) {
+            let culled = match state.cull_face {
+                glow::FRONT => front_facing,
+                glow::BACK => !front_facing,
+                _ => true,
+            };
+            if culled {
+                return;
+            }
+        }
+
+        // wind counter-clockwise so that the inside of every edge is positive
+        let (v1, v2) = if area > 0. { (v1, v2) } else { (v2, v1) };
+        let area = area.abs();
+
+        let (x0, y0, x1, y1) = bounds(state, self.target);
+        let min_x = v0.x.min(v1.x).min(v2.x).floor().max(x0 as f32) as usize;
+        let min_y = v0.y.min(v1.y).min(v2.y).floor().max(y0 as f32) as usize;
+        let max_x = (v0.x.max(v1.x).max(v2.x).ceil().max(0.) as usize).min(x1);
+        let max_y = (v0.y.max(v1.y).max(v2.y).ceil().max(0.) as usize).min(y1);
+
+        let stencil_test = state.capabilities.contains(&glow::STENCIL_TEST);
+        let depth_test = state.capabilities.contains(&glow::DEPTH_TEST);
+        let blend = state.capabilities.contains(&glow::BLEND);
+        let (stencil_func, reference, read_mask) = state.stencil_func;
+        let stencil_ops = if front_facing {
+            state.stencil_op_front
+        } else {
+            state.stencil_op_back
+        };
+        let reference = reference.clamp(0, 255) as u8;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge(v1, v2, px, py);
+                let w1 = edge(v2, v0, px, py);
+                let w2 = edge(v0, v1, px, py);
+                if !(covers(w0, v1, v2) && covers(w1, v2, v0) && covers(w2, v0, v1)) {
+                    continue;
+                }
+                let (b0, b1, b2) = (w0 / area, w1 / area, w2 / area);
+
+                let z = (b0 * v0.z + b1 * v1.z + b2 * v2.z).clamp(0., 1.);
+                let inv_w = b0 * v0.inv_w + b1 * v1.inv_w + b2 * v2.inv_w;
+                let mut varyings = [0.; MAX_VARYINGS];
+                for (i, varying) in varyings.iter_mut().enumerate() {
+                    *varying =
+                        (b0 * v0.varyings[i] + b1 * v1.varyings[i] + b2 * v2.varyings[i]) / inv_w;
+                }
+                let fragment = Fragment {
+                    coord: [px, py, z, inv_w],
+                    front_facing,
+                    varyings,
+                };
+                let color = match self.program.fragment(&fragment, uniforms) {
+                    Some(color) => color,
+                    None => continue,
+                };
+
+                let index = y * self.target.width + x;
+                let target = &mut *self.target;
+                let write_mask = state.stencil_mask as u8;
+                let stencil_op = |op: u32, stencil: &mut Option<Vec<u8>>| {
+                    if let Some(stencil) = stencil.as_mut() {
+                        let value = apply_stencil_op(op, stencil[index], reference);
+                        stencil[index] = (stencil[index] & !write_mask) | (value & write_mask);
+                    }
+                };
+
+                if stencil_test {
+                    let passed = match target.stencil.as_ref() {
+                        Some(stencil) => {
+                            let stored = stencil[index] & read_mask as u8;
+                            compare(stencil_func, reference & read_mask as u8, stored)
+                        }
+                        None => true,
+                    };
+                    if !passed {
+                        stencil_op(stencil_ops[0], &mut target.stencil);
+                        continue;
+                    }
+                }
+                if depth_test {
+                    if let Some(depth) = target.depth.as_mut() {
+                        if !compare(state.depth_func, z, depth[index]) {
+                            if stencil_test {
+                                stencil_op(stencil_ops[1], &mut target.stencil);
+                            }
+                            continue;
+                        }
+                        if state.depth_mask {
+                            depth[index] = z;
+                        }
+                    }
+                }
+                if stencil_test {
+                    stencil_op(stencil_ops[2], &mut target.stencil);
+                }
+
+                if let Some(buffer) = target.color.as_mut() {
+                    let pixel = &mut buffer[index * 4..index * 4 + 4];
+                    let src = color.map(|c| c.clamp(0., 1.));
+                    let color = if blend {
+                        let dst = [pixel[0], pixel[1], pixel[2], pixel[3]].map(from_unorm8);
+                        blend_colors(state, src, dst)
+                    } else {
+                        src
+                    };
+                    pixel.copy_from_slice(&color.map(to_unorm8));
+                }
+            }
+        }
+    }
+}
+
+// The render target bounds, reduced to the scissor rectangle if the scissor test is enabled.
+fn bounds(state: &recording::BoundState, target: &Target) -> (usize, usize, usize, usize) {
+    let (width, height) = (target.width as i32, target.height as i32);
+    let [x, y, w, h] = if state.capabilities.contains(&glow::SCISSOR_TEST) {
+        state.scissor
+    } else {
+        [0, 0, width, height]
+    };
+    let clamp = |v: i32, max: i32| v.max(0).min(max) as usize;
+    (
+        clamp(x, width),
+        clamp(y, height),
+        clamp(x + w, width),
+        clamp(y + h, height),
+    )
+}
+
+fn edge(a: &WindowVertex, b: &WindowVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+// Pixel centers that lie exactly on an edge belong to only one of the triangles sharing it.
+fn covers(weight: f32, a: &WindowVertex, b: &WindowVertex) -> bool {
+    if weight != 0. {
+        return weight > 0.;
+    }
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    dy < 0. || (dy == 0. && dx > 0.)
+}
+
+fn assemble(mode: u32, count: usize) -> Option<Vec<[usize; 3]>> {
+    let triangles = match mode {
+        glow::TRIANGLES => (0..count / 3)
+            .map(|i| [i * 3, i * 3 + 1, i * 3 + 2])
+            .collect(),
+        glow::TRIANGLE_STRIP => (0..count.saturating_sub(2))
+            .map(|i| {
+                if i % 2 == 0 {
+                    [i, i + 1, i + 2]
+                } else {
+                    [i + 1, i, i + 2]
+                }
+            })
+            .collect(),
+        glow::TRIANGLE_FAN => (1..count.saturating_sub(1))
+            .map(|i| [0, i, i + 1])
+            .collect(),
+        _ => return None,
+    };
+    Some(triangles)
+}
+
+// Sutherland-Hodgman against the near plane. The other planes are taken care of by only
+// rasterizing inside of the render target.
+fn clip_near(triangle: &[ClipVertex; 3]) -> Vec<ClipVertex> {
+    let distance = |v: &ClipVertex| v.position[2] + v.position[3];
+    let mut output = Vec::with_capacity(4);
+    for (i, current) in triangle.iter().enumerate() {
+        let next = &triangle[(i + 1) % 3];
+        let (d0, d1) = (distance(current), distance(next));
+        if d0 >= 0. {
+            output.push(*current);
+        }
+        if (d0 >= 0.) != (d1 >= 0.) {
+            let t = d0 / (d0 - d1);
+            let mut vertex = *current;
+            for (v, n) in vertex.position.iter_mut().zip(next.position.iter()) {
+                *v += (n - *v) * t;
+            }
+            for (v, n) in vertex.varyings.iter_mut().zip(next.varyings.iter()) {
+                *v += (n - *v) * t;
+            }
+            output.push(vertex);
+        }
+    }
+    output.retain(|vertex| vertex.position[3] > 0.);
+    output
+}
+
+fn read_indices(data: &[u8], element_type: u32, offset: i32, count: i32) -> Vec<u32> {
+    let size = match element_type {
+        glow::UNSIGNED_BYTE => 1,
+        glow::UNSIGNED_SHORT => 2,
+        _ => 4,
+    };
+    let start = offset.max(0) as usize;
+    let end = (start + count.max(0) as usize * size).min(data.len());
+    data.get(start..end)
+        .unwrap_or_default()
+        .chunks_exact(size)
+        .map(|bytes| match size {
+            1 => bytes[0] as u32,
+            2 => u16::from_ne_bytes([bytes[0], bytes[1]]) as u32,
+            _ => u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        })
+        .collect()
+}
+
+fn fetch(
+    recording: &Recording,
+    attribute: &recording::VertexAttribute,
+    vertex: u32,
+    instance: u32,
+) -> [f32; 4] {
+    let mut value = [0., 0., 0., 1.];
+    let buffer = match attribute
+        .buffer
+        .and_then(|buffer| recording.buffers.get(buffer))
+    {
+        Some(buffer) if attribute.enabled => buffer,
+        _ => return value,
+    };
+    let component_size = match attribute.data_type {
+        glow::BYTE | glow::UNSIGNED_BYTE => 1,
+        glow::SHORT | glow::UNSIGNED_SHORT | glow::HALF_FLOAT => 2,
+        _ => 4,
+    };
+    let size = attribute.size.clamp(0, 4) as usize;
+    let stride = match attribute.stride {
+        0 => size * component_size,
+        stride => stride as usize,
+    };
+    let index = match attribute.divisor {
+        0 => vertex,
+        divisor => instance / divisor,
+    } as usize;
+    let start = attribute.offset as usize + index * stride;
+    let normalized = attribute.normalized && !attribute.integer;
+    for (component, value) in value.iter_mut().take(size).enumerate() {
+        let at = start + component * component_size;
+        match buffer.data.get(at..(at + component_size)) {
+            Some(bytes) => *value = component_to_f32(attribute.data_type, normalized, bytes),
+            None => break,
+        }
+    }
+    value
+}
+
+fn component_to_f32(data_type: u32, normalized: bool, bytes: &[u8]) -> f32 {
+    macro_rules! convert {
+        ($ty:ty) => {{
+            let mut raw = [0; std::mem::size_of::<$ty>()];
+            raw.copy_from_slice(bytes);
+            let value = <$ty>::from_ne_bytes(raw);
+            if normalized {
+                (value as f32 / <$ty>::MAX as f32).max(-1.)
+            } else {
+                value as f32
+            }
+        }};
+    }
+
+    match data_type {
+        glow::FLOAT => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        glow::BYTE => convert!(i8),
+        glow::UNSIGNED_BYTE => convert!(u8),
+        glow::SHORT => convert!(i16),
+        glow::UNSIGNED_SHORT => convert!(u16),
+        glow::INT => convert!(i32),
+        glow::UNSIGNED_INT => convert!(u32),
+        _ => 0.,
+    }
+}
+
+fn sample(texture: &TextureRecord, uv: [f32; 2]) -> [f32; 4] {
+    let (width, height) = (texture.width as i64, texture.height as i64);
+    if width <= 0 || height <= 0 {
+        return [0., 0., 0., 1.];
+    }
+    let parameter = |name: u32, default: u32| {
+        texture
+            .parameters
+            .get(&name)
+            .map_or(default, |value| *value as u32)
+    };
+    let wrap_s = parameter(glow::TEXTURE_WRAP_S, glow::REPEAT);
+    let wrap_t = parameter(glow::TEXTURE_WRAP_T, glow::REPEAT);
+    let texel = |x: i64, y: i64| {
+        let (x, y) = (wrap(x, width, wrap_s), wrap(y, height, wrap_t));
+        texel(texture, (y * width + x) as usize)
+    };
+
+    let (u, v) = (uv[0] * width as f32, uv[1] * height as f32);
+    let color = match parameter(glow::TEXTURE_MAG_FILTER, glow::LINEAR) {
+        glow::NEAREST => texel(u.floor() as i64, v.floor() as i64),
+        _ => {
+            let (u, v) = (u - 0.5, v - 0.5);
+            let (x, y) = (u.floor(), v.floor());
+            let (fx, fy) = (u - x, v - y);
+            let (x, y) = (x as i64, y as i64);
+            let [c00, c10, c01, c11] = [
+                texel(x, y),
+                texel(x + 1, y),
+                texel(x, y + 1),
+                texel(x + 1, y + 1),
+            ];
+            let mut color = [0.; 4];
+            for (i, c) in color.iter_mut().enumerate() {
+                let top = c00[i] + (c10[i] - c00[i]) * fx;
+                let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+                *c = top + (bottom - top) * fy;
+            }
+            color
+        }
+    };
+
+    let swizzle = |name: u32, default: u32| match parameter(name, default) {
+        glow::RED => color[0],
+        glow::GREEN => color[1],
+        glow::BLUE => color[2],
+        glow::ALPHA => color[3],
+        glow::ONE => 1.,
+        _ => 0.,
+    };
+    [
+        swizzle(glow::TEXTURE_SWIZZLE_R, glow::RED),
+        swizzle(glow::TEXTURE_SWIZZLE_G, glow::GREEN),
+        swizzle(glow::TEXTURE_SWIZZLE_B, glow::BLUE),
+        swizzle(glow::TEXTURE_SWIZZLE_A, glow::ALPHA),
+    ]
+}
+
+fn wrap(coordinate: i64, size: i64, mode: u32) -> i64 {
+    match mode {
+        glow::REPEAT => coordinate.rem_euclid(size),
+        glow::MIRRORED_REPEAT => {
+            let coordinate = coordinate.rem_euclid(size * 2);
+            if coordinate >= size {
+                size * 2 - 1 - coordinate
+            } else {
+                coordinate
+            }
+        }
+        _ => coordinate.max(0).min(size - 1),
+    }
+}
+
+fn texel(texture: &TextureRecord, index: usize) -> [f32; 4] {
+    let stride = recording::bytes_per_pixel(texture.format, texture.ty);
+    let bytes = match texture.data.get(index * stride..(index + 1) * stride) {
+        Some(bytes) => bytes,
+        None => return [0., 0., 0., 1.],
+    };
+    let component = |i: usize| match texture.ty {
+        glow::UNSIGNED_BYTE => bytes.get(i).map_or(0., |c| *c as f32 / 255.),
+        glow::UNSIGNED_SHORT => bytes
+            .get(i * 2..i * 2 + 2)
+            .map_or(0., |c| component_to_f32(glow::UNSIGNED_SHORT, true, c)),
+        glow::FLOAT => bytes
+            .get(i * 4..i * 4 + 4)
+            .map_or(0., |c| component_to_f32(glow::FLOAT, false, c)),
+        _ => 0.,
+    };
+    match texture.format {
+        glow::RG => [component(0), component(1), 0., 1.],
+        glow::RGB => [component(0), component(1), component(2), 1.],
+        glow::RGBA => [component(0), component(1), component(2), component(3)],
+        glow::ALPHA => [0., 0., 0., component(0)],
+        glow::LUMINANCE => [component(0), component(0), component(0), 1.],
+        glow::LUMINANCE_ALPHA => [component(0), component(0), component(0), component(1)],
+        _ => [component(0), 0., 0., 1.],
+    }
+}
+
+fn compare<T: PartialOrd>(function: u32, incoming: T, stored: T) -> bool {
+    match function {
+        glow::NEVER => false,
+        glow::LESS => incoming < stored,
+        glow::EQUAL => incoming == stored,
+        glow::LEQUAL => incoming <= stored,
+        glow::GREATER => incoming > stored,
+        glow::NOTEQUAL => incoming != stored,
+        glow::GEQUAL => incoming >= stored,
+        _ => true,
+    }
+}
+
+fn apply_stencil_op(op: u32, value: u8, reference: u8) -> u8 {
+    match op {
+        glow::ZERO => 0,
+        glow::REPLACE => reference,
+        glow::INCR => value.saturating_add(1),
+        glow::INCR_WRAP => value.wrapping_add(1),
+        glow::DECR => value.saturating_sub(1),
+        glow::DECR_WRAP => value.wrapping_sub(1),
+        glow::INVERT => !value,
+        _ => value,
+    }
+}
+
+fn blend_colors(state: &recording::BoundState, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    let constant = state.blend_color;
+    let factor = |factor: u32, channel: usize| match factor {
+        glow::ZERO => 0.,
+        glow::ONE => 1.,
+        glow::SRC_COLOR => src[channel],
+        glow::ONE_MINUS_SRC_COLOR => 1. - src[channel],
+        glow::DST_COLOR => dst[channel],
+        glow::ONE_MINUS_DST_COLOR => 1. - dst[channel],
+        glow::SRC_ALPHA => src[3],
+        glow::ONE_MINUS_SRC_ALPHA => 1. - src[3],
+        glow::DST_ALPHA => dst[3],
+        glow::ONE_MINUS_DST_ALPHA => 1. - dst[3],
+        glow::CONSTANT_COLOR => constant[channel],
+        glow::ONE_MINUS_CONSTANT_COLOR => 1. - constant[channel],
+        glow::CONSTANT_ALPHA => constant[3],
+        glow::ONE_MINUS_CONSTANT_ALPHA => 1. - constant[3],
+        glow::SRC_ALPHA_SATURATE if channel < 3 => src[3].min(1. - dst[3]),
+        glow::SRC_ALPHA_SATURATE => 1.,
+        _ => 0.,
+    };
+
+    let [src_rgb, dst_rgb, src_alpha, dst_alpha] = state.blend_func;
+    let mut color = [0.; 4];
+    for (channel, c) in color.iter_mut().enumerate() {
+        let (equation, src_factor, dst_factor) = if channel < 3 {
+            (state.blend_equation.0, src_rgb, dst_rgb)
+        } else {
+            (state.blend_equation.1, src_alpha, dst_alpha)
+        };
+        let s = src[channel] * factor(src_factor, channel);
+        let d = dst[channel] * factor(dst_factor, channel);
+        *c = match equation {
+            glow::FUNC_SUBTRACT => s - d,
+            glow::FUNC_REVERSE_SUBTRACT => d - s,
+            glow::MIN => src[channel].min(dst[channel]),
+            glow::MAX => src[channel].max(dst[channel]),
+            _ => s + d,
+        }
+        .clamp(0., 1.);
+    }
+    color
+}
+
+fn to_unorm8(value: f32) -> u8 {
+    (value.clamp(0., 1.) * 255.).round() as u8
+}
+
+fn from_unorm8(value: u8) -> f32 {
+    value as f32 / 255.
+}
+
+/// The result of comparing two images with `diff_rgba`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageDiff {
+    /// The number of pixels with a channel that differs by more than the tolerance.
+    pub differing_pixels: usize,
+    /// The largest difference seen in any channel.
+    pub max_difference: u8,
+    /// An RGBA8 image, the same size as the inputs, with the differing pixels in red and the
+    /// rest as a faded copy of `expected`.
+    pub image: Vec<u8>,
+}
+
+impl ImageDiff {
+    pub fn is_match(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+/// Compares two tightly packed RGBA8 images of the same dimensions.
+pub fn diff_rgba(expected: &[u8], actual: &[u8], tolerance: u8) -> ImageDiff {
+    assert_eq!(
+        expected.len(),
+        actual.len(),
+        "images must be the same size to be compared"
+    );
+    let mut diff = ImageDiff {
+        differing_pixels: 0,
+        max_difference: 0,
+        image: Vec::with_capacity(expected.len()),
+    };
+    for (expected, actual) in expected.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let difference = expected
+            .iter()
+            .zip(actual.iter())
+            .map(|(a, b)| (*a as i16 - *b as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap_or(0);
+        diff.max_difference = diff.max_difference.max(difference);
+        if difference > tolerance {
+            diff.differing_pixels += 1;
+            diff.image.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let faded = |c: u8| 191 + c / 4;
+            diff.image.extend_from_slice(&[
+                faded(expected[0]),
+                faded(expected[1]),
+                faded(expected[2]),
+                255,
+            ]);
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        canvas::{self, Canvas},
+        mesh::VertexMesh,
+        shader::DynamicShader,
+        vertex::{AttributeType, Vertex, VertexFormat},
+        viewport::Viewport,
+        BlendState, ClearSettings, Context, DrawMode, Geometry, PipelineSettings, PixelFormat,
+        Renderer, StencilFunction, StencilOperation, StencilOperations, StencilState,
+    };
+
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
+    struct TestVertex {
+        position: [f32; 3],
+        color: [f32; 4],
+    }
+
+    unsafe impl bytemuck::Zeroable for TestVertex {}
+    unsafe impl bytemuck::Pod for TestVertex {}
+
+    impl Vertex for TestVertex {
+        fn build_bindings() -> &'static [VertexFormat] {
+            &[
+                VertexFormat {
+                    name: "position",
+                    offset: 0,
+                    atype: AttributeType::F32F32F32,
+                    normalize: false,
+                },
+                VertexFormat {
+                    name: "color",
+                    offset: std::mem::size_of::<[f32; 3]>(),
+                    atype: AttributeType::F32F32F32F32,
+                    normalize: false,
+                },
+            ]
+        }
+    }
+
+    struct VertexColor;
+
+    impl SoftwareProgram for VertexColor {
+        fn vertex(&self, input: &VertexInput, _: &Uniforms, varyings: &mut Varyings) -> [f32; 4] {
+            varyings[..4].copy_from_slice(&input.get("color"));
+            input.get("position")
+        }
+
+        fn fragment(&self, fragment: &Fragment, _: &Uniforms) -> Option<[f32; 4]> {
+            let mut color = [0.; 4];
+            color.copy_from_slice(&fragment.varyings[..4]);
+            Some(color)
+        }
+    }
+
+    const SRC: &str = r#"
+varying vec4 vColor;
+
+#ifdef VERTEX
+attribute vec3 position;
+attribute vec4 color;
+
+void main() {
+    vColor = color;
+    gl_Position = vec4(position, 1.);
+}
+#endif
+
+#ifdef FRAGMENT
+void main() {
+    fragColor = vColor;
+}
+#endif"#;
+
+    const SIZE: i32 = 4;
+
+    fn context() -> (Context, DynamicShader) {
+        let mut backend = SoftwareBackend::new(SIZE as _, SIZE as _);
+        backend.register_program(|_, _| true, VertexColor);
+        let mut ctx = Context::with_backend(backend);
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        ctx.clear_color(0., 0., 0., 0.);
+        ctx.clear();
+        (ctx, shader)
+    }
+
+    fn quad(ctx: &mut Context, x: [f32; 2], z: f32, color: [f32; 4]) -> VertexMesh<TestVertex> {
+        let vertex = |x, y| TestVertex {
+            position: [x, y, z],
+            color,
+        };
+        let vertices = [
+            vertex(x[0], -1.),
+            vertex(x[1], -1.),
+            vertex(x[1], 1.),
+            vertex(x[0], -1.),
+            vertex(x[1], 1.),
+            vertex(x[0], 1.),
+        ];
+        VertexMesh::with_data(ctx, &vertices).unwrap()
+    }
+
+    fn draw(
+        ctx: &mut Context,
+        shader: &DynamicShader,
+        mesh: &VertexMesh<TestVertex>,
+        settings: PipelineSettings,
+    ) {
+        ctx.draw(
+            shader,
+            &Geometry {
+                mesh,
+                draw_range: 0..6,
+                draw_mode: DrawMode::Triangles,
+                instance_count: 1,
+            },
+            PipelineSettings {
+                viewport: Viewport::new(0, 0, SIZE, SIZE),
+                ..settings
+            },
+        );
+    }
+
+    fn pixels(ctx: &Context) -> Vec<u8> {
+        let mut pixels = vec![0; (SIZE * SIZE * 4) as usize];
+        ctx.read_pixels(0, 0, SIZE, SIZE, PixelFormat::RGBA8, &mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn shared_edges_are_blended_once() {
+        let (mut ctx, shader) = context();
+        let mesh = quad(&mut ctx, [-1., 1.], 0., [1., 0., 0., 0.5]);
+        draw(
+            &mut ctx,
+            &shader,
+            &mesh,
+            PipelineSettings {
+                blend_state: Some(BlendState::alpha()),
+                ..Default::default()
+            },
+        );
+
+        for pixel in pixels(&ctx).chunks(4) {
+            assert_eq!(pixel, [128, 0, 0, 128]);
+        }
+    }
+
+    #[test]
+    fn depth_and_stencil() {
+        let (mut ctx, shader) = context();
+        Renderer::clear(&mut ctx, ClearSettings::default());
+
+        let left = quad(&mut ctx, [-1., 0.], 0.5, [1., 0., 0., 1.]);
+        let mark = StencilState {
+            reference: 1,
+            ..Default::default()
+        }
+        .with_operations(StencilOperations {
+            pass: StencilOperation::Replace,
+            ..Default::default()
+        });
+        draw(
+            &mut ctx,
+            &shader,
+            &left,
+            PipelineSettings {
+                stencil_state: Some(mark),
+                ..Default::default()
+            },
+        );
+
+        let unmarked = StencilState {
+            function: StencilFunction::Equal,
+            reference: 0,
+            ..Default::default()
+        };
+        let near = quad(&mut ctx, [-1., 1.], 0., [0., 1., 0., 1.]);
+        draw(
+            &mut ctx,
+            &shader,
+            &near,
+            PipelineSettings {
+                stencil_state: Some(unmarked),
+                ..Default::default()
+            },
+        );
+
+        let far = quad(&mut ctx, [-1., 1.], 0.9, [0., 0., 1., 1.]);
+        draw(&mut ctx, &shader, &far, PipelineSettings::default());
+
+        let pixels = pixels(&ctx);
+        for (i, pixel) in pixels.chunks(4).enumerate() {
+            let expected = if (i as i32 % SIZE) < SIZE / 2 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 255, 0, 255]
+            };
+            assert_eq!(pixel, expected, "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn canvas_readback() {
+        let (mut ctx, shader) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: SIZE as _,
+                height: SIZE as _,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mesh = quad(&mut ctx, [-1., 0.], 0., [0., 0., 1., 1.]);
+        draw(
+            &mut ctx,
+            &shader,
+            &mesh,
+            PipelineSettings {
+                framebuffer: Some(&canvas),
+                ..Default::default()
+            },
+        );
+
+        let pixels = ctx.read_texture(&canvas, 0).unwrap();
+        assert_eq!(pixels.len(), (SIZE * SIZE * 4) as usize);
+        for (i, pixel) in pixels.chunks(4).enumerate() {
+            let expected = if (i as i32 % SIZE) < SIZE / 2 {
+                [0, 0, 255, 255]
+            } else {
+                [0, 0, 0, 0]
+            };
+            assert_eq!(pixel, expected, "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let a = [0, 0, 0, 255, 10, 10, 10, 255];
+        let b = [0, 0, 0, 255, 12, 10, 10, 255];
+        assert!(diff_rgba(&a, &b, 2).is_match());
+
+        let diff = diff_rgba(&a, &b, 1);
+        assert_eq!(diff.differing_pixels, 1);
+        assert_eq!(diff.max_difference, 2);
+        assert_eq!(&diff.image[4..], [255, 0, 0, 255]);
+    }
+}
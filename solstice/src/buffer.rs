@@ -1,10 +1,11 @@
-use super::BufferKey;
+use super::{resource::ResourceKey, BufferKey};
 
 /// Used to inform the implementation of how it should be bound.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum BufferType {
     Vertex,
     Index,
+    Uniform,
 }
 
 impl From<BufferType> for u32 {
@@ -12,6 +13,7 @@ impl From<BufferType> for u32 {
         match ty {
             BufferType::Vertex => glow::ARRAY_BUFFER,
             BufferType::Index => glow::ELEMENT_ARRAY_BUFFER,
+            BufferType::Uniform => glow::UNIFORM_BUFFER,
         }
     }
 }
@@ -37,19 +39,63 @@ impl Usage {
     }
 }
 
+/// How a growable buffer picks its new size when a write doesn't fit.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum GrowthPolicy {
+    /// Never grow. Writing past the end panics.
+    #[default]
+    Fixed,
+    /// Grow to exactly the size that's needed.
+    Exact,
+    /// Double the size until the write fits.
+    Double,
+    /// Grow in multiples of this many units.
+    Chunked(usize),
+}
+
+impl GrowthPolicy {
+    /// The size to grow to from `size` so that at least `required` fits. `None` if no growth is
+    /// needed or allowed.
+    pub fn grow(self, size: usize, required: usize) -> Option<usize> {
+        if required <= size {
+            return None;
+        }
+        match self {
+            GrowthPolicy::Fixed => None,
+            GrowthPolicy::Exact => Some(required),
+            GrowthPolicy::Double => {
+                let mut grown = size.max(1);
+                while grown < required {
+                    grown *= 2;
+                }
+                Some(grown)
+            }
+            GrowthPolicy::Chunked(chunk) => {
+                let chunk = chunk.max(1);
+                Some(required.div_ceil(chunk) * chunk)
+            }
+        }
+    }
+}
+
 /// A memory map between a CPU and GPU buffer.
 ///
 /// This implementation, while safe, only operates on bytes to better mirror GPU buffers. It is best
 /// used through a [`Mesh`](solstice::mesh::Mesh) to provide information on how the data is laid out
 /// internally and allow the use of more types and structures.
 ///
-/// This buffer is not resizable. All operations are sized in bytes.
+/// All operations are sized in bytes. Resizing a buffer reallocates it with `resize`, or grows a
+/// `MappedBuffer` with a `GrowthPolicy`.
+///
+/// Clones share the GPU buffer, which is deleted once all of them have been dropped, along with
+/// its size so a resize through one of them is seen by all of them.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Buffer {
-    size: usize,
+    size: std::rc::Rc<std::cell::Cell<usize>>,
     handle: BufferKey,
     buffer_type: BufferType,
     usage: Usage,
+    owner: super::resource::Owner,
 }
 
 impl Buffer {
@@ -62,10 +108,11 @@ impl Buffer {
     ) -> Result<Self, super::GraphicsError> {
         let handle = ctx.new_buffer(size, buffer_type, usage, None)?;
         Ok(Self {
-            size,
+            size: std::rc::Rc::new(std::cell::Cell::new(size)),
             handle,
             buffer_type,
             usage,
+            owner: ctx.new_owner(vec![ResourceKey::Buffer(handle)]),
         })
     }
 
@@ -79,10 +126,11 @@ impl Buffer {
         let size = data.len();
         let handle = ctx.new_buffer(size, buffer_type, usage, Some(data))?;
         Ok(Self {
-            size,
+            size: std::rc::Rc::new(std::cell::Cell::new(size)),
             handle,
             buffer_type,
             usage,
+            owner: ctx.new_owner(vec![ResourceKey::Buffer(handle)]),
         })
     }
 
@@ -92,9 +140,49 @@ impl Buffer {
         self.handle
     }
 
-    /// The buffer's capacity/size. Since it's not resizable these concepts are the same.
+    pub fn owner(&self) -> &super::resource::Owner {
+        &self.owner
+    }
+
+    /// The buffer's capacity/size. These concepts are the same since the whole buffer is
+    /// allocated up front.
     pub fn size(&self) -> usize {
-        self.size
+        self.size.get()
+    }
+
+    /// Reallocates the buffer at `size` bytes, keeping as much of the contents as fits. The
+    /// contents are kept in a scratch buffer on the GPU while the storage is reallocated, so the
+    /// handle stays the same and clones keep drawing from this buffer.
+    ///
+    /// The copies need `Context::supports_buffer_copies`. Without it there's nowhere to keep
+    /// the contents and this fails with `GraphicsError::BufferError` unless the buffer is
+    /// empty. `MappedBuffer::resize` works everywhere since it re-uploads from its memory map.
+    pub fn resize(
+        &mut self,
+        ctx: &mut super::Context,
+        size: usize,
+    ) -> Result<(), super::GraphicsError> {
+        let kept = self.size().min(size);
+        if kept > 0 && !ctx.supports_buffer_copies() {
+            return Err(super::GraphicsError::BufferError);
+        }
+        if kept == 0 {
+            ctx.orphan_buffer(self, size);
+        } else {
+            let scratch = Buffer::new(ctx, kept, self.buffer_type, Usage::Stream)?;
+            ctx.copy_buffer(self, &scratch, 0, 0, kept);
+            ctx.orphan_buffer(self, size);
+            ctx.copy_buffer(&scratch, self, 0, 0, kept);
+            ctx.destroy_buffer(&scratch);
+        }
+        self.size.set(size);
+        Ok(())
+    }
+
+    /// Replaces the contents of the buffer with `data`, resizing it to fit.
+    pub fn set_data(&mut self, ctx: &mut super::Context, data: &[u8]) {
+        ctx.buffer_data(self, data);
+        self.size.set(data.len());
     }
 
     /// The buffer's type.
@@ -108,6 +196,102 @@ impl Buffer {
     }
 }
 
+/// A ring buffer for data that's written once and drawn once, like per-frame geometry.
+///
+/// Every write is placed after the previous one so nothing that an earlier draw might still be
+/// reading from gets overwritten. When a write doesn't fit in the space that's left the buffer is
+/// orphaned and writing starts over from the front of the fresh storage. Writes larger than the
+/// whole buffer double its size first.
+///
+/// Meshes can draw from the buffer with [`VertexMesh::with_buffer`](crate::mesh::VertexMesh) or
+/// [`IndexedMesh::with_buffers`](crate::mesh::IndexedMesh) and a draw range starting at the
+/// offset that `write` returns. Since orphaning keeps the handle, those meshes stay valid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamBuffer {
+    inner: Buffer,
+    head: usize,
+}
+
+impl StreamBuffer {
+    /// Constructs a stream buffer of `size` bytes.
+    pub fn new(
+        ctx: &mut super::Context,
+        size: usize,
+        buffer_type: BufferType,
+    ) -> Result<Self, super::GraphicsError> {
+        let inner = Buffer::new(ctx, size, buffer_type, Usage::Stream)?;
+        Ok(Self { inner, head: 0 })
+    }
+
+    pub fn inner(&self) -> &Buffer {
+        &self.inner
+    }
+
+    /// The number of bytes written since the buffer was last orphaned.
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// Uploads `data` and returns where it starts in the buffer, counted in `T`s. The data is
+    /// aligned to the size of `T` so the offset can be used as the start of a draw range.
+    pub fn write<T: bytemuck::Pod>(&mut self, ctx: &mut super::Context, data: &[T]) -> usize {
+        let stride = std::mem::size_of::<T>().max(1);
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let mut offset = self.head.div_ceil(stride) * stride;
+        if offset + bytes.len() > self.inner.size() {
+            let size = GrowthPolicy::Double
+                .grow(self.inner.size(), bytes.len())
+                .unwrap_or(self.inner.size());
+            self.orphan(ctx, size);
+            offset = 0;
+        }
+        ctx.bind_buffer(self.inner.handle, self.inner.buffer_type);
+        ctx.buffer_static_draw(&self.inner, bytes, offset);
+        self.head = offset + bytes.len();
+        offset / stride
+    }
+
+    /// Swaps in fresh storage of `size` bytes and starts writing from the front again.
+    pub fn orphan(&mut self, ctx: &mut super::Context, size: usize) {
+        ctx.orphan_buffer(&self.inner, size);
+        self.inner.size.set(size);
+        self.head = 0;
+    }
+}
+
+/// A buffer holding the data of a `T` laid out for a `layout(std140)` uniform block. Bind it
+/// to the binding point a block is sourced from and the data is shared by every shader with
+/// that block.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UniformBuffer<T> {
+    inner: Buffer,
+    type_marker: std::marker::PhantomData<T>,
+}
+
+impl<T: super::shader::Std140> UniformBuffer<T> {
+    pub fn new(ctx: &mut super::Context, data: &T) -> Result<Self, super::GraphicsError> {
+        let inner = Buffer::with_data(ctx, &data.to_std140(), BufferType::Uniform, Usage::Dynamic)?;
+        Ok(Self {
+            inner,
+            type_marker: std::marker::PhantomData,
+        })
+    }
+
+    pub fn inner(&self) -> &Buffer {
+        &self.inner
+    }
+
+    /// Uploads new data. The old storage is orphaned so draws that are still using it don't
+    /// have to finish first.
+    pub fn set(&mut self, ctx: &mut super::Context, data: &T) {
+        ctx.buffer_data(&self.inner, &data.to_std140());
+    }
+
+    pub fn bind(&self, ctx: &mut super::Context, binding: u32) {
+        ctx.bind_uniform_buffer(&self.inner, binding)
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct ModifiedRange<D> {
     pub offset: D,
@@ -119,6 +303,7 @@ pub struct Mapped<T, D: ndarray::Dimension> {
     pub(crate) inner: T,
     pub(crate) memory_map: ndarray::Array<u8, D>,
     pub(crate) modified_range: Option<ModifiedRange<D>>,
+    pub(crate) growth_policy: GrowthPolicy,
 }
 
 impl<T, D> Mapped<T, D>
@@ -133,6 +318,7 @@ where
             inner,
             memory_map: ndarray::Array::default(shape),
             modified_range: None,
+            growth_policy: GrowthPolicy::Fixed,
         }
     }
 
@@ -151,18 +337,36 @@ impl<T> Mapped<T, ndarray::Ix1> {
             inner,
             memory_map: vec.into(),
             modified_range: None,
+            growth_policy: GrowthPolicy::Fixed,
         }
     }
 
     /// Write new data into the buffer and adjust it's dirty range accordingly.
     ///
-    /// This function will panic if the buffer overflows.
+    /// Writes that overflow the buffer grow it according to its `GrowthPolicy`. This function
+    /// will panic if the buffer overflows and can't grow.
     pub fn write(&mut self, data: &[u8], offset: usize) {
+        let end = offset + data.len();
+        if let Some(size) = self.growth_policy.grow(self.memory_map.len(), end) {
+            let mut memory = std::mem::take(&mut self.memory_map).into_raw_vec();
+            memory.resize(size, 0);
+            self.memory_map = memory.into();
+        }
         self.memory_map.as_slice_memory_order_mut().unwrap()[offset..(offset + data.len())]
             .copy_from_slice(data);
         self.set_modified_range(offset, data.len());
     }
 
+    /// Sets how the memory map grows when a write doesn't fit. The GPU buffer catches up when
+    /// the buffer is unmapped.
+    pub fn set_growth_policy(&mut self, growth_policy: GrowthPolicy) {
+        self.growth_policy = growth_policy;
+    }
+
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy
+    }
+
     pub fn modified_range(&self) -> Option<ModifiedRange<usize>> {
         self.modified_range.map(|range| ModifiedRange {
             offset: range.offset[0],
@@ -202,11 +406,29 @@ impl MappedBuffer {
             inner,
             memory_map,
             modified_range: None,
+            growth_policy: GrowthPolicy::Fixed,
         })
     }
 
     pub fn unmap(&mut self, ctx: &mut super::Context) {
-        ctx.unmap_buffer(self);
+        if self.memory_map.len() != self.inner.size() {
+            // the memory map has grown so the GPU buffer is reallocated to match
+            let data = self.memory_map.as_slice_memory_order().unwrap();
+            self.inner.set_data(ctx, data);
+        } else {
+            ctx.unmap_buffer(self);
+        }
+        self.modified_range = None;
+    }
+
+    /// Resizes the memory map to `size` bytes, zero filling any new space, and uploads all of
+    /// it to a reallocated GPU buffer. Unlike `Buffer::resize` this doesn't need buffer copies.
+    pub fn resize(&mut self, ctx: &mut super::Context, size: usize) {
+        let mut memory = std::mem::take(&mut self.memory_map).into_raw_vec();
+        memory.resize(size, 0);
+        self.memory_map = memory.into();
+        let data = self.memory_map.as_slice_memory_order().unwrap();
+        self.inner.set_data(ctx, data);
         self.modified_range = None;
     }
 }
@@ -250,3 +472,246 @@ impl MappedBuffer {
 //         self.memory_map.get(index)
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::recording::testing::*;
+    use crate::{shader::DynamicShader, Renderer};
+
+    #[test]
+    fn growth_policies() {
+        use crate::buffer::GrowthPolicy;
+        assert_eq!(GrowthPolicy::Fixed.grow(4, 5), None);
+        assert_eq!(GrowthPolicy::Exact.grow(4, 5), Some(5));
+        assert_eq!(GrowthPolicy::Double.grow(4, 9), Some(16));
+        assert_eq!(GrowthPolicy::Double.grow(0, 3), Some(4));
+        assert_eq!(GrowthPolicy::Chunked(3).grow(4, 5), Some(6));
+        assert_eq!(GrowthPolicy::Double.grow(4, 4), None);
+
+        // a chunk size of zero behaves like exact growth
+        assert_eq!(GrowthPolicy::Chunked(0).grow(4, 5), Some(5));
+        assert_eq!(GrowthPolicy::Chunked(0).grow(5, 5), None);
+
+        // growing from nothing
+        for policy in [
+            GrowthPolicy::Exact,
+            GrowthPolicy::Double,
+            GrowthPolicy::Chunked(0),
+            GrowthPolicy::Chunked(1),
+        ]
+        .iter()
+        {
+            assert_eq!(policy.grow(0, 0), None);
+            assert_eq!(policy.grow(0, 1), Some(1));
+        }
+        assert_eq!(GrowthPolicy::Fixed.grow(0, 1), None);
+        assert_eq!(GrowthPolicy::Double.grow(0, 5), Some(8));
+        assert_eq!(GrowthPolicy::Chunked(4).grow(0, 1), Some(4));
+    }
+
+    #[test]
+    fn growing_mapped_buffer() {
+        use crate::buffer::{BufferType, GrowthPolicy, MappedBuffer, Usage};
+        let (mut ctx, backend) = context();
+        let mut buffer =
+            MappedBuffer::with_buffer(&mut ctx, 4, BufferType::Vertex, Usage::Dynamic).unwrap();
+        buffer.set_growth_policy(GrowthPolicy::Double);
+        let handle = buffer.inner().handle();
+
+        buffer.write(&[1, 2, 3, 4, 5, 6], 2);
+        assert_eq!(buffer.memory_map(), &[0, 0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(backend.recording().buffers[handle].data.len(), 4);
+
+        // a sub-data upload would be clipped to the old size so the whole map is reallocated
+        buffer.unmap(&mut ctx);
+        assert_eq!(buffer.inner().handle(), handle);
+        assert_eq!(buffer.inner().size(), 8);
+        assert_eq!(buffer.modified_range(), None);
+        {
+            let recording = backend.recording();
+            let record = &recording.buffers[handle];
+            assert_eq!(record.data, buffer.memory_map());
+            assert_eq!(record.usage, glow::DYNAMIC_DRAW);
+        }
+
+        // writes that fit are uploaded in place
+        buffer.write(&[9], 7);
+        buffer.unmap(&mut ctx);
+        assert_eq!(
+            backend.recording().buffers[handle].data,
+            [0, 0, 1, 2, 3, 4, 5, 9]
+        );
+    }
+
+    #[test]
+    fn uniform_buffers() {
+        use crate::{
+            buffer::UniformBuffer,
+            shader::{Shader, Std140, UniformBlockMember},
+        };
+
+        struct Camera {
+            view: mint::ColumnMatrix4<f32>,
+            scale: f32,
+            offset: mint::Vector3<f32>,
+        }
+
+        impl Std140 for Camera {
+            const ALIGNMENT: usize = 16;
+            const SIZE: usize = 96;
+
+            fn write_std140(&self, out: &mut [u8]) {
+                self.view.write_std140(out);
+                self.scale.write_std140(&mut out[64..]);
+                self.offset.write_std140(&mut out[80..]);
+            }
+        }
+
+        const BLOCK: &str = r#"
+layout(std140) uniform Camera {
+    mat4 view;
+    float scale;
+    highp vec3 offset;
+} camera;
+uniform vec4 color;
+
+#ifdef VERTEX
+attribute vec2 position;
+
+void main() {
+    gl_Position = camera.view * vec4(position * camera.scale + camera.offset.xy, 0., 1.);
+}
+#endif
+
+#ifdef FRAGMENT
+void main() {
+    fragColor = color;
+}
+#endif"#;
+
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(BLOCK, BLOCK);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        assert_eq!(shader.uniforms().len(), 1);
+        assert_eq!(shader.uniform_blocks().len(), 1);
+        let block = shader.get_uniform_block_by_name("Camera").unwrap();
+        assert_eq!(block.index, 0);
+        assert_eq!(block.size, 96);
+        let member = |name: &str, utype, offset| UniformBlockMember {
+            name: name.to_owned(),
+            size: 1,
+            utype,
+            offset,
+        };
+        assert_eq!(
+            block.members,
+            vec![
+                member("view", glow::FLOAT_MAT4, 0),
+                member("scale", glow::FLOAT, 64),
+                member("offset", glow::FLOAT_VEC3, 80),
+            ]
+        );
+
+        let camera = Camera {
+            view: [1.; 16].into(),
+            scale: 2.,
+            offset: [3.; 3].into(),
+        };
+        let mut buffer = UniformBuffer::new(&mut ctx, &camera).unwrap();
+        assert!(shader.set_uniform_block_binding(&mut ctx, "Camera", 3));
+        assert!(!shader.set_uniform_block_binding(&mut ctx, "Lights", 3));
+        buffer.bind(&mut ctx, 3);
+        buffer.set(
+            &mut ctx,
+            &Camera {
+                scale: 4.,
+                ..camera
+            },
+        );
+
+        let recording = backend.recording();
+        let program = &recording.programs[shader.handle()];
+        assert_eq!(program.uniform_block_bindings[&0], 3);
+        let handle = buffer.inner().handle();
+        assert_eq!(
+            recording.state.indexed_buffers[&(glow::UNIFORM_BUFFER, 3)],
+            handle
+        );
+        let data = &recording.buffers[handle].data;
+        assert_eq!(data.len(), 96);
+        assert_eq!(&data[64..68], &4f32.to_ne_bytes());
+        assert_eq!(&data[88..92], &3f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn stream_buffer() {
+        use crate::{
+            buffer::{BufferType, StreamBuffer},
+            mesh::{IndexedMesh, VertexMesh},
+        };
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let vertex = |x: f32| TestVertex { position: [x, x] };
+        let size = std::mem::size_of::<TestVertex>();
+
+        let mut vertices = StreamBuffer::new(&mut ctx, 4 * size, BufferType::Vertex).unwrap();
+        let mut indices = StreamBuffer::new(&mut ctx, 8, BufferType::Index).unwrap();
+        let handle = vertices.inner().handle();
+        assert_eq!(vertices.write(&mut ctx, &[vertex(0.), vertex(1.)]), 0);
+        assert_eq!(vertices.write(&mut ctx, &[vertex(2.)]), 2);
+        assert_eq!(indices.write(&mut ctx, &[1u8]), 0);
+        assert_eq!(indices.write(&mut ctx, &[2u16, 1, 0]), 1);
+        {
+            let recording = backend.recording();
+            let data = &recording.buffers[handle].data;
+            assert_eq!(
+                &data[size * 2..size * 3],
+                bytemuck::cast_slice(&[vertex(2.)])
+            );
+        }
+
+        let mesh = IndexedMesh::<TestVertex, u16>::with_buffers(
+            vertices.inner().clone(),
+            indices.inner().clone(),
+        );
+        ctx.draw(
+            &shader,
+            &crate::Geometry {
+                mesh: &mesh,
+                draw_range: 1..4,
+                draw_mode: crate::DrawMode::Triangles,
+                instance_count: 1,
+            },
+            Default::default(),
+        );
+        assert_eq!(backend.recording().draws().last().unwrap().first, 2);
+
+        // doesn't fit in what's left so the buffer is orphaned and writing starts over
+        assert_eq!(vertices.write(&mut ctx, &[vertex(3.), vertex(4.)]), 0);
+        assert_eq!(vertices.head(), 2 * size);
+        assert_eq!(vertices.inner().handle(), handle);
+        assert_eq!(vertices.inner().size(), 4 * size);
+
+        // bigger than the whole buffer
+        let many = (0..6).map(|i| vertex(i as f32)).collect::<Vec<_>>();
+        assert_eq!(vertices.write(&mut ctx, &many), 0);
+        assert_eq!(vertices.inner().size(), 8 * size);
+        let mesh = VertexMesh::<TestVertex>::with_buffer(vertices.inner().clone());
+        ctx.draw(
+            &shader,
+            &crate::Geometry {
+                mesh: &mesh,
+                draw_range: 0..6,
+                draw_mode: crate::DrawMode::Triangles,
+                instance_count: 1,
+            },
+            Default::default(),
+        );
+        let recording = backend.recording();
+        let data = &recording.buffers[handle].data;
+        assert_eq!(data.len(), 8 * size);
+        assert_eq!(&data[..6 * size], bytemuck::cast_slice(&many));
+        assert_eq!(recording.draws().last().unwrap().count, 6);
+    }
+}
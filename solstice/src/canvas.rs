@@ -1,12 +1,16 @@
 use super::{
-    texture::{Filter, Texture, TextureInfo, TextureType, TextureUpdate, Wrap},
-    Context, PixelFormat,
+    resource::ResourceKey,
+    texture::{Filter, FilterMode, Texture, TextureInfo, TextureType, TextureUpdate, Wrap},
+    Context, GraphicsError, PixelFormat,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MipmapMode {
     None,
-    Manual, // todo: no functional difference between manual and auto right now
+    /// Mipmaps are only regenerated by `Canvas::generate_mipmaps`.
+    Manual,
+    /// Mipmaps are regenerated whenever a color attachment is bound after the canvas has been
+    /// rendered to.
     Auto,
 }
 
@@ -17,6 +21,9 @@ pub struct Settings {
     pub layers: usize,
     pub mipmap_mode: MipmapMode,
     pub format: PixelFormat,
+    /// The formats of any color attachments beyond the first, which uses `format`. Fragment
+    /// shader output `n` is written to attachment `n`.
+    pub extra_attachments: Vec<PixelFormat>,
     pub texture_type: TextureType,
     pub dpi_scale: f32,
     pub msaa: usize,
@@ -24,6 +31,15 @@ pub struct Settings {
     pub wrap: Wrap,
     pub filter: Filter,
     pub with_depth: bool,
+    /// Adds a stencil buffer. It shares a packed depth-stencil renderbuffer with the depth
+    /// buffer so this implies `with_depth`.
+    pub with_stencil: bool,
+    /// The format of the depth and/or stencil buffer, which takes precedence over `with_depth`
+    /// and `with_stencil`. Must be one of the depth or stencil `PixelFormat`s.
+    pub depth_format: Option<PixelFormat>,
+    /// Backs the depth and/or stencil buffer with a texture so that it can be sampled. See
+    /// `Canvas::depth_attachment`.
+    pub depth_texture: bool,
 }
 
 impl Default for Settings {
@@ -34,6 +50,7 @@ impl Default for Settings {
             layers: 1,
             mipmap_mode: MipmapMode::None,
             format: PixelFormat::RGBA8,
+            extra_attachments: vec![],
             texture_type: TextureType::Tex2D,
             dpi_scale: 1.0,
             msaa: 0,
@@ -41,21 +58,142 @@ impl Default for Settings {
             wrap: Default::default(),
             filter: Default::default(),
             with_depth: false,
+            with_stencil: false,
+            depth_format: None,
+            depth_texture: false,
         }
     }
 }
 
+/// Clones share the framebuffers and their attachments, which are deleted once all of them have
+/// been dropped.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Canvas {
     framebuffer_key: super::FramebufferKey,
     texture_key: super::TextureKey,
     renderbuffer_key: Option<super::RenderbufferKey>,
+    multisample: Option<Multisample>,
+    extra_attachments: Vec<AttachmentTexture>,
+    depth_attachment: Option<AttachmentTexture>,
     texture_info: TextureInfo,
     texture_type: TextureType,
+    owner: super::resource::Owner,
+}
+
+/// One of the textures a canvas renders into. Can be bound like any other `Texture`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct AttachmentTexture {
+    texture_key: super::TextureKey,
+    texture_info: TextureInfo,
+    texture_type: TextureType,
+}
+
+// Rendering goes to multisampled renderbuffers which are resolved into the canvas texture.
+#[derive(Debug, PartialEq, Clone)]
+struct Multisample {
+    samples: usize,
+    framebuffer_key: super::FramebufferKey,
+    renderbuffer_keys: Vec<super::RenderbufferKey>,
+}
+
+// Everything a canvas has allocated so far so that a failed `Canvas::new` doesn't leak.
+#[derive(Default)]
+struct Allocations {
+    textures: Vec<super::TextureKey>,
+    framebuffers: Vec<super::FramebufferKey>,
+    renderbuffers: Vec<super::RenderbufferKey>,
+}
+
+impl Allocations {
+    fn texture(
+        &mut self,
+        ctx: &mut Context,
+        texture_info: TextureInfo,
+        texture_type: TextureType,
+    ) -> Result<AttachmentTexture, GraphicsError> {
+        let texture_key = ctx.new_texture(texture_type)?;
+        self.textures.push(texture_key);
+        ctx.bind_texture_to_unit(texture_type, texture_key, 0.into());
+        ctx.set_texture_wrap(texture_key, texture_type, texture_info.wrap());
+        ctx.set_texture_filter(texture_key, texture_type, texture_info.filter());
+        // set format
+        ctx.set_texture_data(texture_key, texture_info, texture_type, None);
+        Ok(AttachmentTexture {
+            texture_key,
+            texture_info,
+            texture_type,
+        })
+    }
+
+    fn framebuffer(&mut self, ctx: &mut Context) -> Result<super::FramebufferKey, GraphicsError> {
+        let framebuffer_key = ctx.new_framebuffer()?;
+        self.framebuffers.push(framebuffer_key);
+        Ok(framebuffer_key)
+    }
+
+    fn renderbuffer(&mut self, ctx: &mut Context) -> Result<super::RenderbufferKey, GraphicsError> {
+        let renderbuffer_key = ctx.new_renderbuffer()?;
+        self.renderbuffers.push(renderbuffer_key);
+        ctx.bind_renderbuffer(Some(renderbuffer_key));
+        Ok(renderbuffer_key)
+    }
+
+    fn resources(&self) -> Vec<ResourceKey> {
+        let textures = self.textures.iter().copied().map(ResourceKey::Texture);
+        let framebuffers = self
+            .framebuffers
+            .iter()
+            .copied()
+            .map(ResourceKey::Framebuffer);
+        let renderbuffers = self
+            .renderbuffers
+            .iter()
+            .copied()
+            .map(ResourceKey::Renderbuffer);
+        framebuffers.chain(renderbuffers).chain(textures).collect()
+    }
+
+    fn release(self, ctx: &mut Context) {
+        for framebuffer_key in self.framebuffers {
+            ctx.destroy_framebuffer(framebuffer_key);
+        }
+        for renderbuffer_key in self.renderbuffers {
+            ctx.destroy_renderbuffer(renderbuffer_key);
+        }
+        for texture_key in self.textures {
+            ctx.destroy_texture(texture_key);
+        }
+    }
+}
+
+fn check_status(ctx: &Context, target: Target) -> Result<(), GraphicsError> {
+    match ctx.check_framebuffer_status(target) {
+        Status::Complete => Ok(()),
+        status => Err(GraphicsError::IncompleteFramebuffer(status)),
+    }
 }
 
 impl Canvas {
-    pub fn new(ctx: &mut Context, settings: Settings) -> Result<Self, super::GraphicsError> {
+    /// Fails without leaking anything when the texture type or one of the formats isn't
+    /// supported, when the driver rejects the combination of attachments or when it runs out of
+    /// memory.
+    pub fn new(ctx: &mut Context, settings: Settings) -> Result<Self, GraphicsError> {
+        let target = Target::All;
+        let current_framebuffer = ctx.get_active_framebuffer(target);
+        let mut allocations = Allocations::default();
+        let canvas = Self::allocate(ctx, &settings, &mut allocations);
+        if canvas.is_err() {
+            allocations.release(ctx);
+        }
+        ctx.bind_framebuffer(target, current_framebuffer);
+        canvas
+    }
+
+    fn allocate(
+        ctx: &mut Context,
+        settings: &Settings,
+        allocations: &mut Allocations,
+    ) -> Result<Self, GraphicsError> {
         let texture = TextureInfo::new(
             settings.format,
             (settings.width as f32 * settings.dpi_scale + 0.5) as u32,
@@ -64,70 +202,274 @@ impl Canvas {
             settings.wrap,
             settings.mipmap_mode != MipmapMode::None,
         );
-        let (framebuffer_key, texture_key, renderbuffer_key) = {
-            let texture_key = ctx.new_texture(settings.texture_type)?;
-            ctx.bind_texture_to_unit(settings.texture_type, texture_key, 0.into());
-            ctx.set_texture_wrap(texture_key, settings.texture_type, texture.wrap());
-            ctx.set_texture_filter(texture_key, settings.texture_type, texture.filter());
-            // set format
-            ctx.set_texture_data(texture_key, texture, settings.texture_type, None);
-
-            let target = Target::All;
-            let current_framebuffer = ctx.get_active_framebuffer(target);
-
-            let framebuffer_key = {
-                let framebuffer_key = ctx.new_framebuffer()?;
-                ctx.bind_framebuffer(target, Some(framebuffer_key));
-
-                ctx.framebuffer_texture(
-                    target,
-                    Attachment::Color,
-                    settings.texture_type,
-                    texture_key,
-                    0,
+        let samples = settings.msaa.min(ctx.max_samples());
+        let depth_format = settings.depth_format.or(if settings.with_stencil {
+            Some(PixelFormat::Depth24Stencil8)
+        } else if settings.with_depth {
+            Some(PixelFormat::Depth16)
+        } else {
+            None
+        });
+        let formats = std::iter::once(settings.format)
+            .chain(settings.extra_attachments.iter().copied())
+            .collect::<Vec<_>>();
+        let draw_buffers = (0..formats.len() as u32)
+            .map(Attachment::ColorIndex)
+            .collect::<Vec<_>>();
+
+        if !ctx.supports_texture_type(settings.texture_type) {
+            return Err(GraphicsError::UnsupportedTextureType(settings.texture_type));
+        }
+        for format in formats.iter().chain(depth_format.iter()) {
+            // compressed formats can't be rendered to
+            if !ctx.supports_pixel_format(*format) || format.is_compressed() {
+                return Err(GraphicsError::UnsupportedPixelFormat(*format));
+            }
+        }
+        let depth_attachment = match depth_format {
+            Some(format) => match (format.is_depth(), format.is_stencil()) {
+                (true, true) => Some((format, Attachment::DepthStencil)),
+                (true, false) => Some((format, Attachment::Depth)),
+                (false, true) => Some((format, Attachment::Stencil)),
+                (false, false) => return Err(GraphicsError::UnsupportedPixelFormat(format)),
+            },
+            None => None,
+        };
+
+        let mut attachments = Vec::with_capacity(formats.len());
+        for format in formats.iter() {
+            let texture_info = TextureInfo::new(
+                *format,
+                texture.width(),
+                texture.height(),
+                texture.filter(),
+                texture.wrap(),
+                texture.mipmaps(),
+            );
+            attachments.push(allocations.texture(ctx, texture_info, settings.texture_type)?);
+        }
+        let texture_keys = attachments
+            .iter()
+            .map(|attachment| attachment.texture_key)
+            .collect::<Vec<_>>();
+        let texture_key = texture_keys[0];
+
+        // depth values can't be filtered without a comparison mode
+        let depth_texture = match depth_attachment {
+            Some((format, _)) if settings.depth_texture => {
+                let texture_info = TextureInfo::new(
+                    format,
+                    texture.width(),
+                    texture.height(),
+                    Filter::new(
+                        FilterMode::Nearest,
+                        FilterMode::Nearest,
+                        FilterMode::None,
+                        0.,
+                    ),
+                    texture.wrap(),
+                    false,
                 );
-                ctx.clear_color(0., 0., 0., 0.);
-                ctx.clear();
-
-                match ctx.check_framebuffer_status(target) {
-                    Status::Complete => (),
-                    status => {
-                        ctx.destroy_framebuffer(framebuffer_key);
-                        panic!("Failed to create framebuffer: {:?}", status);
-                    }
-                }
+                Some(allocations.texture(ctx, texture_info, settings.texture_type)?)
+            }
+            _ => None,
+        };
+
+        let target = Target::All;
+        let framebuffer_key = allocations.framebuffer(ctx)?;
+        ctx.bind_framebuffer(target, Some(framebuffer_key));
+        for (attachment, texture_key) in draw_buffers.iter().zip(texture_keys.iter()) {
+            ctx.framebuffer_texture(target, *attachment, settings.texture_type, *texture_key, 0);
+        }
+        if let (Some(depth_texture), Some((_, attachment))) = (depth_texture, depth_attachment) {
+            ctx.framebuffer_texture(
+                target,
+                attachment,
+                settings.texture_type,
+                depth_texture.texture_key,
+                0,
+            );
+        }
+        if draw_buffers.len() > 1 {
+            ctx.set_draw_buffers(&draw_buffers);
+        }
 
-                framebuffer_key
-            };
+        let multisample = if samples > 1 {
+            let multisample_key = allocations.framebuffer(ctx)?;
+            ctx.bind_framebuffer(target, Some(multisample_key));
 
-            let renderbuffer_key = if settings.with_depth {
-                let depth_buffer_key = ctx.new_renderbuffer()?;
-                ctx.bind_renderbuffer(Some(depth_buffer_key));
-                ctx.renderbuffer_storage(
-                    PixelFormat::Depth16,
+            let mut color_keys = Vec::with_capacity(formats.len());
+            for (attachment, format) in draw_buffers.iter().zip(formats.iter()) {
+                let color_key = allocations.renderbuffer(ctx)?;
+                ctx.renderbuffer_storage_multisample(
+                    *format,
+                    samples,
                     texture.width() as _,
                     texture.height() as _,
                 );
-                ctx.framebuffer_renderbuffer(Attachment::Depth, Some(depth_buffer_key));
-                Some(depth_buffer_key)
-            } else {
-                None
-            };
-
-            ctx.bind_framebuffer(target, current_framebuffer);
+                ctx.framebuffer_renderbuffer(*attachment, Some(color_key));
+                color_keys.push(color_key);
+            }
+            if draw_buffers.len() > 1 {
+                ctx.set_draw_buffers(&draw_buffers);
+            }
+            Some(Multisample {
+                samples,
+                framebuffer_key: multisample_key,
+                renderbuffer_keys: color_keys,
+            })
+        } else {
+            None
+        };
 
-            (framebuffer_key, texture_key, renderbuffer_key)
+        // depth and stencil are only needed on whichever framebuffer is rendered to so a
+        // multisampled canvas renders into a renderbuffer that's resolved into the texture
+        let depth_renderbuffer = match depth_attachment {
+            Some(_) if depth_texture.is_some() && multisample.is_none() => None,
+            depth_attachment => depth_attachment,
+        };
+        let renderbuffer_key = if let Some((format, attachment)) = depth_renderbuffer {
+            let depth_buffer_key = allocations.renderbuffer(ctx)?;
+            let (width, height) = (texture.width() as _, texture.height() as _);
+            match &multisample {
+                Some(multisample) => {
+                    ctx.renderbuffer_storage_multisample(
+                        format,
+                        multisample.samples,
+                        width,
+                        height,
+                    );
+                }
+                None => ctx.renderbuffer_storage(format, width, height),
+            }
+            ctx.framebuffer_renderbuffer(attachment, Some(depth_buffer_key));
+            Some(depth_buffer_key)
+        } else {
+            None
         };
+
+        let framebuffers = std::iter::once(framebuffer_key).chain(
+            multisample
+                .iter()
+                .map(|multisample| multisample.framebuffer_key),
+        );
+        for framebuffer_key in framebuffers {
+            ctx.bind_framebuffer(target, Some(framebuffer_key));
+            check_status(ctx, target)?;
+            ctx.clear_color(0., 0., 0., 0.);
+            ctx.clear();
+        }
+        ctx.check_out_of_memory()?;
+
+        if let Some(multisample) = &multisample {
+            ctx.set_multisample_resolve(
+                multisample.framebuffer_key,
+                framebuffer_key,
+                &texture_keys,
+                depth_texture.map(|depth_texture| {
+                    (
+                        depth_texture.texture_key,
+                        depth_texture.texture_info.get_format(),
+                    )
+                }),
+                texture.width(),
+                texture.height(),
+            );
+        }
+
+        if settings.mipmap_mode == MipmapMode::Auto {
+            let rendered = multisample
+                .as_ref()
+                .map_or(framebuffer_key, |multisample| multisample.framebuffer_key);
+            ctx.set_mipmap_regeneration(rendered, &texture_keys, settings.texture_type);
+        }
+
+        attachments.remove(0);
         Ok(Self {
             texture_type: settings.texture_type,
             framebuffer_key,
             renderbuffer_key,
+            multisample,
+            extra_attachments: attachments,
+            depth_attachment: depth_texture,
             texture_key,
             texture_info: texture,
+            owner: ctx.new_owner(allocations.resources()),
         })
     }
 
+    pub fn owner(&self) -> &super::resource::Owner {
+        &self.owner
+    }
+
+    /// The number of samples per pixel. Zero when the canvas isn't multisampled, including when
+    /// `Settings::msaa` asked for more than the driver supports.
+    pub fn msaa(&self) -> usize {
+        self.multisample
+            .as_ref()
+            .map_or(0, |multisample| multisample.samples)
+    }
+
+    /// The number of color attachments, which is one more than `Settings::extra_attachments`.
+    pub fn color_attachment_count(&self) -> usize {
+        self.extra_attachments.len() + 1
+    }
+
+    /// The texture that fragment shader output `index` is written to. Index zero is the
+    /// canvas itself.
+    pub fn color_attachment(&self, index: usize) -> Option<AttachmentTexture> {
+        match index {
+            0 => Some(AttachmentTexture {
+                texture_key: self.texture_key,
+                texture_info: self.texture_info,
+                texture_type: self.texture_type,
+            }),
+            index => self.extra_attachments.get(index - 1).copied(),
+        }
+    }
+
+    /// The texture holding the depth and/or stencil buffer. Only available when the canvas was
+    /// created with `Settings::depth_texture`.
+    pub fn depth_attachment(&self) -> Option<AttachmentTexture> {
+        self.depth_attachment
+    }
+
+    /// Copies what has been rendered into a multisampled canvas into its texture. This happens
+    /// automatically when the texture is bound but may be needed before reading pixels.
+    pub fn resolve(&self, ctx: &mut Context) {
+        if let Some(multisample) = &self.multisample {
+            ctx.resolve_multisample(multisample.framebuffer_key);
+        }
+    }
+
+    /// Regenerates the mipmaps of every color attachment from what has been rendered so far,
+    /// resolving a multisampled canvas first. Does nothing for canvases without mipmaps.
+    pub fn generate_mipmaps(&self, ctx: &mut Context) {
+        if !self.texture_info.mipmaps() {
+            return;
+        }
+        let textures = std::iter::once(self.texture_key).chain(
+            self.extra_attachments
+                .iter()
+                .map(|attachment| attachment.texture_key),
+        );
+        for texture_key in textures {
+            ctx.generate_mipmaps(texture_key, self.texture_type);
+        }
+    }
+
+    /// The framebuffer that is rendered to. For multisampled canvases this isn't the one
+    /// holding the texture.
     pub fn get_framebuffer_key(&self) -> super::FramebufferKey {
+        self.multisample
+            .as_ref()
+            .map_or(self.framebuffer_key, |multisample| {
+                multisample.framebuffer_key
+            })
+    }
+
+    /// The framebuffer the canvas texture is attached to.
+    pub fn get_resolve_framebuffer_key(&self) -> super::FramebufferKey {
         self.framebuffer_key
     }
 }
@@ -160,18 +502,52 @@ impl Texture for &Canvas {
     }
 }
 
+impl Texture for AttachmentTexture {
+    fn get_texture_key(&self) -> super::TextureKey {
+        self.texture_key
+    }
+
+    fn get_texture_type(&self) -> TextureType {
+        self.texture_type
+    }
+
+    fn get_texture_info(&self) -> TextureInfo {
+        self.texture_info
+    }
+}
+
+impl Texture for &AttachmentTexture {
+    fn get_texture_key(&self) -> super::TextureKey {
+        AttachmentTexture::get_texture_key(self)
+    }
+
+    fn get_texture_type(&self) -> TextureType {
+        AttachmentTexture::get_texture_type(self)
+    }
+
+    fn get_texture_info(&self) -> TextureInfo {
+        AttachmentTexture::get_texture_info(self)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Attachment {
     Color,
+    /// The color attachment at `index`. `ColorIndex(0)` is the same as `Color`.
+    ColorIndex(u32),
     Depth,
     Stencil,
+    DepthStencil,
 }
 
 impl Attachment {
     pub fn to_gl(&self) -> u32 {
         match self {
             Attachment::Color => glow::COLOR_ATTACHMENT0,
+            Attachment::ColorIndex(index) => glow::COLOR_ATTACHMENT0 + index,
             Attachment::Depth => glow::DEPTH_ATTACHMENT,
             Attachment::Stencil => glow::STENCIL_ATTACHMENT,
+            Attachment::DepthStencil => glow::DEPTH_STENCIL_ATTACHMENT,
         }
     }
 }
@@ -193,7 +569,7 @@ impl Target {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Status {
     Complete,
     IncompleteAttachment,
@@ -203,3 +579,392 @@ pub enum Status {
     IncompleteMultisample,
     Unknown,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::recording::testing::*;
+    use crate::{
+        backend::recording::{Command, FramebufferAttachment, RecordingBackend},
+        canvas,
+        image::Image,
+        ClearSettings, Color, Renderer,
+    };
+
+    #[test]
+    fn canvas() {
+        let (mut ctx, backend) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 2,
+                with_stencil: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let recording = backend.recording();
+        assert_eq!(recording.state.draw_framebuffer, None);
+        let framebuffer = &recording.framebuffers[canvas.get_framebuffer_key()];
+        match framebuffer.attachments[&glow::COLOR_ATTACHMENT0] {
+            FramebufferAttachment::Texture { texture, level, .. } => {
+                let texture = &recording.textures[texture];
+                assert_eq!(level, 0);
+                assert_eq!((texture.width, texture.height), (4, 2));
+            }
+            attachment => panic!("unexpected color attachment {:?}", attachment),
+        }
+        match framebuffer.attachments[&glow::DEPTH_STENCIL_ATTACHMENT] {
+            FramebufferAttachment::Renderbuffer(renderbuffer) => {
+                let renderbuffer = recording.renderbuffers[renderbuffer];
+                assert_eq!(renderbuffer.internal_format, glow::DEPTH24_STENCIL8);
+                assert_eq!((renderbuffer.width, renderbuffer.height), (4, 2));
+            }
+            attachment => panic!("unexpected depth attachment {:?}", attachment),
+        }
+    }
+
+    #[test]
+    fn multisampled_canvas() {
+        let (mut ctx, backend) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 2,
+                msaa: 16,
+                with_depth: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(canvas.msaa(), 4);
+        assert_ne!(
+            canvas.get_framebuffer_key(),
+            canvas.get_resolve_framebuffer_key()
+        );
+        {
+            let recording = backend.recording();
+            let framebuffer = &recording.framebuffers[canvas.get_framebuffer_key()];
+            for attachment in &[glow::COLOR_ATTACHMENT0, glow::DEPTH_ATTACHMENT] {
+                match framebuffer.attachments[attachment] {
+                    FramebufferAttachment::Renderbuffer(renderbuffer) => {
+                        assert_eq!(recording.renderbuffers[renderbuffer].samples, 4)
+                    }
+                    attachment => panic!("unexpected attachment {:?}", attachment),
+                }
+            }
+        }
+
+        let blits = |backend: &RecordingBackend| {
+            backend
+                .recording()
+                .commands
+                .iter()
+                .filter(|command| matches!(command, Command::Blit { .. }))
+                .count()
+        };
+        Renderer::clear(
+            &mut ctx,
+            ClearSettings {
+                target: Some(&canvas),
+                ..Default::default()
+            },
+        );
+        assert_eq!(blits(&backend), 0);
+
+        ctx.bind_texture_to_unit(
+            canvas.get_texture_type(),
+            canvas.get_texture_key(),
+            1.into(),
+        );
+        assert_eq!(blits(&backend), 1);
+        ctx.bind_texture_to_unit(
+            canvas.get_texture_type(),
+            canvas.get_texture_key(),
+            2.into(),
+        );
+        assert_eq!(blits(&backend), 1);
+
+        let recording = backend.recording();
+        match recording.commands.last() {
+            Some(Command::Blit {
+                src, dst, state, ..
+            }) => {
+                assert_eq!(*src, [0, 0, 4, 2]);
+                assert_eq!(src, dst);
+                assert_eq!(state.read_framebuffer, Some(canvas.get_framebuffer_key()));
+                assert_eq!(
+                    state.draw_framebuffer,
+                    Some(canvas.get_resolve_framebuffer_key())
+                );
+            }
+            command => panic!("expected a blit, got {:?}", command),
+        }
+        // the clear left the canvas bound
+        let bound = Some(canvas.get_framebuffer_key());
+        assert_eq!(recording.state.read_framebuffer, bound);
+        assert_eq!(recording.state.draw_framebuffer, bound);
+    }
+
+    #[test]
+    fn multiple_render_targets() {
+        let (mut ctx, backend) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 2,
+                extra_attachments: vec![PixelFormat::RGBA16F, PixelFormat::R32F],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(canvas.color_attachment_count(), 3);
+        assert!(canvas.color_attachment(3).is_none());
+        let normals = canvas.color_attachment(1).unwrap();
+        assert_eq!(
+            normals.get_texture_info().get_format(),
+            PixelFormat::RGBA16F
+        );
+        {
+            let recording = backend.recording();
+            let framebuffer = &recording.framebuffers[canvas.get_framebuffer_key()];
+            for index in 0..3 {
+                let attachment = glow::COLOR_ATTACHMENT0 + index as u32;
+                assert_eq!(framebuffer.draw_buffer(index), Some(attachment));
+                let texture = canvas.color_attachment(index).unwrap().get_texture_key();
+                match framebuffer.attachments[&attachment] {
+                    FramebufferAttachment::Texture { texture: key, .. } => {
+                        assert_eq!(key, texture)
+                    }
+                    attachment => panic!("unexpected attachment {:?}", attachment),
+                }
+            }
+            assert_eq!(recording.textures[normals.get_texture_key()].width, 4);
+        }
+
+        let red = Color {
+            red: 1.,
+            green: 0.,
+            blue: 0.,
+            alpha: 1.,
+        }
+        .into();
+        Renderer::clear(
+            &mut ctx,
+            ClearSettings {
+                target: Some(&canvas),
+                attachment_colors: &[None, None, Some(red)],
+                ..Default::default()
+            },
+        );
+        let recording = backend.recording();
+        match recording.commands.last() {
+            Some(Command::ClearBuffer {
+                draw_buffer,
+                color,
+                state,
+            }) => {
+                assert_eq!(*draw_buffer, 2);
+                assert_eq!(*color, [1., 0., 0., 1.]);
+                assert_eq!(state.draw_framebuffer, Some(canvas.get_framebuffer_key()));
+            }
+            command => panic!("expected a buffer clear, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn multisampled_render_targets() {
+        let (mut ctx, backend) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 2,
+                msaa: 4,
+                extra_attachments: vec![PixelFormat::RGBA8],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        Renderer::clear(
+            &mut ctx,
+            ClearSettings {
+                target: Some(&canvas),
+                ..Default::default()
+            },
+        );
+        let extra = canvas.color_attachment(1).unwrap();
+        ctx.bind_texture_to_unit(extra.get_texture_type(), extra.get_texture_key(), 1.into());
+
+        let recording = backend.recording();
+        let blits = recording
+            .commands
+            .iter()
+            .filter(|command| matches!(command, Command::Blit { .. }))
+            .count();
+        assert_eq!(blits, 2);
+        let multisampled = &recording.framebuffers[canvas.get_framebuffer_key()];
+        assert_eq!(multisampled.read_buffer, glow::COLOR_ATTACHMENT0);
+        let resolve = &recording.framebuffers[canvas.get_resolve_framebuffer_key()];
+        assert_eq!(resolve.draw_buffer(1), Some(glow::COLOR_ATTACHMENT1));
+    }
+
+    #[test]
+    fn depth_texture_canvas() {
+        let (mut ctx, backend) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 2,
+                depth_format: Some(PixelFormat::Depth24Stencil8),
+                depth_texture: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let depth = canvas.depth_attachment().unwrap();
+        assert_eq!(
+            depth.get_texture_info().get_format(),
+            PixelFormat::Depth24Stencil8
+        );
+
+        let recording = backend.recording();
+        let framebuffer = &recording.framebuffers[canvas.get_framebuffer_key()];
+        assert_eq!(
+            framebuffer.attachments[&glow::DEPTH_STENCIL_ATTACHMENT],
+            FramebufferAttachment::Texture {
+                target: glow::TEXTURE_2D,
+                texture: depth.get_texture_key(),
+                level: 0,
+                layer: 0,
+            }
+        );
+        assert!(recording.renderbuffers.is_empty());
+        let texture = &recording.textures[depth.get_texture_key()];
+        assert_eq!(texture.internal_format, glow::DEPTH24_STENCIL8 as i32);
+        assert_eq!(texture.format, glow::DEPTH_STENCIL);
+        assert_eq!(
+            texture.parameters[&glow::TEXTURE_MIN_FILTER],
+            glow::NEAREST as i32
+        );
+    }
+
+    #[test]
+    fn multisampled_depth_texture_canvas() {
+        let (mut ctx, backend) = context();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 2,
+                msaa: 4,
+                depth_format: Some(PixelFormat::Depth32F),
+                depth_texture: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        {
+            let recording = backend.recording();
+            let multisampled = &recording.framebuffers[canvas.get_framebuffer_key()];
+            match multisampled.attachments[&glow::DEPTH_ATTACHMENT] {
+                FramebufferAttachment::Renderbuffer(renderbuffer) => {
+                    let renderbuffer = &recording.renderbuffers[renderbuffer];
+                    assert_eq!(renderbuffer.internal_format, glow::DEPTH_COMPONENT32F);
+                    assert_eq!(renderbuffer.samples, 4);
+                }
+                attachment => panic!("unexpected depth attachment {:?}", attachment),
+            }
+        }
+
+        Renderer::clear(
+            &mut ctx,
+            ClearSettings {
+                target: Some(&canvas),
+                ..Default::default()
+            },
+        );
+        let depth = canvas.depth_attachment().unwrap();
+        ctx.bind_texture_to_unit(depth.get_texture_type(), depth.get_texture_key(), 1.into());
+        let recording = backend.recording();
+        match recording.commands.last() {
+            Some(Command::Blit { mask, .. }) => {
+                assert_eq!(*mask, glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT)
+            }
+            command => panic!("expected a blit, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn canvas_errors() {
+        let (mut ctx, backend) = context();
+        let resources = |backend: &RecordingBackend| {
+            let recording = backend.recording();
+            (
+                recording.textures.len(),
+                recording.framebuffers.len(),
+                recording.renderbuffers.len(),
+            )
+        };
+        let settings = canvas::Settings {
+            width: 4,
+            height: 2,
+            msaa: 4,
+            with_depth: true,
+            ..Default::default()
+        };
+
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                format: PixelFormat::Depth16,
+                ..settings.clone()
+            },
+        );
+        match canvas {
+            Err(GraphicsError::IncompleteFramebuffer(canvas::Status::IncompleteAttachment)) => (),
+            canvas => panic!("expected an incomplete framebuffer, got {:?}", canvas),
+        }
+        assert_eq!(resources(&backend), (0, 0, 0));
+
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                depth_format: Some(PixelFormat::RGBA8),
+                ..settings.clone()
+            },
+        );
+        match canvas {
+            Err(GraphicsError::UnsupportedPixelFormat(PixelFormat::RGBA8)) => (),
+            canvas => panic!("expected an unsupported format, got {:?}", canvas),
+        }
+
+        backend.raise_error(glow::OUT_OF_MEMORY);
+        match Canvas::new(&mut ctx, settings) {
+            Err(GraphicsError::OutOfMemory) => (),
+            canvas => panic!("expected to run out of memory, got {:?}", canvas),
+        }
+        assert_eq!(resources(&backend), (0, 0, 0));
+        assert_eq!(backend.recording().state.draw_framebuffer, None);
+
+        backend.raise_error(glow::OUT_OF_MEMORY);
+        match Image::with_data(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RGBA8,
+            1,
+            1,
+            &[0; 4],
+            Default::default(),
+        ) {
+            Err(GraphicsError::OutOfMemory) => (),
+            image => panic!("expected to run out of memory, got {:?}", image),
+        }
+        assert_eq!(resources(&backend), (0, 0, 0));
+    }
+}
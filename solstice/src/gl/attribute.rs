@@ -1,7 +1,7 @@
 use crate::vertex::AttributeType;
 
-pub fn from_gl(atype: u32) -> AttributeType {
-    match atype {
+pub fn try_from_gl(atype: u32) -> Option<AttributeType> {
+    let atype = match atype {
         glow::FLOAT => AttributeType::F32,
         glow::FLOAT_VEC2 => AttributeType::F32F32,
         glow::FLOAT_VEC3 => AttributeType::F32F32F32,
@@ -13,6 +13,16 @@ pub fn from_gl(atype: u32) -> AttributeType {
         glow::INT_VEC2 => AttributeType::I32I32,
         glow::INT_VEC3 => AttributeType::I32I32I32,
         glow::INT_VEC4 => AttributeType::I32I32I32I32,
-        v => panic!("Unknown value returned by OpenGL attribute type: {:#x}", v),
-    }
+        _ => return None,
+    };
+    Some(atype)
+}
+
+pub fn from_gl(atype: u32) -> AttributeType {
+    try_from_gl(atype).unwrap_or_else(|| {
+        panic!(
+            "Unknown value returned by OpenGL attribute type: {:#x}",
+            atype
+        )
+    })
 }
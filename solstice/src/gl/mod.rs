@@ -2,6 +2,7 @@ pub mod attribute;
 pub mod draw_mode;
 pub mod pixel_format;
 pub mod texture;
+pub mod uniform;
 pub mod vertex_winding;
 pub mod wrap_mode;
 
@@ -1,5 +1,163 @@
 use crate::PixelFormat;
 
+// glow is missing the S3TC and ASTC enums
+const COMPRESSED_RGBA_S3TC_DXT1_EXT: u32 = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3_EXT: u32 = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5_EXT: u32 = 0x83F3;
+const COMPRESSED_RGBA_ASTC_4X4_KHR: u32 = 0x93B0;
+const COMPRESSED_RGBA_ASTC_5X4_KHR: u32 = 0x93B1;
+const COMPRESSED_RGBA_ASTC_5X5_KHR: u32 = 0x93B2;
+const COMPRESSED_RGBA_ASTC_6X5_KHR: u32 = 0x93B3;
+const COMPRESSED_RGBA_ASTC_6X6_KHR: u32 = 0x93B4;
+const COMPRESSED_RGBA_ASTC_8X5_KHR: u32 = 0x93B5;
+const COMPRESSED_RGBA_ASTC_8X6_KHR: u32 = 0x93B6;
+const COMPRESSED_RGBA_ASTC_8X8_KHR: u32 = 0x93B7;
+const COMPRESSED_RGBA_ASTC_10X5_KHR: u32 = 0x93B8;
+const COMPRESSED_RGBA_ASTC_10X6_KHR: u32 = 0x93B9;
+const COMPRESSED_RGBA_ASTC_10X8_KHR: u32 = 0x93BA;
+const COMPRESSED_RGBA_ASTC_10X10_KHR: u32 = 0x93BB;
+const COMPRESSED_RGBA_ASTC_12X10_KHR: u32 = 0x93BC;
+const COMPRESSED_RGBA_ASTC_12X12_KHR: u32 = 0x93BD;
+
+const COMPRESSED_FORMATS: [PixelFormat; 31] = [
+    PixelFormat::BC1,
+    PixelFormat::BC2,
+    PixelFormat::BC3,
+    PixelFormat::BC4,
+    PixelFormat::BC4Signed,
+    PixelFormat::BC5,
+    PixelFormat::BC5Signed,
+    PixelFormat::BC6H,
+    PixelFormat::BC6HSigned,
+    PixelFormat::BC7,
+    PixelFormat::ETC2RGB8,
+    PixelFormat::ETC2RGB8A1,
+    PixelFormat::ETC2RGBA8,
+    PixelFormat::EACR11,
+    PixelFormat::EACR11Signed,
+    PixelFormat::EACRG11,
+    PixelFormat::EACRG11Signed,
+    PixelFormat::ASTC4x4,
+    PixelFormat::ASTC5x4,
+    PixelFormat::ASTC5x5,
+    PixelFormat::ASTC6x5,
+    PixelFormat::ASTC6x6,
+    PixelFormat::ASTC8x5,
+    PixelFormat::ASTC8x6,
+    PixelFormat::ASTC8x8,
+    PixelFormat::ASTC10x5,
+    PixelFormat::ASTC10x6,
+    PixelFormat::ASTC10x8,
+    PixelFormat::ASTC10x10,
+    PixelFormat::ASTC12x10,
+    PixelFormat::ASTC12x12,
+];
+
+/// The internal format of block-compressed formats, `None` for everything else.
+pub fn compressed_to_gl(format: PixelFormat) -> Option<u32> {
+    let internal = match format {
+        PixelFormat::BC1 => COMPRESSED_RGBA_S3TC_DXT1_EXT,
+        PixelFormat::BC2 => COMPRESSED_RGBA_S3TC_DXT3_EXT,
+        PixelFormat::BC3 => COMPRESSED_RGBA_S3TC_DXT5_EXT,
+        PixelFormat::BC4 => glow::COMPRESSED_RED_RGTC1,
+        PixelFormat::BC4Signed => glow::COMPRESSED_SIGNED_RED_RGTC1,
+        PixelFormat::BC5 => glow::COMPRESSED_RG_RGTC2,
+        PixelFormat::BC5Signed => glow::COMPRESSED_SIGNED_RG_RGTC2,
+        PixelFormat::BC6H => glow::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+        PixelFormat::BC6HSigned => glow::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+        PixelFormat::BC7 => glow::COMPRESSED_RGBA_BPTC_UNORM,
+        PixelFormat::ETC2RGB8 => glow::COMPRESSED_RGB8_ETC2,
+        PixelFormat::ETC2RGB8A1 => glow::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+        PixelFormat::ETC2RGBA8 => glow::COMPRESSED_RGBA8_ETC2_EAC,
+        PixelFormat::EACR11 => glow::COMPRESSED_R11_EAC,
+        PixelFormat::EACR11Signed => glow::COMPRESSED_SIGNED_R11_EAC,
+        PixelFormat::EACRG11 => glow::COMPRESSED_RG11_EAC,
+        PixelFormat::EACRG11Signed => glow::COMPRESSED_SIGNED_RG11_EAC,
+        PixelFormat::ASTC4x4 => COMPRESSED_RGBA_ASTC_4X4_KHR,
+        PixelFormat::ASTC5x4 => COMPRESSED_RGBA_ASTC_5X4_KHR,
+        PixelFormat::ASTC5x5 => COMPRESSED_RGBA_ASTC_5X5_KHR,
+        PixelFormat::ASTC6x5 => COMPRESSED_RGBA_ASTC_6X5_KHR,
+        PixelFormat::ASTC6x6 => COMPRESSED_RGBA_ASTC_6X6_KHR,
+        PixelFormat::ASTC8x5 => COMPRESSED_RGBA_ASTC_8X5_KHR,
+        PixelFormat::ASTC8x6 => COMPRESSED_RGBA_ASTC_8X6_KHR,
+        PixelFormat::ASTC8x8 => COMPRESSED_RGBA_ASTC_8X8_KHR,
+        PixelFormat::ASTC10x5 => COMPRESSED_RGBA_ASTC_10X5_KHR,
+        PixelFormat::ASTC10x6 => COMPRESSED_RGBA_ASTC_10X6_KHR,
+        PixelFormat::ASTC10x8 => COMPRESSED_RGBA_ASTC_10X8_KHR,
+        PixelFormat::ASTC10x10 => COMPRESSED_RGBA_ASTC_10X10_KHR,
+        PixelFormat::ASTC12x10 => COMPRESSED_RGBA_ASTC_12X10_KHR,
+        PixelFormat::ASTC12x12 => COMPRESSED_RGBA_ASTC_12X12_KHR,
+        _ => return None,
+    };
+    Some(internal)
+}
+
+/// The reverse of `compressed_to_gl`.
+pub fn compressed_from_gl(internal: u32) -> Option<PixelFormat> {
+    COMPRESSED_FORMATS
+        .iter()
+        .copied()
+        .find(|format| compressed_to_gl(*format) == Some(internal))
+}
+
+/// The format with a sized internal format of `internal`, compressed or not.
+pub fn from_gl(internal: u32) -> Option<PixelFormat> {
+    let format = match internal {
+        glow::LUMINANCE => PixelFormat::LUMINANCE,
+        glow::ALPHA => PixelFormat::Alpha,
+        glow::RG8 => PixelFormat::RG8,
+        glow::RGB8 => PixelFormat::RGB8,
+        glow::RGBA8 => PixelFormat::RGBA8,
+        glow::SRGB8_ALPHA8 => PixelFormat::SRGBA8,
+        glow::R16 => PixelFormat::R16,
+        glow::RG16 => PixelFormat::RG16,
+        glow::RGBA16 => PixelFormat::RGBA16,
+        glow::R16F => PixelFormat::R16F,
+        glow::RG16F => PixelFormat::RG16F,
+        glow::RGBA16F => PixelFormat::RGBA16F,
+        glow::R32F => PixelFormat::R32F,
+        glow::RG32F => PixelFormat::RG32F,
+        glow::RGBA32F => PixelFormat::RGBA32F,
+        internal => return compressed_from_gl(internal),
+    };
+    Some(format)
+}
+
+/// The width and height of the blocks a compressed format is made of, a single pixel for
+/// everything else.
+pub fn block_dimensions(format: PixelFormat) -> (u32, u32) {
+    match format {
+        PixelFormat::ASTC5x4 => (5, 4),
+        PixelFormat::ASTC5x5 => (5, 5),
+        PixelFormat::ASTC6x5 => (6, 5),
+        PixelFormat::ASTC6x6 => (6, 6),
+        PixelFormat::ASTC8x5 => (8, 5),
+        PixelFormat::ASTC8x6 => (8, 6),
+        PixelFormat::ASTC8x8 => (8, 8),
+        PixelFormat::ASTC10x5 => (10, 5),
+        PixelFormat::ASTC10x6 => (10, 6),
+        PixelFormat::ASTC10x8 => (10, 8),
+        PixelFormat::ASTC10x10 => (10, 10),
+        PixelFormat::ASTC12x10 => (12, 10),
+        PixelFormat::ASTC12x12 => (12, 12),
+        format if compressed_to_gl(format).is_some() => (4, 4),
+        _ => (1, 1),
+    }
+}
+
+/// The number of bytes an image takes up. Compressed formats are stored in whole blocks so
+/// partial blocks at the edges count fully.
+pub fn image_size(format: PixelFormat, width: u32, height: u32) -> usize {
+    let (block_width, block_height) = block_dimensions(format);
+    let blocks_wide = width.div_ceil(block_width);
+    let blocks_high = height.div_ceil(block_height);
+    // saturates rather than overflowing for the dimensions a malformed file can claim
+    (blocks_wide as usize)
+        .saturating_mul(blocks_high as usize)
+        .saturating_mul(size(format))
+}
+
+/// Bytes per pixel, or bytes per block for compressed formats.
 #[allow(unused)]
 pub fn size(format: PixelFormat) -> usize {
     match format {
@@ -20,6 +178,37 @@ pub fn size(format: PixelFormat) -> usize {
         | PixelFormat::RG32F
         | PixelFormat::Depth32fStencil8 => 8,
         PixelFormat::RGBA32F => 16,
+        PixelFormat::BC1
+        | PixelFormat::BC4
+        | PixelFormat::BC4Signed
+        | PixelFormat::ETC2RGB8
+        | PixelFormat::ETC2RGB8A1
+        | PixelFormat::EACR11
+        | PixelFormat::EACR11Signed => 8,
+        PixelFormat::BC2
+        | PixelFormat::BC3
+        | PixelFormat::BC5
+        | PixelFormat::BC5Signed
+        | PixelFormat::BC6H
+        | PixelFormat::BC6HSigned
+        | PixelFormat::BC7
+        | PixelFormat::ETC2RGBA8
+        | PixelFormat::EACRG11
+        | PixelFormat::EACRG11Signed
+        | PixelFormat::ASTC4x4
+        | PixelFormat::ASTC5x4
+        | PixelFormat::ASTC5x5
+        | PixelFormat::ASTC6x5
+        | PixelFormat::ASTC6x6
+        | PixelFormat::ASTC8x5
+        | PixelFormat::ASTC8x6
+        | PixelFormat::ASTC8x8
+        | PixelFormat::ASTC10x5
+        | PixelFormat::ASTC10x6
+        | PixelFormat::ASTC10x8
+        | PixelFormat::ASTC10x10
+        | PixelFormat::ASTC12x10
+        | PixelFormat::ASTC12x12 => 16,
     }
 }
 
@@ -48,6 +237,7 @@ pub fn to_gl(
     is_renderbuffer: bool,
 ) -> super::TextureFormat {
     use super::TextureFormat as TF;
+    let sized_depth = format.is_depth() || format.is_stencil();
     let format = match format {
         PixelFormat::Unknown => panic!("Unknown pixel format!"),
         PixelFormat::LUMINANCE => {
@@ -101,7 +291,7 @@ pub fn to_gl(
         PixelFormat::Depth24 => (
             glow::DEPTH_COMPONENT24,
             glow::DEPTH_COMPONENT,
-            glow::UNSIGNED_INT_24_8,
+            glow::UNSIGNED_INT,
         )
             .into(),
         PixelFormat::Depth32F => {
@@ -119,9 +309,15 @@ pub fn to_gl(
             glow::FLOAT_32_UNSIGNED_INT_24_8_REV,
         )
             .into(),
+        // uploaded as-is, there's no external format or type to speak of
+        compressed => {
+            let internal = compressed_to_gl(compressed).expect("Unknown pixel format!");
+            return (internal, internal, glow::UNSIGNED_BYTE).into();
+        }
     };
 
-    if version.gles && !is_renderbuffer {
+    // GLES 3 only accepts sized internal formats for depth textures
+    if version.gles && !is_renderbuffer && !(sized_depth && version.major >= 3) {
         TF {
             internal: format.external,
             ..format
@@ -0,0 +1,21 @@
+/// Whether a uniform type is one of the sampler types, which are set with texture unit indices.
+pub fn is_sampler(utype: u32) -> bool {
+    matches!(
+        utype,
+        glow::SAMPLER_2D
+            | glow::SAMPLER_3D
+            | glow::SAMPLER_CUBE
+            | glow::SAMPLER_2D_ARRAY
+            | glow::SAMPLER_2D_SHADOW
+            | glow::SAMPLER_CUBE_SHADOW
+            | glow::SAMPLER_2D_ARRAY_SHADOW
+            | glow::INT_SAMPLER_2D
+            | glow::INT_SAMPLER_3D
+            | glow::INT_SAMPLER_CUBE
+            | glow::INT_SAMPLER_2D_ARRAY
+            | glow::UNSIGNED_INT_SAMPLER_2D
+            | glow::UNSIGNED_INT_SAMPLER_3D
+            | glow::UNSIGNED_INT_SAMPLER_CUBE
+            | glow::UNSIGNED_INT_SAMPLER_2D_ARRAY
+    )
+}
@@ -1,3 +1,9 @@
+mod container;
+mod dds;
+mod ktx;
+
+pub use container::{ContainerError, TextureContainer};
+
 use super::PixelFormat;
 use super::{
     buffer::Mapped,
@@ -29,11 +35,13 @@ impl Default for Settings {
     }
 }
 
+/// Clones share the texture, which is deleted once all of them have been dropped.
 #[derive(Clone, Debug)]
 pub struct Image {
     texture_key: super::TextureKey,
     texture_info: TextureInfo,
     texture_type: TextureType,
+    owner: super::resource::Owner,
 }
 
 impl Image {
@@ -45,16 +53,19 @@ impl Image {
         height: u32,
         settings: Settings,
     ) -> Result<Self, super::GraphicsError> {
-        assert!(
-            texture_type.is_supported(),
-            "Unsupported Texture Type: {:?}",
-            texture_type
-        );
+        if !ctx.supports_texture_type(texture_type) {
+            return Err(super::GraphicsError::UnsupportedTextureType(texture_type));
+        }
+        if !ctx.supports_pixel_format(format) {
+            return Err(super::GraphicsError::UnsupportedPixelFormat(format));
+        }
         let texture_key = ctx.new_texture(texture_type)?;
+        // mipmaps can't be generated from compressed data
+        let mipmaps = settings.mipmaps && !format.is_compressed();
         let filter = Filter::new(
             settings.filter,
             settings.filter,
-            if settings.mipmaps {
+            if mipmaps {
                 settings.filter
             } else {
                 FilterMode::None
@@ -64,20 +75,56 @@ impl Image {
         let wrap = Wrap::new(settings.wrap, settings.wrap, settings.wrap);
         ctx.set_texture_filter(texture_key, texture_type, filter);
         ctx.set_texture_wrap(texture_key, texture_type, wrap);
+        let mut texture_info = TextureInfo::new(
+            format,
+            (width as f32 * settings.dpi_scale + 0.5) as u32,
+            (height as f32 * settings.dpi_scale + 0.5) as u32,
+            filter,
+            wrap,
+            mipmaps,
+        );
+        texture_info.set_depth(texture_type.layers(settings.slices as u32));
         Ok(Self {
             texture_type,
             texture_key,
-            texture_info: TextureInfo::new(
-                format,
-                (width as f32 * settings.dpi_scale + 0.5) as u32,
-                (height as f32 * settings.dpi_scale + 0.5) as u32,
-                filter,
-                wrap,
-                settings.mipmaps,
-            ),
+            texture_info,
+            owner: ctx.new_owner(vec![super::resource::ResourceKey::Texture(texture_key)]),
         })
     }
 
+    /// Creates a texture with one layer per slice of `layers`. Volume textures treat the layers
+    /// as depth slices and cube maps take exactly six faces, ordered +X, -X, +Y, -Y, +Z, -Z.
+    /// Any other number of layers is `GraphicsError::InvalidTextureData`.
+    pub fn with_layers(
+        ctx: &mut Context,
+        texture_type: TextureType,
+        format: PixelFormat,
+        width: u32,
+        height: u32,
+        layers: &[&[u8]],
+        settings: Settings,
+    ) -> Result<Self, super::GraphicsError> {
+        let settings = Settings {
+            slices: layers.len(),
+            ..settings
+        };
+        if texture_type.layers(layers.len() as u32) as usize != layers.len() {
+            return Err(super::GraphicsError::InvalidTextureData);
+        }
+        let data = layers.concat();
+        Image::with_data(ctx, texture_type, format, width, height, &data, settings)
+    }
+
+    pub fn with_faces(
+        ctx: &mut Context,
+        format: PixelFormat,
+        size: u32,
+        faces: [&[u8]; 6],
+        settings: Settings,
+    ) -> Result<Self, super::GraphicsError> {
+        Image::with_layers(ctx, TextureType::Cube, format, size, size, &faces, settings)
+    }
+
     pub fn with_data(
         ctx: &mut Context,
         texture_type: TextureType,
@@ -94,9 +141,98 @@ impl Image {
             this.texture_type,
             Some(data),
         );
+        if let Err(err) = ctx.check_out_of_memory() {
+            ctx.destroy_texture(this.texture_key);
+            return Err(err);
+        }
         Ok(this)
     }
 
+    /// Creates a texture from a prebuilt mipmap chain, largest level first, rather than having
+    /// the levels generated. Each level is laid out like the data for `with_data` and the chain
+    /// may stop short of 1x1. A single level is the same as `with_data`. No levels, or a level
+    /// of the wrong size, is `GraphicsError::InvalidTextureData`.
+    pub fn with_mipmaps(
+        ctx: &mut Context,
+        texture_type: TextureType,
+        format: PixelFormat,
+        width: u32,
+        height: u32,
+        levels: &[&[u8]],
+        settings: Settings,
+    ) -> Result<Self, super::GraphicsError> {
+        match levels {
+            [] => return Err(super::GraphicsError::InvalidTextureData),
+            [data] => {
+                return Image::with_data(ctx, texture_type, format, width, height, data, settings)
+            }
+            _ => (),
+        }
+        let mut this = Image::new(
+            ctx,
+            texture_type,
+            format,
+            width,
+            height,
+            Settings {
+                mipmaps: false,
+                ..settings
+            },
+        )?;
+        let filter = Filter::new(settings.filter, settings.filter, settings.filter, 0.);
+        this.texture_info.set_filter(filter);
+        this.texture_info.set_mipmaps(true);
+        ctx.set_texture_filter(this.texture_key, texture_type, filter);
+        let wrong_size = levels.iter().enumerate().any(|(level, data)| {
+            super::level_size(this.texture_info, texture_type, level as u32) != data.len()
+        });
+        if wrong_size {
+            ctx.destroy_texture(this.texture_key);
+            return Err(super::GraphicsError::InvalidTextureData);
+        }
+        ctx.set_texture_level_range(this.texture_key, texture_type, 0, levels.len() as u32 - 1);
+        for (level, data) in levels.iter().enumerate() {
+            ctx.set_texture_level_data(
+                this.texture_key,
+                this.texture_info,
+                texture_type,
+                level as u32,
+                Some(data),
+            );
+        }
+        if let Err(err) = ctx.check_out_of_memory() {
+            ctx.destroy_texture(this.texture_key);
+            return Err(err);
+        }
+        Ok(this)
+    }
+
+    /// Creates the texture described by a KTX, KTX2 or DDS file along with any mipmaps it
+    /// carries.
+    pub fn with_container(
+        ctx: &mut Context,
+        container: &TextureContainer,
+        settings: Settings,
+    ) -> Result<Self, super::GraphicsError> {
+        let levels = container
+            .levels
+            .iter()
+            .map(Vec::as_slice)
+            .collect::<Vec<_>>();
+        Image::with_mipmaps(
+            ctx,
+            container.texture_type,
+            container.format,
+            container.width,
+            container.height,
+            &levels,
+            Settings {
+                slices: container.depth as usize,
+                ..settings
+            },
+        )
+    }
+
     #[cfg(target_arch = "wasm32")]
     pub fn with_html_image(
         ctx: &mut Context,
@@ -112,9 +248,71 @@ impl Image {
         Ok(this)
     }
 
+    /// Replaces a single layer, or cube map face, of the texture. Mipmaps are regenerated
+    /// afterwards if the image has them.
+    pub fn set_layer_data(&mut self, ctx: &mut Context, layer: u32, data: &[u8]) {
+        let mut texture_info = self.texture_info;
+        texture_info.set_depth(1);
+        ctx.set_texture_layer_sub_data(
+            self.texture_key,
+            texture_info,
+            self.texture_type,
+            data,
+            0,
+            0,
+            layer,
+        );
+        self.generate_mipmaps(ctx);
+    }
+
+    /// Replaces a single mipmap level, laid out like the data for `with_data`, without touching
+    /// the others. Levels past 0 are only sampled if the image has mipmaps. Data of the wrong
+    /// size for the level is `GraphicsError::InvalidTextureData`.
+    pub fn set_level_data(
+        &mut self,
+        ctx: &mut Context,
+        level: u32,
+        data: &[u8],
+    ) -> Result<(), super::GraphicsError> {
+        if super::level_size(self.texture_info, self.texture_type, level) != data.len() {
+            return Err(super::GraphicsError::InvalidTextureData);
+        }
+        ctx.set_texture_level_data(
+            self.texture_key,
+            self.texture_info,
+            self.texture_type,
+            level,
+            Some(data),
+        );
+        Ok(())
+    }
+
+    /// Regenerates every mipmap level from level 0. Does nothing for images without mipmaps,
+    /// which includes every compressed image.
+    pub fn generate_mipmaps(&self, ctx: &mut Context) {
+        if self.texture_info.mipmaps() {
+            ctx.generate_mipmaps(self.texture_key, self.texture_type);
+        }
+    }
+
+    /// Restricts sampling to levels `base_level..=max_level`. See
+    /// `TextureUpdate::set_texture_level_range`.
+    pub fn set_level_range(&self, ctx: &mut Context, base_level: u32, max_level: u32) {
+        ctx.set_texture_level_range(self.texture_key, self.texture_type, base_level, max_level);
+    }
+
+    /// See `TextureUpdate::set_texture_lod_bias`.
+    pub fn set_lod_bias(&self, ctx: &mut Context, bias: f32) {
+        ctx.set_texture_lod_bias(self.texture_key, self.texture_type, bias);
+    }
+
     pub fn set_texture_info(&mut self, texture_info: TextureInfo) {
         self.texture_info = texture_info;
     }
+
+    pub fn owner(&self) -> &super::resource::Owner {
+        &self.owner
+    }
 }
 
 impl Texture for Image {
@@ -183,6 +381,7 @@ impl MappedImage {
             )
             .unwrap(),
             modified_range: None,
+            growth_policy: Default::default(),
         })
     }
 
@@ -231,3 +430,374 @@ impl MappedImage {
         &self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::recording::testing::*;
+    use crate::{
+        backend::recording::RecordingBackend,
+        canvas::{self, Canvas},
+        image, ClearSettings, Renderer,
+    };
+
+    #[test]
+    fn layered_textures() {
+        let (mut ctx, backend) = context();
+        let settings = image::Settings {
+            mipmaps: false,
+            ..Default::default()
+        };
+
+        let mut array = Image::with_layers(
+            &mut ctx,
+            TextureType::Tex2DArray,
+            PixelFormat::LUMINANCE,
+            2,
+            1,
+            &[&[1, 2], &[3, 4], &[5, 6]],
+            settings,
+        )
+        .unwrap();
+        array.set_layer_data(&mut ctx, 1, &[7, 8]);
+        {
+            let recording = backend.recording();
+            let texture = &recording.textures[array.get_texture_key()];
+            assert_eq!(texture.target, Some(glow::TEXTURE_2D_ARRAY));
+            assert_eq!(texture.depth, 3);
+            assert_eq!(texture.data, [1, 2, 7, 8, 5, 6]);
+        }
+
+        let mut cube = Image::with_faces(
+            &mut ctx,
+            PixelFormat::LUMINANCE,
+            1,
+            [&[1], &[2], &[3], &[4], &[5], &[6]],
+            settings,
+        )
+        .unwrap();
+        assert_eq!(cube.get_texture_info().depth(), 6);
+        cube.set_layer_data(&mut ctx, 4, &[9]);
+        assert!(matches!(
+            Image::with_layers(
+                &mut ctx,
+                TextureType::Cube,
+                PixelFormat::LUMINANCE,
+                1,
+                1,
+                &[&[1], &[2]],
+                settings,
+            ),
+            Err(crate::GraphicsError::InvalidTextureData)
+        ));
+        let recording = backend.recording();
+        let texture = &recording.textures[cube.get_texture_key()];
+        assert_eq!(texture.target, Some(glow::TEXTURE_CUBE_MAP));
+        assert_eq!(texture.depth, 6);
+        assert_eq!(texture.data, [1, 2, 3, 4, 9, 6]);
+    }
+
+    #[test]
+    fn compressed_textures() {
+        use crate::texture::TextureUpdate;
+        // GL 3.3 has RGTC but nothing else without extensions
+        let (ctx, _backend) = context();
+        assert!(ctx.supports_pixel_format(PixelFormat::BC4));
+        assert!(!ctx.supports_pixel_format(PixelFormat::BC1));
+        assert!(!ctx.supports_pixel_format(PixelFormat::ETC2RGBA8));
+        assert!(!ctx.supports_pixel_format(PixelFormat::ASTC4x4));
+
+        let backend = RecordingBackend::new();
+        backend.set_extensions(&[
+            "GL_EXT_texture_compression_s3tc",
+            "GL_KHR_texture_compression_astc_ldr",
+        ]);
+        let mut ctx = Context::with_backend(backend.clone());
+        assert!(ctx.supports_extension("GL_EXT_texture_compression_s3tc"));
+        assert!(!ctx.supports_extension("GL_ARB_ES3_compatibility"));
+        assert!(ctx.supports_pixel_format(PixelFormat::BC1));
+        assert!(ctx.supports_pixel_format(PixelFormat::ASTC4x4));
+        assert!(!ctx.supports_pixel_format(PixelFormat::BC7));
+
+        // WebGL names its extensions differently and only has ETC2 through one of them
+        let webgl = |extensions: &[&str]| {
+            let backend = RecordingBackend::new();
+            backend.set_version("WebGL 2.0");
+            backend.set_extensions(extensions);
+            Context::with_backend(backend)
+        };
+        let web = webgl(&["WEBGL_compressed_texture_s3tc"]);
+        assert!(web.supports_pixel_format(PixelFormat::BC1));
+        assert!(!web.supports_pixel_format(PixelFormat::ETC2RGB8));
+        let web = webgl(&[
+            "WEBGL_compressed_texture_etc",
+            "EXT_texture_compression_bptc",
+        ]);
+        assert!(web.supports_pixel_format(PixelFormat::ETC2RGB8));
+        assert!(web.supports_pixel_format(PixelFormat::BC7));
+        assert!(!web.supports_pixel_format(PixelFormat::BC1));
+
+        // 6x6 pixels are 2x2 blocks of 8 bytes, the partial blocks included
+        let start = ctx.resource_stats().textures.bytes;
+        let data = (0..32).collect::<Vec<u8>>();
+        let image = Image::with_data(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::BC1,
+            6,
+            6,
+            &data,
+            Default::default(),
+        )
+        .unwrap();
+        assert!(!image.get_texture_info().mipmaps());
+        assert_eq!(ctx.resource_stats().textures.bytes, start + 32);
+        {
+            let recording = backend.recording();
+            let texture = &recording.textures[image.get_texture_key()];
+            assert_eq!(texture.internal_format, 0x83F1);
+            assert_eq!(texture.data, data);
+            assert_eq!(texture.mipmap_generations, 0);
+        }
+
+        // the bottom right block
+        let mut region = image.get_texture_info();
+        region.set_width(4);
+        region.set_height(4);
+        ctx.set_texture_sub_data(
+            image.get_texture_key(),
+            region,
+            TextureType::Tex2D,
+            &[255; 8],
+            4,
+            4,
+        );
+        let mut expected = data;
+        expected[24..].fill(255);
+        assert_eq!(
+            backend.recording().textures[image.get_texture_key()].data,
+            expected
+        );
+
+        // storage for compressed formats can't be allocated without data so zeroes are uploaded
+        let image = Image::new(
+            &mut ctx,
+            TextureType::Cube,
+            PixelFormat::ASTC5x5,
+            10,
+            10,
+            Default::default(),
+        )
+        .unwrap();
+        ctx.set_texture_data(
+            image.get_texture_key(),
+            image.get_texture_info(),
+            TextureType::Cube,
+            None,
+        );
+        let recording = backend.recording();
+        let texture = &recording.textures[image.get_texture_key()];
+        assert_eq!(texture.depth, 6);
+        assert_eq!(texture.data, vec![0; 2 * 2 * 16 * 6]);
+    }
+
+    #[test]
+    fn mipmap_levels() {
+        use crate::texture::TextureUpdate;
+
+        let (mut ctx, backend) = context();
+        let generations = |backend: &RecordingBackend, texture: &dyn Texture| {
+            backend.recording().textures[texture.get_texture_key()].mipmap_generations
+        };
+
+        let mut image = Image::with_data(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RG8,
+            2,
+            2,
+            &[0; 8],
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(generations(&backend, &image), 1);
+
+        // sub-uploads regenerate the other levels unless the region opts out
+        let mut region = image.get_texture_info();
+        region.set_width(1);
+        region.set_height(1);
+        ctx.set_texture_sub_data(
+            image.get_texture_key(),
+            region,
+            TextureType::Tex2D,
+            &[1, 2],
+            1,
+            1,
+        );
+        assert_eq!(generations(&backend, &image), 2);
+        region.set_mipmaps(false);
+        ctx.set_texture_sub_data(
+            image.get_texture_key(),
+            region,
+            TextureType::Tex2D,
+            &[1, 2],
+            1,
+            1,
+        );
+        assert_eq!(generations(&backend, &image), 2);
+        image.generate_mipmaps(&mut ctx);
+        assert_eq!(generations(&backend, &image), 3);
+
+        image.set_level_data(&mut ctx, 1, &[3, 4]).unwrap();
+        assert!(matches!(
+            image.set_level_data(&mut ctx, 1, &[3, 4, 5, 6]),
+            Err(crate::GraphicsError::InvalidTextureData)
+        ));
+        image.set_level_range(&mut ctx, 1, 1);
+        image.set_lod_bias(&mut ctx, -0.5);
+        {
+            let recording = backend.recording();
+            let texture = &recording.textures[image.get_texture_key()];
+            assert_eq!(texture.mipmaps, vec![vec![3, 4]]);
+            assert_eq!(texture.mipmap_generations, 3);
+            assert_eq!(texture.parameters.get(&glow::TEXTURE_BASE_LEVEL), Some(&1));
+            assert_eq!(texture.parameters.get(&glow::TEXTURE_MAX_LEVEL), Some(&1));
+            assert_eq!(
+                texture.float_parameters.get(&glow::TEXTURE_LOD_BIAS),
+                Some(&-0.5)
+            );
+        }
+        let textures = ctx.resource_stats().textures.count;
+        for levels in [&[][..], &[&[0; 8][..], &[0; 4][..]][..]] {
+            assert!(matches!(
+                Image::with_mipmaps(
+                    &mut ctx,
+                    TextureType::Tex2D,
+                    PixelFormat::RG8,
+                    2,
+                    2,
+                    levels,
+                    Default::default(),
+                ),
+                Err(crate::GraphicsError::InvalidTextureData)
+            ));
+        }
+        assert_eq!(ctx.resource_stats().textures.count, textures);
+
+        ctx.set_texture_max_level(image.get_texture_key(), TextureType::Tex2D, 0);
+        {
+            let recording = backend.recording();
+            let texture = &recording.textures[image.get_texture_key()];
+            assert_eq!(texture.parameters.get(&glow::TEXTURE_BASE_LEVEL), Some(&0));
+            assert_eq!(texture.parameters.get(&glow::TEXTURE_MAX_LEVEL), Some(&0));
+        }
+
+        let canvas = |ctx: &mut Context, mipmap_mode| {
+            Canvas::new(
+                ctx,
+                canvas::Settings {
+                    width: 4,
+                    height: 4,
+                    mipmap_mode,
+                    msaa: 4,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        let draw = |ctx: &mut Context, canvas: &Canvas| {
+            Renderer::clear(
+                ctx,
+                ClearSettings {
+                    target: Some(canvas),
+                    ..Default::default()
+                },
+            );
+            ctx.bind_texture_to_unit(
+                canvas.get_texture_type(),
+                canvas.get_texture_key(),
+                1.into(),
+            );
+        };
+
+        let manual = canvas(&mut ctx, canvas::MipmapMode::Manual);
+        let allocated = generations(&backend, &manual);
+        draw(&mut ctx, &manual);
+        assert_eq!(generations(&backend, &manual), allocated);
+        manual.generate_mipmaps(&mut ctx);
+        assert_eq!(generations(&backend, &manual), allocated + 1);
+
+        // regenerated once each time it's rendered to
+        let auto = canvas(&mut ctx, canvas::MipmapMode::Auto);
+        let allocated = generations(&backend, &auto);
+        draw(&mut ctx, &auto);
+        assert_eq!(generations(&backend, &auto), allocated + 1);
+        ctx.bind_texture_to_unit(auto.get_texture_type(), auto.get_texture_key(), 2.into());
+        assert_eq!(generations(&backend, &auto), allocated + 1);
+        draw(&mut ctx, &auto);
+        assert_eq!(generations(&backend, &auto), allocated + 2);
+
+        let none = canvas(&mut ctx, canvas::MipmapMode::None);
+        none.generate_mipmaps(&mut ctx);
+        assert_eq!(generations(&backend, &none), 0);
+    }
+
+    #[test]
+    fn texture_containers() {
+        use crate::image::TextureContainer;
+        let (mut ctx, backend) = context();
+
+        // a 3x2 RGB8 image with a 1x1 mipmap
+        let container = TextureContainer {
+            texture_type: TextureType::Tex2D,
+            format: PixelFormat::RGB8,
+            width: 3,
+            height: 2,
+            depth: 1,
+            levels: vec![(0..18).collect(), vec![18, 19, 20]],
+        };
+        let image = Image::with_container(&mut ctx, &container, Default::default()).unwrap();
+        assert!(image.get_texture_info().mipmaps());
+        {
+            let recording = backend.recording();
+            let texture = &recording.textures[image.get_texture_key()];
+            assert_eq!(texture.data, container.levels[0]);
+            assert_eq!(texture.mipmaps, vec![vec![18, 19, 20]]);
+            assert_eq!(texture.parameters.get(&glow::TEXTURE_MAX_LEVEL), Some(&1));
+            assert_eq!(texture.mipmap_generations, 0);
+        }
+
+        // an array of two 8x8 BC4 layers
+        let container = TextureContainer {
+            texture_type: TextureType::Tex2DArray,
+            format: PixelFormat::BC4,
+            width: 8,
+            height: 8,
+            depth: 2,
+            levels: vec![vec![0; 64], vec![1; 16]],
+        };
+        let image = Image::with_container(&mut ctx, &container, Default::default()).unwrap();
+        {
+            let recording = backend.recording();
+            let texture = &recording.textures[image.get_texture_key()];
+            assert_eq!(texture.internal_format, glow::COMPRESSED_RED_RGTC1 as i32);
+            assert_eq!(texture.depth, 2);
+            assert_eq!(texture.mipmaps, vec![vec![1; 16]]);
+        }
+
+        // a 2x2 cube map, faces one after another
+        let container = TextureContainer {
+            texture_type: TextureType::Cube,
+            format: PixelFormat::RGBA8,
+            width: 2,
+            height: 2,
+            depth: 6,
+            levels: vec![(0..6).flat_map(|face| vec![face; 16]).collect()],
+        };
+        let image = Image::with_container(&mut ctx, &container, Default::default()).unwrap();
+        let recording = backend.recording();
+        let texture = &recording.textures[image.get_texture_key()];
+        assert_eq!(texture.depth, 6);
+        assert_eq!(texture.data, container.levels[0]);
+    }
+}
@@ -0,0 +1,144 @@
+use super::{dds, ktx};
+use crate::{
+    texture::{TextureInfo, TextureType},
+    PixelFormat,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    /// Not a KTX, KTX2 or DDS file.
+    UnknownContainer,
+    /// The file ends before all of the data its header describes.
+    Truncated,
+    /// The header contradicts itself or the data that follows it.
+    Invalid(&'static str),
+    /// The container's own code for a pixel format that has no `PixelFormat` equivalent. That's
+    /// the `glInternalFormat` for KTX, the `VkFormat` for KTX2 and the FourCC or DXGI format for
+    /// DDS, or zero for DDS formats that are described by bit masks.
+    UnsupportedFormat(u32),
+    /// A feature of the container that isn't supported, like supercompression or cube map arrays.
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+/// The contents of a KTX, KTX2 or DDS file, ready to be turned into an `Image` with
+/// `Image::with_container`.
+///
+/// Only the data GL needs is kept, metadata like orientation and color space hints is ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureContainer {
+    pub texture_type: TextureType,
+    pub format: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+    /// The depth of a volume texture, the layer count of an array texture and six for a cube
+    /// map. Always one for 2D textures.
+    pub depth: u32,
+    /// Mipmap levels, largest first, each holding every layer or face one after another. Volume
+    /// textures lose depth slices at smaller levels, the others keep their layers.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureContainer {
+    /// Reads whichever of the supported containers `bytes` turn out to hold.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ContainerError> {
+        if bytes.starts_with(&ktx::KTX_IDENTIFIER) {
+            ktx::parse_ktx(bytes)
+        } else if bytes.starts_with(&ktx::KTX2_IDENTIFIER) {
+            ktx::parse_ktx2(bytes)
+        } else if bytes.starts_with(dds::DDS_MAGIC) {
+            dds::parse(bytes)
+        } else {
+            Err(ContainerError::UnknownContainer)
+        }
+    }
+
+    /// The number of bytes every layer of a mipmap level takes up.
+    pub fn level_size(&self, level: u32) -> usize {
+        let mut info = TextureInfo::new(
+            self.format,
+            self.width,
+            self.height,
+            Default::default(),
+            Default::default(),
+            false,
+        );
+        info.set_depth(self.depth);
+        crate::level_size(info, self.texture_type, level)
+    }
+}
+
+// levels past 1x1 can't exist, checked before anything is allocated for them
+pub(super) fn check_level_count(
+    container: &TextureContainer,
+    level_count: u32,
+) -> Result<(), ContainerError> {
+    let depth = match container.texture_type {
+        TextureType::Volume => container.depth,
+        _ => 1,
+    };
+    let largest = container.width.max(container.height).max(depth).max(1);
+    if level_count > 32 - largest.leading_zeros() {
+        return Err(ContainerError::Invalid(
+            "more mipmap levels than the dimensions allow",
+        ));
+    }
+    Ok(())
+}
+
+pub(super) fn read_bytes(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], ContainerError> {
+    offset
+        .checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or(ContainerError::Truncated)
+}
+
+pub(super) fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ContainerError> {
+    let b = read_bytes(bytes, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+pub(super) fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ContainerError> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(high << 32 | low)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub(in crate::image) fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn unknown_container() {
+        assert_eq!(
+            TextureContainer::parse(b"nope"),
+            Err(ContainerError::UnknownContainer)
+        );
+    }
+
+    #[test]
+    fn level_sizes() {
+        let container = TextureContainer {
+            texture_type: TextureType::Volume,
+            format: PixelFormat::RGBA8,
+            width: 4,
+            height: 2,
+            depth: 2,
+            levels: vec![],
+        };
+        assert_eq!(container.level_size(0), 4 * 2 * 2 * 4);
+        assert_eq!(container.level_size(1), 2 * 4);
+        assert_eq!(container.level_size(40), 4);
+    }
+}
@@ -0,0 +1,261 @@
+use super::container::{check_level_count, read_bytes, read_u32, ContainerError, TextureContainer};
+use crate::{texture::TextureType, PixelFormat};
+
+pub(super) const DDS_MAGIC: &[u8] = b"DDS ";
+
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+pub(super) fn parse(bytes: &[u8]) -> Result<TextureContainer, ContainerError> {
+    if read_u32(bytes, 4)? != 124 {
+        return Err(ContainerError::Invalid("the header should be 124 bytes"));
+    }
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let volume_depth = read_u32(bytes, 24)?;
+    let level_count = if flags & DDSD_MIPMAPCOUNT == 0 {
+        1
+    } else {
+        read_u32(bytes, 28)?.max(1)
+    };
+    let caps2 = read_u32(bytes, 112)?;
+
+    let (format, bgr, cube, volume, array_size, mut offset) =
+        if read_u32(bytes, 84)? == four_cc(b"DX10") {
+            let dxgi_format = read_u32(bytes, 128)?;
+            let (format, bgr) = format_from_dxgi(dxgi_format)
+                .ok_or(ContainerError::UnsupportedFormat(dxgi_format))?;
+            let volume = read_u32(bytes, 132)? == D3D10_RESOURCE_DIMENSION_TEXTURE3D;
+            let cube = read_u32(bytes, 136)? & D3D10_RESOURCE_MISC_TEXTURECUBE != 0;
+            let array_size = read_u32(bytes, 140)?.max(1);
+            (format, bgr, cube, volume, array_size, 148)
+        } else {
+            let (format, bgr) = legacy_format(bytes)?;
+            let cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+            if cube && caps2 & DDSCAPS2_CUBEMAP_ALLFACES != DDSCAPS2_CUBEMAP_ALLFACES {
+                return Err(ContainerError::Unsupported(
+                    "cube maps without all six faces",
+                ));
+            }
+            let volume = caps2 & DDSCAPS2_VOLUME != 0;
+            (format, bgr, cube, volume, 1, 128)
+        };
+    let (texture_type, depth, layers) = match (cube, volume, array_size) {
+        (true, _, 1) => (TextureType::Cube, 6, 6),
+        (true, _, _) => return Err(ContainerError::Unsupported("cube map arrays")),
+        (false, true, 1) => (TextureType::Volume, volume_depth.max(1), 1),
+        (false, true, _) => return Err(ContainerError::Invalid("volume textures can't be arrays")),
+        (false, false, 1) => (TextureType::Tex2D, 1, 1),
+        (false, false, array_size) => (TextureType::Tex2DArray, array_size, array_size),
+    };
+
+    let mut container = TextureContainer {
+        texture_type,
+        format,
+        width,
+        height,
+        depth,
+        levels: vec![],
+    };
+    check_level_count(&container, level_count)?;
+    container.levels = vec![vec![]; level_count as usize];
+    // every layer, or face, is stored with all of its levels before the next one starts
+    for _ in 0..layers {
+        for level in 0..level_count {
+            let size = container.level_size(level) / layers as usize;
+            let data = read_bytes(bytes, offset, size)?;
+            let level = &mut container.levels[level as usize];
+            let start = level.len();
+            level.extend_from_slice(data);
+            if bgr {
+                let stride = crate::gl::pixel_format::size(format);
+                for pixel in level[start..].chunks_mut(stride) {
+                    pixel.swap(0, 2);
+                }
+            }
+            offset += size;
+        }
+    }
+    Ok(container)
+}
+
+// the second value is whether red and blue are swapped
+fn legacy_format(bytes: &[u8]) -> Result<(PixelFormat, bool), ContainerError> {
+    let flags = read_u32(bytes, 80)?;
+    let code = read_u32(bytes, 84)?;
+    if flags & DDPF_FOURCC != 0 {
+        let format = match &code.to_le_bytes() {
+            b"DXT1" => PixelFormat::BC1,
+            b"DXT2" | b"DXT3" => PixelFormat::BC2,
+            b"DXT4" | b"DXT5" => PixelFormat::BC3,
+            b"ATI1" | b"BC4U" => PixelFormat::BC4,
+            b"BC4S" => PixelFormat::BC4Signed,
+            b"ATI2" | b"BC5U" => PixelFormat::BC5,
+            b"BC5S" => PixelFormat::BC5Signed,
+            // D3DFORMAT values
+            _ => match code {
+                36 => PixelFormat::RGBA16,
+                111 => PixelFormat::R16F,
+                112 => PixelFormat::RG16F,
+                113 => PixelFormat::RGBA16F,
+                114 => PixelFormat::R32F,
+                115 => PixelFormat::RG32F,
+                116 => PixelFormat::RGBA32F,
+                _ => return Err(ContainerError::UnsupportedFormat(code)),
+            },
+        };
+        return Ok((format, false));
+    }
+
+    let bits = read_u32(bytes, 88)?;
+    let masks = [
+        read_u32(bytes, 92)?,
+        read_u32(bytes, 96)?,
+        read_u32(bytes, 100)?,
+        read_u32(bytes, 104)?,
+    ];
+    let format = if flags & DDPF_RGB != 0 {
+        match (bits, masks) {
+            (32, [0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]) => (PixelFormat::RGBA8, false),
+            (32, [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]) => (PixelFormat::RGBA8, true),
+            (24, [0xFF, 0xFF00, 0xFF_0000, 0]) => (PixelFormat::RGB8, false),
+            (24, [0xFF_0000, 0xFF00, 0xFF, 0]) => (PixelFormat::RGB8, true),
+            (32, [0xFFFF, 0xFFFF_0000, 0, 0]) => (PixelFormat::RG16, false),
+            (16, [0xFF, 0xFF00, 0, 0]) => (PixelFormat::RG8, false),
+            _ => return Err(ContainerError::UnsupportedFormat(0)),
+        }
+    } else if flags & DDPF_LUMINANCE != 0 && bits == 8 {
+        (PixelFormat::LUMINANCE, false)
+    } else if flags & DDPF_ALPHA != 0 && bits == 8 {
+        (PixelFormat::Alpha, false)
+    } else {
+        return Err(ContainerError::UnsupportedFormat(0));
+    };
+    Ok(format)
+}
+
+// the second value is whether red and blue are swapped
+fn format_from_dxgi(dxgi_format: u32) -> Option<(PixelFormat, bool)> {
+    let format = match dxgi_format {
+        2 => PixelFormat::RGBA32F,
+        10 => PixelFormat::RGBA16F,
+        11 => PixelFormat::RGBA16,
+        16 => PixelFormat::RG32F,
+        28 => PixelFormat::RGBA8,
+        29 => PixelFormat::SRGBA8,
+        34 => PixelFormat::RG16F,
+        35 => PixelFormat::RG16,
+        41 => PixelFormat::R32F,
+        49 => PixelFormat::RG8,
+        54 => PixelFormat::R16F,
+        56 => PixelFormat::R16,
+        65 => PixelFormat::Alpha,
+        71 => PixelFormat::BC1,
+        74 => PixelFormat::BC2,
+        77 => PixelFormat::BC3,
+        80 => PixelFormat::BC4,
+        81 => PixelFormat::BC4Signed,
+        83 => PixelFormat::BC5,
+        84 => PixelFormat::BC5Signed,
+        87 => return Some((PixelFormat::RGBA8, true)),
+        95 => PixelFormat::BC6H,
+        96 => PixelFormat::BC6HSigned,
+        98 => PixelFormat::BC7,
+        _ => return None,
+    };
+    Some((format, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::container::tests::words;
+
+    fn header(flags: u32, width: u32, height: u32, level_count: u32) -> Vec<u8> {
+        let mut dds = DDS_MAGIC.to_vec();
+        let mut header = [0; 31];
+        header[..4].copy_from_slice(&[124, flags, height, width]);
+        header[6] = level_count;
+        dds.extend(words(&header));
+        dds
+    }
+
+    #[test]
+    fn cube_map() {
+        // a 2x2 BGRA8 cube map
+        let mut dds = header(0x1007, 2, 2, 0);
+        let pixel_format = words(&[
+            32,
+            DDPF_RGB | 0x1,
+            0,
+            32,
+            0xFF_0000,
+            0xFF00,
+            0xFF,
+            0xFF00_0000,
+        ]);
+        dds[76..108].copy_from_slice(&pixel_format);
+        dds[108..124].copy_from_slice(&words(&[
+            0x1008,
+            DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES,
+            0,
+            0,
+        ]));
+        for face in 0..6 {
+            dds.extend([face, 1, 2, 3].iter().cycle().take(16));
+        }
+        let container = parse(&dds).unwrap();
+        assert_eq!(container.texture_type, TextureType::Cube);
+        assert_eq!(container.format, PixelFormat::RGBA8);
+        assert_eq!(container.depth, 6);
+        assert_eq!(container.levels.len(), 1);
+        // swizzled from BGRA
+        for face in 0..6 {
+            assert_eq!(
+                &container.levels[0][face * 16..][..4],
+                &[2, 1, face as u8, 3]
+            );
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        // a 1x1 RGBA8 image, first with more levels than can be allocated and then with more
+        // levels than a 1x1 texture has
+        let dds = |level_count: u32| {
+            let mut dds = header(0x2_1007, 1, 1, level_count);
+            let pixel_format = words(&[
+                32,
+                DDPF_RGB | 0x1,
+                0,
+                32,
+                0xFF,
+                0xFF00,
+                0xFF_0000,
+                0xFF00_0000,
+            ]);
+            dds[76..108].copy_from_slice(&pixel_format);
+            dds
+        };
+        let too_many_levels = Err(ContainerError::Invalid(
+            "more mipmap levels than the dimensions allow",
+        ));
+        assert_eq!(dds(u32::MAX).len(), 128);
+        assert_eq!(parse(&dds(u32::MAX)), too_many_levels);
+        assert_eq!(parse(&dds(40)), too_many_levels);
+        assert_eq!(parse(&dds(1)), Err(ContainerError::Truncated));
+    }
+}
@@ -0,0 +1,347 @@
+use super::container::{
+    check_level_count, read_bytes, read_u32, read_u64, ContainerError, TextureContainer,
+};
+use crate::{gl::pixel_format, texture::TextureType, PixelFormat};
+
+pub(super) const KTX_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+pub(super) const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+// a zero height means a 1D texture, a zero depth a texture that isn't a volume and zero layers one
+// that isn't an array
+fn texture_type(
+    height: u32,
+    depth: u32,
+    layers: u32,
+    faces: u32,
+) -> Result<(TextureType, u32, u32), ContainerError> {
+    let height = height.max(1);
+    match (depth, layers, faces) {
+        (0, 0, 6) => Ok((TextureType::Cube, height, 6)),
+        (_, _, 6) => Err(ContainerError::Unsupported("cube map arrays")),
+        (_, _, faces) if faces != 1 => Err(ContainerError::Invalid("faces must be 1 or 6")),
+        (0, 0, _) => Ok((TextureType::Tex2D, height, 1)),
+        (0, layers, _) => Ok((TextureType::Tex2DArray, height, layers)),
+        (depth, 0, _) => Ok((TextureType::Volume, height, depth)),
+        _ => Err(ContainerError::Invalid("volume textures can't be arrays")),
+    }
+}
+
+pub(super) fn parse_ktx(bytes: &[u8]) -> Result<TextureContainer, ContainerError> {
+    let swap = match read_u32(bytes, 12)? {
+        0x0403_0201 => false,
+        0x0102_0304 => true,
+        _ => return Err(ContainerError::Invalid("unknown endianness")),
+    };
+    let field = |offset| read_u32(bytes, offset).map(|v| if swap { v.swap_bytes() } else { v });
+    let gl_type = field(16)?;
+    let type_size = field(20)? as usize;
+    let internal_format = field(28)?;
+    let width = field(36)?;
+    let (texture_type, height, depth) =
+        texture_type(field(40)?, field(44)?, field(48)?, field(52)?)?;
+    let level_count = field(56)?.max(1);
+    let format = pixel_format::from_gl(internal_format)
+        .ok_or(ContainerError::UnsupportedFormat(internal_format))?;
+    if format.is_compressed() != (gl_type == 0) {
+        return Err(ContainerError::Invalid(
+            "glType doesn't match glInternalFormat",
+        ));
+    }
+
+    let mut container = TextureContainer {
+        texture_type,
+        format,
+        width,
+        height,
+        depth,
+        levels: vec![],
+    };
+    check_level_count(&container, level_count)?;
+    let mut offset = 64 + field(60)? as usize;
+    for level in 0..level_count {
+        let image_size = field(offset)? as usize;
+        offset += 4;
+        // cube maps that aren't arrays give the size of a single face
+        let images = if texture_type == TextureType::Cube {
+            6
+        } else {
+            1
+        };
+        // the header can claim any size, only what's actually in the file is allocated
+        let remaining = bytes.len().saturating_sub(offset);
+        if image_size.saturating_mul(images) > remaining {
+            return Err(ContainerError::Truncated);
+        }
+        let mut data = Vec::with_capacity(image_size * images);
+        for _ in 0..images {
+            data.extend_from_slice(read_bytes(bytes, offset, image_size)?);
+            offset += align(image_size);
+        }
+        if !format.is_compressed() {
+            // rows are padded to four bytes
+            let row = (width >> level).max(1) as usize * pixel_format::size(format);
+            if align(row) != row {
+                data = data
+                    .chunks(align(row))
+                    .flat_map(|padded| &padded[..row.min(padded.len())])
+                    .copied()
+                    .collect();
+            }
+            if swap && type_size > 1 {
+                for element in data.chunks_mut(type_size) {
+                    element.reverse();
+                }
+            }
+        }
+        if data.len() != container.level_size(level) {
+            return Err(ContainerError::Invalid(
+                "imageSize doesn't match the dimensions",
+            ));
+        }
+        container.levels.push(data);
+    }
+    Ok(container)
+}
+
+pub(super) fn parse_ktx2(bytes: &[u8]) -> Result<TextureContainer, ContainerError> {
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let (texture_type, height, depth) = texture_type(
+        read_u32(bytes, 24)?,
+        read_u32(bytes, 28)?,
+        read_u32(bytes, 32)?,
+        read_u32(bytes, 36)?,
+    )?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    if vk_format == 0 {
+        return Err(ContainerError::Unsupported("Basis Universal"));
+    }
+    if read_u32(bytes, 44)? != 0 {
+        return Err(ContainerError::Unsupported("supercompression"));
+    }
+    let format = format_from_vk(vk_format).ok_or(ContainerError::UnsupportedFormat(vk_format))?;
+
+    let mut container = TextureContainer {
+        texture_type,
+        format,
+        width,
+        height,
+        depth,
+        levels: vec![],
+    };
+    check_level_count(&container, level_count)?;
+    // the level index follows the 80 byte header, largest level first
+    for level in 0..level_count {
+        let index = 80 + level as usize * 24;
+        let offset = read_u64(bytes, index)? as usize;
+        let length = read_u64(bytes, index + 8)? as usize;
+        if length != container.level_size(level) {
+            return Err(ContainerError::Invalid(
+                "byteLength doesn't match the dimensions",
+            ));
+        }
+        container
+            .levels
+            .push(read_bytes(bytes, offset, length)?.to_vec());
+    }
+    Ok(container)
+}
+
+fn align(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+// BC1 is the variant with punch-through alpha, VkFormat 131 without it has no `PixelFormat`.
+// Neither do the sRGB block formats: 132, 134, 136, 138 and 146 for BC, 148, 150 and 152 for
+// ETC2 and the even codes from 158 to 184 for ASTC. They're all rejected.
+fn format_from_vk(vk_format: u32) -> Option<PixelFormat> {
+    let format = match vk_format {
+        16 => PixelFormat::RG8,
+        23 => PixelFormat::RGB8,
+        37 => PixelFormat::RGBA8,
+        43 => PixelFormat::SRGBA8,
+        70 => PixelFormat::R16,
+        76 => PixelFormat::R16F,
+        77 => PixelFormat::RG16,
+        83 => PixelFormat::RG16F,
+        91 => PixelFormat::RGBA16,
+        97 => PixelFormat::RGBA16F,
+        100 => PixelFormat::R32F,
+        103 => PixelFormat::RG32F,
+        109 => PixelFormat::RGBA32F,
+        133 => PixelFormat::BC1,
+        135 => PixelFormat::BC2,
+        137 => PixelFormat::BC3,
+        139 => PixelFormat::BC4,
+        140 => PixelFormat::BC4Signed,
+        141 => PixelFormat::BC5,
+        142 => PixelFormat::BC5Signed,
+        143 => PixelFormat::BC6H,
+        144 => PixelFormat::BC6HSigned,
+        145 => PixelFormat::BC7,
+        147 => PixelFormat::ETC2RGB8,
+        149 => PixelFormat::ETC2RGB8A1,
+        151 => PixelFormat::ETC2RGBA8,
+        153 => PixelFormat::EACR11,
+        154 => PixelFormat::EACR11Signed,
+        155 => PixelFormat::EACRG11,
+        156 => PixelFormat::EACRG11Signed,
+        157 => PixelFormat::ASTC4x4,
+        159 => PixelFormat::ASTC5x4,
+        161 => PixelFormat::ASTC5x5,
+        163 => PixelFormat::ASTC6x5,
+        165 => PixelFormat::ASTC6x6,
+        167 => PixelFormat::ASTC8x5,
+        169 => PixelFormat::ASTC8x6,
+        171 => PixelFormat::ASTC8x8,
+        173 => PixelFormat::ASTC10x5,
+        175 => PixelFormat::ASTC10x6,
+        177 => PixelFormat::ASTC10x8,
+        179 => PixelFormat::ASTC10x10,
+        181 => PixelFormat::ASTC12x10,
+        183 => PixelFormat::ASTC12x12,
+        _ => return None,
+    };
+    Some(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::container::tests::words;
+
+    fn ktx(header: &[u32]) -> Vec<u8> {
+        let mut ktx = KTX_IDENTIFIER.to_vec();
+        ktx.extend(words(header));
+        ktx
+    }
+
+    fn ktx2(header: &[u32]) -> Vec<u8> {
+        let mut ktx2 = KTX2_IDENTIFIER.to_vec();
+        ktx2.extend(words(header));
+        ktx2
+    }
+
+    #[test]
+    fn ktx_levels() {
+        // 3x2 RGB8 with rows padded to four bytes and a 1x1 mipmap
+        let mut bytes = ktx(&[
+            0x0403_0201,
+            glow::UNSIGNED_BYTE,
+            1,
+            glow::RGB,
+            glow::RGB8,
+            glow::RGB,
+            3,
+            2,
+            0,
+            0,
+            1,
+            2,
+            0,
+        ]);
+        bytes.extend(words(&[24]));
+        bytes.extend((0..9).chain([0; 3]).chain(9..18).chain([0; 3]));
+        bytes.extend(words(&[4]));
+        bytes.extend(&[18, 19, 20, 0]);
+        let container = parse_ktx(&bytes).unwrap();
+        assert_eq!(container.texture_type, TextureType::Tex2D);
+        assert_eq!(container.format, PixelFormat::RGB8);
+        assert_eq!(
+            (container.width, container.height, container.depth),
+            (3, 2, 1)
+        );
+        assert_eq!(
+            container.levels,
+            vec![(0..18).collect::<Vec<u8>>(), vec![18, 19, 20]]
+        );
+        assert_eq!(parse_ktx(&bytes[..70]), Err(ContainerError::Truncated));
+    }
+
+    #[test]
+    fn malformed_ktx() {
+        // claims to hold a 65535x65535 RGBA32F image that isn't there
+        let header = |level_count: u32, image_size: u32| {
+            ktx(&[
+                0x0403_0201,
+                glow::FLOAT,
+                4,
+                glow::RGBA,
+                glow::RGBA32F,
+                glow::RGBA,
+                65535,
+                65535,
+                0,
+                0,
+                1,
+                level_count,
+                0,
+                image_size,
+            ])
+        };
+        assert_eq!(header(1, u32::MAX).len(), 68);
+        assert_eq!(
+            parse_ktx(&header(1, u32::MAX)),
+            Err(ContainerError::Truncated)
+        );
+        assert_eq!(
+            parse_ktx(&header(17, 0)),
+            Err(ContainerError::Invalid(
+                "more mipmap levels than the dimensions allow"
+            ))
+        );
+    }
+
+    #[test]
+    fn ktx2_levels() {
+        // an array of two 8x8 BC4 layers, levels are stored smallest first
+        let mut bytes = ktx2(&[139, 1, 8, 8, 0, 2, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend(words(&[144, 0, 64, 0, 64, 0, 128, 0, 16, 0, 16, 0]));
+        bytes.extend(&[1; 16]);
+        bytes.extend(&[0; 64]);
+        let container = parse_ktx2(&bytes).unwrap();
+        assert_eq!(container.texture_type, TextureType::Tex2DArray);
+        assert_eq!(container.format, PixelFormat::BC4);
+        assert_eq!(container.depth, 2);
+        assert_eq!(container.levels, vec![vec![0; 64], vec![1; 16]]);
+    }
+
+    #[test]
+    fn vk_formats() {
+        assert_eq!(format_from_vk(133), Some(PixelFormat::BC1));
+        assert_eq!(format_from_vk(145), Some(PixelFormat::BC7));
+        assert_eq!(format_from_vk(147), Some(PixelFormat::ETC2RGB8));
+        // opaque BC1 and the sRGB block formats
+        for vk_format in [131, 132, 134, 136, 138, 146, 148, 150, 152, 158, 184] {
+            assert_eq!(format_from_vk(vk_format), None);
+        }
+
+        let bytes = ktx2(&[131, 1, 4, 4, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            parse_ktx2(&bytes),
+            Err(ContainerError::UnsupportedFormat(131))
+        );
+    }
+
+    #[test]
+    fn malformed_ktx2() {
+        // a 2x2 RGBA8 image with one level cut short
+        let header = |level_count: u32| {
+            let mut bytes = ktx2(&[37, 1, 2, 2, 0, 0, 1, level_count, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            bytes.extend(words(&[104, 0, 16, 0, 16, 0]));
+            bytes.extend(&[0; 8]);
+            bytes
+        };
+        assert_eq!(
+            parse_ktx2(&header(3)),
+            Err(ContainerError::Invalid(
+                "more mipmap levels than the dimensions allow"
+            ))
+        );
+        assert_eq!(parse_ktx2(&header(1)), Err(ContainerError::Truncated));
+    }
+}
@@ -3,11 +3,14 @@ pub use glow;
 #[cfg(feature = "derive")]
 extern crate solstice_derive;
 
+pub mod backend;
 pub mod buffer;
 pub mod canvas;
 pub mod image;
 pub mod mesh;
 pub mod quad_batch;
+pub mod query;
+pub mod resource;
 pub mod shader;
 pub mod texture;
 pub mod vertex;
@@ -15,8 +18,8 @@ pub mod viewport;
 
 mod gl;
 
-use glow::HasContext;
-use slotmap::SlotMap;
+use backend::Backend;
+use slotmap::{SecondaryMap, SlotMap};
 use std::{
     fmt::{Debug, Error, Formatter},
     str::FromStr,
@@ -29,6 +32,16 @@ pub enum GraphicsError {
     BufferError,
     FramebufferError,
     RenderbufferError,
+    QueryError,
+    /// The driver rejected the combination of attachments.
+    IncompleteFramebuffer(canvas::Status),
+    UnsupportedTextureType(texture::TextureType),
+    /// The format can't be used with this `GLVersion` and the extensions it has.
+    UnsupportedPixelFormat(PixelFormat),
+    UnsupportedQueryType(query::QueryType),
+    OutOfMemory,
+    /// The data passed in doesn't fit the texture, like the wrong number of layers.
+    InvalidTextureData,
 }
 
 impl std::fmt::Display for GraphicsError {
@@ -39,33 +52,24 @@ impl std::fmt::Display for GraphicsError {
 
 impl std::error::Error for GraphicsError {}
 
-type GLContext = glow::Context;
-
-type GLBuffer = <GLContext as HasContext>::Buffer;
-type GLProgram = <GLContext as HasContext>::Program;
-type GLTexture = <GLContext as HasContext>::Texture;
-type GLFramebuffer = <GLContext as HasContext>::Framebuffer;
-type GLRenderbuffer = <GLContext as HasContext>::Renderbuffer;
-type GLUniformLocation = <GLContext as HasContext>::UniformLocation;
-
 slotmap::new_key_type! {
     pub struct ShaderKey;
     pub struct BufferKey;
     pub struct TextureKey;
     pub struct FramebufferKey;
     pub struct RenderbufferKey;
+    pub struct QueryKey;
 }
 
 pub struct DebugGroup<'a> {
-    ctx: &'a GLContext,
+    ctx: &'a dyn Backend,
 }
 
 impl<'a> DebugGroup<'a> {
-    pub fn new(ctx: &'a GLContext, message: &str) -> Self {
+    pub fn new(ctx: &'a Context, message: &str) -> Self {
+        let ctx = ctx.backend.as_ref();
         if ctx.supports_debug() {
-            unsafe {
-                ctx.push_debug_group(glow::DEBUG_SOURCE_APPLICATION, 0, message);
-            }
+            ctx.push_debug_group(message);
         }
         Self { ctx }
     }
@@ -74,9 +78,7 @@ impl<'a> DebugGroup<'a> {
 impl<'a> Drop for DebugGroup<'a> {
     fn drop(&mut self) {
         if self.ctx.supports_debug() {
-            unsafe {
-                self.ctx.pop_debug_group();
-            }
+            self.ctx.pop_debug_group();
         }
     }
 }
@@ -110,6 +112,71 @@ pub enum PixelFormat {
     Depth32F,
     Depth24Stencil8,
     Depth32fStencil8,
+
+    // block-compressed formats: S3TC/RGTC/BPTC
+    BC1,
+    BC2,
+    BC3,
+    BC4,
+    BC4Signed,
+    BC5,
+    BC5Signed,
+    BC6H,
+    BC6HSigned,
+    BC7,
+
+    // block-compressed formats: ETC2/EAC
+    ETC2RGB8,
+    ETC2RGB8A1,
+    ETC2RGBA8,
+    EACR11,
+    EACR11Signed,
+    EACRG11,
+    EACRG11Signed,
+
+    // block-compressed formats: ASTC LDR
+    ASTC4x4,
+    ASTC5x4,
+    ASTC5x5,
+    ASTC6x5,
+    ASTC6x6,
+    ASTC8x5,
+    ASTC8x6,
+    ASTC8x8,
+    ASTC10x5,
+    ASTC10x6,
+    ASTC10x8,
+    ASTC10x10,
+    ASTC12x10,
+    ASTC12x12,
+}
+
+impl PixelFormat {
+    /// Whether the format has a depth component.
+    pub fn is_depth(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Depth16
+                | PixelFormat::Depth24
+                | PixelFormat::Depth32F
+                | PixelFormat::Depth24Stencil8
+                | PixelFormat::Depth32fStencil8
+        )
+    }
+
+    /// Whether the format has a stencil component.
+    pub fn is_stencil(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Stencil8 | PixelFormat::Depth24Stencil8 | PixelFormat::Depth32fStencil8
+        )
+    }
+
+    /// Whether the format is stored in compressed blocks of pixels. Data for these formats is
+    /// uploaded as-is and can't be rendered to.
+    pub fn is_compressed(self) -> bool {
+        gl::pixel_format::compressed_to_gl(self).is_some()
+    }
 }
 
 fn target_to_index(target: canvas::Target) -> usize {
@@ -124,6 +191,7 @@ fn buffer_type_to_index(buffer_type: buffer::BufferType) -> usize {
     match buffer_type {
         buffer::BufferType::Vertex => 0,
         buffer::BufferType::Index => 1,
+        buffer::BufferType::Uniform => 2,
     }
 }
 
@@ -185,6 +253,72 @@ pub enum Feature {
 struct GLConstants {
     max_vertex_attributes: usize,
     max_texture_units: usize,
+    max_samples: usize,
+    max_uniform_buffer_bindings: usize,
+}
+
+// a multisampled framebuffer is resolved into `framebuffer` whose color attachments are `textures`
+// and, optionally, whose depth/stencil attachment is `depth_stencil`
+#[derive(Clone, Debug)]
+struct MultisampleResolve {
+    framebuffer: FramebufferKey,
+    textures: Vec<TextureKey>,
+    depth_stencil: Option<(TextureKey, PixelFormat)>,
+    width: i32,
+    height: i32,
+    dirty: bool,
+}
+
+// the mipmaps of `textures` are regenerated once they're bound after a framebuffer has been drawn
+// to
+#[derive(Clone, Debug)]
+struct MipmapRegeneration {
+    textures: Vec<TextureKey>,
+    texture_type: texture::TextureType,
+    dirty: bool,
+}
+
+// mirrors the fixed-function state last sent to GL so that redundant calls can be skipped
+#[derive(Clone, Debug)]
+struct PipelineStateCache {
+    depth_test: bool,
+    depth_function: DepthFunction,
+    depth_write_mask: bool,
+    depth_range: std::ops::RangeInclusive<ClampedF32>,
+    blend: bool,
+    blend_state: BlendState,
+    stencil_test: bool,
+    // `stencil_state.write_mask` is tracked separately since clearing changes it
+    stencil_state: StencilState,
+    stencil_write_mask: u32,
+    cull_face: bool,
+    culling_state: CullingState,
+    polygon_offset_fill: bool,
+    polygon_offset: (f32, f32),
+}
+
+impl Default for PipelineStateCache {
+    // these are the GL defaults except for blending, which `Context::new` enables
+    fn default() -> Self {
+        Self {
+            depth_test: false,
+            depth_function: DepthFunction::Less,
+            depth_write_mask: true,
+            depth_range: ClampedF32(0.)..=ClampedF32(1.),
+            blend: true,
+            blend_state: BlendState::alpha(),
+            stencil_test: false,
+            stencil_state: StencilState::default(),
+            stencil_write_mask: !0,
+            cull_face: false,
+            culling_state: CullingState {
+                mode: CullFace::Back,
+                winding: VertexWinding::CounterClockWise,
+            },
+            polygon_offset_fill: false,
+            polygon_offset: (0., 0.),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -198,7 +332,23 @@ pub enum DrawMode {
     TriangleFan,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// A snapshot of the GL state that `Context` caches, from `Context::save_state`.
+#[derive(Clone, Debug)]
+pub struct ContextState {
+    shader: Option<ShaderKey>,
+    buffers: [Option<BufferKey>; 3],
+    uniform_buffers: Vec<Option<BufferKey>>,
+    textures: Vec<Vec<Option<TextureKey>>>,
+    texture_unit: TextureUnit,
+    framebuffers: [Option<FramebufferKey>; 2],
+    renderbuffer: Option<RenderbufferKey>,
+    viewport: viewport::Viewport<i32>,
+    scissor: Option<viewport::Viewport<i32>>,
+    pipeline: PipelineStateCache,
+    enabled_attributes: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct TextureUnit {
     index: u32,
     gl: u32,
@@ -265,14 +415,13 @@ pub struct GLVersion {
     major: u32,
     minor: u32,
     gles: bool,
+    webgl: bool,
 }
 
 impl FromStr for GLVersion {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // the webgl property is about whether the string was parsed as webgl
-        // rather than whether the gl implementation is webgl
         let (major, minor, gles, webgl) = if s.starts_with("WebGL ") {
             (s.chars().nth(6), s.chars().nth(8), true, true)
         } else if s.contains("OpenGL ES ") {
@@ -292,108 +441,269 @@ impl FromStr for GLVersion {
                 major: major + major_incr as u32,
                 minor: minor as u32,
                 gles,
+                webgl,
             }),
             _ => Err(()),
         }
     }
 }
 
+impl GLVersion {
+    fn supports_texture_type(&self, texture_type: texture::TextureType) -> bool {
+        use texture::TextureType;
+        match texture_type {
+            TextureType::Tex2D | TextureType::Cube => true,
+            TextureType::Volume => !self.gles || self.major >= 3,
+            TextureType::Tex2DArray => self.major >= 3,
+        }
+    }
+
+    // what the version guarantees without extensions, see `Context::supports_pixel_format`
+    fn supports_pixel_format(&self, format: PixelFormat) -> bool {
+        let at_least = |major, minor| (self.major, self.minor) >= (major, minor);
+        match format {
+            PixelFormat::Unknown => false,
+            PixelFormat::LUMINANCE
+            | PixelFormat::Alpha
+            | PixelFormat::RGB8
+            | PixelFormat::RGBA8
+            | PixelFormat::Stencil8
+            | PixelFormat::Depth16 => true,
+            PixelFormat::R16 | PixelFormat::RG16 | PixelFormat::RGBA16 => {
+                !self.gles && self.major >= 3
+            }
+            PixelFormat::RG8
+            | PixelFormat::SRGBA8
+            | PixelFormat::R16F
+            | PixelFormat::RG16F
+            | PixelFormat::RGBA16F
+            | PixelFormat::R32F
+            | PixelFormat::RG32F
+            | PixelFormat::RGBA32F
+            | PixelFormat::Depth24
+            | PixelFormat::Depth32F
+            | PixelFormat::Depth24Stencil8
+            | PixelFormat::Depth32fStencil8 => self.major >= 3,
+            PixelFormat::BC1 | PixelFormat::BC2 | PixelFormat::BC3 => false,
+            PixelFormat::BC4
+            | PixelFormat::BC4Signed
+            | PixelFormat::BC5
+            | PixelFormat::BC5Signed => !self.gles && self.major >= 3,
+            PixelFormat::BC6H | PixelFormat::BC6HSigned | PixelFormat::BC7 => {
+                !self.gles && at_least(4, 2)
+            }
+            PixelFormat::ETC2RGB8
+            | PixelFormat::ETC2RGB8A1
+            | PixelFormat::ETC2RGBA8
+            | PixelFormat::EACR11
+            | PixelFormat::EACR11Signed
+            | PixelFormat::EACRG11
+            | PixelFormat::EACRG11Signed => {
+                // WebGL 2 leaves them to WEBGL_compressed_texture_etc
+                if self.gles {
+                    self.major >= 3 && !self.webgl
+                } else {
+                    at_least(4, 3)
+                }
+            }
+            PixelFormat::ASTC4x4
+            | PixelFormat::ASTC5x4
+            | PixelFormat::ASTC5x5
+            | PixelFormat::ASTC6x5
+            | PixelFormat::ASTC6x6
+            | PixelFormat::ASTC8x5
+            | PixelFormat::ASTC8x6
+            | PixelFormat::ASTC8x8
+            | PixelFormat::ASTC10x5
+            | PixelFormat::ASTC10x6
+            | PixelFormat::ASTC10x8
+            | PixelFormat::ASTC10x10
+            | PixelFormat::ASTC12x10
+            | PixelFormat::ASTC12x12 => self.gles && at_least(3, 2),
+        }
+    }
+}
+
+impl GLVersion {
+    // copying between buffers came with GL 3.1, GLES 3 and WebGL 2
+    fn supports_buffer_copies(&self) -> bool {
+        let at_least = |major, minor| (self.major, self.minor) >= (major, minor);
+        if self.gles {
+            self.major >= 3
+        } else {
+            at_least(3, 1)
+        }
+    }
+
+    // timer queries on GLES and WebGL need EXT_disjoint_timer_query which isn't checked for
+    fn supports_query_type(&self, query_type: query::QueryType) -> bool {
+        use query::QueryType;
+        let at_least = |major, minor| (self.major, self.minor) >= (major, minor);
+        match query_type {
+            QueryType::TimeElapsed | QueryType::SamplesPassed if self.gles => false,
+            QueryType::TimeElapsed => at_least(3, 3),
+            QueryType::SamplesPassed => true,
+            QueryType::AnySamplesPassed => self.major >= 3 && (self.gles || at_least(3, 3)),
+            QueryType::AnySamplesPassedConservative => {
+                self.major >= 3 && (self.gles || at_least(4, 3))
+            }
+        }
+    }
+}
+
 impl Debug for GLVersion {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(
             f,
-            "GLVersion {{ major: {}, minor: {}, ES: {} }}",
-            self.major, self.minor, self.gles
+            "GLVersion {{ major: {}, minor: {}, ES: {}, WebGL: {} }}",
+            self.major, self.minor, self.gles, self.webgl
         )
     }
 }
 
-// a caching, convenience and safety layer around glow
+// a caching, convenience and safety layer around a graphics backend
 pub struct Context {
-    ctx: GLContext,
+    backend: Box<dyn Backend>,
     version: GLVersion,
     gl_constants: GLConstants,
-    shaders: SlotMap<ShaderKey, GLProgram>,
+    extensions: std::collections::HashSet<String>,
+    shaders: SlotMap<ShaderKey, ()>,
     active_shader: Option<ShaderKey>,
-    buffers: SlotMap<BufferKey, GLBuffer>,
-    active_buffers: [Option<BufferKey>; 2],
-    textures: SlotMap<TextureKey, GLTexture>,
-    bound_textures: Vec<Vec<Option<GLTexture>>>,
-    framebuffers: SlotMap<FramebufferKey, GLFramebuffer>,
+    // the values are the estimated sizes in bytes
+    buffers: SlotMap<BufferKey, usize>,
+    active_buffers: [Option<BufferKey>; 3],
+    bound_uniform_buffers: Vec<Option<BufferKey>>,
+    textures: SlotMap<TextureKey, usize>,
+    bound_textures: Vec<Vec<Option<TextureKey>>>,
+    framebuffers: SlotMap<FramebufferKey, ()>,
     active_framebuffer: [Option<FramebufferKey>; 2],
-    renderbuffers: SlotMap<RenderbufferKey, GLRenderbuffer>,
+    multisample_resolves: SecondaryMap<FramebufferKey, MultisampleResolve>,
+    mipmap_regenerations: SecondaryMap<FramebufferKey, MipmapRegeneration>,
+    renderbuffers: SlotMap<RenderbufferKey, usize>,
     active_renderbuffer: Option<RenderbufferKey>,
+    queries: SlotMap<QueryKey, ()>,
+    // the active timer query and the active occlusion query
+    active_queries: [Option<(QueryKey, query::QueryType)>; 2],
+    deletion_queue: resource::DeletionQueue,
+    leak_report: bool,
+    program_cache: Option<Box<dyn shader::ProgramCache>>,
+    attribute_validation: bool,
+    attribute_mismatches: Vec<(ShaderKey, mesh::AttributeMismatch)>,
     current_texture_unit: TextureUnit,
     current_viewport: viewport::Viewport<i32>,
     current_scissor: Option<viewport::Viewport<i32>>,
+    pipeline_state: PipelineStateCache,
     enabled_attributes: u32, // a bitmask that represents the vertex attribute state
 }
 
 impl Context {
-    pub fn new(ctx: GLContext) -> Self {
+    pub fn new(ctx: glow::Context) -> Self {
+        Self::with_backend(backend::GlowBackend::new(ctx))
+    }
+
+    /// Like `new` but creates the `glow::Context` itself so that the GL functions glow doesn't
+    /// provide, like the ones program caching and compressed textures need, get loaded too.
+    ///
+    /// # Safety
+    ///
+    /// The same as `glow::Context::from_loader_function`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn from_loader_function<F>(loader: F) -> Self
+    where
+        F: FnMut(&str) -> *const std::os::raw::c_void,
+    {
+        Self::with_backend(backend::GlowBackend::from_loader_function(loader))
+    }
+
+    /// Like `new` but keeps the extensions the context supports so that `supports_extension`
+    /// works.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_webgl1_context(context: web_sys::WebGlRenderingContext) -> Self {
+        Self::with_backend(backend::GlowBackend::from_webgl1_context(context))
+    }
+
+    /// Like `new` but keeps the extensions the context supports so that `supports_extension`
+    /// works.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_webgl2_context(context: web_sys::WebGl2RenderingContext) -> Self {
+        Self::with_backend(backend::GlowBackend::from_webgl2_context(context))
+    }
+
+    pub fn with_backend<B: Backend + 'static>(backend: B) -> Self {
+        let ctx = backend;
         let gl_constants = GLConstants {
-            max_vertex_attributes: unsafe {
-                ctx.get_parameter_i32(glow::MAX_VERTEX_ATTRIBS) as usize
-            },
-            max_texture_units: unsafe {
-                ctx.get_parameter_i32(glow::MAX_COMBINED_TEXTURE_IMAGE_UNITS) as usize
-            },
+            max_vertex_attributes: ctx.get_parameter_i32(glow::MAX_VERTEX_ATTRIBS) as usize,
+            max_texture_units: ctx.get_parameter_i32(glow::MAX_COMBINED_TEXTURE_IMAGE_UNITS)
+                as usize,
+            max_samples: ctx.get_parameter_i32(glow::MAX_SAMPLES).max(0) as usize,
+            max_uniform_buffer_bindings: ctx
+                .get_parameter_i32(glow::MAX_UNIFORM_BUFFER_BINDINGS)
+                .max(0) as usize,
         };
 
+        let bound_uniform_buffers = vec![None; gl_constants.max_uniform_buffer_bindings];
         let bound_textures = texture::TextureType::enumerate()
             .iter()
             .map(|_tt| vec![None; gl_constants.max_texture_units])
             .collect();
 
+        let version = {
+            let str_version = ctx.get_parameter_string(glow::VERSION);
+            str_version.parse::<GLVersion>().unwrap_or_default()
+        };
+        let extensions = query_extensions(&ctx, &version);
+
         for texture_unit in 0..gl_constants.max_texture_units {
-            unsafe {
-                ctx.active_texture(glow::TEXTURE0 + texture_unit as u32);
-                // do this for every supported texture type
-                for texture_type in texture::TextureType::enumerate() {
-                    if texture_type.is_supported() {
-                        ctx.bind_texture(gl::texture::to_gl(*texture_type), None);
-                    }
+            ctx.active_texture(glow::TEXTURE0 + texture_unit as u32);
+            // do this for every supported texture type
+            for texture_type in texture::TextureType::enumerate() {
+                if texture_type.is_supported() && version.supports_texture_type(*texture_type) {
+                    ctx.bind_texture(gl::texture::to_gl(*texture_type), None);
                 }
             }
         }
-        unsafe { ctx.active_texture(glow::TEXTURE0) }
-        unsafe {
-            // TODO: this should be left to the consumer
-            ctx.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
-            ctx.enable(glow::BLEND);
-            ctx.blend_equation(glow::FUNC_ADD);
-            ctx.blend_func_separate(
-                glow::SRC_ALPHA,
-                glow::ONE_MINUS_SRC_ALPHA,
-                glow::ONE,
-                glow::ONE_MINUS_SRC_ALPHA,
-            );
-
-            ctx.bind_vertex_array(ctx.create_vertex_array().ok());
-        }
-
-        let version = {
-            let str_version = unsafe { ctx.get_parameter_string(glow::VERSION) };
-            str_version.parse::<GLVersion>().unwrap_or_default()
-        };
+        ctx.active_texture(glow::TEXTURE0);
+        // TODO: this should be left to the consumer
+        ctx.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+        ctx.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+        ctx.enable(glow::BLEND);
+        ctx.blend_equation_separate(glow::FUNC_ADD, glow::FUNC_ADD);
+        ctx.blend_func_separate(
+            glow::SRC_ALPHA,
+            glow::ONE_MINUS_SRC_ALPHA,
+            glow::ONE,
+            glow::ONE_MINUS_SRC_ALPHA,
+        );
 
         let mut ctx = Self {
-            ctx,
+            backend: Box::new(ctx),
             version,
             gl_constants,
+            extensions,
             shaders: SlotMap::with_key(),
             active_shader: None,
             buffers: SlotMap::with_key(),
-            active_buffers: [None; 2],
+            active_buffers: [None; 3],
+            bound_uniform_buffers,
             textures: SlotMap::with_key(),
             bound_textures,
             framebuffers: SlotMap::with_key(),
             active_framebuffer: [None; 2],
+            multisample_resolves: SecondaryMap::new(),
+            mipmap_regenerations: SecondaryMap::new(),
             renderbuffers: SlotMap::with_key(),
             active_renderbuffer: None,
+            queries: SlotMap::with_key(),
+            active_queries: [None; 2],
+            deletion_queue: Default::default(),
+            leak_report: false,
+            program_cache: None,
+            attribute_validation: false,
+            attribute_mismatches: vec![],
             current_texture_unit: 0.into(),
             current_viewport: viewport::Viewport::default(),
             current_scissor: None,
+            pipeline_state: PipelineStateCache::default(),
             enabled_attributes: std::u32::MAX,
         };
         ctx.set_vertex_attributes(0, &[]);
@@ -402,28 +712,202 @@ impl Context {
 
     pub fn enable(&mut self, feature: Feature) {
         match feature {
-            Feature::DepthTest(func) => unsafe {
-                self.ctx.enable(glow::DEPTH_TEST);
-                self.ctx.depth_func(func.to_gl());
-            },
-            Feature::CullFace(cull_face, winding_order) => unsafe {
-                self.ctx.enable(glow::CULL_FACE);
-                self.ctx.cull_face(cull_face.to_gl());
-                self.ctx
-                    .front_face(gl::vertex_winding::to_gl(winding_order));
-            },
+            Feature::DepthTest(function) => self.set_depth_state(Some(&DepthState {
+                function,
+                range: self.pipeline_state.depth_range.clone(),
+                write_mask: self.pipeline_state.depth_write_mask,
+            })),
+            Feature::CullFace(mode, winding) => self.set_polygon_state(PolygonState {
+                culling_state: Some(CullingState { mode, winding }),
+                polygon_offset_factor: self.pipeline_state.polygon_offset.0,
+                polygon_offset_units: self.pipeline_state.polygon_offset.1,
+            }),
         }
     }
 
     pub fn disable(&mut self, feature: Feature) {
         match feature {
-            Feature::DepthTest(_) => unsafe { self.ctx.disable(glow::DEPTH_TEST) },
-            Feature::CullFace(_, _) => unsafe { self.ctx.disable(glow::CULL_FACE) },
+            Feature::DepthTest(_) => self.set_depth_state(None),
+            Feature::CullFace(_, _) => self.set_polygon_state(PolygonState {
+                culling_state: None,
+                polygon_offset_factor: self.pipeline_state.polygon_offset.0,
+                polygon_offset_units: self.pipeline_state.polygon_offset.1,
+            }),
+        }
+    }
+
+    fn set_capability(&self, capability: u32, enabled: bool) {
+        if enabled {
+            self.backend.enable(capability);
+        } else {
+            self.backend.disable(capability);
+        }
+    }
+
+    /// Enables the depth test with the given state or disables it when passed `None`. GL is only
+    /// called for the parts of the state that differ from what was last set.
+    pub fn set_depth_state(&mut self, depth_state: Option<&DepthState>) {
+        let cache = &mut self.pipeline_state;
+        let enabled = depth_state.is_some();
+        if cache.depth_test != enabled {
+            cache.depth_test = enabled;
+            self.set_capability(glow::DEPTH_TEST, enabled);
+        }
+
+        if let Some(depth_state) = depth_state {
+            let cache = &mut self.pipeline_state;
+            if cache.depth_function != depth_state.function {
+                cache.depth_function = depth_state.function;
+                self.backend.depth_func(depth_state.function.to_gl());
+            }
+            self.set_depth_write_mask(depth_state.write_mask);
+            let cache = &mut self.pipeline_state;
+            if cache.depth_range != depth_state.range {
+                cache.depth_range = depth_state.range.clone();
+                self.backend
+                    .depth_range_f32(**depth_state.range.start(), **depth_state.range.end());
+            }
+        }
+    }
+
+    fn set_depth_write_mask(&mut self, write_mask: bool) {
+        if self.pipeline_state.depth_write_mask != write_mask {
+            self.pipeline_state.depth_write_mask = write_mask;
+            self.backend.depth_mask(write_mask);
+        }
+    }
+
+    /// Enables blending with the given state or disables it when passed `None`.
+    pub fn set_blend_state(&mut self, blend_state: Option<BlendState>) {
+        let enabled = blend_state.is_some();
+        if self.pipeline_state.blend != enabled {
+            self.pipeline_state.blend = enabled;
+            self.set_capability(glow::BLEND, enabled);
+        }
+
+        if let Some(new) = blend_state {
+            let current = self.pipeline_state.blend_state;
+            if (current.equation_rgb, current.equation_alpha)
+                != (new.equation_rgb, new.equation_alpha)
+            {
+                self.backend
+                    .blend_equation_separate(new.equation_rgb.to_gl(), new.equation_alpha.to_gl());
+            }
+            if (
+                current.source_rgb,
+                current.destination_rgb,
+                current.source_alpha,
+                current.destination_alpha,
+            ) != (
+                new.source_rgb,
+                new.destination_rgb,
+                new.source_alpha,
+                new.destination_alpha,
+            ) {
+                self.backend.blend_func_separate(
+                    new.source_rgb.to_gl(),
+                    new.destination_rgb.to_gl(),
+                    new.source_alpha.to_gl(),
+                    new.destination_alpha.to_gl(),
+                );
+            }
+            if current.color != new.color {
+                let Color::<f32> {
+                    red,
+                    blue,
+                    green,
+                    alpha,
+                } = new.color.into();
+                self.backend.blend_color(red, green, blue, alpha);
+            }
+            self.pipeline_state.blend_state = new;
+        }
+    }
+
+    /// Enables the stencil test with the given state or disables it when passed `None`.
+    pub fn set_stencil_state(&mut self, stencil_state: Option<StencilState>) {
+        let enabled = stencil_state.is_some();
+        if self.pipeline_state.stencil_test != enabled {
+            self.pipeline_state.stencil_test = enabled;
+            self.set_capability(glow::STENCIL_TEST, enabled);
+        }
+
+        if let Some(new) = stencil_state {
+            let old = self.pipeline_state.stencil_state;
+            if old.function != new.function
+                || old.reference != new.reference
+                || old.read_mask != new.read_mask
+            {
+                self.backend
+                    .stencil_func(new.function.to_gl(), new.reference, new.read_mask);
+            }
+            if old.front != new.front {
+                self.set_stencil_operations(glow::FRONT, new.front);
+            }
+            if old.back != new.back {
+                self.set_stencil_operations(glow::BACK, new.back);
+            }
+            self.pipeline_state.stencil_state = new;
+            self.set_stencil_write_mask(new.write_mask);
+        }
+    }
+
+    fn set_stencil_operations(&self, face: u32, operations: StencilOperations) {
+        let StencilOperations {
+            fail,
+            depth_fail,
+            pass,
+        } = operations;
+        self.backend
+            .stencil_op_separate(face, fail.to_gl(), depth_fail.to_gl(), pass.to_gl());
+    }
+
+    fn set_stencil_write_mask(&mut self, write_mask: u32) {
+        if self.pipeline_state.stencil_write_mask != write_mask {
+            self.pipeline_state.stencil_write_mask = write_mask;
+            self.backend.stencil_mask(write_mask);
+        }
+    }
+
+    /// Sets face culling and polygon offset.
+    pub fn set_polygon_state(&mut self, polygon_state: PolygonState) {
+        let PolygonState {
+            culling_state,
+            polygon_offset_units,
+            polygon_offset_factor,
+        } = polygon_state;
+
+        let cull_face = culling_state.is_some();
+        if self.pipeline_state.cull_face != cull_face {
+            self.pipeline_state.cull_face = cull_face;
+            self.set_capability(glow::CULL_FACE, cull_face);
+        }
+        if let Some(new) = culling_state {
+            let current = self.pipeline_state.culling_state;
+            if current.mode != new.mode {
+                self.backend.cull_face(new.mode.to_gl());
+            }
+            if current.winding != new.winding {
+                self.backend
+                    .front_face(gl::vertex_winding::to_gl(new.winding));
+            }
+            self.pipeline_state.culling_state = new;
+        }
+
+        let offset = (polygon_offset_factor, polygon_offset_units);
+        let polygon_offset_fill = offset != (0., 0.);
+        if self.pipeline_state.polygon_offset_fill != polygon_offset_fill {
+            self.pipeline_state.polygon_offset_fill = polygon_offset_fill;
+            self.set_capability(glow::POLYGON_OFFSET_FILL, polygon_offset_fill);
+        }
+        if polygon_offset_fill && self.pipeline_state.polygon_offset != offset {
+            self.pipeline_state.polygon_offset = offset;
+            self.backend.polygon_offset(offset.0, offset.1);
         }
     }
 
     pub fn new_debug_group(&self, message: &str) -> DebugGroup {
-        DebugGroup::new(&self.ctx, message)
+        DebugGroup::new(self, message)
     }
 
     pub fn new_buffer(
@@ -433,48 +917,66 @@ impl Context {
         usage: buffer::Usage,
         initial_data: Option<&[u8]>,
     ) -> Result<BufferKey, GraphicsError> {
-        let vbo = unsafe {
-            let vbo = self
-                .ctx
-                .create_buffer()
-                .map_err(|_| GraphicsError::BufferError)?;
-            self.ctx.bind_buffer(buffer_type.into(), Some(vbo));
-            if let Some(initial_data) = initial_data {
-                self.ctx
-                    .buffer_data_u8_slice(buffer_type.into(), initial_data, usage.to_gl());
-            } else {
-                self.ctx
-                    .buffer_data_size(buffer_type.into(), size as _, usage.to_gl());
-            }
-
-            vbo
-        };
-        let buffer_key = self.buffers.insert(vbo);
+        self.delete_dropped_resources();
+        let size = initial_data.map_or(size, <[u8]>::len);
+        let buffer_key = self.buffers.insert(size);
+        if let Err(err) = self.backend.create_buffer(buffer_key) {
+            self.buffers.remove(buffer_key);
+            return Err(err);
+        }
+        self.backend
+            .bind_buffer(buffer_type.into(), Some(buffer_key));
+        if let Some(initial_data) = initial_data {
+            self.backend
+                .buffer_data_u8_slice(buffer_type.into(), initial_data, usage.to_gl());
+        } else {
+            self.backend
+                .buffer_data_size(buffer_type.into(), size as _, usage.to_gl());
+        }
         self.active_buffers[buffer_type_to_index(buffer_type)] = Some(buffer_key);
+        if let Err(err) = self.check_out_of_memory() {
+            self.buffers.remove(buffer_key);
+            self.active_buffers[buffer_type_to_index(buffer_type)] = None;
+            self.backend.delete_buffer(buffer_key);
+            return Err(err);
+        }
         Ok(buffer_key)
     }
 
     pub fn destroy_buffer(&mut self, buffer: &buffer::Buffer) {
-        if let Some(gl_buffer) = self.buffers.remove(buffer.handle()) {
-            unsafe {
-                self.ctx.delete_buffer(gl_buffer);
+        self.destroy_buffer_key(buffer.handle())
+    }
+
+    fn destroy_buffer_key(&mut self, buffer: BufferKey) {
+        if self.buffers.remove(buffer).is_some() {
+            for bound in self
+                .active_buffers
+                .iter_mut()
+                .chain(self.bound_uniform_buffers.iter_mut())
+            {
+                if *bound == Some(buffer) {
+                    *bound = None;
+                }
             }
+            self.backend.delete_buffer(buffer);
         }
     }
 
     pub fn bind_buffer(&mut self, buffer_key: BufferKey, buffer_type: buffer::BufferType) {
-        if let Some(&vbo) = self.buffers.get(buffer_key) {
+        if self.buffers.contains_key(buffer_key) {
             let buffer_index = buffer_type_to_index(buffer_type);
             match self.active_buffers.get_mut(buffer_index) {
                 Some(Some(active_buffer)) => {
                     if active_buffer != &buffer_key {
                         *active_buffer = buffer_key;
-                        unsafe { self.ctx.bind_buffer(buffer_type.into(), Some(vbo)) };
+                        self.backend
+                            .bind_buffer(buffer_type.into(), Some(buffer_key));
                     }
                 }
                 _ => {
                     self.active_buffers[buffer_index] = Some(buffer_key);
-                    unsafe { self.ctx.bind_buffer(buffer_type.into(), Some(vbo)) };
+                    self.backend
+                        .bind_buffer(buffer_type.into(), Some(buffer_key));
                 }
             }
         }
@@ -482,10 +984,90 @@ impl Context {
 
     pub fn buffer_static_draw(&self, buffer: &buffer::Buffer, data: &[u8], offset: usize) {
         let target = buffer.buffer_type().into();
-        unsafe {
-            self.ctx
-                .buffer_sub_data_u8_slice(target, offset as i32, data)
+        self.backend
+            .buffer_sub_data_u8_slice(target, offset as i32, data)
+    }
+
+    /// Replaces the storage of `buffer` with `data`, which may be a different size.
+    pub fn buffer_data(&mut self, buffer: &buffer::Buffer, data: &[u8]) {
+        self.bind_buffer(buffer.handle(), buffer.buffer_type());
+        if let Some(size) = self.buffers.get_mut(buffer.handle()) {
+            *size = data.len();
+        }
+        self.backend.buffer_data_u8_slice(
+            buffer.buffer_type().into(),
+            data,
+            buffer.usage().to_gl(),
+        );
+    }
+
+    /// Binds `buffer` to a uniform buffer binding point. Uniform blocks are sourced from a
+    /// binding point with `set_uniform_block_binding`.
+    pub fn bind_uniform_buffer(&mut self, buffer: &buffer::Buffer, binding: u32) {
+        let handle = buffer.handle();
+        if !self.buffers.contains_key(handle) {
+            return;
+        }
+        match self.bound_uniform_buffers.get_mut(binding as usize) {
+            Some(bound) => {
+                if *bound != Some(handle) {
+                    *bound = Some(handle);
+                    self.backend
+                        .bind_buffer_base(glow::UNIFORM_BUFFER, binding, Some(handle));
+                    // binding to an index also binds to the generic target
+                    self.active_buffers[buffer_type_to_index(buffer::BufferType::Uniform)] =
+                        Some(handle);
+                }
+            }
+            None => log::warn!(
+                "uniform buffer binding {} is out of range, the maximum is {}",
+                binding,
+                self.bound_uniform_buffers.len()
+            ),
+        }
+    }
+
+    /// Replaces the storage of `buffer` with `size` bytes of fresh, undefined memory. Draws that
+    /// were already issued keep reading from the old storage so this doesn't have to wait on them.
+    pub fn orphan_buffer(&mut self, buffer: &buffer::Buffer, size: usize) {
+        self.bind_buffer(buffer.handle(), buffer.buffer_type());
+        if let Some(buffer_size) = self.buffers.get_mut(buffer.handle()) {
+            *buffer_size = size;
         }
+        self.backend.buffer_data_size(
+            buffer.buffer_type().into(),
+            size as _,
+            buffer.usage().to_gl(),
+        );
+    }
+
+    /// Whether `copy_buffer` is available. It needs GL 3.1, GLES 3 or WebGL 2.
+    pub fn supports_buffer_copies(&self) -> bool {
+        self.version.supports_buffer_copies()
+    }
+
+    /// Copies `size` bytes from `src` to `dst` without a round trip through the CPU. See
+    /// `supports_buffer_copies`.
+    pub fn copy_buffer(
+        &mut self,
+        src: &buffer::Buffer,
+        dst: &buffer::Buffer,
+        src_offset: usize,
+        dst_offset: usize,
+        size: usize,
+    ) {
+        // the copy targets aren't used for anything else so they aren't cached
+        self.backend
+            .bind_buffer(glow::COPY_READ_BUFFER, Some(src.handle()));
+        self.backend
+            .bind_buffer(glow::COPY_WRITE_BUFFER, Some(dst.handle()));
+        self.backend.copy_buffer_sub_data(
+            glow::COPY_READ_BUFFER,
+            glow::COPY_WRITE_BUFFER,
+            src_offset as _,
+            dst_offset as _,
+            size as _,
+        );
     }
 
     fn buffer_stream_draw(&self, map: &buffer::MappedBuffer) {
@@ -493,19 +1075,17 @@ impl Context {
         let target = buffer.buffer_type().into();
         let data = map.memory_map();
 
-        unsafe {
-            // "orphan" current buffer to avoid implicit synchronisation on the GPU:
-            // http://www.seas.upenn.edu/~pcozzi/OpenGLInsights/OpenGLInsights-AsynchronousBufferTransfers.pdf
-            self.ctx
-                .buffer_data_size(target, buffer.size() as i32, buffer.usage().to_gl());
-            self.ctx.buffer_sub_data_u8_slice(target, 0, data);
-        }
+        // "orphan" current buffer to avoid implicit synchronisation on the GPU:
+        // http://www.seas.upenn.edu/~pcozzi/OpenGLInsights/OpenGLInsights-AsynchronousBufferTransfers.pdf
+        self.backend
+            .buffer_data_size(target, buffer.size() as i32, buffer.usage().to_gl());
+        self.backend.buffer_sub_data_u8_slice(target, 0, data);
     }
 
     pub fn unmap_buffer(&mut self, map: &buffer::MappedBuffer) {
         let buffer = map.inner();
         self.bind_buffer(buffer.handle(), buffer.buffer_type());
-        if self.buffers.get(buffer.handle()).is_some() {
+        if self.buffers.contains_key(buffer.handle()) {
             if let Some(modified_range) = map.modified_range() {
                 let modified_offset =
                     std::cmp::min(modified_range.offset, buffer.size().saturating_sub(1));
@@ -547,181 +1127,121 @@ impl Context {
         vertex_source: &str,
         fragment_source: &str,
     ) -> Result<ShaderKey, shader::ShaderError> {
-        use shader::*;
-        let program = unsafe {
-            let gl = &self.ctx;
-            let vertex = gl
-                .create_shader(glow::VERTEX_SHADER)
-                .map_err(|_| ShaderError::ResourceCreationError)?;
-            gl.shader_source(vertex, vertex_source);
-            gl.compile_shader(vertex);
-            if !gl.get_shader_compile_status(vertex) {
-                let err = Err(ShaderError::VertexCompileError(
-                    gl.get_shader_info_log(vertex),
-                ));
-                gl.delete_shader(vertex);
-                return err;
-            }
-            let fragment = gl
-                .create_shader(glow::FRAGMENT_SHADER)
-                .expect("Failed to create Fragment shader.");
-            gl.shader_source(fragment, fragment_source);
-            gl.compile_shader(fragment);
-            if !gl.get_shader_compile_status(fragment) {
-                let err = Err(ShaderError::FragmentCompileError(
-                    gl.get_shader_info_log(fragment),
-                ));
-                gl.delete_shader(fragment);
-                return err;
-            }
-            let program = gl.create_program().expect("Failed to create program.");
-            gl.attach_shader(program, vertex);
-            gl.attach_shader(program, fragment);
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                let err = Err(ShaderError::LinkError(gl.get_program_info_log(program)));
-                gl.delete_program(program);
-                return err;
-            }
-
-            program
-        };
+        self.delete_dropped_resources();
+        let shader = self.shaders.insert(());
+        let cache_key = self.program_cache_key(vertex_source, fragment_source);
+        if let Some(cache_key) = &cache_key {
+            let binary = self
+                .program_cache
+                .as_mut()
+                .and_then(|cache| cache.load(cache_key));
+            if let Some(binary) = binary {
+                match self.backend.create_program_from_binary(
+                    shader,
+                    &binary,
+                    vertex_source,
+                    fragment_source,
+                ) {
+                    Ok(()) => return Ok(shader),
+                    Err(err) => {
+                        log::debug!("compiling program {} from source: {:?}", cache_key, err)
+                    }
+                }
+            }
+        }
+        match self
+            .backend
+            .create_program(shader, vertex_source, fragment_source)
+        {
+            Ok(()) => {
+                let binary = cache_key.and_then(|cache_key| {
+                    Some((cache_key, self.backend.get_program_binary(shader)?))
+                });
+                if let (Some(cache), Some((cache_key, binary))) =
+                    (self.program_cache.as_mut(), binary)
+                {
+                    cache.store(&cache_key, &binary);
+                }
+                Ok(shader)
+            }
+            Err(err) => {
+                self.shaders.remove(shader);
+                Err(err)
+            }
+        }
+    }
+
+    fn program_cache_key(&self, vertex_source: &str, fragment_source: &str) -> Option<String> {
+        if self.program_cache.is_none() || !self.backend.supports_program_binaries() {
+            return None;
+        }
+        let driver = [glow::VENDOR, glow::RENDERER, glow::VERSION]
+            .iter()
+            .map(|parameter| self.backend.get_parameter_string(*parameter))
+            .collect::<Vec<_>>();
+        let driver = driver.iter().map(String::as_str).collect::<Vec<_>>();
+        Some(shader::cache_key(&driver, vertex_source, fragment_source))
+    }
+
+    pub fn supports_program_binaries(&self) -> bool {
+        self.backend.supports_program_binaries()
+    }
 
-        Ok(self.shaders.insert(program))
+    /// Saves linked programs to `cache` and loads them from it instead of compiling when the
+    /// same sources come up again. Binaries the driver rejects are compiled from source as usual.
+    /// This does nothing, besides logging a warning, if the backend doesn't support program
+    /// binaries.
+    pub fn set_program_cache<C: shader::ProgramCache + 'static>(&mut self, cache: C) {
+        if !self.backend.supports_program_binaries() {
+            log::warn!("program binaries aren't supported so the program cache won't be used");
+        }
+        self.program_cache = Some(Box::new(cache));
+    }
+
+    /// Stops using the program cache and hands it back.
+    pub fn take_program_cache(&mut self) -> Option<Box<dyn shader::ProgramCache>> {
+        self.program_cache.take()
     }
 
     pub fn get_shader_attributes(&self, shader: ShaderKey) -> Vec<shader::Attribute> {
-        if let Some(program) = self.shaders.get(shader).cloned() {
-            let count = unsafe { self.ctx.get_active_attributes(program) };
-            let mut attributes = Vec::with_capacity(count as usize);
-            for index in 0..count {
-                unsafe {
-                    let glow::ActiveAttribute { name, size, atype } =
-                        self.ctx.get_active_attribute(program, index).unwrap();
-                    if let Some(location) = self.ctx.get_attrib_location(program, name.as_str()) {
-                        // specifically this is for gl_InstanceID
-                        attributes.push(shader::Attribute {
-                            name,
-                            size,
-                            atype: gl::attribute::from_gl(atype),
-                            location,
-                        });
-                    }
-                }
-            }
-            attributes.sort_by(|a, b| a.location.partial_cmp(&b.location).unwrap());
-            attributes
+        if self.shaders.contains_key(shader) {
+            self.backend.get_program_attributes(shader)
         } else {
             Vec::new()
         }
     }
 
     pub fn get_shader_uniforms(&self, shader: ShaderKey) -> Vec<shader::Uniform> {
-        unsafe fn get_initial_uniform_data(
-            gl: &glow::Context,
-            utype: u32,
-            program: GLProgram,
-            location: &GLUniformLocation,
-        ) -> shader::RawUniformValue {
-            use shader::RawUniformValue;
-            macro_rules! get_uniform_data {
-                (f32, 1, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
-                    let mut data = [0.; 1];
-                    $gl.get_uniform_f32($program, $location, &mut data);
-                    RawUniformValue::$uni_ty(data[0].into())
-                }};
-                (i32, 1, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
-                    let mut data = [0; 1];
-                    $gl.get_uniform_i32($program, $location, &mut data);
-                    RawUniformValue::$uni_ty(data[0].into())
-                }};
-                (f32, $data_size:expr, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
-                    let mut data = [0.; $data_size];
-                    $gl.get_uniform_f32($program, $location, &mut data);
-                    RawUniformValue::$uni_ty(data.into())
-                }};
-                (i32, $data_size:expr, $uni_ty:ident, $gl:expr, $program:expr, $location:expr) => {{
-                    let mut data = [0; $data_size];
-                    $gl.get_uniform_i32($program, $location, &mut data);
-                    RawUniformValue::$uni_ty(data.into())
-                }};
-            }
-
-            match utype {
-                glow::FLOAT => get_uniform_data!(f32, 1, Float, gl, program, location),
-                glow::FLOAT_VEC2 => get_uniform_data!(f32, 2, Vec2, gl, program, location),
-                glow::FLOAT_VEC3 => get_uniform_data!(f32, 3, Vec3, gl, program, location),
-                glow::FLOAT_VEC4 => get_uniform_data!(f32, 4, Vec4, gl, program, location),
-                glow::FLOAT_MAT2 => get_uniform_data!(f32, 4, Mat2, gl, program, location),
-                glow::FLOAT_MAT3 => get_uniform_data!(f32, 9, Mat3, gl, program, location),
-                glow::FLOAT_MAT4 => get_uniform_data!(f32, 16, Mat4, gl, program, location),
-                glow::INT | glow::SAMPLER_2D | glow::SAMPLER_CUBE => {
-                    get_uniform_data!(i32, 1, SignedInt, gl, program, location)
-                }
-                glow::INT_VEC2 => get_uniform_data!(i32, 2, IntVec2, gl, program, location),
-                glow::INT_VEC3 => get_uniform_data!(i32, 3, IntVec3, gl, program, location),
-                glow::INT_VEC4 => get_uniform_data!(i32, 4, IntVec4, gl, program, location),
-                _ => {
-                    panic!("failed to match uniform type");
-                }
-            }
+        if self.shaders.contains_key(shader) {
+            self.backend.get_program_uniforms(shader)
+        } else {
+            Default::default()
         }
+    }
 
-        use shader::{Uniform, UniformLocation};
-        let gl = &self.ctx;
-        if let Some(program) = self.shaders.get(shader).cloned() {
-            let count = unsafe { gl.get_active_uniforms(program) };
-            let mut uniforms = Vec::with_capacity(count as usize);
-            for index in 0..count {
-                unsafe {
-                    let glow::ActiveUniform { name, size, utype } =
-                        gl.get_active_uniform(program, index).unwrap();
-                    if size > 1 {
-                        let name = name.trim_end_matches("[0]");
-                        uniforms.extend((0..size).filter_map(|i| {
-                            let name = format!("{}[{}]", name, i);
-                            gl.get_uniform_location(program, name.as_str())
-                                .map(|location| {
-                                    let initial_data =
-                                        get_initial_uniform_data(&gl, utype, program, &location);
-                                    let location = UniformLocation(location);
-                                    Uniform {
-                                        name,
-                                        size: 1,
-                                        utype,
-                                        location,
-                                        initial_data,
-                                    }
-                                })
-                        }));
-                    } else {
-                        if let Some(location) = gl.get_uniform_location(program, name.as_str()) {
-                            let initial_data =
-                                get_initial_uniform_data(&gl, utype, program, &location);
-                            let location = UniformLocation(location);
-                            uniforms.push(Uniform {
-                                name,
-                                size,
-                                utype,
-                                location,
-                                initial_data,
-                            });
-                        }
-                    }
-                }
-            }
-            uniforms
+    pub fn get_shader_uniform_blocks(&self, shader: ShaderKey) -> Vec<shader::UniformBlock> {
+        if self.shaders.contains_key(shader) {
+            self.backend.get_program_uniform_blocks(shader)
         } else {
-            Default::default()
+            Vec::new()
+        }
+    }
+
+    /// Sources the uniform block at `block_index` of `shader` from the uniform buffer bound at
+    /// `binding`.
+    pub fn set_uniform_block_binding(&mut self, shader: ShaderKey, block_index: u32, binding: u32) {
+        if self.shaders.contains_key(shader) {
+            self.backend
+                .uniform_block_binding(shader, block_index, binding);
         }
     }
 
     pub fn destroy_shader(&mut self, shader: ShaderKey) {
-        match self.shaders.remove(shader) {
-            None => (),
-            Some(shader) => unsafe {
-                self.ctx.delete_program(shader);
-            },
+        if self.shaders.remove(shader).is_some() {
+            if self.active_shader == Some(shader) {
+                self.active_shader = None;
+            }
+            self.backend.delete_program(shader);
         }
     }
 
@@ -730,48 +1250,126 @@ impl Context {
             None => {
                 if self.active_shader.is_some() {
                     self.active_shader = None;
-                    unsafe {
-                        self.ctx.use_program(None);
-                    }
+                    self.backend.use_program(None);
                 }
             }
             Some(shader) => {
                 if self.active_shader != Some(shader.handle()) {
-                    match self.shaders.get(shader.handle()) {
-                        None => log::warn!(
+                    if self.shaders.contains_key(shader.handle()) {
+                        self.active_shader = Some(shader.handle());
+                        self.backend.use_program(Some(shader.handle()));
+                    } else {
+                        log::warn!(
                             "Attempting to bind shader not in cache: {:?}",
                             shader.handle()
-                        ),
-                        Some(gl_shader) => {
-                            self.active_shader = Some(shader.handle());
-                            unsafe { self.ctx.use_program(Some(*gl_shader)) }
-                        }
+                        )
                     }
                 }
             }
         }
     }
 
+    /// Whether both solstice and the driver support textures of this type.
+    pub fn supports_texture_type(&self, texture_type: texture::TextureType) -> bool {
+        texture_type.is_supported() && self.version.supports_texture_type(texture_type)
+    }
+
+    /// Whether uniform blocks can be sourced from buffers. GLES 2 and WebGL 1 don't have them.
+    pub fn supports_uniform_buffers(&self) -> bool {
+        self.gl_constants.max_uniform_buffer_bindings > 0
+    }
+
+    /// Whether the driver advertises the extension, spelled the way the driver reports it:
+    /// `GL_EXT_texture_compression_s3tc` on desktop GL and GLES, `WEBGL_compressed_texture_s3tc`
+    /// on the web. WebGL extensions are only known if the backend was created with
+    /// `GlowBackend::from_webgl1_context` or `from_webgl2_context`, otherwise there are none.
+    /// An advertised extension isn't necessarily used by solstice, see `supports_pixel_format`.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+
+    /// Whether textures of this format can be created, going by the version and the extensions
+    /// under either their GL or WebGL names. Compressed formats also need a backend that can
+    /// upload them, which the glow backend can't do on the web, so they're never supported
+    /// there.
+    pub fn supports_pixel_format(&self, format: PixelFormat) -> bool {
+        if self.version.supports_pixel_format(format) {
+            return !format.is_compressed() || self.backend.supports_compressed_textures();
+        }
+        let any = |names: &[&str]| names.iter().any(|name| self.supports_extension(name));
+        let extension = match format {
+            PixelFormat::BC1 | PixelFormat::BC2 | PixelFormat::BC3 => any(&[
+                "GL_EXT_texture_compression_s3tc",
+                "WEBGL_compressed_texture_s3tc",
+            ]),
+            PixelFormat::BC4
+            | PixelFormat::BC4Signed
+            | PixelFormat::BC5
+            | PixelFormat::BC5Signed => any(&[
+                "GL_ARB_texture_compression_rgtc",
+                "GL_EXT_texture_compression_rgtc",
+                "EXT_texture_compression_rgtc",
+            ]),
+            PixelFormat::BC6H | PixelFormat::BC6HSigned | PixelFormat::BC7 => any(&[
+                "GL_ARB_texture_compression_bptc",
+                "GL_EXT_texture_compression_bptc",
+                "EXT_texture_compression_bptc",
+            ]),
+            PixelFormat::ETC2RGB8
+            | PixelFormat::ETC2RGB8A1
+            | PixelFormat::ETC2RGBA8
+            | PixelFormat::EACR11
+            | PixelFormat::EACR11Signed
+            | PixelFormat::EACRG11
+            | PixelFormat::EACRG11Signed => {
+                any(&["GL_ARB_ES3_compatibility", "WEBGL_compressed_texture_etc"])
+            }
+            format if format.is_compressed() => any(&[
+                "GL_KHR_texture_compression_astc_ldr",
+                "WEBGL_compressed_texture_astc",
+            ]),
+            _ => false,
+        };
+        extension && self.backend.supports_compressed_textures()
+    }
+
+    /// Fails if the driver has run out of memory since the last check. Any other errors that
+    /// have been raised in the meantime are logged and discarded.
+    pub fn check_out_of_memory(&self) -> Result<(), GraphicsError> {
+        let mut result = Ok(());
+        loop {
+            match self.backend.get_error() {
+                glow::NO_ERROR => return result,
+                glow::OUT_OF_MEMORY => result = Err(GraphicsError::OutOfMemory),
+                error => log::warn!("discarding GL error {:#x}", error),
+            }
+        }
+    }
+
     pub fn new_texture(
         &mut self,
         texture_type: texture::TextureType,
     ) -> Result<TextureKey, GraphicsError> {
-        unsafe {
-            let handle = self
-                .ctx
-                .create_texture()
-                .map_err(|_| GraphicsError::TextureError)?;
-            let texture = self.textures.insert(handle);
-            self.ctx.active_texture(glow::TEXTURE0);
-            self.bind_texture_to_unit(texture_type, texture, 0.into());
-            Ok(texture)
+        self.delete_dropped_resources();
+        let texture = self.textures.insert(0);
+        if let Err(err) = self.backend.create_texture(texture) {
+            self.textures.remove(texture);
+            return Err(err);
         }
+        self.backend.active_texture(glow::TEXTURE0);
+        self.current_texture_unit = 0.into();
+        self.bind_texture_to_unit(texture_type, texture, 0.into());
+        Ok(texture)
     }
 
     pub fn destroy_texture(&mut self, texture_key: TextureKey) {
-        match self.textures.remove(texture_key) {
-            None => (),
-            Some(texture) => unsafe { self.ctx.delete_texture(texture) },
+        if self.textures.remove(texture_key).is_some() {
+            for bound in self.bound_textures.iter_mut().flatten() {
+                if *bound == Some(texture_key) {
+                    *bound = None;
+                }
+            }
+            self.backend.delete_texture(texture_key);
         }
     }
 
@@ -781,71 +1379,72 @@ impl Context {
         texture_key: TextureKey,
         texture_unit: TextureUnit,
     ) {
+        let pending_resolve = self
+            .multisample_resolves
+            .iter()
+            .find(|(_, resolve)| {
+                resolve.dirty
+                    && (resolve.textures.contains(&texture_key)
+                        || matches!(resolve.depth_stencil, Some((texture, _)) if texture == texture_key))
+            })
+            .map(|(multisampled, _)| multisampled);
+        if let Some(multisampled) = pending_resolve {
+            self.resolve_multisample(multisampled);
+        }
+        let pending_regeneration = self.mipmap_regenerations.values_mut().find(|regeneration| {
+            regeneration.dirty && regeneration.textures.contains(&texture_key)
+        });
+        if let Some(regeneration) = pending_regeneration {
+            regeneration.dirty = false;
+            let MipmapRegeneration {
+                textures,
+                texture_type,
+                ..
+            } = regeneration.clone();
+            for texture in textures {
+                texture::TextureUpdate::generate_mipmaps(self, texture, texture_type);
+            }
+        }
+
         let TextureUnit { index, gl: unit } = texture_unit;
         let texture_unit_index = index as usize;
-        match (
-            self.textures.get(texture_key),
-            self.bound_textures[texture_type.to_index()][texture_unit_index],
-        ) {
-            (Some(&texture), None) => {
-                if texture_unit != self.current_texture_unit {
-                    unsafe {
-                        self.ctx.active_texture(unit);
-                    }
-                    self.current_texture_unit = texture_unit;
-                }
-                self.bound_textures[texture_type.to_index()][texture_unit_index] = Some(texture);
-                unsafe {
-                    self.ctx
-                        .bind_texture(gl::texture::to_gl(texture_type), Some(texture))
-                }
-            }
-            (Some(&texture), Some(bound_texture)) => {
-                if texture != bound_texture {
-                    if texture_unit != self.current_texture_unit {
-                        unsafe {
-                            self.ctx.active_texture(unit);
-                        }
-                        self.current_texture_unit = texture_unit;
-                    }
-                    self.bound_textures[texture_type.to_index()][texture_unit_index] =
-                        Some(texture);
-                    unsafe {
-                        self.ctx
-                            .bind_texture(gl::texture::to_gl(texture_type), Some(texture))
-                    }
-                }
-            }
-            (None, Some(_)) => {
-                if texture_unit != self.current_texture_unit {
-                    unsafe {
-                        self.ctx.active_texture(unit);
-                    }
-                    self.current_texture_unit = texture_unit;
-                }
-                self.bound_textures[texture_type.to_index()][texture_unit_index] = None;
-                unsafe {
-                    self.ctx
-                        .bind_texture(gl::texture::to_gl(texture_type), None)
-                }
+        let texture = Some(texture_key).filter(|key| self.textures.contains_key(*key));
+        let bound_texture = self.bound_textures[texture_type.to_index()][texture_unit_index];
+        if texture != bound_texture {
+            if texture_unit != self.current_texture_unit {
+                self.backend.active_texture(unit);
+                self.current_texture_unit = texture_unit;
             }
-            (None, None) => (),
+            self.bound_textures[texture_type.to_index()][texture_unit_index] = texture;
+            self.backend
+                .bind_texture(gl::texture::to_gl(texture_type), texture);
         }
     }
 
     pub fn new_framebuffer(&mut self) -> Result<FramebufferKey, GraphicsError> {
-        let framebuffer = unsafe {
-            self.ctx
-                .create_framebuffer()
-                .map_err(|_| GraphicsError::FramebufferError)?
-        };
-        Ok(self.framebuffers.insert(framebuffer))
+        self.delete_dropped_resources();
+        let framebuffer = self.framebuffers.insert(());
+        match self.backend.create_framebuffer(framebuffer) {
+            Ok(()) => Ok(framebuffer),
+            Err(err) => {
+                self.framebuffers.remove(framebuffer);
+                Err(err)
+            }
+        }
     }
 
     pub fn destroy_framebuffer(&mut self, framebuffer_key: FramebufferKey) {
-        match self.framebuffers.remove(framebuffer_key) {
-            None => (),
-            Some(framebuffer) => unsafe { self.ctx.delete_framebuffer(framebuffer) },
+        if self.framebuffers.remove(framebuffer_key).is_some() {
+            self.multisample_resolves.remove(framebuffer_key);
+            self.multisample_resolves
+                .retain(|_, resolve| resolve.framebuffer != framebuffer_key);
+            self.mipmap_regenerations.remove(framebuffer_key);
+            for active in self.active_framebuffer.iter_mut() {
+                if *active == Some(framebuffer_key) {
+                    *active = None;
+                }
+            }
+            self.backend.delete_framebuffer(framebuffer_key);
         }
     }
 
@@ -854,42 +1453,181 @@ impl Context {
         target: canvas::Target,
         framebuffer_key: Option<FramebufferKey>,
     ) {
-        let target_index = target_to_index(target);
-        match (framebuffer_key, self.active_framebuffer[target_index]) {
-            (None, None) => (),
-            (Some(framebuffer_key), None) => match self.framebuffers.get(framebuffer_key) {
-                None => (),
-                Some(framebuffer) => {
-                    self.active_framebuffer[target_index] = Some(framebuffer_key);
-                    unsafe {
-                        self.ctx
-                            .bind_framebuffer(target.to_gl(), Some(*framebuffer))
-                    }
-                }
-            },
-            (Some(framebuffer_key), Some(current_framebuffer_key)) => {
-                if framebuffer_key != current_framebuffer_key {
-                    match self.framebuffers.get(framebuffer_key) {
-                        None => (),
-                        Some(framebuffer) => {
-                            self.active_framebuffer[target_index] = Some(framebuffer_key);
-                            unsafe {
-                                self.ctx
-                                    .bind_framebuffer(target.to_gl(), Some(*framebuffer))
-                            }
-                        }
-                    }
-                }
+        let framebuffer_key = match framebuffer_key {
+            Some(key) if !self.framebuffers.contains_key(key) => return,
+            key => key,
+        };
+        // binding to both targets has to invalidate the cached read framebuffer too
+        let active = match target {
+            canvas::Target::All => &mut self.active_framebuffer[..],
+            target => {
+                let index = target_to_index(target);
+                &mut self.active_framebuffer[index..=index]
             }
-            (None, Some(_current_framebuffer_key)) => {
-                self.active_framebuffer[target_index] = None;
-                unsafe { self.ctx.bind_framebuffer(target.to_gl(), None) }
+        };
+        if active.iter().any(|active| *active != framebuffer_key) {
+            active
+                .iter_mut()
+                .for_each(|active| *active = framebuffer_key);
+            self.backend
+                .bind_framebuffer(target.to_gl(), framebuffer_key);
+        }
+    }
+
+    /// The most samples a multisampled renderbuffer can have. Zero if multisampling isn't
+    /// supported.
+    pub fn max_samples(&self) -> usize {
+        self.gl_constants.max_samples
+    }
+
+    /// Whenever one of `textures` is bound after `multisampled` has been drawn to,
+    /// `multisampled` is first resolved into `framebuffer`, which should have `textures` as its
+    /// color attachments, in order. A `depth_stencil` texture of the given format is resolved
+    /// from the depth and/or stencil buffers too.
+    pub fn set_multisample_resolve(
+        &mut self,
+        multisampled: FramebufferKey,
+        framebuffer: FramebufferKey,
+        textures: &[TextureKey],
+        depth_stencil: Option<(TextureKey, PixelFormat)>,
+        width: u32,
+        height: u32,
+    ) {
+        self.multisample_resolves.insert(
+            multisampled,
+            MultisampleResolve {
+                framebuffer,
+                textures: textures.to_vec(),
+                depth_stencil,
+                width: width as _,
+                height: height as _,
+                dirty: false,
+            },
+        );
+    }
+
+    /// Whenever one of `textures` is bound after `framebuffer` has been drawn to, the mipmaps of
+    /// all of them are regenerated first. For multisampled canvases `framebuffer` is the one that
+    /// is rendered to and the resolve happens before the regeneration.
+    pub fn set_mipmap_regeneration(
+        &mut self,
+        framebuffer: FramebufferKey,
+        textures: &[TextureKey],
+        texture_type: texture::TextureType,
+    ) {
+        self.mipmap_regenerations.insert(
+            framebuffer,
+            MipmapRegeneration {
+                textures: textures.to_vec(),
+                texture_type,
+                dirty: false,
+            },
+        );
+    }
+
+    /// Blits the contents of a multisampled framebuffer into the framebuffer it was registered with
+    /// by `set_multisample_resolve`.
+    pub fn resolve_multisample(&mut self, multisampled: FramebufferKey) {
+        let resolve = match self.multisample_resolves.get_mut(multisampled) {
+            Some(resolve) => {
+                resolve.dirty = false;
+                resolve.clone()
+            }
+            None => return,
+        };
+        let read = self.get_active_framebuffer(canvas::Target::Read);
+        let draw = self.get_active_framebuffer(canvas::Target::Draw);
+        // blits are clipped by the scissor test
+        let scissor = self.current_scissor;
+        self.set_scissor(None);
+        self.bind_framebuffer(canvas::Target::Read, Some(multisampled));
+        self.bind_framebuffer(canvas::Target::Draw, Some(resolve.framebuffer));
+        let MultisampleResolve {
+            width,
+            height,
+            textures,
+            depth_stencil,
+            ..
+        } = resolve;
+        // depth and stencil go along with the first color attachment
+        let mut mask = glow::COLOR_BUFFER_BIT;
+        if let Some((_, format)) = depth_stencil {
+            if format.is_depth() {
+                mask |= glow::DEPTH_BUFFER_BIT;
+            }
+            if format.is_stencil() {
+                mask |= glow::STENCIL_BUFFER_BIT;
             }
         }
+        let mut blit = |ctx: &mut Self| {
+            ctx.backend.blit_framebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                mask,
+                glow::NEAREST,
+            );
+            mask = glow::COLOR_BUFFER_BIT;
+        };
+        if textures.len() > 1 {
+            // blits only read one buffer so each attachment is routed through on its own
+            let mut draw_buffers = vec![glow::NONE; textures.len()];
+            for index in 0..textures.len() {
+                let attachment = glow::COLOR_ATTACHMENT0 + index as u32;
+                self.backend.read_buffer(attachment);
+                draw_buffers
+                    .iter_mut()
+                    .for_each(|buffer| *buffer = glow::NONE);
+                draw_buffers[index] = attachment;
+                self.backend.draw_buffers(&draw_buffers);
+                blit(self);
+            }
+            self.backend.read_buffer(glow::COLOR_ATTACHMENT0);
+            for (index, buffer) in draw_buffers.iter_mut().enumerate() {
+                *buffer = glow::COLOR_ATTACHMENT0 + index as u32;
+            }
+            self.backend.draw_buffers(&draw_buffers);
+        } else {
+            blit(self);
+        }
+        self.bind_framebuffer(canvas::Target::Read, read);
+        self.bind_framebuffer(canvas::Target::Draw, draw);
+        self.set_scissor(scissor);
+    }
+
+    fn mark_framebuffer_dirty(&mut self, framebuffer: Option<FramebufferKey>) {
+        let resolve = framebuffer.and_then(|key| self.multisample_resolves.get_mut(key));
+        if let Some(resolve) = resolve {
+            resolve.dirty = true;
+        }
+        let regeneration = framebuffer.and_then(|key| self.mipmap_regenerations.get_mut(key));
+        if let Some(regeneration) = regeneration {
+            regeneration.dirty = true;
+        }
+    }
+
+    /// Sets which attachments of the bound draw framebuffer the fragment shader outputs are
+    /// written to, by output index.
+    pub fn set_draw_buffers(&mut self, attachments: &[canvas::Attachment]) {
+        let buffers = attachments
+            .iter()
+            .map(canvas::Attachment::to_gl)
+            .collect::<Vec<_>>();
+        self.backend.draw_buffers(&buffers);
+    }
+
+    /// Sets which color attachment of the bound read framebuffer pixels are read from.
+    pub fn set_read_buffer(&mut self, attachment: canvas::Attachment) {
+        self.backend.read_buffer(attachment.to_gl());
     }
 
     pub fn check_framebuffer_status(&self, target: canvas::Target) -> canvas::Status {
-        match unsafe { self.ctx.check_framebuffer_status(target.to_gl()) } {
+        match self.backend.check_framebuffer_status(target.to_gl()) {
             glow::FRAMEBUFFER_COMPLETE => canvas::Status::Complete,
             glow::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => canvas::Status::IncompleteAttachment,
             glow::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => canvas::Status::MissingAttachment,
@@ -911,43 +1649,77 @@ impl Context {
         texture_key: TextureKey,
         level: u32,
     ) {
-        unsafe {
-            self.ctx.framebuffer_texture_2d(
-                target.to_gl(),
-                attachment.to_gl(),
-                gl::texture::to_gl(texture_type),
-                self.textures.get(texture_key).copied(),
-                level as i32,
-            )
-        }
+        self.backend.framebuffer_texture_2d(
+            target.to_gl(),
+            attachment.to_gl(),
+            gl::texture::to_gl(texture_type),
+            Some(texture_key).filter(|key| self.textures.contains_key(*key)),
+            level as i32,
+        )
     }
 
     pub fn new_renderbuffer(&mut self) -> Result<RenderbufferKey, GraphicsError> {
-        let renderbuffer = unsafe {
-            self.ctx
-                .create_renderbuffer()
-                .map_err(|_| GraphicsError::RenderbufferError)?
-        };
-        Ok(self.renderbuffers.insert(renderbuffer))
+        self.delete_dropped_resources();
+        let renderbuffer = self.renderbuffers.insert(0);
+        match self.backend.create_renderbuffer(renderbuffer) {
+            Ok(()) => Ok(renderbuffer),
+            Err(err) => {
+                self.renderbuffers.remove(renderbuffer);
+                Err(err)
+            }
+        }
     }
 
     pub fn bind_renderbuffer(&mut self, renderbuffer: Option<RenderbufferKey>) {
         if self.active_renderbuffer != renderbuffer {
             self.active_renderbuffer = renderbuffer;
-            let gl_renderbuffer =
-                renderbuffer.and_then(|renderbuffer| self.renderbuffers.get(renderbuffer).cloned());
-            unsafe {
-                self.ctx
-                    .bind_renderbuffer(glow::RENDERBUFFER, gl_renderbuffer);
-            }
+            let renderbuffer =
+                renderbuffer.filter(|renderbuffer| self.renderbuffers.contains_key(*renderbuffer));
+            self.backend
+                .bind_renderbuffer(glow::RENDERBUFFER, renderbuffer);
         }
     }
 
     pub fn renderbuffer_storage(&mut self, format: PixelFormat, width: i32, height: i32) {
+        self.set_renderbuffer_size(format, 1, width, height);
+        let gl_format = gl::pixel_format::to_gl(format, &self.version, true);
+        self.backend
+            .renderbuffer_storage(glow::RENDERBUFFER, gl_format.internal, width, height)
+    }
+
+    pub fn renderbuffer_storage_multisample(
+        &mut self,
+        format: PixelFormat,
+        samples: usize,
+        width: i32,
+        height: i32,
+    ) {
+        self.set_renderbuffer_size(format, samples, width, height);
         let gl_format = gl::pixel_format::to_gl(format, &self.version, true);
-        unsafe {
-            self.ctx
-                .renderbuffer_storage(glow::RENDERBUFFER, gl_format.internal, width, height)
+        self.backend.renderbuffer_storage_multisample(
+            glow::RENDERBUFFER,
+            samples as _,
+            gl_format.internal,
+            width,
+            height,
+        )
+    }
+
+    fn set_renderbuffer_size(
+        &mut self,
+        format: PixelFormat,
+        samples: usize,
+        width: i32,
+        height: i32,
+    ) {
+        let renderbuffer = self
+            .active_renderbuffer
+            .and_then(|renderbuffer| self.renderbuffers.get_mut(renderbuffer));
+        if let Some(size) = renderbuffer {
+            *size = width.max(0) as usize
+                * height.max(0) as usize
+                * samples.max(1)
+                * gl::pixel_format::size(format);
         }
     }
 
@@ -956,23 +1728,391 @@ impl Context {
         attachment: canvas::Attachment,
         renderbuffer: Option<RenderbufferKey>,
     ) {
-        let gl_renderbuffer =
-            renderbuffer.and_then(|renderbuffer| self.renderbuffers.get(renderbuffer).cloned());
-        unsafe {
-            self.ctx.framebuffer_renderbuffer(
-                glow::FRAMEBUFFER,
-                attachment.to_gl(),
-                glow::RENDERBUFFER,
-                gl_renderbuffer,
+        let renderbuffer =
+            renderbuffer.filter(|renderbuffer| self.renderbuffers.contains_key(*renderbuffer));
+        self.backend.framebuffer_renderbuffer(
+            glow::FRAMEBUFFER,
+            attachment.to_gl(),
+            glow::RENDERBUFFER,
+            renderbuffer,
+        )
+    }
+
+    pub fn destroy_renderbuffer(&mut self, renderbuffer_key: RenderbufferKey) {
+        if self.renderbuffers.remove(renderbuffer_key).is_some() {
+            if self.active_renderbuffer == Some(renderbuffer_key) {
+                self.active_renderbuffer = None;
+            }
+            self.backend.delete_renderbuffer(renderbuffer_key);
+        }
+    }
+
+    /// Shares ownership of `resources` between the clones of the returned `Owner`. They're
+    /// deleted after the last one is dropped.
+    pub fn new_owner(&self, resources: Vec<resource::ResourceKey>) -> resource::Owner {
+        resource::Owner::new(&self.deletion_queue, resources)
+    }
+
+    /// Deletes the objects whose last `Owner` has been dropped. This happens whenever an object
+    /// is created too so it only needs calling to free memory sooner.
+    pub fn delete_dropped_resources(&mut self) {
+        let dropped = std::mem::take(&mut *self.deletion_queue.borrow_mut());
+        for resource in dropped {
+            self.destroy_resource(resource);
+        }
+    }
+
+    pub fn destroy_resource(&mut self, resource: resource::ResourceKey) {
+        use resource::ResourceKey;
+        match resource {
+            ResourceKey::Buffer(buffer) => self.destroy_buffer_key(buffer),
+            ResourceKey::Shader(shader) => self.destroy_shader(shader),
+            ResourceKey::Texture(texture) => self.destroy_texture(texture),
+            ResourceKey::Framebuffer(framebuffer) => self.destroy_framebuffer(framebuffer),
+            ResourceKey::Renderbuffer(renderbuffer) => self.destroy_renderbuffer(renderbuffer),
+            ResourceKey::Query(query) => self.destroy_query(query),
+        }
+    }
+
+    /// When enabled, every draw checks the attributes of the mesh against those of the shader
+    /// with `mesh::validate_attributes`. Each mismatch is logged as a warning the first time it
+    /// comes up and kept for `take_attribute_mismatches`. It's off by default since it adds some
+    /// work to every draw.
+    pub fn set_attribute_validation(&mut self, enabled: bool) {
+        self.attribute_validation = enabled;
+    }
+
+    /// The mismatches found by attribute validation since this was last called, along with the
+    /// shader they were found with. Mismatches that come up again afterwards are logged again.
+    pub fn take_attribute_mismatches(&mut self) -> Vec<(ShaderKey, mesh::AttributeMismatch)> {
+        std::mem::take(&mut self.attribute_mismatches)
+    }
+
+    fn validate_attributes<S>(&mut self, shader: &S, attachments: &[mesh::AttachedAttributes])
+    where
+        S: shader::Shader + ?Sized,
+    {
+        let handle = shader.handle();
+        for mismatch in mesh::validate_attributes(shader, attachments) {
+            let mismatch = (handle, mismatch);
+            if !self.attribute_mismatches.contains(&mismatch) {
+                log::warn!("{:?} drawing with shader {:?}", mismatch.1, handle);
+                self.attribute_mismatches.push(mismatch);
+            }
+        }
+    }
+
+    /// Counts the objects that are alive and estimates how much memory they take up.
+    pub fn resource_stats(&self) -> resource::ResourceStats {
+        fn usage<K: slotmap::Key, V>(
+            objects: &SlotMap<K, V>,
+            bytes: impl Fn(&V) -> usize,
+        ) -> resource::ResourceUsage {
+            resource::ResourceUsage {
+                count: objects.len(),
+                bytes: objects.values().map(bytes).sum(),
+            }
+        }
+        resource::ResourceStats {
+            buffers: usage(&self.buffers, |size| *size),
+            shaders: usage(&self.shaders, |_| 0),
+            textures: usage(&self.textures, |size| *size),
+            framebuffers: usage(&self.framebuffers, |_| 0),
+            renderbuffers: usage(&self.renderbuffers, |size| *size),
+            queries: usage(&self.queries, |_| 0),
+        }
+    }
+
+    /// Every object that hasn't been destroyed yet, including those whose last `Owner` has been
+    /// dropped but haven't been deleted yet.
+    pub fn live_resources(&self) -> Vec<resource::ResourceKey> {
+        use resource::ResourceKey;
+        self.buffers
+            .keys()
+            .map(ResourceKey::Buffer)
+            .chain(self.shaders.keys().map(ResourceKey::Shader))
+            .chain(self.textures.keys().map(ResourceKey::Texture))
+            .chain(self.framebuffers.keys().map(ResourceKey::Framebuffer))
+            .chain(self.renderbuffers.keys().map(ResourceKey::Renderbuffer))
+            .chain(self.queries.keys().map(ResourceKey::Query))
+            .collect()
+    }
+
+    /// When enabled, every object that's still alive when the context is dropped is logged as a
+    /// warning. Objects are usually kept alive by a handle outliving the context or by a key
+    /// that was never destroyed so this helps track down leaks.
+    pub fn set_leak_report(&mut self, enabled: bool) {
+        self.leak_report = enabled;
+    }
+
+    fn report_leaks(&self) {
+        let live = self.live_resources();
+        if live.is_empty() {
+            return;
+        }
+        let stats = self.resource_stats();
+        log::warn!(
+            "{} objects taking up about {} bytes were still alive when the context was dropped: {:?}",
+            live.len(),
+            stats.total_bytes(),
+            stats
+        );
+        for resource in live {
+            log::warn!("leaked {:?}", resource);
+        }
+    }
+
+    pub fn supports_query_type(&self, query_type: query::QueryType) -> bool {
+        self.version.supports_query_type(query_type)
+    }
+
+    pub fn new_query(&mut self) -> Result<QueryKey, GraphicsError> {
+        self.delete_dropped_resources();
+        let query = self.queries.insert(());
+        match self.backend.create_query(query) {
+            Ok(()) => Ok(query),
+            Err(err) => {
+                self.queries.remove(query);
+                Err(err)
+            }
+        }
+    }
+
+    /// Starts measuring with `query`. Only one timer query and one occlusion query can be active
+    /// at a time so this does nothing while another query of the same kind is active.
+    pub fn begin_query(&mut self, query: QueryKey, query_type: query::QueryType) {
+        if !self.queries.contains_key(query) {
+            return;
+        }
+        let active = &mut self.active_queries[query_type.is_occlusion() as usize];
+        match active {
+            Some((active, _)) => log::warn!(
+                "can't begin {:?} query while query {:?} is active",
+                query_type,
+                active
+            ),
+            None => {
+                *active = Some((query, query_type));
+                self.backend.begin_query(query_type.to_gl(), query);
+            }
+        }
+    }
+
+    /// Stops measuring with `query`. This does nothing unless `query` is the active query of its
+    /// kind.
+    pub fn end_query(&mut self, query: QueryKey) {
+        let active = self
+            .active_queries
+            .iter_mut()
+            .find(|active| matches!(active, Some((active, _)) if *active == query));
+        match active.and_then(Option::take) {
+            Some((_, query_type)) => self.backend.end_query(query_type.to_gl()),
+            None => log::warn!("can't end query {:?} since it isn't active", query),
+        }
+    }
+
+    fn is_query_active(&self, query: QueryKey) -> bool {
+        self.active_queries
+            .iter()
+            .any(|active| matches!(active, Some((active, _)) if *active == query))
+    }
+
+    /// Polls for the result of `query` without waiting for the GPU. The result is read as 64
+    /// bits so timers don't wrap.
+    pub fn get_query_result(&self, query: QueryKey) -> Option<u64> {
+        if !self.queries.contains_key(query) || self.is_query_active(query) {
+            return None;
+        }
+        let available = self
+            .backend
+            .get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE);
+        if available == 0 {
+            None
+        } else {
+            Some(
+                self.backend
+                    .get_query_parameter_u64(query, glow::QUERY_RESULT),
             )
         }
     }
 
-    pub fn destroy_renderbuffer(&mut self, renderbuffer_key: RenderbufferKey) {
-        match self.renderbuffers.remove(renderbuffer_key) {
-            None => (),
-            Some(renderbuffer) => unsafe { self.ctx.delete_renderbuffer(renderbuffer) },
+    /// Deleting an active query ends it.
+    pub fn destroy_query(&mut self, query: QueryKey) {
+        if self.queries.remove(query).is_some() {
+            for active in self.active_queries.iter_mut() {
+                if matches!(active, Some((active, _)) if *active == query) {
+                    *active = None;
+                }
+            }
+            self.backend.delete_query(query);
+        }
+    }
+
+    /// Snapshots the cached GL state. Pair it with `restore_state` around code that makes its own
+    /// GL calls, like another renderer sharing the context.
+    pub fn save_state(&self) -> ContextState {
+        ContextState {
+            shader: self.active_shader,
+            buffers: self.active_buffers,
+            uniform_buffers: self.bound_uniform_buffers.clone(),
+            textures: self.bound_textures.clone(),
+            texture_unit: self.current_texture_unit,
+            framebuffers: self.active_framebuffer,
+            renderbuffer: self.active_renderbuffer,
+            viewport: self.current_viewport,
+            scissor: self.current_scissor,
+            pipeline: self.pipeline_state.clone(),
+            enabled_attributes: self.enabled_attributes,
+        }
+    }
+
+    /// Sets all of the GL state in `state` whether or not the cache thinks it's already set.
+    /// Objects that have been destroyed since the snapshot are unbound.
+    pub fn restore_state(&mut self, state: &ContextState) {
+        self.backend.restore_vertex_array();
+        self.backend.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+        self.backend.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+
+        self.active_shader = state
+            .shader
+            .filter(|shader| self.shaders.contains_key(*shader));
+        self.backend.use_program(self.active_shader);
+
+        // indexed binds also bind the generic target so they go first
+        for (binding, buffer) in state.uniform_buffers.iter().enumerate() {
+            let buffer = buffer.filter(|buffer| self.buffers.contains_key(*buffer));
+            self.bound_uniform_buffers[binding] = buffer;
+            self.backend
+                .bind_buffer_base(glow::UNIFORM_BUFFER, binding as u32, buffer);
+        }
+        for buffer_type in [
+            buffer::BufferType::Vertex,
+            buffer::BufferType::Index,
+            buffer::BufferType::Uniform,
+        ] {
+            if buffer_type == buffer::BufferType::Uniform && !self.supports_uniform_buffers() {
+                continue;
+            }
+            let index = buffer_type_to_index(buffer_type);
+            let buffer = state.buffers[index].filter(|buffer| self.buffers.contains_key(*buffer));
+            self.active_buffers[index] = buffer;
+            self.backend.bind_buffer(buffer_type.into(), buffer);
+        }
+
+        for unit in 0..self.gl_constants.max_texture_units {
+            self.backend.active_texture(glow::TEXTURE0 + unit as u32);
+            for texture_type in texture::TextureType::enumerate() {
+                if !self.supports_texture_type(*texture_type) {
+                    continue;
+                }
+                let texture = state.textures[texture_type.to_index()][unit]
+                    .filter(|texture| self.textures.contains_key(*texture));
+                self.bound_textures[texture_type.to_index()][unit] = texture;
+                self.backend
+                    .bind_texture(gl::texture::to_gl(*texture_type), texture);
+            }
+        }
+        self.current_texture_unit = state.texture_unit;
+        self.backend.active_texture(state.texture_unit.gl);
+
+        let [draw, read] = state
+            .framebuffers
+            .map(|framebuffer| framebuffer.filter(|f| self.framebuffers.contains_key(*f)));
+        self.active_framebuffer = [draw, read];
+        self.backend
+            .bind_framebuffer(canvas::Target::Draw.to_gl(), draw);
+        self.backend
+            .bind_framebuffer(canvas::Target::Read.to_gl(), read);
+
+        self.active_renderbuffer = state
+            .renderbuffer
+            .filter(|renderbuffer| self.renderbuffers.contains_key(*renderbuffer));
+        self.backend
+            .bind_renderbuffer(glow::RENDERBUFFER, self.active_renderbuffer);
+
+        let viewport = state.viewport;
+        self.current_viewport = viewport;
+        self.backend.viewport(
+            viewport.x(),
+            viewport.y(),
+            viewport.width(),
+            viewport.height(),
+        );
+        self.current_scissor = state.scissor;
+        match state.scissor {
+            Some(scissor) => {
+                self.backend.enable(glow::SCISSOR_TEST);
+                self.backend
+                    .scissor(scissor.x(), scissor.y(), scissor.width(), scissor.height());
+            }
+            None => self.backend.disable(glow::SCISSOR_TEST),
         }
+
+        self.restore_pipeline_state(&state.pipeline);
+
+        for i in 0..self.gl_constants.max_vertex_attributes as u32 {
+            if state.enabled_attributes & (1 << i) != 0 {
+                self.backend.enable_vertex_attrib_array(i);
+            } else {
+                self.backend.disable_vertex_attrib_array(i);
+            }
+        }
+        self.enabled_attributes = state.enabled_attributes;
+    }
+
+    /// Sets the GL state to what the cache holds again. Call this after something else has made
+    /// GL calls without a `save_state` beforehand.
+    pub fn invalidate_cache(&mut self) {
+        let state = self.save_state();
+        self.restore_state(&state);
+    }
+
+    fn restore_pipeline_state(&mut self, pipeline: &PipelineStateCache) {
+        self.set_capability(glow::DEPTH_TEST, pipeline.depth_test);
+        self.backend.depth_func(pipeline.depth_function.to_gl());
+        self.backend.depth_mask(pipeline.depth_write_mask);
+        self.backend
+            .depth_range_f32(**pipeline.depth_range.start(), **pipeline.depth_range.end());
+
+        let blend = pipeline.blend_state;
+        self.set_capability(glow::BLEND, pipeline.blend);
+        self.backend
+            .blend_equation_separate(blend.equation_rgb.to_gl(), blend.equation_alpha.to_gl());
+        self.backend.blend_func_separate(
+            blend.source_rgb.to_gl(),
+            blend.destination_rgb.to_gl(),
+            blend.source_alpha.to_gl(),
+            blend.destination_alpha.to_gl(),
+        );
+        let Color::<f32> {
+            red,
+            blue,
+            green,
+            alpha,
+        } = blend.color.into();
+        self.backend.blend_color(red, green, blue, alpha);
+
+        let stencil = pipeline.stencil_state;
+        self.set_capability(glow::STENCIL_TEST, pipeline.stencil_test);
+        self.backend.stencil_func(
+            stencil.function.to_gl(),
+            stencil.reference,
+            stencil.read_mask,
+        );
+        self.set_stencil_operations(glow::FRONT, stencil.front);
+        self.set_stencil_operations(glow::BACK, stencil.back);
+        self.backend.stencil_mask(pipeline.stencil_write_mask);
+
+        self.set_capability(glow::CULL_FACE, pipeline.cull_face);
+        self.backend.cull_face(pipeline.culling_state.mode.to_gl());
+        self.backend
+            .front_face(gl::vertex_winding::to_gl(pipeline.culling_state.winding));
+
+        self.set_capability(glow::POLYGON_OFFSET_FILL, pipeline.polygon_offset_fill);
+        self.backend
+            .polygon_offset(pipeline.polygon_offset.0, pipeline.polygon_offset.1);
+
+        self.pipeline_state = pipeline.clone();
     }
 
     pub fn set_vertex_attributes(
@@ -986,13 +2126,9 @@ impl Context {
 
             if diff & bit != 0 {
                 if desired & bit != 0 {
-                    unsafe {
-                        self.ctx.enable_vertex_attrib_array(i);
-                    }
+                    self.backend.enable_vertex_attrib_array(i);
                 } else {
-                    unsafe {
-                        self.ctx.disable_vertex_attrib_array(i);
-                    }
+                    self.backend.disable_vertex_attrib_array(i);
                 }
             }
 
@@ -1001,35 +2137,33 @@ impl Context {
                     binding_info[i as usize].unwrap();
                 self.bind_buffer(buffer_key, buffer_type);
                 let (data_type, elements_count, _instances_count) = vertex_format.atype.to_gl();
-                unsafe {
-                    self.ctx.vertex_attrib_divisor(i, step);
-                    use vertex::AttributeType;
-                    match vertex_format.atype {
-                        AttributeType::F32
-                        | AttributeType::F32F32
-                        | AttributeType::F32F32F32
-                        | AttributeType::F32F32F32F32
-                        | AttributeType::F32x2x2
-                        | AttributeType::F32x3x3
-                        | AttributeType::F32x4x4 => self.ctx.vertex_attrib_pointer_f32(
-                            i,
-                            elements_count,
-                            data_type,
-                            vertex_format.normalize,
-                            stride as i32,
-                            vertex_format.offset as i32,
-                        ),
-                        AttributeType::I32
-                        | AttributeType::I32I32
-                        | AttributeType::I32I32I32
-                        | AttributeType::I32I32I32I32 => self.ctx.vertex_attrib_pointer_i32(
-                            i,
-                            elements_count,
-                            data_type,
-                            stride as i32,
-                            vertex_format.offset as i32,
-                        ),
-                    }
+                self.backend.vertex_attrib_divisor(i, step);
+                use vertex::AttributeType;
+                match vertex_format.atype {
+                    AttributeType::F32
+                    | AttributeType::F32F32
+                    | AttributeType::F32F32F32
+                    | AttributeType::F32F32F32F32
+                    | AttributeType::F32x2x2
+                    | AttributeType::F32x3x3
+                    | AttributeType::F32x4x4 => self.backend.vertex_attrib_pointer_f32(
+                        i,
+                        elements_count,
+                        data_type,
+                        vertex_format.normalize,
+                        stride as i32,
+                        vertex_format.offset as i32,
+                    ),
+                    AttributeType::I32
+                    | AttributeType::I32I32
+                    | AttributeType::I32I32I32
+                    | AttributeType::I32I32I32I32 => self.backend.vertex_attrib_pointer_i32(
+                        i,
+                        elements_count,
+                        data_type,
+                        stride as i32,
+                        vertex_format.offset as i32,
+                    ),
                 }
             }
         }
@@ -1046,61 +2180,17 @@ impl Context {
             self.active_shader.is_some(),
             "Setting a uniform without an active shader."
         );
-        use shader::RawUniformValue;
-        let location = Some(&location.0);
-        unsafe {
-            match data {
-                RawUniformValue::SignedInt(data) => self.ctx.uniform_1_i32(location, *data),
-                RawUniformValue::Float(data) => self.ctx.uniform_1_f32(location, *data),
-                RawUniformValue::Mat2(data) => self.ctx.uniform_matrix_2_f32_slice(
-                    location,
-                    false,
-                    &AsRef::<[f32; 4]>::as_ref(data)[..],
-                ),
-                RawUniformValue::Mat3(data) => self.ctx.uniform_matrix_3_f32_slice(
-                    location,
-                    false,
-                    &AsRef::<[f32; 9]>::as_ref(data)[..],
-                ),
-                RawUniformValue::Mat4(data) => self.ctx.uniform_matrix_4_f32_slice(
-                    location,
-                    false,
-                    &AsRef::<[f32; 16]>::as_ref(data)[..],
-                ),
-                RawUniformValue::Vec2(data) => {
-                    self.ctx.uniform_2_f32_slice(location, data.as_ref())
-                }
-                RawUniformValue::Vec3(data) => {
-                    self.ctx.uniform_3_f32_slice(location, data.as_ref())
-                }
-                RawUniformValue::Vec4(data) => {
-                    self.ctx.uniform_4_f32_slice(location, data.as_ref())
-                }
-                RawUniformValue::IntVec2(data) => {
-                    self.ctx.uniform_2_i32_slice(location, data.as_ref())
-                }
-                RawUniformValue::IntVec3(data) => {
-                    self.ctx.uniform_3_i32_slice(location, data.as_ref())
-                }
-                RawUniformValue::IntVec4(data) => {
-                    self.ctx.uniform_4_i32_slice(location, data.as_ref())
-                }
-            }
-        }
+        self.backend.set_uniform(*location, data);
     }
 
     pub fn draw_arrays(&self, mode: DrawMode, first: i32, count: i32) {
-        unsafe {
-            self.ctx
-                .draw_arrays(gl::draw_mode::to_gl(mode), first, count);
-        }
+        self.backend
+            .draw_arrays(gl::draw_mode::to_gl(mode), first, count);
     }
 
     pub fn draw_elements(&self, mode: DrawMode, count: i32, element_type: u32, offset: i32) {
-        unsafe {
-            self.ctx
-                .draw_elements(gl::draw_mode::to_gl(mode), count, element_type, offset);
-        }
+        self.backend
+            .draw_elements(gl::draw_mode::to_gl(mode), count, element_type, offset);
     }
 
     pub fn draw_arrays_instanced(
@@ -1110,10 +2200,8 @@ impl Context {
         count: i32,
         instance_count: i32,
     ) {
-        unsafe {
-            self.ctx
-                .draw_arrays_instanced(gl::draw_mode::to_gl(mode), first, count, instance_count)
-        }
+        self.backend
+            .draw_arrays_instanced(gl::draw_mode::to_gl(mode), first, count, instance_count)
     }
 
     pub fn draw_elements_instanced(
@@ -1124,22 +2212,20 @@ impl Context {
         offset: i32,
         instance_count: i32,
     ) {
-        unsafe {
-            self.ctx.draw_elements_instanced(
-                gl::draw_mode::to_gl(mode),
-                count,
-                element_type as u32,
-                offset,
-                instance_count,
-            )
-        }
+        self.backend.draw_elements_instanced(
+            gl::draw_mode::to_gl(mode),
+            count,
+            element_type,
+            offset,
+            instance_count,
+        )
     }
 
     pub fn set_viewport(&mut self, x: i32, y: i32, width: i32, height: i32) {
         let new_viewport = viewport::Viewport::new(x, y, width, height);
         if self.current_viewport != new_viewport {
             self.current_viewport = new_viewport;
-            unsafe { self.ctx.viewport(x, y, width, height) }
+            self.backend.viewport(x, y, width, height);
         }
     }
 
@@ -1150,25 +2236,19 @@ impl Context {
     pub fn set_scissor(&mut self, region: Option<viewport::Viewport<i32>>) {
         match (region, &mut self.current_scissor) {
             (None, Some(_current)) => {
-                unsafe {
-                    self.ctx.disable(glow::SCISSOR_TEST);
-                }
+                self.backend.disable(glow::SCISSOR_TEST);
                 self.current_scissor = None;
             }
             (Some(new), None) => {
-                unsafe {
-                    self.ctx.enable(glow::SCISSOR_TEST);
-                    self.ctx
-                        .scissor(new.x(), new.y(), new.width(), new.height());
-                }
+                self.backend.enable(glow::SCISSOR_TEST);
+                self.backend
+                    .scissor(new.x(), new.y(), new.width(), new.height());
                 self.current_scissor = Some(new);
             }
             (Some(new), Some(current)) => {
                 if &new != current {
-                    unsafe {
-                        self.ctx
-                            .scissor(new.x(), new.y(), new.width(), new.height());
-                    }
+                    self.backend
+                        .scissor(new.x(), new.y(), new.width(), new.height());
                     *current = new;
                 }
             }
@@ -1177,14 +2257,12 @@ impl Context {
     }
 
     pub fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
-        unsafe { self.ctx.clear_color(red, green, blue, alpha) }
+        self.backend.clear_color(red, green, blue, alpha)
     }
 
     pub fn clear(&self) {
-        unsafe {
-            self.ctx
-                .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
-        }
+        self.backend
+            .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
     }
 
     pub fn read_pixels(
@@ -1198,62 +2276,135 @@ impl Context {
     ) {
         let gl::TextureFormat { external, ty, .. } =
             gl::pixel_format::to_gl(format, &self.version, false);
-        unsafe {
-            self.ctx.read_pixels(
-                x,
-                y,
-                width,
-                height,
+        self.backend
+            .read_pixels(x, y, width, height, external, ty, data)
+    }
+
+    /// Reads a mipmap level of any texture back into CPU memory. The pixels are tightly packed
+    /// and laid out like the data `Image::with_data` takes, rows in the order they were uploaded
+    /// and every layer, or cube map face, one after another. Multisampled canvases are resolved
+    /// first.
+    ///
+    /// Compressed, depth and stencil formats can't be read back and neither can formats the
+    /// driver can't render to. GLES only guarantees that RGBA8 textures can be read.
+    pub fn read_texture<T: texture::Texture>(
+        &mut self,
+        texture: T,
+        level: u32,
+    ) -> Result<Vec<u8>, GraphicsError> {
+        use texture::TextureType;
+
+        let texture_key = texture.get_texture_key();
+        let texture_type = texture.get_texture_type();
+        let texture = level_info(texture.get_texture_info(), texture_type, level);
+        let format = texture.get_format();
+        if format.is_compressed() || format.is_depth() || format.is_stencil() {
+            return Err(GraphicsError::UnsupportedPixelFormat(format));
+        }
+        // binding resolves multisampled canvases and regenerates stale mipmaps
+        self.bind_texture_to_unit(texture_type, texture_key, 0.into());
+
+        let face_size = layer_size(texture);
+        let mut data = vec![0; face_size * texture_type.layers(texture.depth()) as usize];
+        let gl::TextureFormat { external, ty, .. } =
+            gl::pixel_format::to_gl(format, &self.version, false);
+        let read = self.get_active_framebuffer(canvas::Target::Read);
+        let draw = self.get_active_framebuffer(canvas::Target::Draw);
+        let framebuffer = self.new_framebuffer()?;
+        let target = canvas::Target::All;
+        self.bind_framebuffer(target, Some(framebuffer));
+        let texture_key = Some(texture_key).filter(|key| self.textures.contains_key(*key));
+        let mut result = Ok(());
+        for (layer, pixels) in data.chunks_mut(face_size).enumerate() {
+            let (attachment, level) = (glow::COLOR_ATTACHMENT0, level as i32);
+            match texture_type {
+                TextureType::Tex2D | TextureType::Cube => {
+                    let texture_target = match texture_type {
+                        TextureType::Cube => glow::TEXTURE_CUBE_MAP_POSITIVE_X + layer as u32,
+                        _ => glow::TEXTURE_2D,
+                    };
+                    self.backend.framebuffer_texture_2d(
+                        target.to_gl(),
+                        attachment,
+                        texture_target,
+                        texture_key,
+                        level,
+                    )
+                }
+                TextureType::Volume | TextureType::Tex2DArray => {
+                    self.backend.framebuffer_texture_layer(
+                        target.to_gl(),
+                        attachment,
+                        texture_key,
+                        level,
+                        layer as i32,
+                    )
+                }
+            }
+            match self.check_framebuffer_status(target) {
+                canvas::Status::Complete => (),
+                status => {
+                    result = Err(GraphicsError::IncompleteFramebuffer(status));
+                    break;
+                }
+            }
+            self.backend.read_pixels(
+                0,
+                0,
+                texture.width() as i32,
+                texture.height() as i32,
                 external,
                 ty,
-                glow::PixelPackData::Slice(data),
-            )
+                pixels,
+            );
         }
+        self.bind_framebuffer(canvas::Target::Read, read);
+        self.bind_framebuffer(canvas::Target::Draw, draw);
+        self.destroy_framebuffer(framebuffer);
+        result.map(|()| data)
     }
 
     pub fn debug_message_callback<F>(&self, mut callback: F)
     where
         F: FnMut(DebugSource, DebugType, u32, DebugSeverity, &str),
     {
-        if self.ctx.supports_debug() {
-            unsafe {
-                self.ctx.enable(glow::DEBUG_OUTPUT);
-                self.ctx
-                    .debug_message_callback(|source, event_type, id, severity, msg| {
-                        let source = match source {
-                            glow::DEBUG_SOURCE_API => DebugSource::API,
-                            glow::DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
-                            glow::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
-                            glow::DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
-                            glow::DEBUG_SOURCE_APPLICATION => DebugSource::Application,
-                            glow::DEBUG_SOURCE_OTHER => DebugSource::Other,
-                            _ => DebugSource::Other,
-                        };
-
-                        let event_type = match event_type {
-                            glow::DEBUG_TYPE_ERROR => DebugType::Error,
-                            glow::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
-                            glow::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::DeprecatedBehavior,
-                            glow::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
-                            glow::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
-                            glow::DEBUG_TYPE_MARKER => DebugType::Marker,
-                            glow::DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
-                            glow::DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
-                            glow::DEBUG_TYPE_OTHER => DebugType::Other,
-                            _ => DebugType::Other,
-                        };
-
-                        let severity = match severity {
-                            glow::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
-                            glow::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
-                            glow::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
-                            glow::DEBUG_SEVERITY_NOTIFICATION => DebugSeverity::Notification,
-                            _ => DebugSeverity::Notification,
-                        };
-
-                        callback(source, event_type, id, severity, msg)
-                    });
-            }
+        if self.backend.supports_debug() {
+            self.backend.debug_message_callback(Box::new(
+                move |source, event_type, id, severity, msg| {
+                    let source = match source {
+                        glow::DEBUG_SOURCE_API => DebugSource::API,
+                        glow::DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
+                        glow::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+                        glow::DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
+                        glow::DEBUG_SOURCE_APPLICATION => DebugSource::Application,
+                        glow::DEBUG_SOURCE_OTHER => DebugSource::Other,
+                        _ => DebugSource::Other,
+                    };
+
+                    let event_type = match event_type {
+                        glow::DEBUG_TYPE_ERROR => DebugType::Error,
+                        glow::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+                        glow::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::DeprecatedBehavior,
+                        glow::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+                        glow::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+                        glow::DEBUG_TYPE_MARKER => DebugType::Marker,
+                        glow::DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
+                        glow::DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
+                        glow::DEBUG_TYPE_OTHER => DebugType::Other,
+                        _ => DebugType::Other,
+                    };
+
+                    let severity = match severity {
+                        glow::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+                        glow::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+                        glow::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+                        glow::DEBUG_SEVERITY_NOTIFICATION => DebugSeverity::Notification,
+                        _ => DebugSeverity::Notification,
+                    };
+
+                    callback(source, event_type, id, severity, msg)
+                },
+            ));
         }
     }
 }
@@ -1289,8 +2440,210 @@ pub enum DebugSource {
     Other,
 }
 
-impl texture::TextureUpdate for Context {
-    fn set_texture_sub_data(
+// the estimated size of a texture with all of its mipmaps
+fn texture_size(texture: texture::TextureInfo, texture_type: texture::TextureType) -> usize {
+    let levels = if texture.mipmaps() {
+        32 - texture.width().max(texture.height()).max(1).leading_zeros()
+    } else {
+        1
+    };
+    (0..levels)
+        .map(|level| level_size(texture, texture_type, level))
+        .sum()
+}
+
+// GL 3 and GLES 3 list extensions one at a time, earlier versions in one long string. WebGL
+// can't list them through parameters at all so the backend has to.
+fn query_extensions(ctx: &dyn Backend, version: &GLVersion) -> std::collections::HashSet<String> {
+    if let Some(extensions) = ctx.supported_extensions() {
+        extensions.into_iter().collect()
+    } else if cfg!(target_arch = "wasm32") {
+        Default::default()
+    } else if version.major >= 3 {
+        let count = ctx.get_parameter_i32(glow::NUM_EXTENSIONS).max(0) as u32;
+        (0..count)
+            .map(|index| ctx.get_parameter_indexed_string(glow::EXTENSIONS, index))
+            .collect()
+    } else {
+        ctx.get_parameter_string(glow::EXTENSIONS)
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+// the dimensions of a mipmap level, only volume textures have fewer layers at smaller levels
+fn level_info(
+    mut texture: texture::TextureInfo,
+    texture_type: texture::TextureType,
+    level: u32,
+) -> texture::TextureInfo {
+    let shrink = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+    texture.set_width(shrink(texture.width()));
+    texture.set_height(shrink(texture.height()));
+    if texture_type == texture::TextureType::Volume {
+        texture.set_depth(shrink(texture.depth()));
+    }
+    texture
+}
+
+// every layer of a mipmap level
+pub(crate) fn level_size(
+    texture: texture::TextureInfo,
+    texture_type: texture::TextureType,
+    level: u32,
+) -> usize {
+    let texture = level_info(texture, texture_type, level);
+    layer_size(texture).saturating_mul(texture_type.layers(texture.depth()) as usize)
+}
+
+fn layer_size(texture: texture::TextureInfo) -> usize {
+    gl::pixel_format::image_size(texture.get_format(), texture.width(), texture.height())
+}
+
+// compressed data is uploaded as-is through its own set of functions and mipmaps can't be
+// generated for it
+impl Context {
+    fn set_compressed_texture_data(
+        &mut self,
+        texture: texture::TextureInfo,
+        texture_type: texture::TextureType,
+        level: i32,
+        internal: u32,
+        data: Option<&[u8]>,
+    ) {
+        use texture::TextureType;
+
+        let face_size = layer_size(texture);
+        let width = texture.width() as i32;
+        let height = texture.height() as i32;
+        let depth = texture.depth();
+        // compressed storage can't be allocated without data
+        let zeroed;
+        let data = match data {
+            Some(data) => data,
+            None => {
+                zeroed = vec![0; face_size * texture_type.layers(depth) as usize];
+                &zeroed
+            }
+        };
+        let gl_target = gl::texture::to_gl(texture_type);
+        match texture_type {
+            TextureType::Tex2D => self
+                .backend
+                .compressed_tex_image_2d(gl_target, level, internal, width, height, 0, data),
+            TextureType::Volume | TextureType::Tex2DArray => self.backend.compressed_tex_image_3d(
+                gl_target,
+                level,
+                internal,
+                width,
+                height,
+                depth as i32,
+                0,
+                data,
+            ),
+            TextureType::Cube => {
+                for face in 0..6 {
+                    let offset = face * face_size;
+                    self.backend.compressed_tex_image_2d(
+                        glow::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                        level,
+                        internal,
+                        width,
+                        height,
+                        0,
+                        &data[offset..(offset + face_size)],
+                    );
+                }
+            }
+        }
+    }
+
+    fn set_compressed_texture_sub_data(
+        &mut self,
+        texture: texture::TextureInfo,
+        texture_type: texture::TextureType,
+        internal: u32,
+        data: &[u8],
+        [x_offset, y_offset, layer]: [u32; 3],
+    ) {
+        use texture::TextureType;
+
+        let width = texture.width() as i32;
+        let height = texture.height() as i32;
+        let depth = texture.depth();
+        let gl_target = gl::texture::to_gl(texture_type);
+        match texture_type {
+            TextureType::Tex2D => self.backend.compressed_tex_sub_image_2d(
+                gl_target,
+                0,
+                x_offset as i32,
+                y_offset as i32,
+                width,
+                height,
+                internal,
+                data,
+            ),
+            TextureType::Volume | TextureType::Tex2DArray => {
+                self.backend.compressed_tex_sub_image_3d(
+                    gl_target,
+                    0,
+                    x_offset as i32,
+                    y_offset as i32,
+                    layer as i32,
+                    width,
+                    height,
+                    depth as i32,
+                    internal,
+                    data,
+                )
+            }
+            TextureType::Cube => {
+                let face_size = layer_size(texture);
+                for face in layer..(layer + depth) {
+                    let offset = (face - layer) as usize * face_size;
+                    self.backend.compressed_tex_sub_image_2d(
+                        glow::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                        0,
+                        x_offset as i32,
+                        y_offset as i32,
+                        width,
+                        height,
+                        internal,
+                        &data[offset..(offset + face_size)],
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl texture::TextureUpdate for Context {
+    fn set_texture_sub_data(
+        &mut self,
+        texture_key: TextureKey,
+        texture: texture::TextureInfo,
+        texture_type: texture::TextureType,
+        data: &[u8],
+        x_offset: u32,
+        y_offset: u32,
+    ) {
+        self.set_texture_layer_sub_data(
+            texture_key,
+            texture,
+            texture_type,
+            data,
+            x_offset,
+            y_offset,
+            0,
+        );
+        if texture.mipmaps() && !texture.get_format().is_compressed() {
+            self.backend
+                .generate_mipmap(gl::texture::to_gl(texture_type));
+        }
+    }
+
+    fn set_texture_layer_sub_data(
         &mut self,
         texture_key: TextureKey,
         texture: texture::TextureInfo,
@@ -1298,15 +2651,28 @@ impl texture::TextureUpdate for Context {
         data: &[u8],
         x_offset: u32,
         y_offset: u32,
+        layer: u32,
     ) {
-        let gl::TextureFormat { external, ty, .. } =
-            gl::pixel_format::to_gl(texture.get_format(), &self.version, false);
+        use texture::TextureType;
+
+        let gl::TextureFormat {
+            internal,
+            external,
+            ty,
+            ..
+        } = gl::pixel_format::to_gl(texture.get_format(), &self.version, false);
         let width = texture.width();
         let height = texture.height();
+        let depth = texture.depth();
         let gl_target = gl::texture::to_gl(texture_type);
         self.bind_texture_to_unit(texture_type, texture_key, 0.into());
-        unsafe {
-            self.ctx.tex_sub_image_2d(
+        if texture.get_format().is_compressed() {
+            let offset = [x_offset, y_offset, layer];
+            self.set_compressed_texture_sub_data(texture, texture_type, internal, data, offset);
+            return;
+        }
+        match texture_type {
+            TextureType::Tex2D => self.backend.tex_sub_image_2d(
                 gl_target,
                 0,
                 x_offset as i32,
@@ -1315,10 +2681,37 @@ impl texture::TextureUpdate for Context {
                 height as i32,
                 external,
                 ty,
-                glow::PixelUnpackData::Slice(data),
-            );
-            if texture.mipmaps() {
-                self.ctx.generate_mipmap(gl_target);
+                data,
+            ),
+            TextureType::Volume | TextureType::Tex2DArray => self.backend.tex_sub_image_3d(
+                gl_target,
+                0,
+                x_offset as i32,
+                y_offset as i32,
+                layer as i32,
+                width as i32,
+                height as i32,
+                depth as i32,
+                external,
+                ty,
+                data,
+            ),
+            TextureType::Cube => {
+                let face_size = layer_size(texture);
+                for face in layer..(layer + depth) {
+                    let offset = (face - layer) as usize * face_size;
+                    self.backend.tex_sub_image_2d(
+                        glow::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                        0,
+                        x_offset as i32,
+                        y_offset as i32,
+                        width as i32,
+                        height as i32,
+                        external,
+                        ty,
+                        &data[offset..(offset + face_size)],
+                    );
+                }
             }
         }
     }
@@ -1330,40 +2723,92 @@ impl texture::TextureUpdate for Context {
         texture_type: texture::TextureType,
         data: Option<&[u8]>,
     ) {
+        self.set_texture_level_data(texture_key, texture, texture_type, 0, data);
+        if texture.mipmaps() && !texture.get_format().is_compressed() {
+            self.backend
+                .generate_mipmap(gl::texture::to_gl(texture_type));
+        }
+    }
+
+    fn set_texture_level_data(
+        &mut self,
+        texture_key: TextureKey,
+        texture: texture::TextureInfo,
+        texture_type: texture::TextureType,
+        level: u32,
+        data: Option<&[u8]>,
+    ) {
+        use texture::TextureType;
+
         let gl::TextureFormat {
             internal,
             external,
             ty,
             swizzle,
         } = gl::pixel_format::to_gl(texture.get_format(), &self.version, false);
-        let width = texture.width();
-        let height = texture.height();
         let gl_target = gl::texture::to_gl(texture_type);
+        if level == 0 {
+            if let Some(size) = self.textures.get_mut(texture_key) {
+                *size = texture_size(texture, texture_type);
+            }
+        }
+        let texture = level_info(texture, texture_type, level);
+        let (width, height, level) = (texture.width(), texture.height(), level as i32);
         self.bind_texture_to_unit(texture_type, texture_key, 0.into());
-        unsafe {
-            if let Some(swizzle) = swizzle {
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_R, swizzle[0]);
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_G, swizzle[1]);
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_B, swizzle[2]);
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_A, swizzle[3]);
-            }
-            self.ctx.tex_image_2d(
+        if texture.get_format().is_compressed() {
+            self.set_compressed_texture_data(texture, texture_type, level, internal, data);
+            return;
+        }
+        if let (Some(swizzle), 0) = (swizzle, level) {
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_R, swizzle[0]);
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_G, swizzle[1]);
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_B, swizzle[2]);
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_A, swizzle[3]);
+        }
+        match texture_type {
+            TextureType::Tex2D => self.backend.tex_image_2d(
                 gl_target,
+                level,
+                internal as i32,
+                width as i32,
+                height as i32,
                 0,
+                external,
+                ty,
+                data,
+            ),
+            TextureType::Volume | TextureType::Tex2DArray => self.backend.tex_image_3d(
+                gl_target,
+                level,
                 internal as i32,
                 width as i32,
                 height as i32,
+                texture.depth() as i32,
                 0,
                 external,
                 ty,
                 data,
-            );
-            if texture.mipmaps() {
-                self.ctx.generate_mipmap(gl_target);
+            ),
+            TextureType::Cube => {
+                let face_size = layer_size(texture);
+                for face in 0..6 {
+                    let offset = face * face_size;
+                    self.backend.tex_image_2d(
+                        glow::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                        level,
+                        internal as i32,
+                        width as i32,
+                        height as i32,
+                        0,
+                        external,
+                        ty,
+                        data.map(|data| &data[offset..(offset + face_size)]),
+                    );
+                }
             }
         }
     }
@@ -1382,33 +2827,34 @@ impl texture::TextureUpdate for Context {
             swizzle,
         } = gl::pixel_format::to_gl(texture_info.get_format(), &self.version, false);
         let gl_target = gl::texture::to_gl(texture.get_texture_type());
+        if let Some(size) = self.textures.get_mut(texture.get_texture_key()) {
+            *size = texture_size(texture_info, texture.get_texture_type());
+        }
         self.bind_texture_to_unit(
             texture.get_texture_type(),
             texture.get_texture_key(),
             0.into(),
         );
-        unsafe {
-            if let Some(swizzle) = swizzle {
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_R, swizzle[0]);
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_G, swizzle[1]);
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_B, swizzle[2]);
-                self.ctx
-                    .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_A, swizzle[3]);
-            }
-            self.ctx.tex_image_2d_with_html_image(
-                gl_target,
-                0,
-                internal as i32,
-                external,
-                ty,
-                data,
-            );
-            if texture_info.mipmaps() {
-                self.ctx.generate_mipmap(gl_target);
-            }
+        if let Some(swizzle) = swizzle {
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_R, swizzle[0]);
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_G, swizzle[1]);
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_B, swizzle[2]);
+            self.backend
+                .tex_parameter_i32(gl_target, glow::TEXTURE_SWIZZLE_A, swizzle[3]);
+        }
+        self.backend.tex_image_2d_with_html_image(
+            gl_target,
+            0,
+            internal as i32,
+            external,
+            ty,
+            data,
+        );
+        if texture_info.mipmaps() {
+            self.backend.generate_mipmap(gl_target);
         }
     }
 
@@ -1419,27 +2865,25 @@ impl texture::TextureUpdate for Context {
         wrap: texture::Wrap,
     ) {
         let gl_target = gl::texture::to_gl(texture_type);
-        unsafe {
-            self.bind_texture_to_unit(texture_type, texture_key, 0.into());
-            self.ctx.tex_parameter_i32(
-                gl_target,
-                glow::TEXTURE_WRAP_S,
-                gl::wrap_mode::to_gl(wrap.s()) as i32,
-            );
-            self.ctx.tex_parameter_i32(
+        self.bind_texture_to_unit(texture_type, texture_key, 0.into());
+        self.backend.tex_parameter_i32(
+            gl_target,
+            glow::TEXTURE_WRAP_S,
+            gl::wrap_mode::to_gl(wrap.s()) as i32,
+        );
+        self.backend.tex_parameter_i32(
+            gl_target,
+            glow::TEXTURE_WRAP_T,
+            gl::wrap_mode::to_gl(wrap.t()) as i32,
+        );
+        use texture::TextureType;
+        match texture_type {
+            TextureType::Tex2D | TextureType::Tex2DArray | TextureType::Cube => (),
+            TextureType::Volume => self.backend.tex_parameter_i32(
                 gl_target,
-                glow::TEXTURE_WRAP_T,
-                gl::wrap_mode::to_gl(wrap.t()) as i32,
-            );
-            use texture::TextureType;
-            match texture_type {
-                TextureType::Tex2D | TextureType::Tex2DArray | TextureType::Cube => (),
-                TextureType::Volume => self.ctx.tex_parameter_i32(
-                    gl_target,
-                    glow::TEXTURE_WRAP_R,
-                    gl::wrap_mode::to_gl(wrap.r()) as i32,
-                ),
-            }
+                glow::TEXTURE_WRAP_R,
+                gl::wrap_mode::to_gl(wrap.r()) as i32,
+            ),
         }
     }
 
@@ -1472,26 +2916,70 @@ impl texture::TextureUpdate for Context {
         };
 
         let gl_target = gl::texture::to_gl(texture_type);
-        unsafe {
-            self.bind_texture_to_unit(texture_type, texture_key, 0.into());
-            self.ctx
-                .tex_parameter_i32(gl_target, glow::TEXTURE_MIN_FILTER, gl_min as i32);
-            self.ctx
-                .tex_parameter_i32(gl_target, glow::TEXTURE_MAG_FILTER, gl_mag as i32);
+        self.bind_texture_to_unit(texture_type, texture_key, 0.into());
+        self.backend
+            .tex_parameter_i32(gl_target, glow::TEXTURE_MIN_FILTER, gl_min as i32);
+        self.backend
+            .tex_parameter_i32(gl_target, glow::TEXTURE_MAG_FILTER, gl_mag as i32);
+    }
+
+    fn set_texture_level_range(
+        &mut self,
+        texture_key: TextureKey,
+        texture_type: texture::TextureType,
+        base_level: u32,
+        max_level: u32,
+    ) {
+        if self.version.gles && self.version.major < 3 {
+            return;
+        }
+        let gl_target = gl::texture::to_gl(texture_type);
+        self.bind_texture_to_unit(texture_type, texture_key, 0.into());
+        self.backend
+            .tex_parameter_i32(gl_target, glow::TEXTURE_BASE_LEVEL, base_level as i32);
+        self.backend
+            .tex_parameter_i32(gl_target, glow::TEXTURE_MAX_LEVEL, max_level as i32);
+    }
+
+    fn set_texture_lod_bias(
+        &mut self,
+        texture_key: TextureKey,
+        texture_type: texture::TextureType,
+        bias: f32,
+    ) {
+        if self.version.gles {
+            return;
         }
+        let gl_target = gl::texture::to_gl(texture_type);
+        self.bind_texture_to_unit(texture_type, texture_key, 0.into());
+        self.backend
+            .tex_parameter_f32(gl_target, glow::TEXTURE_LOD_BIAS, bias);
+    }
+
+    fn generate_mipmaps(&mut self, texture_key: TextureKey, texture_type: texture::TextureType) {
+        self.bind_texture_to_unit(texture_type, texture_key, 0.into());
+        self.backend
+            .generate_mipmap(gl::texture::to_gl(texture_type));
     }
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
-        for (_, shader) in self.shaders.drain() {
-            unsafe {
-                self.ctx.delete_program(shader);
-            }
+        self.delete_dropped_resources();
+        if self.leak_report {
+            self.report_leaks();
+        }
+
+        for (shader, ()) in self.shaders.drain() {
+            self.backend.delete_program(shader);
         }
 
-        for (_, buffer) in self.buffers.drain() {
-            unsafe { self.ctx.delete_buffer(buffer) }
+        for (buffer, _) in self.buffers.drain() {
+            self.backend.delete_buffer(buffer);
+        }
+
+        for (query, ()) in self.queries.drain() {
+            self.backend.delete_query(query);
         }
     }
 }
@@ -1508,6 +2996,7 @@ impl Renderer for Context {
     fn clear(&mut self, settings: ClearSettings) {
         let ClearSettings {
             color,
+            attachment_colors,
             depth,
             stencil,
             target,
@@ -1524,32 +3013,44 @@ impl Renderer for Context {
                 green,
                 alpha,
             } = color.into();
-            unsafe {
-                self.ctx.clear_color(red, green, blue, alpha);
-            }
+            self.backend.clear_color(red, green, blue, alpha);
             clear_bits |= glow::COLOR_BUFFER_BIT;
         }
 
         if let Some(depth) = depth {
-            unsafe {
-                self.ctx.clear_depth_f32(depth.0);
-            }
+            // the depth write mask also applies to clears
+            self.set_depth_write_mask(true);
+            self.backend.clear_depth_f32(depth.0);
             clear_bits |= glow::DEPTH_BUFFER_BIT;
         }
 
         if let Some(stencil) = stencil {
-            unsafe {
-                self.ctx.clear_stencil(stencil);
-            }
+            // as does the stencil write mask
+            self.set_stencil_write_mask(!0);
+            self.backend.clear_stencil(stencil);
             clear_bits |= glow::STENCIL_BUFFER_BIT;
         }
 
-        self.bind_framebuffer(
-            canvas::Target::All,
-            target.map(canvas::Canvas::get_framebuffer_key),
-        );
-        unsafe {
-            self.ctx.clear(clear_bits);
+        let framebuffer = target.map(canvas::Canvas::get_framebuffer_key);
+        self.bind_framebuffer(canvas::Target::All, framebuffer);
+        self.mark_framebuffer_dirty(framebuffer);
+        if clear_bits != 0 {
+            self.backend.clear(clear_bits);
+        }
+        for (index, color) in attachment_colors.iter().enumerate() {
+            if let Some(color) = color {
+                let Color::<f32> {
+                    red,
+                    blue,
+                    green,
+                    alpha,
+                } = (*color).into();
+                self.backend.clear_buffer_f32_slice(
+                    glow::COLOR,
+                    index as u32,
+                    &[red, green, blue, alpha],
+                );
+            }
         }
     }
 
@@ -1560,19 +3061,33 @@ impl Renderer for Context {
     {
         self.use_shader(Some(shader));
 
-        if let Some(depth_state) = settings.depth_state {
-            self.enable(Feature::DepthTest(depth_state.function));
-        } else {
-            self.disable(Feature::DepthTest(DepthFunction::Never));
+        let PipelineSettings {
+            viewport,
+            framebuffer,
+            polygon_state,
+            depth_state,
+            blend_state,
+            stencil_state,
+            scissor_state,
+        } = settings;
+
+        if viewport.width() != 0 || viewport.height() != 0 {
+            self.set_viewport(
+                viewport.x(),
+                viewport.y(),
+                viewport.width(),
+                viewport.height(),
+            );
         }
-        self.set_scissor(settings.scissor_state);
+        self.set_depth_state(depth_state.as_ref());
+        self.set_blend_state(blend_state);
+        self.set_stencil_state(stencil_state);
+        self.set_polygon_state(polygon_state);
+        self.set_scissor(scissor_state);
 
-        self.bind_framebuffer(
-            canvas::Target::All,
-            settings
-                .framebuffer
-                .map(canvas::Canvas::get_framebuffer_key),
-        );
+        let framebuffer = framebuffer.map(canvas::Canvas::get_framebuffer_key);
+        self.bind_framebuffer(canvas::Target::All, framebuffer);
+        self.mark_framebuffer_dirty(framebuffer);
 
         let Geometry {
             mesh,
@@ -1583,6 +3098,9 @@ impl Renderer for Context {
         } = geometry;
 
         let attached_attributes = mesh.attachments();
+        if self.attribute_validation {
+            self.validate_attributes(shader, &attached_attributes);
+        }
         let (desired_attribute_state, attributes) = prepare_draw(shader, &attached_attributes);
         self.set_vertex_attributes(desired_attribute_state, &attributes);
 
@@ -1710,7 +3228,10 @@ impl From<Color<ClampedF32>> for Color<f32> {
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct ClearSettings<'a> {
+    /// Clears every color attachment of the target.
     pub color: Option<Color<ClampedF32>>,
+    /// Clears individual color attachments, by index, after `color` has been applied.
+    pub attachment_colors: &'a [Option<Color<ClampedF32>>],
     pub depth: Option<ClampedF32>,
     pub stencil: Option<i32>, // TODO: does signed make sense here?
     pub target: Option<&'a canvas::Canvas>,
@@ -1721,6 +3242,7 @@ impl Default for ClearSettings<'_> {
     fn default() -> Self {
         Self {
             color: Some(Color::default()),
+            attachment_colors: &[],
             depth: Some(ClampedF32(1.)),
             stencil: Some(0),
             target: None,
@@ -1788,6 +3310,28 @@ pub enum BlendSource {
     SourceAlphaSaturate,
 }
 
+impl BlendSource {
+    pub fn to_gl(&self) -> u32 {
+        match self {
+            BlendSource::Zero => glow::ZERO,
+            BlendSource::One => glow::ONE,
+            BlendSource::SourceColor => glow::SRC_COLOR,
+            BlendSource::OneMinusSourceColor => glow::ONE_MINUS_SRC_COLOR,
+            BlendSource::DestinationColor => glow::DST_COLOR,
+            BlendSource::OneMinusDestinationColor => glow::ONE_MINUS_DST_COLOR,
+            BlendSource::SourceAlpha => glow::SRC_ALPHA,
+            BlendSource::OneMinusSourceAlpha => glow::ONE_MINUS_SRC_ALPHA,
+            BlendSource::DestinationAlpha => glow::DST_ALPHA,
+            BlendSource::OneMinusDestinationAlpha => glow::ONE_MINUS_DST_ALPHA,
+            BlendSource::ConstantColor => glow::CONSTANT_COLOR,
+            BlendSource::OneMinusConstantColor => glow::ONE_MINUS_CONSTANT_COLOR,
+            BlendSource::ConstantAlpha => glow::CONSTANT_ALPHA,
+            BlendSource::OneMinusConstantAlpha => glow::ONE_MINUS_CONSTANT_ALPHA,
+            BlendSource::SourceAlphaSaturate => glow::SRC_ALPHA_SATURATE,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum BlendDestination {
     Zero,
@@ -1806,6 +3350,27 @@ pub enum BlendDestination {
     OneMinusConstantAlpha,
 }
 
+impl BlendDestination {
+    pub fn to_gl(&self) -> u32 {
+        match self {
+            BlendDestination::Zero => glow::ZERO,
+            BlendDestination::One => glow::ONE,
+            BlendDestination::SourceColor => glow::SRC_COLOR,
+            BlendDestination::OneMinusSourceColor => glow::ONE_MINUS_SRC_COLOR,
+            BlendDestination::DestinationColor => glow::DST_COLOR,
+            BlendDestination::OneMinusDestinationColor => glow::ONE_MINUS_DST_COLOR,
+            BlendDestination::SourceAlpha => glow::SRC_ALPHA,
+            BlendDestination::OneMinusSourceAlpha => glow::ONE_MINUS_SRC_ALPHA,
+            BlendDestination::DestinationAlpha => glow::DST_ALPHA,
+            BlendDestination::OneMinusDestinationAlpha => glow::ONE_MINUS_DST_ALPHA,
+            BlendDestination::ConstantColor => glow::CONSTANT_COLOR,
+            BlendDestination::OneMinusConstantColor => glow::ONE_MINUS_CONSTANT_COLOR,
+            BlendDestination::ConstantAlpha => glow::CONSTANT_ALPHA,
+            BlendDestination::OneMinusConstantAlpha => glow::ONE_MINUS_CONSTANT_ALPHA,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum BlendEquation {
     Add,
@@ -1815,6 +3380,18 @@ pub enum BlendEquation {
     Max,
 }
 
+impl BlendEquation {
+    pub fn to_gl(&self) -> u32 {
+        match self {
+            BlendEquation::Add => glow::FUNC_ADD,
+            BlendEquation::Subtract => glow::FUNC_SUBTRACT,
+            BlendEquation::ReverseSubtract => glow::FUNC_REVERSE_SUBTRACT,
+            BlendEquation::Min => glow::MIN,
+            BlendEquation::Max => glow::MAX,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct BlendState {
     pub destination_rgb: BlendDestination,
@@ -1826,6 +3403,19 @@ pub struct BlendState {
     pub equation_alpha: BlendEquation,
 }
 
+impl BlendState {
+    /// Standard "over" compositing for straight (non-premultiplied) alpha.
+    pub fn alpha() -> Self {
+        Self {
+            destination_rgb: BlendDestination::OneMinusSourceAlpha,
+            source_rgb: BlendSource::SourceAlpha,
+            destination_alpha: BlendDestination::OneMinusSourceAlpha,
+            source_alpha: BlendSource::One,
+            ..Default::default()
+        }
+    }
+}
+
 impl Default for BlendState {
     fn default() -> Self {
         Self {
@@ -1852,26 +3442,120 @@ pub enum StencilFunction {
     Always,
 }
 
+impl StencilFunction {
+    pub fn to_gl(&self) -> u32 {
+        match self {
+            StencilFunction::Never => glow::NEVER,
+            StencilFunction::Less => glow::LESS,
+            StencilFunction::LessOrEqual => glow::LEQUAL,
+            StencilFunction::Greater => glow::GREATER,
+            StencilFunction::GreaterOrEqual => glow::GEQUAL,
+            StencilFunction::Equal => glow::EQUAL,
+            StencilFunction::NoteEqual => glow::NOTEQUAL,
+            StencilFunction::Always => glow::ALWAYS,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StencilOperation {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOperation {
+    pub fn to_gl(&self) -> u32 {
+        match self {
+            StencilOperation::Keep => glow::KEEP,
+            StencilOperation::Zero => glow::ZERO,
+            StencilOperation::Replace => glow::REPLACE,
+            StencilOperation::Increment => glow::INCR,
+            StencilOperation::IncrementWrap => glow::INCR_WRAP,
+            StencilOperation::Decrement => glow::DECR,
+            StencilOperation::DecrementWrap => glow::DECR_WRAP,
+            StencilOperation::Invert => glow::INVERT,
+        }
+    }
+}
+
+/// What happens to the stencil buffer for one polygon face.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StencilOperations {
+    /// The stencil test failed.
+    pub fail: StencilOperation,
+    /// The stencil test passed but the depth test failed.
+    pub depth_fail: StencilOperation,
+    /// Both the stencil and depth tests passed.
+    pub pass: StencilOperation,
+}
+
+impl Default for StencilOperations {
+    fn default() -> Self {
+        Self {
+            fail: StencilOperation::Keep,
+            depth_fail: StencilOperation::Keep,
+            pass: StencilOperation::Keep,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct StencilState {
     pub function: StencilFunction,
-    // TODO the rest
+    /// The value the stencil buffer is compared against and, with
+    /// `StencilOperation::Replace`, the value that gets written.
+    pub reference: i32,
+    /// ANDed with both the reference and the stored value before comparing.
+    pub read_mask: u32,
+    /// Which bits of the stencil buffer may be written.
+    pub write_mask: u32,
+    pub front: StencilOperations,
+    pub back: StencilOperations,
+}
+
+impl StencilState {
+    /// Uses the same operations for front and back faces.
+    pub fn with_operations(mut self, operations: StencilOperations) -> Self {
+        self.front = operations;
+        self.back = operations;
+        self
+    }
 }
 
 impl Default for StencilState {
     fn default() -> Self {
         Self {
             function: StencilFunction::Always,
+            reference: 0,
+            read_mask: !0,
+            write_mask: !0,
+            front: Default::default(),
+            back: Default::default(),
         }
     }
 }
 
+/// The state `Renderer::draw` applies before drawing.
+///
+/// The default depth tests, alpha blends and keeps whatever viewport is already set. Blending
+/// used to be disabled and the viewport set to 0x0 by default, so code relying on that has to
+/// set `blend_state: None` and an explicit viewport.
 #[derive(Debug, Clone, PartialEq)]
 pub struct PipelineSettings<'a> {
+    /// A zero sized viewport, the default, leaves the current viewport in place rather than
+    /// setting a 0x0 one.
     pub viewport: viewport::Viewport<i32>,
     pub framebuffer: Option<&'a canvas::Canvas>,
     pub polygon_state: PolygonState,
     pub depth_state: Option<DepthState>,
+    /// `None` disables blending. Defaults to `BlendState::alpha()`, the blending a new
+    /// `Context` starts with.
     pub blend_state: Option<BlendState>,
     pub stencil_state: Option<StencilState>,
     pub scissor_state: Option<viewport::Viewport<i32>>,
@@ -1884,16 +3568,27 @@ impl<'a> Default for PipelineSettings<'a> {
             framebuffer: None,
             depth_state: Some(DepthState::default()),
             polygon_state: Default::default(),
-            blend_state: None,
+            blend_state: Some(BlendState::alpha()),
             stencil_state: None,
             scissor_state: None,
         }
     }
 }
 
-#[cfg(all(test, not(target_os = "linux")))]
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        backend::recording::{
+            testing::{self, context, SRC},
+            Command, RecordingBackend,
+        },
+        canvas::Canvas,
+        image::Image,
+        quad_batch::{Quad, QuadBatch},
+        shader::DynamicShader,
+        texture::{Texture, TextureType},
+    };
 
     #[test]
     fn pipeline() {
@@ -1917,12 +3612,15 @@ mod tests {
     }
 
     #[repr(C)]
-    #[derive(Debug, Copy, Clone, PartialEq, Default, bytemuck::Zeroable, bytemuck::Pod)]
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
     struct TestVertex {
         color: f32,
         position: f32,
     }
 
+    unsafe impl bytemuck::Zeroable for TestVertex {}
+    unsafe impl bytemuck::Pod for TestVertex {}
+
     use vertex::VertexFormat;
     impl vertex::Vertex for TestVertex {
         fn build_bindings() -> &'static [VertexFormat] {
@@ -1943,36 +3641,15 @@ mod tests {
         }
     }
 
-    #[cfg(target_os = "windows")]
-    fn get_headless_context(
-        width: u32,
-        height: u32,
-    ) -> (glow::Context, glutin::Context<glutin::PossiblyCurrent>) {
-        use glutin::platform::windows::EventLoopExtWindows;
-        let el = glutin::event_loop::EventLoop::<()>::new_any_thread();
-        let window = glutin::ContextBuilder::new()
-            .build_headless(&el, glutin::dpi::PhysicalSize::new(width, height))
-            .unwrap();
-        let window = unsafe { window.make_current().unwrap() };
-        (
-            unsafe { glow::Context::from_loader_function(|name| window.get_proc_address(name)) },
-            window,
-        )
-    }
-
-    #[cfg(target_os = "windows")]
     #[test]
     fn basic() {
-        let (ctx, _window) = get_headless_context(100, 100);
-        let ctx = Context::new(ctx);
+        let (ctx, _backend) = context();
         ctx.clear();
     }
 
-    #[cfg(target_os = "windows")]
     #[test]
     fn unused_vertex_attribute() {
-        let (ctx, _window) = get_headless_context(100, 100);
-        let mut ctx = Context::new(ctx);
+        let (mut ctx, _backend) = context();
 
         let mesh = mesh::VertexMesh::with_data(
             &mut ctx,
@@ -2027,11 +3704,9 @@ void main() {
         );
     }
 
-    #[cfg(target_os = "windows")]
     #[test]
     fn mapped_mesh() {
-        let (ctx, _window) = get_headless_context(100, 100);
-        let mut ctx = Context::new(ctx);
+        let (mut ctx, _backend) = context();
 
         let vertices = [
             TestVertex {
@@ -2068,15 +3743,13 @@ void main() {
         }
     }
 
-    #[cfg(target_os = "windows")]
     #[test]
     fn mapped_image() {
         use super::PixelFormat;
         use image::*;
         use texture::*;
 
-        let (ctx, _window) = get_headless_context(100, 100);
-        let mut ctx = Context::new(ctx);
+        let (mut ctx, _backend) = context();
         {
             // RGBA
             let data = vec![234; 3 * 3 * 4];
@@ -2148,11 +3821,9 @@ void main() {
         }
     }
 
-    #[cfg(target_os = "windows")]
     #[test]
     fn quad_batch_test() {
-        let (ctx, _window) = get_headless_context(100, 100);
-        let mut ctx = Context::new(ctx);
+        let (mut ctx, _backend) = context();
 
         let quad = quad_batch::Quad::from(viewport::Viewport::new(0., 0., 1., 1.)).map(|(x, y)| {
             TestVertex {
@@ -2161,8 +3832,335 @@ void main() {
             }
         });
         let mut batch = quad_batch::QuadBatch::<TestVertex>::new(&mut ctx, 1).unwrap();
-        let index = batch.push(quad.clone());
+        let index = batch.push(quad);
 
         assert_eq!(batch.get_quad(index).unwrap(), quad);
     }
+
+    #[test]
+    fn pipeline_state_cache() {
+        use crate::{
+            BlendDestination, BlendSource, BlendState, ClampedF32, CullFace, CullingState,
+            DepthFunction, DepthState, PolygonState, VertexWinding,
+        };
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let mut batch = QuadBatch::<testing::TestVertex>::new(&mut ctx, 1).unwrap();
+        batch.push(
+            Quad::from(crate::viewport::Viewport::new(0., 0., 1., 1.))
+                .map(|(x, y)| testing::TestVertex { position: [x, y] }),
+        );
+        let geometry = batch.unmap(&mut ctx);
+        ctx.set_viewport(0, 0, 8, 8);
+
+        let settings = PipelineSettings {
+            depth_state: Some(DepthState {
+                function: DepthFunction::LessEqual,
+                range: ClampedF32(0.25)..=ClampedF32(0.75),
+                write_mask: false,
+            }),
+            blend_state: Some(BlendState {
+                source_rgb: BlendSource::One,
+                destination_rgb: BlendDestination::One,
+                ..Default::default()
+            }),
+            polygon_state: PolygonState {
+                culling_state: Some(CullingState {
+                    mode: CullFace::Front,
+                    winding: VertexWinding::ClockWise,
+                }),
+                polygon_offset_units: 2.,
+                polygon_offset_factor: 1.,
+            },
+            ..Default::default()
+        };
+        ctx.draw(&shader, &geometry, settings.clone());
+        let state_calls = backend.recording().state_calls;
+        ctx.draw(&shader, &geometry, settings);
+        {
+            let recording = backend.recording();
+            assert_eq!(recording.state_calls, state_calls);
+            let draws = recording.draws().collect::<Vec<_>>();
+            assert_eq!(draws.len(), 2);
+            assert_eq!(draws[0].state, draws[1].state);
+
+            let state = &draws[0].state;
+            for capability in [
+                glow::BLEND,
+                glow::DEPTH_TEST,
+                glow::CULL_FACE,
+                glow::POLYGON_OFFSET_FILL,
+            ]
+            .iter()
+            {
+                assert!(state.capabilities.contains(capability));
+            }
+            assert_eq!(
+                state.blend_func,
+                [glow::ONE, glow::ONE, glow::ONE, glow::ZERO]
+            );
+            assert_eq!(state.blend_equation, (glow::FUNC_ADD, glow::FUNC_ADD));
+            assert_eq!(state.depth_func, glow::LEQUAL);
+            assert!(!state.depth_mask);
+            assert_eq!(state.depth_range, (0.25, 0.75));
+            assert_eq!(state.cull_face, glow::FRONT);
+            assert_eq!(state.front_face, glow::CW);
+            assert_eq!(state.polygon_offset, (1., 2.));
+            assert_eq!(state.viewport, [0, 0, 8, 8]);
+        }
+
+        // the defaults keep the current viewport and the context's alpha blending
+        ctx.draw(&shader, &geometry, PipelineSettings::default());
+        let recording = backend.recording();
+        let state = &recording.draws().last().unwrap().state;
+        assert!(state.capabilities.contains(&glow::BLEND));
+        assert_eq!(
+            state.blend_func,
+            [
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA
+            ]
+        );
+        assert!(!state.capabilities.contains(&glow::CULL_FACE));
+        assert!(!state.capabilities.contains(&glow::POLYGON_OFFSET_FILL));
+        assert_eq!(state.depth_func, glow::LESS);
+        assert_eq!(state.viewport, [0, 0, 8, 8]);
+    }
+
+    #[test]
+    fn stencil_state() {
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let mut batch = QuadBatch::<testing::TestVertex>::new(&mut ctx, 1).unwrap();
+        batch.push(
+            Quad::from(crate::viewport::Viewport::new(0., 0., 1., 1.))
+                .map(|(x, y)| testing::TestVertex { position: [x, y] }),
+        );
+        let geometry = batch.unmap(&mut ctx);
+
+        let stencil = StencilState {
+            function: StencilFunction::Greater,
+            reference: 3,
+            read_mask: 0x0f,
+            write_mask: 0xf0,
+            front: StencilOperations {
+                fail: StencilOperation::Zero,
+                depth_fail: StencilOperation::Invert,
+                pass: StencilOperation::Replace,
+            },
+            back: StencilOperations {
+                fail: StencilOperation::Keep,
+                depth_fail: StencilOperation::DecrementWrap,
+                pass: StencilOperation::IncrementWrap,
+            },
+        };
+        let settings = PipelineSettings {
+            stencil_state: Some(stencil),
+            ..Default::default()
+        };
+        ctx.draw(&shader, &geometry, settings.clone());
+        {
+            let recording = backend.recording();
+            let state = &recording.draws().last().unwrap().state;
+            assert!(state.capabilities.contains(&glow::STENCIL_TEST));
+            assert_eq!(state.stencil_func, (glow::GREATER, 3, 0x0f));
+            assert_eq!(
+                state.stencil_op_front,
+                [glow::ZERO, glow::INVERT, glow::REPLACE]
+            );
+            assert_eq!(
+                state.stencil_op_back,
+                [glow::KEEP, glow::DECR_WRAP, glow::INCR_WRAP]
+            );
+            assert_eq!(state.stencil_mask, 0xf0);
+        }
+
+        // clears write every bit of the stencil buffer, whatever the last draw's write mask
+        Renderer::clear(
+            &mut ctx,
+            ClearSettings {
+                color: None,
+                depth: None,
+                stencil: Some(5),
+                ..Default::default()
+            },
+        );
+        {
+            let recording = backend.recording();
+            match recording.commands.last() {
+                Some(Command::Clear { mask, state }) => {
+                    assert_eq!(*mask, glow::STENCIL_BUFFER_BIT);
+                    assert_eq!(state.clear_stencil, 5);
+                    assert_eq!(state.stencil_mask, !0);
+                }
+                command => panic!("expected a clear, got {:?}", command),
+            }
+        }
+
+        // and the next draw puts its own write mask back
+        ctx.draw(&shader, &geometry, settings);
+        let recording = backend.recording();
+        let state = &recording.draws().last().unwrap().state;
+        assert_eq!(state.stencil_mask, 0xf0);
+        assert_eq!(state.stencil_func, (glow::GREATER, 3, 0x0f));
+        drop(recording);
+
+        ctx.draw(&shader, &geometry, PipelineSettings::default());
+        let recording = backend.recording();
+        let state = &recording.draws().last().unwrap().state;
+        assert!(!state.capabilities.contains(&glow::STENCIL_TEST));
+    }
+
+    #[test]
+    fn restore_state_without_uniform_buffers() {
+        let backend = RecordingBackend::new();
+        backend.set_version("OpenGL ES 2.0");
+        let mut ctx = Context::with_backend(backend.clone());
+        assert!(!ctx.supports_uniform_buffers());
+        let state = ctx.save_state();
+        ctx.restore_state(&state);
+        assert_eq!(backend.get_error(), glow::NO_ERROR);
+    }
+
+    #[test]
+    fn save_and_restore_state() {
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let image = Image::with_data(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RGBA8,
+            1,
+            1,
+            &[0; 4],
+            Default::default(),
+        )
+        .unwrap();
+        ctx.use_shader(Some(&shader));
+        ctx.bind_texture_to_unit(TextureType::Tex2D, image.get_texture_key(), 1.into());
+        ctx.set_viewport(0, 0, 720, 480);
+        ctx.set_blend_state(None);
+        let state = ctx.save_state();
+
+        // another renderer using the same context
+        let foreign = |backend: &RecordingBackend| {
+            backend.use_program(None);
+            backend.active_texture(glow::TEXTURE1);
+            backend.bind_texture(glow::TEXTURE_2D, None);
+            backend.viewport(0, 0, 1, 1);
+            backend.enable(glow::BLEND);
+            backend.enable(glow::DEPTH_TEST);
+        };
+        let assert_restored = |backend: &RecordingBackend| {
+            let recording = backend.recording();
+            let state = &recording.state;
+            assert_eq!(state.program, Some(shader.handle()));
+            assert_eq!(
+                state.textures.get(&(1, glow::TEXTURE_2D)),
+                Some(&image.get_texture_key())
+            );
+            assert_eq!(state.active_texture, 1);
+            assert_eq!(state.viewport, [0, 0, 720, 480]);
+            assert!(!state.capabilities.contains(&glow::BLEND));
+            assert!(!state.capabilities.contains(&glow::DEPTH_TEST));
+        };
+
+        foreign(&backend);
+        ctx.restore_state(&state);
+        assert_restored(&backend);
+
+        foreign(&backend);
+        ctx.invalidate_cache();
+        assert_restored(&backend);
+
+        // destroyed objects aren't brought back
+        ctx.destroy_texture(image.get_texture_key());
+        ctx.restore_state(&state);
+        assert_eq!(
+            backend
+                .recording()
+                .state
+                .textures
+                .get(&(1, glow::TEXTURE_2D)),
+            None
+        );
+    }
+
+    #[test]
+    fn texture_readback() {
+        let (mut ctx, backend) = context();
+        let levels: [&[u8]; 2] = [
+            &[
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+            ],
+            &[19, 20, 21],
+        ];
+        let image = Image::with_mipmaps(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RGB8,
+            3,
+            2,
+            &levels,
+            Default::default(),
+        )
+        .unwrap();
+        let framebuffers = backend.recording().framebuffers.len();
+        assert_eq!(ctx.read_texture(&image, 0).unwrap(), levels[0]);
+        assert_eq!(ctx.read_texture(&image, 1).unwrap(), levels[1]);
+        {
+            let recording = backend.recording();
+            assert_eq!(recording.framebuffers.len(), framebuffers);
+            assert_eq!(recording.state.read_framebuffer, None);
+            assert_eq!(recording.state.draw_framebuffer, None);
+        }
+
+        let layers: [&[u8]; 2] = [&[1, 2, 3, 4], &[5, 6, 7, 8]];
+        let array = Image::with_layers(
+            &mut ctx,
+            TextureType::Tex2DArray,
+            PixelFormat::RG8,
+            2,
+            1,
+            &layers,
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(ctx.read_texture(&array, 0).unwrap(), layers.concat());
+        let faces = [&[1][..], &[2], &[3], &[4], &[5], &[6]];
+        let cube =
+            Image::with_faces(&mut ctx, PixelFormat::Alpha, 1, faces, Default::default()).unwrap();
+        assert_eq!(ctx.read_texture(&cube, 0).unwrap(), faces.concat());
+
+        // canvases are read from whatever their texture holds, which is nothing for a recording
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 2,
+                height: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(ctx.read_texture(&canvas, 0).unwrap(), vec![0; 16]);
+
+        let compressed = Image::new(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::BC4,
+            4,
+            4,
+            Default::default(),
+        )
+        .unwrap();
+        assert!(matches!(
+            ctx.read_texture(&compressed, 0),
+            Err(GraphicsError::UnsupportedPixelFormat(PixelFormat::BC4))
+        ));
+    }
 }
@@ -1,6 +1,6 @@
 use super::{
-    buffer::{Buffer, BufferType, MappedBuffer, Usage},
-    vertex::{Vertex, VertexFormat},
+    buffer::{Buffer, BufferType, GrowthPolicy, MappedBuffer, Usage},
+    vertex::{AttributeType, Vertex, VertexFormat},
     Context,
 };
 
@@ -100,13 +100,16 @@ pub type BindingInfo<'a> = (&'a VertexFormat, usize, u32, super::BufferKey, Buff
 /// mesh.set_vertices(&vertex_data, 0);
 /// ```
 ///
-/// Once constructed, a Mesh is of an immutable size but the draw range can be modified to
-/// effectively change it's size without changing the underlying memory's size.
+/// The draw range can be modified to effectively change it's size without changing the
+/// underlying memory's size.
 ///
 /// ```ignore
 /// let mut mesh = solstice::mesh::Mesh::new(&mut ctx, 3000).unwrap();
 /// mesh.set_draw_range(Some(0..3)); // draws only the first three vertices of the 3000 allocated
 /// ```
+///
+/// Changing the memory's size means reallocating it with `resize`. The mapped meshes can also
+/// grow on their own as data is written to them by setting a `GrowthPolicy`.
 #[derive(Debug, PartialEq, Clone)]
 pub struct VertexMesh<V> {
     vbo: Buffer,
@@ -175,6 +178,13 @@ where
     pub fn len(&self) -> usize {
         self.vbo.size() / std::mem::size_of::<V>()
     }
+
+    /// Reallocates the mesh to hold `size` vertices, keeping the ones that still fit. This
+    /// copies on the GPU so it fails without `Context::supports_buffer_copies`, see
+    /// `MappedVertexMesh::resize` for a version that doesn't.
+    pub fn resize(&mut self, ctx: &mut Context, size: usize) -> Result<(), super::GraphicsError> {
+        self.vbo.resize(ctx, size * std::mem::size_of::<V>())
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -194,6 +204,16 @@ where
         Ok(Self { inner, memory_map })
     }
 
+    /// Sets how the mesh grows when vertices are written past the end. The policy is applied
+    /// to the size in bytes.
+    pub fn set_growth_policy(&mut self, growth_policy: GrowthPolicy) {
+        self.memory_map.set_growth_policy(growth_policy)
+    }
+
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.memory_map.growth_policy()
+    }
+
     pub fn set_vertices(&mut self, vertices: &[V], offset: usize) {
         set_buffer(&mut self.memory_map, vertices, offset)
     }
@@ -202,8 +222,17 @@ where
         get_buffer(&self.memory_map)
     }
 
+    /// Reallocates the mesh to hold `size` vertices, keeping the ones that still fit. The
+    /// vertices are uploaded again from the memory map.
+    pub fn resize(&mut self, ctx: &mut Context, size: usize) {
+        self.memory_map.resize(ctx, size * std::mem::size_of::<V>());
+        self.inner.vbo = self.memory_map.inner().clone();
+    }
+
     pub fn unmap(&mut self, ctx: &mut super::Context) -> &VertexMesh<V> {
         self.memory_map.unmap(ctx);
+        // the buffer may have been reallocated
+        self.inner.vbo = self.memory_map.inner().clone();
         self.inner()
     }
 
@@ -281,6 +310,16 @@ where
         })
     }
 
+    /// Construct a mesh from existing vertex and index buffers, like the ones in a
+    /// [`StreamBuffer`](crate::buffer::StreamBuffer).
+    pub fn with_buffers(vbo: Buffer, ibo: Buffer) -> Self {
+        Self {
+            mesh: VertexMesh::with_buffer(vbo),
+            ibo,
+            type_marker: std::marker::PhantomData,
+        }
+    }
+
     /// Write new data into a range of the Mesh's vertex data.
     pub fn set_vertices(&self, ctx: &mut Context, vertices: &[V], offset: usize) {
         self.mesh.set_vertices(ctx, vertices, offset)
@@ -314,6 +353,19 @@ where
     pub fn len(&self) -> usize {
         self.ibo.size() / std::mem::size_of::<I>()
     }
+
+    /// Reallocates the mesh to hold `vertex_count` vertices and `index_count` indices, keeping
+    /// the ones that still fit. Like `VertexMesh::resize` this needs
+    /// `Context::supports_buffer_copies`.
+    pub fn resize(
+        &mut self,
+        ctx: &mut Context,
+        vertex_count: usize,
+        index_count: usize,
+    ) -> Result<(), super::GraphicsError> {
+        self.mesh.resize(ctx, vertex_count)?;
+        self.ibo.resize(ctx, index_count * std::mem::size_of::<I>())
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -372,6 +424,13 @@ where
         self.ibo.memory_map.len() / std::mem::size_of::<I>()
     }
 
+    /// Sets how the vertex and index buffers grow when data is written past their ends. The
+    /// policy is applied to the sizes in bytes.
+    pub fn set_growth_policy(&mut self, growth_policy: GrowthPolicy) {
+        self.vbo.set_growth_policy(growth_policy);
+        self.ibo.set_growth_policy(growth_policy);
+    }
+
     pub fn set_draw_range(&mut self, draw_range: Option<std::ops::Range<usize>>) {
         self.inner.set_draw_range(draw_range)
     }
@@ -396,9 +455,22 @@ where
         get_buffer(&self.ibo)
     }
 
+    /// Reallocates the mesh to hold `vertex_count` vertices and `index_count` indices, keeping
+    /// the ones that still fit. The data is uploaded again from the memory maps.
+    pub fn resize(&mut self, ctx: &mut Context, vertex_count: usize, index_count: usize) {
+        self.vbo
+            .resize(ctx, vertex_count * std::mem::size_of::<V>());
+        self.ibo.resize(ctx, index_count * std::mem::size_of::<I>());
+        self.inner.mesh.vbo = self.vbo.inner().clone();
+        self.inner.ibo = self.ibo.inner().clone();
+    }
+
     pub fn unmap(&mut self, ctx: &mut Context) -> &IndexedMesh<V, I> {
         self.vbo.unmap(ctx);
         self.ibo.unmap(ctx);
+        // either buffer may have been reallocated
+        self.inner.mesh.vbo = self.vbo.inner().clone();
+        self.inner.ibo = self.ibo.inner().clone();
         self.inner()
     }
 
@@ -415,6 +487,87 @@ pub struct AttachedAttributes<'a> {
     pub stride: usize,
 }
 
+/// A disagreement between the attributes a shader reads and the ones a mesh provides, as found
+/// by `validate_attributes`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttributeMismatch {
+    /// The shader reads an attribute that none of the attached buffers provide so it gets a
+    /// constant value instead.
+    Missing {
+        name: String,
+        shader_type: AttributeType,
+    },
+    /// The mesh provides the attribute as a type the shader can't read it as: floats and
+    /// integers are mixed up, a matrix doesn't match or there are more components than the shader
+    /// declares. Fewer components are fine, the rest are filled in with 0, 0 and 1.
+    WrongType {
+        name: String,
+        shader_type: AttributeType,
+        mesh_type: AttributeType,
+    },
+    /// The mesh provides an attribute that the shader doesn't read, which is usually a typo.
+    Unused { name: String },
+}
+
+fn compatible(shader_type: AttributeType, mesh_type: AttributeType) -> bool {
+    let (shader_data_type, shader_components, shader_columns) = shader_type.to_gl();
+    let (mesh_data_type, mesh_components, mesh_columns) = mesh_type.to_gl();
+    if shader_data_type != mesh_data_type || shader_columns != mesh_columns {
+        false
+    } else if shader_columns > 1 {
+        shader_components == mesh_components
+    } else {
+        mesh_components <= shader_components
+    }
+}
+
+/// Checks the attributes of `shader` against the ones provided by `attachments`.
+/// `Context::set_attribute_validation` does this for every draw.
+pub fn validate_attributes<S>(
+    shader: &S,
+    attachments: &[AttachedAttributes],
+) -> Vec<AttributeMismatch>
+where
+    S: super::shader::Shader + ?Sized,
+{
+    let shader_attributes = shader.attributes();
+    let formats = attachments
+        .iter()
+        .flat_map(|attachment| attachment.formats.iter())
+        .collect::<Vec<_>>();
+    let mut mismatches = vec![];
+    for attribute in shader_attributes {
+        match formats
+            .iter()
+            .find(|format| format.name == attribute.name.as_str())
+        {
+            None => mismatches.push(AttributeMismatch::Missing {
+                name: attribute.name.clone(),
+                shader_type: attribute.atype,
+            }),
+            Some(format) if !compatible(attribute.atype, format.atype) => {
+                mismatches.push(AttributeMismatch::WrongType {
+                    name: attribute.name.clone(),
+                    shader_type: attribute.atype,
+                    mesh_type: format.atype,
+                })
+            }
+            Some(_) => (),
+        }
+    }
+    for format in formats {
+        if !shader_attributes
+            .iter()
+            .any(|attribute| attribute.name.as_str() == format.name)
+        {
+            mismatches.push(AttributeMismatch::Unused {
+                name: format.name.to_owned(),
+            });
+        }
+    }
+    mismatches
+}
+
 pub trait Mesh {
     fn attachments(&self) -> Vec<AttachedAttributes>;
     fn draw(
@@ -491,9 +644,10 @@ impl<V: Vertex, I: Index> Mesh for IndexedMesh<V, I> {
             return;
         }
 
+        // the offset into the index buffer is in bytes
         let (count, offset) = (
             (draw_range.end - draw_range.start) as i32,
-            draw_range.start as i32,
+            (draw_range.start * std::mem::size_of::<I>()) as i32,
         );
 
         let ibo = &self.ibo;
@@ -530,7 +684,7 @@ impl<V: Vertex, I: Index> Mesh for &IndexedMesh<V, I> {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MultiMesh<'a> {
-    ibo: Option<(&'a Buffer, u32)>,
+    ibo: Option<(&'a Buffer, u32, usize)>,
     attachments: Vec<AttachedAttributes<'a>>,
 }
 
@@ -562,14 +716,14 @@ impl<'a> Mesh for MultiMesh<'a> {
                     ctx.draw_arrays(draw_mode, offset, count);
                 }
             }
-            Some((ibo, element_type)) => {
+            Some((ibo, element_type, element_size)) => {
                 if draw_range.start >= draw_range.end {
                     return;
                 }
 
                 let (count, offset) = (
                     (draw_range.end - draw_range.start) as i32,
-                    draw_range.start as i32,
+                    (draw_range.start * element_size) as i32,
                 );
 
                 ctx.bind_buffer(ibo.handle(), ibo.buffer_type());
@@ -634,7 +788,7 @@ impl<V: Vertex, I: Index> MeshAttacher for IndexedMesh<V, I> {
             a
         }));
         MultiMesh {
-            ibo: Some((&self.ibo, I::GL_TYPE)),
+            ibo: Some((&self.ibo, I::GL_TYPE, std::mem::size_of::<I>())),
             attachments,
         }
     }
@@ -651,3 +805,264 @@ impl Index for u32 {
 impl Index for u16 {
     const GL_TYPE: u32 = glow::UNSIGNED_SHORT;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::recording::testing::*;
+    use crate::{shader::DynamicShader, PipelineSettings, Renderer};
+
+    #[test]
+    fn index_offsets() {
+        use crate::{
+            mesh::{IndexedMesh, MeshAttacher, VertexMesh},
+            DrawMode, Geometry,
+        };
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let vertices = (0..4)
+            .map(|i| TestVertex {
+                position: [i as f32, 0.],
+            })
+            .collect::<Vec<_>>();
+        let draw_range = 3..6;
+
+        // the offset passed to draw_elements is in bytes, not indices
+        let short =
+            IndexedMesh::<TestVertex, u16>::with_data(&mut ctx, &vertices, &[0, 1, 2, 2, 3, 0])
+                .unwrap();
+        let int =
+            IndexedMesh::<TestVertex, u32>::with_data(&mut ctx, &vertices, &[0, 1, 2, 2, 3, 0])
+                .unwrap();
+        let instances = VertexMesh::with_data(&mut ctx, &vertices[..2]).unwrap();
+        let multi = int.attach_with_step(&instances, 1);
+        ctx.draw(
+            &shader,
+            &Geometry {
+                mesh: &short,
+                draw_range: draw_range.clone(),
+                draw_mode: DrawMode::Triangles,
+                instance_count: 1,
+            },
+            PipelineSettings::default(),
+        );
+        ctx.draw(
+            &shader,
+            &Geometry {
+                mesh: &multi,
+                draw_range,
+                draw_mode: DrawMode::Triangles,
+                instance_count: 2,
+            },
+            PipelineSettings::default(),
+        );
+
+        let recording = backend.recording();
+        let draws = recording.draws().collect::<Vec<_>>();
+        assert_eq!(draws.len(), 2);
+        assert_eq!(draws[0].element_type, Some(glow::UNSIGNED_SHORT));
+        assert_eq!((draws[0].first, draws[0].count), (6, 3));
+        assert_eq!(draws[1].element_type, Some(glow::UNSIGNED_INT));
+        assert_eq!((draws[1].first, draws[1].count), (12, 3));
+        assert_eq!(draws[1].instance_count, 2);
+    }
+
+    #[test]
+    fn growing_meshes() {
+        use crate::{
+            buffer::GrowthPolicy,
+            mesh::{MappedIndexedMesh, Mesh, VertexMesh},
+        };
+        let (mut ctx, backend) = context();
+        let vertex = |x: f32| TestVertex { position: [x, x] };
+        let size = std::mem::size_of::<TestVertex>();
+
+        let mut mesh = MappedIndexedMesh::<TestVertex, u16>::new(&mut ctx, 2, 3).unwrap();
+        mesh.set_growth_policy(GrowthPolicy::Double);
+        let vertices = (0..5).map(|i| vertex(i as f32)).collect::<Vec<_>>();
+        mesh.set_vertices(&vertices, 0);
+        mesh.set_indices(&[0, 1, 2, 2, 3, 4], 0);
+        assert_eq!(mesh.vertex_capacity(), 8);
+        assert_eq!(mesh.index_capacity(), 6);
+        let inner = mesh.unmap(&mut ctx);
+        assert_eq!(inner.len(), 6);
+        assert_eq!(inner.draw_range(), 0..6);
+        {
+            let recording = backend.recording();
+            let vbo = &recording.buffers[inner.attachments()[0].buffer.handle()];
+            assert_eq!(vbo.data.len(), 8 * size);
+            assert_eq!(&vbo.data[..5 * size], bytemuck::cast_slice(&vertices));
+        }
+
+        let mut mesh = VertexMesh::with_data(&mut ctx, &vertices[..2]).unwrap();
+        let clone = mesh.clone();
+        let buffer_count = backend.recording().buffers.len();
+        mesh.resize(&mut ctx, 4).unwrap();
+        assert_eq!(mesh.len(), 4);
+        // the storage is reallocated in place so clones still point at a live buffer
+        let handle = mesh.attachments()[0].buffer.handle();
+        assert_eq!(clone.attachments()[0].buffer.handle(), handle);
+        assert_eq!(clone.len(), 4);
+        let recording = backend.recording();
+        assert_eq!(recording.buffers.len(), buffer_count);
+        let vbo = &recording.buffers[handle];
+        assert_eq!(vbo.data.len(), 4 * size);
+        assert_eq!(&vbo.data[..2 * size], bytemuck::cast_slice(&vertices[..2]));
+        drop(recording);
+
+        mesh.resize(&mut ctx, 1).unwrap();
+        let vbo = &backend.recording().buffers[handle];
+        assert_eq!(vbo.data, bytemuck::cast_slice::<_, u8>(&vertices[..1]));
+    }
+
+    #[test]
+    fn resizing_without_buffer_copies() {
+        use crate::{
+            backend::recording::RecordingBackend,
+            mesh::{MappedIndexedMesh, MappedVertexMesh, Mesh, VertexMesh},
+            Context,
+        };
+        let backend = RecordingBackend::new();
+        backend.set_version("OpenGL ES 2.0");
+        let mut ctx = Context::with_backend(backend.clone());
+        assert!(!ctx.supports_buffer_copies());
+        let vertex = |x: f32| TestVertex { position: [x, x] };
+        let vertices = (0..3).map(|i| vertex(i as f32)).collect::<Vec<_>>();
+        let size = std::mem::size_of::<TestVertex>();
+
+        let mut mesh = VertexMesh::with_data(&mut ctx, &vertices).unwrap();
+        assert!(matches!(
+            mesh.resize(&mut ctx, 4),
+            Err(crate::GraphicsError::BufferError)
+        ));
+        assert_eq!(mesh.len(), 3);
+
+        let mut mesh = MappedVertexMesh::<TestVertex>::new(&mut ctx, 2).unwrap();
+        mesh.set_vertices(&vertices[..2], 0);
+        mesh.unmap(&mut ctx);
+        mesh.resize(&mut ctx, 4);
+        assert_eq!(mesh.get_vertices().len(), 4);
+        assert_eq!(mesh.inner().len(), 4);
+        {
+            let recording = backend.recording();
+            let vbo = &recording.buffers[mesh.inner().attachments()[0].buffer.handle()];
+            assert_eq!(vbo.data.len(), 4 * size);
+            assert_eq!(&vbo.data[..2 * size], bytemuck::cast_slice(&vertices[..2]));
+            assert!(vbo.data[2 * size..].iter().all(|byte| *byte == 0));
+        }
+
+        let mut mesh = MappedIndexedMesh::<TestVertex, u16>::new(&mut ctx, 3, 3).unwrap();
+        mesh.set_vertices(&vertices, 0);
+        mesh.set_indices(&[0, 1, 2], 0);
+        mesh.resize(&mut ctx, 2, 2);
+        assert_eq!(mesh.get_vertices(), &vertices[..2]);
+        assert_eq!(mesh.get_indices(), &[0, 1]);
+        assert_eq!(mesh.inner().len(), 2);
+        let vbo = &backend.recording().buffers[mesh.inner().attachments()[0].buffer.handle()];
+        assert_eq!(vbo.data, bytemuck::cast_slice::<_, u8>(&vertices[..2]));
+    }
+
+    #[test]
+    fn attribute_validation() {
+        use crate::mesh::{validate_attributes, AttributeMismatch, Mesh, VertexMesh};
+
+        #[repr(C)]
+        #[derive(Debug, Copy, Clone, PartialEq, Default)]
+        struct Misnamed {
+            position: [f32; 3],
+            colour: [f32; 4],
+            cell: [f32; 2],
+        }
+
+        unsafe impl bytemuck::Zeroable for Misnamed {}
+        unsafe impl bytemuck::Pod for Misnamed {}
+
+        impl Vertex for Misnamed {
+            fn build_bindings() -> &'static [VertexFormat] {
+                &[
+                    VertexFormat {
+                        name: "position",
+                        offset: 0,
+                        atype: AttributeType::F32F32F32,
+                        normalize: false,
+                    },
+                    VertexFormat {
+                        name: "colour",
+                        offset: 12,
+                        atype: AttributeType::F32F32F32F32,
+                        normalize: false,
+                    },
+                    VertexFormat {
+                        name: "cell",
+                        offset: 28,
+                        atype: AttributeType::F32F32,
+                        normalize: false,
+                    },
+                ]
+            }
+        }
+
+        const SHADER: &str = r#"
+#ifdef VERTEX
+attribute vec4 position;
+attribute vec4 tint;
+attribute ivec2 cell;
+
+void main() {
+    gl_Position = position * tint * float(cell.x);
+}
+#endif
+
+#ifdef FRAGMENT
+void main() {
+    fragColor = vec4(1.);
+}
+#endif"#;
+
+        let (mut ctx, _backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SHADER, SHADER);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let mesh = VertexMesh::with_data(&mut ctx, &[Misnamed::default(); 3]).unwrap();
+        let geometry = crate::Geometry {
+            mesh: &mesh,
+            draw_range: 0..3,
+            draw_mode: crate::DrawMode::Triangles,
+            instance_count: 1,
+        };
+
+        // fewer components than the shader declares is fine
+        let expected = vec![
+            AttributeMismatch::Missing {
+                name: "tint".to_owned(),
+                shader_type: AttributeType::F32F32F32F32,
+            },
+            AttributeMismatch::WrongType {
+                name: "cell".to_owned(),
+                shader_type: AttributeType::I32I32,
+                mesh_type: AttributeType::F32F32,
+            },
+            AttributeMismatch::Unused {
+                name: "colour".to_owned(),
+            },
+        ];
+        assert_eq!(validate_attributes(&shader, &mesh.attachments()), expected);
+
+        ctx.draw(&shader, &geometry, Default::default());
+        assert!(ctx.take_attribute_mismatches().is_empty());
+
+        ctx.set_attribute_validation(true);
+        ctx.draw(&shader, &geometry, Default::default());
+        ctx.draw(&shader, &geometry, Default::default());
+        let mismatches = ctx.take_attribute_mismatches();
+        assert!(mismatches.iter().all(|(key, _)| *key == shader.handle()));
+        assert_eq!(
+            mismatches
+                .into_iter()
+                .map(|(_, mismatch)| mismatch)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!(ctx.take_attribute_mismatches().is_empty());
+    }
+}
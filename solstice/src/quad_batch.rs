@@ -1,4 +1,5 @@
 use super::{
+    buffer::GrowthPolicy,
     mesh::{IndexedMesh, MappedIndexedMesh},
     Context,
 };
@@ -60,6 +61,19 @@ where
 
 pub const INDICES: [u16; 6] = [0, 1, 3, 1, 2, 3];
 
+// the indices of the quads in `quads`
+fn indices(quads: std::ops::Range<usize>) -> Vec<u16> {
+    let mut indices: Vec<u16> = Vec::with_capacity(quads.len() * 6);
+    for i in quads {
+        let vi = (i * 4) as u16;
+        indices.extend(IntoIterator::into_iter(INDICES).map(|i| vi + i));
+    }
+    indices
+}
+
+// `u16` indices can't address any more vertices than this
+const MAX_QUADS: usize = (u16::MAX as usize + 1) / 4;
+
 /// 0---3
 /// | / |
 /// 1---2
@@ -68,6 +82,7 @@ pub struct QuadBatch<T> {
     mesh: MappedIndexedMesh<T, u16>,
     count: usize,
     capacity: usize,
+    growth_policy: GrowthPolicy,
 }
 
 impl<T> QuadBatch<T>
@@ -76,25 +91,21 @@ where
 {
     pub fn new(gl: &mut Context, capacity: usize) -> Result<Self, super::GraphicsError> {
         let vertex_capacity = capacity * 4;
-        let index_capacity = capacity * 6;
 
-        let indices = {
-            let mut indices: Vec<u16> = Vec::with_capacity(index_capacity);
-            for i in 0..capacity {
-                let vi = (i * 4) as u16;
-                indices.extend(IntoIterator::into_iter(INDICES).map(|i| vi + i));
-            }
-            indices
-        };
-
-        let mut mesh =
-            MappedIndexedMesh::with_data(gl, vec![T::default(); vertex_capacity], indices)?;
+        let mut mesh = MappedIndexedMesh::with_data(
+            gl,
+            vec![T::default(); vertex_capacity],
+            indices(0..capacity),
+        )?;
         mesh.set_draw_range(Some(0..0));
+        // the batch decides how much to grow by
+        mesh.set_growth_policy(GrowthPolicy::Exact);
 
         Ok(Self {
             mesh,
             count: 0,
             capacity,
+            growth_policy: GrowthPolicy::Fixed,
         })
     }
 
@@ -102,7 +113,22 @@ where
         self.count
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets how the batch grows, in quads, when more are pushed than it has room for. It can't
+    /// grow past the 16384 quads that 16-bit indices can address.
+    pub fn set_growth_policy(&mut self, growth_policy: GrowthPolicy) {
+        self.growth_policy = growth_policy;
+    }
+
     pub fn push(&mut self, quad: Quad<T>) -> QuadIndex {
+        if self.count == self.capacity {
+            if let Some(capacity) = self.growth_policy.grow(self.capacity, self.count + 1) {
+                self.reserve(capacity.min(MAX_QUADS));
+            }
+        }
         assert!(
             self.count < self.capacity,
             "Adding too many quads to QuadBatch"
@@ -138,6 +164,18 @@ where
         self.mesh.set_vertices(&quad.vertices, index.0 * 4);
     }
 
+    // the new quads are written to the memory map and uploaded on the next unmap
+    fn reserve(&mut self, capacity: usize) {
+        if capacity <= self.capacity {
+            return;
+        }
+        let vertices = vec![T::default(); (capacity - self.capacity) * 4];
+        self.mesh.set_vertices(&vertices, self.capacity * 4);
+        self.mesh
+            .set_indices(&indices(self.capacity..capacity), self.capacity * 6);
+        self.capacity = capacity;
+    }
+
     pub fn clear(&mut self) {
         self.count = 0;
         self.mesh.set_draw_range(Some(0..0));
@@ -159,3 +197,97 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::recording::testing::*;
+    use crate::{
+        shader::{DynamicShader, RawUniformValue},
+        PipelineSettings, Renderer, StencilFunction, StencilOperation, StencilOperations,
+        StencilState,
+    };
+
+    #[test]
+    fn quad_batch() {
+        let (mut ctx, backend) = context();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        ctx.use_shader(Some(&shader));
+        let color = shader.get_uniform_by_name("color").unwrap().location;
+        ctx.set_uniform_by_location(&color, &RawUniformValue::Vec4([1., 0., 0., 1.].into()));
+
+        let mut batch = QuadBatch::<TestVertex>::new(&mut ctx, 4).unwrap();
+        for _ in 0..2 {
+            batch.push(
+                Quad::from(crate::viewport::Viewport::new(0., 0., 1., 1.))
+                    .map(|(x, y)| TestVertex { position: [x, y] }),
+            );
+        }
+        let geometry = batch.unmap(&mut ctx);
+        let stencil = StencilState {
+            function: StencilFunction::Equal,
+            reference: 1,
+            ..Default::default()
+        }
+        .with_operations(StencilOperations {
+            pass: StencilOperation::Increment,
+            ..Default::default()
+        });
+        ctx.draw(
+            &shader,
+            &geometry,
+            PipelineSettings {
+                stencil_state: Some(stencil),
+                ..Default::default()
+            },
+        );
+
+        let recording = backend.recording();
+        let draws = recording.draws().collect::<Vec<_>>();
+        assert_eq!(draws.len(), 1);
+        let draw = draws[0];
+        assert_eq!(draw.mode, glow::TRIANGLES);
+        assert_eq!(draw.count, 12);
+        assert_eq!(draw.element_type, Some(glow::UNSIGNED_SHORT));
+        assert_eq!(
+            draw.uniforms.get("color"),
+            Some(&RawUniformValue::Vec4([1., 0., 0., 1.].into()))
+        );
+
+        let attribute = draw.state.vertex_attributes[&0];
+        assert!(attribute.enabled);
+        assert_eq!((attribute.size, attribute.data_type), (2, glow::FLOAT));
+        let vertices = &recording.buffers[attribute.buffer.unwrap()].data;
+        assert_eq!(vertices.len(), 4 * 4 * std::mem::size_of::<TestVertex>());
+
+        assert!(draw.state.capabilities.contains(&glow::STENCIL_TEST));
+        assert_eq!(draw.state.stencil_func, (glow::EQUAL, 1, !0));
+        assert_eq!(draw.state.stencil_op_front[2], glow::INCR);
+    }
+
+    #[test]
+    fn growing_quad_batch() {
+        let (mut ctx, backend) = context();
+        let mut batch = QuadBatch::<TestVertex>::new(&mut ctx, 1).unwrap();
+        batch.set_growth_policy(crate::buffer::GrowthPolicy::Double);
+        for _ in 0..3 {
+            batch.push(
+                Quad::from(crate::viewport::Viewport::new(0., 0., 1., 1.))
+                    .map(|(x, y)| TestVertex { position: [x, y] }),
+            );
+        }
+        assert_eq!(batch.capacity(), 4);
+        let geometry = batch.unmap(&mut ctx);
+        assert_eq!(geometry.draw_range, 0..18);
+
+        let recording = backend.recording();
+        let indices = recording
+            .buffers
+            .values()
+            .find(|buffer| buffer.data.len() == 4 * 6 * 2)
+            .unwrap();
+        let indices: &[u16] = bytemuck::cast_slice(&indices.data);
+        assert_eq!(&indices[18..], &[12, 13, 15, 13, 14, 15]);
+    }
+}
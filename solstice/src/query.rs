@@ -0,0 +1,135 @@
+use super::QueryKey;
+
+/// What a query measures while it's active.
+/// * `TimeElapsed`: The time the GPU spent on the commands, in nanoseconds.
+/// * `SamplesPassed`: The number of samples that passed the depth and stencil tests.
+/// * `AnySamplesPassed`: Whether any samples passed, 1 if they did and 0 otherwise.
+/// * `AnySamplesPassedConservative`: Like `AnySamplesPassed` but the implementation may trade
+///   accuracy for speed and report samples that didn't actually pass.
+///
+/// The occlusion types, everything but `TimeElapsed`, share one slot so only one of them can be
+/// active at a time.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum QueryType {
+    TimeElapsed,
+    SamplesPassed,
+    AnySamplesPassed,
+    AnySamplesPassedConservative,
+}
+
+impl QueryType {
+    pub fn to_gl(self) -> u32 {
+        match self {
+            QueryType::TimeElapsed => glow::TIME_ELAPSED,
+            QueryType::SamplesPassed => glow::SAMPLES_PASSED,
+            QueryType::AnySamplesPassed => glow::ANY_SAMPLES_PASSED,
+            QueryType::AnySamplesPassedConservative => glow::ANY_SAMPLES_PASSED_CONSERVATIVE,
+        }
+    }
+
+    pub fn is_occlusion(self) -> bool {
+        self != QueryType::TimeElapsed
+    }
+}
+
+/// Measures the GPU time or visibility of the commands issued between `begin` and `end`.
+///
+/// Results arrive some time after `end`, usually a frame or two later, and `result` doesn't wait
+/// for them. A query can be reused once its result has been read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query {
+    handle: QueryKey,
+    query_type: QueryType,
+}
+
+impl Query {
+    pub fn new(
+        ctx: &mut super::Context,
+        query_type: QueryType,
+    ) -> Result<Self, super::GraphicsError> {
+        if !ctx.supports_query_type(query_type) {
+            return Err(super::GraphicsError::UnsupportedQueryType(query_type));
+        }
+        let handle = ctx.new_query()?;
+        Ok(Self { handle, query_type })
+    }
+
+    /// Returns an identifier that can be used with the graphics context to retrieve the raw GPU
+    /// query handle.
+    pub fn handle(&self) -> QueryKey {
+        self.handle
+    }
+
+    pub fn query_type(&self) -> QueryType {
+        self.query_type
+    }
+
+    pub fn begin(&self, ctx: &mut super::Context) {
+        ctx.begin_query(self.handle, self.query_type)
+    }
+
+    pub fn end(&self, ctx: &mut super::Context) {
+        ctx.end_query(self.handle)
+    }
+
+    /// The result if it's available yet. It's never available while the query is active.
+    pub fn result(&self, ctx: &mut super::Context) -> Option<u64> {
+        ctx.get_query_result(self.handle)
+    }
+
+    /// The result of a `TimeElapsed` query as a `Duration`.
+    pub fn elapsed(&self, ctx: &mut super::Context) -> Option<std::time::Duration> {
+        self.result(ctx).map(std::time::Duration::from_nanos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::recording::testing::*;
+    use crate::GraphicsError;
+
+    #[test]
+    fn queries() {
+        use crate::query::{Query, QueryType};
+        let (mut ctx, backend) = context();
+        assert!(ctx.supports_query_type(QueryType::TimeElapsed));
+        assert!(matches!(
+            Query::new(&mut ctx, QueryType::AnySamplesPassedConservative),
+            Err(GraphicsError::UnsupportedQueryType(_))
+        ));
+
+        let timer = Query::new(&mut ctx, QueryType::TimeElapsed).unwrap();
+        let samples = Query::new(&mut ctx, QueryType::SamplesPassed).unwrap();
+        let any = Query::new(&mut ctx, QueryType::AnySamplesPassed).unwrap();
+        timer.begin(&mut ctx);
+        samples.begin(&mut ctx);
+        // only one occlusion query at a time
+        any.begin(&mut ctx);
+        ctx.draw_arrays(crate::DrawMode::Triangles, 0, 6);
+        assert_eq!(samples.result(&mut ctx), None);
+        // ending a query that never began leaves the active one of its kind alone
+        any.end(&mut ctx);
+        assert!(backend.recording().queries[samples.handle()].active);
+        samples.end(&mut ctx);
+        timer.end(&mut ctx);
+        assert_eq!(samples.result(&mut ctx), Some(6));
+        // it never began so there's nothing to report
+        assert_eq!(any.result(&mut ctx), None);
+        assert_eq!(
+            timer.elapsed(&mut ctx),
+            Some(std::time::Duration::from_nanos(0))
+        );
+
+        backend.hold_query_results(true);
+        any.begin(&mut ctx);
+        ctx.draw_arrays(crate::DrawMode::Triangles, 0, 3);
+        any.end(&mut ctx);
+        assert_eq!(any.result(&mut ctx), None);
+        backend.hold_query_results(false);
+        assert_eq!(any.result(&mut ctx), Some(1));
+
+        ctx.destroy_query(any.handle());
+        assert_eq!(any.result(&mut ctx), None);
+        assert!(backend.recording().queries.get(any.handle()).is_none());
+    }
+}
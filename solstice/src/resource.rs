@@ -0,0 +1,270 @@
+use super::{BufferKey, FramebufferKey, QueryKey, RenderbufferKey, ShaderKey, TextureKey};
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+/// Any of the GPU objects that `Context` creates.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ResourceKey {
+    Buffer(BufferKey),
+    Shader(ShaderKey),
+    Texture(TextureKey),
+    Framebuffer(FramebufferKey),
+    Renderbuffer(RenderbufferKey),
+    Query(QueryKey),
+}
+
+pub(crate) type DeletionQueue = Rc<RefCell<Vec<ResourceKey>>>;
+
+/// Shared ownership of some GPU objects. Clones share ownership and when the last one is dropped
+/// the objects are queued up for deletion. `Context` deletes them the next time it creates an
+/// object or when `Context::delete_dropped_resources` is called.
+///
+/// Buffers, images, canvases and shaders each hold one of these so they clean up after
+/// themselves. Destroying one of their objects explicitly is still fine.
+#[derive(Clone)]
+pub struct Owner(Rc<Owned>);
+
+struct Owned {
+    resources: Vec<ResourceKey>,
+    queue: Weak<RefCell<Vec<ResourceKey>>>,
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        // the context might be gone already, in which case so are the objects
+        if let Some(queue) = self.queue.upgrade() {
+            queue.borrow_mut().append(&mut self.resources);
+        }
+    }
+}
+
+impl Owner {
+    pub(crate) fn new(queue: &DeletionQueue, resources: Vec<ResourceKey>) -> Self {
+        Self(Rc::new(Owned {
+            resources,
+            queue: Rc::downgrade(queue),
+        }))
+    }
+
+    pub fn resources(&self) -> &[ResourceKey] {
+        &self.0.resources
+    }
+
+    /// How many handles share these objects, including this one.
+    pub fn handle_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+}
+
+impl PartialEq for Owner {
+    fn eq(&self, other: &Self) -> bool {
+        self.resources() == other.resources()
+    }
+}
+
+impl Eq for Owner {}
+
+impl std::fmt::Debug for Owner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Owner").field(&self.resources()).finish()
+    }
+}
+
+/// How many objects of one kind are alive and roughly how much memory they take up.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResourceUsage {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// The objects a `Context` is keeping alive, see `Context::resource_stats`.
+///
+/// Sizes are estimated from what was asked of the driver: buffer sizes, and texture and
+/// renderbuffer dimensions times the size of a pixel, including mipmaps and samples. Drivers pad
+/// and keep copies of their own so the real footprint is usually larger. Shaders, framebuffers
+/// and queries are only counted.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResourceStats {
+    pub buffers: ResourceUsage,
+    pub shaders: ResourceUsage,
+    pub textures: ResourceUsage,
+    pub framebuffers: ResourceUsage,
+    pub renderbuffers: ResourceUsage,
+    pub queries: ResourceUsage,
+}
+
+impl ResourceStats {
+    fn usages(&self) -> [ResourceUsage; 6] {
+        [
+            self.buffers,
+            self.shaders,
+            self.textures,
+            self.framebuffers,
+            self.renderbuffers,
+            self.queries,
+        ]
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.usages().iter().map(|usage| usage.count).sum()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.usages().iter().map(|usage| usage.bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::recording::testing::*;
+    use crate::{
+        backend::recording::RecordingBackend,
+        canvas::{self, Canvas},
+        image::Image,
+        shader::DynamicShader,
+        texture::{Texture, TextureType},
+        PixelFormat,
+    };
+
+    #[test]
+    fn dropped_resources() {
+        use crate::buffer::{Buffer, BufferType, Usage};
+        let (mut ctx, backend) = context();
+        let resources = |backend: &RecordingBackend| {
+            let recording = backend.recording();
+            (
+                recording.buffers.len(),
+                recording.programs.len(),
+                recording.textures.len(),
+                recording.framebuffers.len(),
+                recording.renderbuffers.len(),
+            )
+        };
+        let start = resources(&backend);
+
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let shader = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        let buffer = Buffer::new(&mut ctx, 16, BufferType::Vertex, Usage::Static).unwrap();
+        let canvas = Canvas::new(
+            &mut ctx,
+            canvas::Settings {
+                width: 4,
+                height: 4,
+                msaa: 4,
+                with_depth: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let image = Image::new(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RGBA8,
+            1,
+            1,
+            Default::default(),
+        )
+        .unwrap();
+        let (buffers, programs, textures, framebuffers, renderbuffers) = resources(&backend);
+        assert_eq!(
+            (buffers, programs, textures, framebuffers, renderbuffers),
+            (
+                start.0 + 1,
+                start.1 + 1,
+                start.2 + 2,
+                start.3 + 2,
+                start.4 + 2
+            )
+        );
+
+        // clones keep the objects alive
+        let image_clone = image.clone();
+        assert_eq!(image.owner().handle_count(), 2);
+        drop(image);
+        ctx.delete_dropped_resources();
+        assert_eq!(resources(&backend).2, textures);
+        drop(image_clone);
+        // deletion waits for the context
+        assert_eq!(resources(&backend).2, textures);
+        ctx.delete_dropped_resources();
+        assert_eq!(resources(&backend).2, textures - 1);
+
+        // explicitly destroyed objects aren't deleted twice
+        ctx.destroy_buffer(&buffer);
+        drop((shader, buffer, canvas));
+        let _image = Image::new(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RGBA8,
+            1,
+            1,
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            resources(&backend),
+            (start.0, start.1, start.2 + 1, start.3, start.4)
+        );
+    }
+
+    #[test]
+    fn resource_stats() {
+        use crate::buffer::{Buffer, BufferType, Usage};
+        use crate::resource::ResourceKey;
+        let (mut ctx, _backend) = context();
+        let start = ctx.resource_stats();
+
+        let mut buffer = Buffer::new(&mut ctx, 64, BufferType::Vertex, Usage::Static).unwrap();
+        let image = Image::with_data(
+            &mut ctx,
+            TextureType::Tex2D,
+            PixelFormat::RGBA8,
+            4,
+            4,
+            &[0; 64],
+            Default::default(),
+        )
+        .unwrap();
+        let renderbuffer = ctx.new_renderbuffer().unwrap();
+        ctx.bind_renderbuffer(Some(renderbuffer));
+        ctx.renderbuffer_storage_multisample(PixelFormat::RGBA8, 4, 2, 2);
+
+        let stats = ctx.resource_stats();
+        assert_eq!(stats.buffers.count, start.buffers.count + 1);
+        assert_eq!(stats.buffers.bytes, start.buffers.bytes + 64);
+        // 4x4, 2x2 and 1x1 mipmaps
+        assert_eq!(stats.textures.count, start.textures.count + 1);
+        assert_eq!(
+            stats.textures.bytes,
+            start.textures.bytes + (16 + 4 + 1) * 4
+        );
+        assert_eq!(
+            stats.renderbuffers.bytes,
+            start.renderbuffers.bytes + 4 * 4 * 4
+        );
+        assert_eq!(stats.total_count(), start.total_count() + 3);
+        assert_eq!(
+            stats.total_bytes(),
+            start.total_bytes() + 64 + (16 + 4 + 1) * 4 + 4 * 4 * 4
+        );
+        let live = ctx.live_resources();
+        assert!(live.contains(&ResourceKey::Buffer(buffer.handle())));
+        assert!(live.contains(&ResourceKey::Texture(image.get_texture_key())));
+        assert!(live.contains(&ResourceKey::Renderbuffer(renderbuffer)));
+
+        buffer.set_data(&mut ctx, &[0; 16]);
+        assert_eq!(ctx.resource_stats().buffers.bytes, start.buffers.bytes + 16);
+
+        ctx.destroy_renderbuffer(renderbuffer);
+        drop(image);
+        ctx.delete_dropped_resources();
+        let stats = ctx.resource_stats();
+        assert_eq!(stats.textures, start.textures);
+        assert_eq!(stats.renderbuffers, start.renderbuffers);
+        assert!(!ctx
+            .live_resources()
+            .contains(&ResourceKey::Renderbuffer(renderbuffer)));
+    }
+}
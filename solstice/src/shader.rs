@@ -1,3 +1,15 @@
+#[cfg(feature = "derive")]
+pub use solstice_derive::Std140;
+
+mod preprocessor;
+mod program_cache;
+
+pub use preprocessor::{ShaderLibrary, ShaderVariants};
+pub(crate) use program_cache::cache_key;
+#[cfg(not(target_arch = "wasm32"))]
+pub use program_cache::DirectoryProgramCache;
+pub use program_cache::{MemoryProgramCache, ProgramBinary, ProgramCache};
+
 use super::vertex::AttributeType;
 use crate::{GraphicsError, ShaderKey};
 
@@ -9,8 +21,10 @@ pub struct Attribute {
     pub location: u32,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct UniformLocation(pub(crate) super::GLUniformLocation);
+slotmap::new_key_type! {
+    /// Handed out by the backend when a program is created.
+    pub struct UniformLocation;
+}
 
 #[derive(Clone, Debug)]
 pub struct Uniform {
@@ -21,10 +35,38 @@ pub struct Uniform {
     pub initial_data: RawUniformValue,
 }
 
-#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniformBlockMember {
+    pub name: String,
+    pub size: i32,
+    pub utype: u32,
+    /// The offset in bytes from the start of the block.
+    pub offset: usize,
+}
+
+/// A `uniform` interface block. Its data comes from whichever buffer is bound to the binding
+/// point the block is assigned to with `Context::set_uniform_block_binding`, binding 0 until
+/// then.
+///
+/// Offsets and sizes follow the std140 rules so blocks should be declared `layout(std140)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniformBlock {
+    pub name: String,
+    pub index: u32,
+    /// The size of the block's data in bytes.
+    pub size: usize,
+    pub members: Vec<UniformBlockMember>,
+}
+
+/// A value for a uniform.
+///
+/// The array variants can be sent to the location of any element of a uniform array to set
+/// that element and the ones after it.
+#[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub enum RawUniformValue {
     SignedInt(i32),
-    //    UnsignedInt(u32),
+    UnsignedInt(u32),
+    Bool(bool),
     Float(f32),
     Mat2(mint::ColumnMatrix2<f32>),
     Mat3(mint::ColumnMatrix3<f32>),
@@ -32,12 +74,67 @@ pub enum RawUniformValue {
     Vec2(mint::Vector2<f32>),
     Vec3(mint::Vector3<f32>),
     Vec4(mint::Vector4<f32>),
+    /// Also used for `bvec2`.
     IntVec2(mint::Vector2<i32>),
+    /// Also used for `bvec3`.
     IntVec3(mint::Vector3<i32>),
+    /// Also used for `bvec4`.
     IntVec4(mint::Vector4<i32>),
-    //    UnsignedIntVec2([u32; 2]),
-    //    UnsignedIntVec3([u32; 3]),
-    //    UnsignedIntVec4([u32; 4]),
+    UnsignedIntVec2(mint::Vector2<u32>),
+    UnsignedIntVec3(mint::Vector3<u32>),
+    UnsignedIntVec4(mint::Vector4<u32>),
+    /// Also used for arrays of samplers.
+    SignedIntArray(Vec<i32>),
+    UnsignedIntArray(Vec<u32>),
+    BoolArray(Vec<bool>),
+    FloatArray(Vec<f32>),
+    Mat2Array(Vec<mint::ColumnMatrix2<f32>>),
+    Mat3Array(Vec<mint::ColumnMatrix3<f32>>),
+    Mat4Array(Vec<mint::ColumnMatrix4<f32>>),
+    Vec2Array(Vec<mint::Vector2<f32>>),
+    Vec3Array(Vec<mint::Vector3<f32>>),
+    Vec4Array(Vec<mint::Vector4<f32>>),
+    IntVec2Array(Vec<mint::Vector2<i32>>),
+    IntVec3Array(Vec<mint::Vector3<i32>>),
+    IntVec4Array(Vec<mint::Vector4<i32>>),
+    UnsignedIntVec2Array(Vec<mint::Vector2<u32>>),
+    UnsignedIntVec3Array(Vec<mint::Vector3<u32>>),
+    UnsignedIntVec4Array(Vec<mint::Vector4<u32>>),
+}
+
+impl RawUniformValue {
+    /// The value of each element of an array, or just this value if it isn't one.
+    pub fn elements(&self) -> Vec<RawUniformValue> {
+        macro_rules! elements {
+            ($($array:ident => $element:ident),*) => {
+                match self {
+                    $(RawUniformValue::$array(v) => {
+                        v.iter().copied().map(RawUniformValue::$element).collect()
+                    })*
+                    value => vec![value.clone()],
+                }
+            };
+        }
+
+        elements!(
+            SignedIntArray => SignedInt,
+            UnsignedIntArray => UnsignedInt,
+            BoolArray => Bool,
+            FloatArray => Float,
+            Mat2Array => Mat2,
+            Mat3Array => Mat3,
+            Mat4Array => Mat4,
+            Vec2Array => Vec2,
+            Vec3Array => Vec3,
+            Vec4Array => Vec4,
+            IntVec2Array => IntVec2,
+            IntVec3Array => IntVec3,
+            IntVec4Array => IntVec4,
+            UnsignedIntVec2Array => UnsignedIntVec2,
+            UnsignedIntVec3Array => UnsignedIntVec3,
+            UnsignedIntVec4Array => UnsignedIntVec4
+        )
+    }
 }
 
 macro_rules! raw_uniform_conv {
@@ -61,7 +158,21 @@ macro_rules! raw_uniform_conv {
     };
 }
 
+macro_rules! raw_uniform_array_conv {
+    ($from:ty, $to:ident) => {
+        raw_uniform_conv!(Vec<$from>, $to);
+
+        impl From<&[$from]> for RawUniformValue {
+            fn from(v: &[$from]) -> Self {
+                RawUniformValue::$to(v.to_vec())
+            }
+        }
+    };
+}
+
 raw_uniform_conv!(i32, SignedInt);
+raw_uniform_conv!(u32, UnsignedInt);
+raw_uniform_conv!(bool, Bool);
 raw_uniform_conv!(f32, Float);
 raw_uniform_conv!(mint::ColumnMatrix2<f32>, Mat2);
 raw_uniform_conv!(mint::ColumnMatrix3<f32>, Mat3);
@@ -72,6 +183,146 @@ raw_uniform_conv!(mint::Vector4<f32>, Vec4);
 raw_uniform_conv!(mint::Vector2<i32>, IntVec2);
 raw_uniform_conv!(mint::Vector3<i32>, IntVec3);
 raw_uniform_conv!(mint::Vector4<i32>, IntVec4);
+raw_uniform_conv!(mint::Vector2<u32>, UnsignedIntVec2);
+raw_uniform_conv!(mint::Vector3<u32>, UnsignedIntVec3);
+raw_uniform_conv!(mint::Vector4<u32>, UnsignedIntVec4);
+raw_uniform_array_conv!(i32, SignedIntArray);
+raw_uniform_array_conv!(u32, UnsignedIntArray);
+raw_uniform_array_conv!(bool, BoolArray);
+raw_uniform_array_conv!(f32, FloatArray);
+raw_uniform_array_conv!(mint::ColumnMatrix2<f32>, Mat2Array);
+raw_uniform_array_conv!(mint::ColumnMatrix3<f32>, Mat3Array);
+raw_uniform_array_conv!(mint::ColumnMatrix4<f32>, Mat4Array);
+raw_uniform_array_conv!(mint::Vector2<f32>, Vec2Array);
+raw_uniform_array_conv!(mint::Vector3<f32>, Vec3Array);
+raw_uniform_array_conv!(mint::Vector4<f32>, Vec4Array);
+raw_uniform_array_conv!(mint::Vector2<i32>, IntVec2Array);
+raw_uniform_array_conv!(mint::Vector3<i32>, IntVec3Array);
+raw_uniform_array_conv!(mint::Vector4<i32>, IntVec4Array);
+raw_uniform_array_conv!(mint::Vector2<u32>, UnsignedIntVec2Array);
+raw_uniform_array_conv!(mint::Vector3<u32>, UnsignedIntVec3Array);
+raw_uniform_array_conv!(mint::Vector4<u32>, UnsignedIntVec4Array);
+
+/// Rounds `offset` up to the next multiple of `alignment`.
+pub const fn std140_align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+/// The alignment of a struct whose members have `alignments`: the largest of them rounded up to
+/// the alignment of a `vec4`.
+pub const fn std140_struct_alignment(alignments: &[usize]) -> usize {
+    let mut alignment = 16;
+    let mut i = 0;
+    while i < alignments.len() {
+        if alignments[i] > alignment {
+            alignment = alignments[i];
+        }
+        i += 1;
+    }
+    std140_align(alignment, 16)
+}
+
+/// Types that can be written into a uniform buffer with the std140 layout rules. Vectors and
+/// matrices are the `mint` types, `[T; N]` is a GLSL array and structs can be derived with
+/// [`Std140`](solstice_derive::Std140).
+pub trait Std140 {
+    /// The base alignment in bytes.
+    const ALIGNMENT: usize;
+    /// The size in bytes, including any padding at the end.
+    const SIZE: usize;
+
+    /// Writes the value to the start of `out` which is at least `SIZE` bytes long.
+    fn write_std140(&self, out: &mut [u8]);
+
+    fn to_std140(&self) -> Vec<u8> {
+        let mut out = vec![0; Self::SIZE];
+        self.write_std140(&mut out);
+        out
+    }
+}
+
+macro_rules! std140_scalar {
+    ($ty:ty) => {
+        impl Std140 for $ty {
+            const ALIGNMENT: usize = 4;
+            const SIZE: usize = 4;
+
+            fn write_std140(&self, out: &mut [u8]) {
+                out[..4].copy_from_slice(bytemuck::bytes_of(self));
+            }
+        }
+    };
+}
+
+std140_scalar!(f32);
+std140_scalar!(i32);
+std140_scalar!(u32);
+
+impl Std140 for bool {
+    const ALIGNMENT: usize = 4;
+    const SIZE: usize = 4;
+
+    fn write_std140(&self, out: &mut [u8]) {
+        (*self as u32).write_std140(out)
+    }
+}
+
+macro_rules! std140_vector {
+    ($ty:ty, $components:expr, $alignment:expr) => {
+        impl Std140 for $ty {
+            const ALIGNMENT: usize = $alignment;
+            const SIZE: usize = 4 * $components;
+
+            fn write_std140(&self, out: &mut [u8]) {
+                let components: &[_; $components] = self.as_ref();
+                out[..Self::SIZE].copy_from_slice(bytemuck::cast_slice(components));
+            }
+        }
+    };
+}
+
+std140_vector!(mint::Vector2<f32>, 2, 8);
+std140_vector!(mint::Vector3<f32>, 3, 16);
+std140_vector!(mint::Vector4<f32>, 4, 16);
+std140_vector!(mint::Vector2<i32>, 2, 8);
+std140_vector!(mint::Vector3<i32>, 3, 16);
+std140_vector!(mint::Vector4<i32>, 4, 16);
+std140_vector!(mint::Vector2<u32>, 2, 8);
+std140_vector!(mint::Vector3<u32>, 3, 16);
+std140_vector!(mint::Vector4<u32>, 4, 16);
+
+// matrices are laid out like an array of their column vectors
+macro_rules! std140_matrix {
+    ($ty:ty, $columns:expr, $rows:expr) => {
+        impl Std140 for $ty {
+            const ALIGNMENT: usize = 16;
+            const SIZE: usize = 16 * $columns;
+
+            fn write_std140(&self, out: &mut [u8]) {
+                let columns: &[[f32; $rows]; $columns] = self.as_ref();
+                for (column, out) in columns.iter().zip(out.chunks_mut(16)) {
+                    out[..4 * $rows].copy_from_slice(bytemuck::cast_slice(column));
+                }
+            }
+        }
+    };
+}
+
+std140_matrix!(mint::ColumnMatrix2<f32>, 2, 2);
+std140_matrix!(mint::ColumnMatrix3<f32>, 3, 3);
+std140_matrix!(mint::ColumnMatrix4<f32>, 4, 4);
+
+impl<T: Std140, const N: usize> Std140 for [T; N] {
+    const ALIGNMENT: usize = std140_align(T::ALIGNMENT, 16);
+    const SIZE: usize = std140_align(T::SIZE, Self::ALIGNMENT) * N;
+
+    fn write_std140(&self, out: &mut [u8]) {
+        let stride = std140_align(T::SIZE, Self::ALIGNMENT);
+        for (element, out) in self.iter().zip(out.chunks_mut(stride)) {
+            element.write_std140(out);
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum ShaderError {
@@ -79,6 +330,7 @@ pub enum ShaderError {
     FragmentCompileError(String),
     LinkError(String),
     ResourceCreationError,
+    IncludeNotFound(String),
 }
 
 impl std::fmt::Display for ShaderError {
@@ -89,11 +341,14 @@ impl std::fmt::Display for ShaderError {
 
 impl std::error::Error for ShaderError {}
 
+/// Clones share the program, which is deleted once all of them have been dropped.
 #[derive(Clone, Debug)]
 pub struct DynamicShader {
     inner: super::ShaderKey,
     attributes: Vec<Attribute>,
     uniforms: Vec<Uniform>,
+    uniform_blocks: Vec<UniformBlock>,
+    owner: super::resource::Owner,
 }
 
 impl std::cmp::PartialEq for DynamicShader {
@@ -113,11 +368,14 @@ impl DynamicShader {
             .map_err(GraphicsError::ShaderError)?;
         let attributes = gl.get_shader_attributes(inner);
         let uniforms = gl.get_shader_uniforms(inner);
+        let uniform_blocks = gl.get_shader_uniform_blocks(inner);
 
         Ok(Self {
             inner,
             attributes,
             uniforms,
+            uniform_blocks,
+            owner: gl.new_owner(vec![super::resource::ResourceKey::Shader(inner)]),
         })
     }
 
@@ -125,6 +383,10 @@ impl DynamicShader {
         self.inner
     }
 
+    pub fn owner(&self) -> &super::resource::Owner {
+        &self.owner
+    }
+
     pub fn get_attribute_by_name(&self, name: &str) -> Option<&Attribute> {
         self.attributes
             .iter()
@@ -135,16 +397,50 @@ impl DynamicShader {
         self.uniforms.iter().find(|uniform| uniform.name == name)
     }
 
+    pub fn get_uniform_block_by_name(&self, name: &str) -> Option<&UniformBlock> {
+        self.uniform_blocks.iter().find(|block| block.name == name)
+    }
+
+    /// Sources the block called `name` from the uniform buffer bound at `binding`. Returns
+    /// false if the shader has no such block.
+    pub fn set_uniform_block_binding(
+        &self,
+        ctx: &mut super::Context,
+        name: &str,
+        binding: u32,
+    ) -> bool {
+        match self.get_uniform_block_by_name(name) {
+            Some(block) => {
+                ctx.set_uniform_block_binding(self.inner, block.index, binding);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn create_source(vertex: &str, fragment: &str) -> (String, String) {
-        let vertex = format!(
-            "{}\n{}\n{}\n{}\n{}\n{}",
-            GLSL_VERSION, SYNTAX, VERTEX_HEADER, FUNCTIONS, LINE_PRAGMA, vertex
-        );
-        let fragment = format!(
-            "{}\n{}\n{}\n{}\n{}\n{}",
-            GLSL_VERSION, SYNTAX, FRAG_HEADER, FUNCTIONS, LINE_PRAGMA, fragment
-        );
-        (vertex, fragment)
+        (
+            assemble_source(VERTEX_HEADER, "", vertex),
+            assemble_source(FRAG_HEADER, "", fragment),
+        )
+    }
+
+    /// Like `create_source` but resolves `#include`s from `library` first and defines each of
+    /// `defines` ahead of everything but the version.
+    pub fn create_source_with(
+        vertex: &str,
+        fragment: &str,
+        library: &ShaderLibrary,
+        defines: &[(&str, &str)],
+    ) -> Result<(String, String), ShaderError> {
+        let defines = defines
+            .iter()
+            .map(|(name, value)| format!("#define {} {}\n", name, value))
+            .collect::<String>();
+        Ok((
+            assemble_source(VERTEX_HEADER, &defines, &library.resolve(vertex)?),
+            assemble_source(FRAG_HEADER, &defines, &library.resolve(fragment)?),
+        ))
     }
 }
 
@@ -160,6 +456,17 @@ impl Shader for DynamicShader {
     fn uniforms(&self) -> &[Uniform] {
         &self.uniforms
     }
+
+    fn uniform_blocks(&self) -> &[UniformBlock] {
+        &self.uniform_blocks
+    }
+}
+
+fn assemble_source(header: &str, defines: &str, source: &str) -> String {
+    format!(
+        "{}\n{}{}\n{}\n{}\n{}\n{}",
+        GLSL_VERSION, defines, SYNTAX, header, FUNCTIONS, LINE_PRAGMA, source
+    )
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -296,6 +603,9 @@ pub trait Shader {
     fn handle(&self) -> super::ShaderKey;
     fn attributes(&self) -> &[Attribute];
     fn uniforms(&self) -> &[Uniform];
+    fn uniform_blocks(&self) -> &[UniformBlock] {
+        &[]
+    }
 }
 
 pub trait UniformGetter<U: UniformTrait> {
@@ -362,7 +672,10 @@ pub trait CachedUniformSetter: BasicUniformSetter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backend::recording::testing::*;
+    use crate::backend::recording::RecordingBackend;
     use std::convert::TryInto;
+    use std::{cell::RefCell, collections::HashMap};
 
     #[test]
     fn uniform_conv() {
@@ -371,4 +684,110 @@ mod tests {
         let c: mint::Vector2<f32> = b.try_into().unwrap();
         assert_eq!(a, c);
     }
+
+    #[test]
+    fn shader_includes_and_variants() {
+        use crate::shader::{ShaderError, ShaderLibrary, ShaderVariants};
+
+        let mut library = ShaderLibrary::new();
+        library.insert("noise.glsl", "uniform float uNoise;");
+        library.insert(
+            "lighting.glsl",
+            "#include \"noise.glsl\"\nuniform vec3 uLight;",
+        );
+
+        const SHADER: &str = r#"
+#include "lighting.glsl"
+#ifdef SHADOWS
+uniform float uShadow;
+#endif
+void main() {}
+"#;
+        let (mut ctx, _backend) = context();
+        let mut variants = ShaderVariants::new(SHADER, SHADER, library);
+        let plain = variants.get(&mut ctx, &[]).unwrap().clone();
+        assert!(plain.get_uniform_by_name("uLight").is_some());
+        assert!(plain.get_uniform_by_name("uNoise").is_some());
+        assert!(plain.get_uniform_by_name("uShadow").is_none());
+
+        let shadowed = variants
+            .get(&mut ctx, &[("SHADOWS", "1"), ("QUALITY", "2")])
+            .unwrap()
+            .clone();
+        assert!(shadowed.get_uniform_by_name("uShadow").is_some());
+        assert_ne!(plain.handle(), shadowed.handle());
+        assert_eq!(variants.len(), 2);
+
+        // the order of the defines doesn't matter
+        let cached = variants
+            .get(&mut ctx, &[("QUALITY", "2"), ("SHADOWS", "1")])
+            .unwrap();
+        assert_eq!(cached.handle(), shadowed.handle());
+        assert_eq!(variants.len(), 2);
+
+        variants.library_mut().remove("noise.glsl");
+        variants.clear();
+        match variants.get(&mut ctx, &[]) {
+            Err(GraphicsError::ShaderError(ShaderError::IncludeNotFound(path))) => {
+                assert_eq!(path, "noise.glsl")
+            }
+            result => panic!("expected a missing include, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn program_cache() {
+        use crate::shader::{ProgramBinary, ProgramCache, Shader};
+        use std::rc::Rc;
+
+        #[derive(Clone, Default)]
+        struct SharedCache(Rc<RefCell<HashMap<String, ProgramBinary>>>);
+
+        impl ProgramCache for SharedCache {
+            fn load(&mut self, key: &str) -> Option<ProgramBinary> {
+                self.0.borrow().get(key).cloned()
+            }
+
+            fn store(&mut self, key: &str, binary: &ProgramBinary) {
+                self.0.borrow_mut().insert(key.to_owned(), binary.clone());
+            }
+        }
+
+        let cache = SharedCache::default();
+        let (vert, frag) = DynamicShader::create_source(SRC, SRC);
+        let from_binary = |backend: &RecordingBackend, shader: &DynamicShader| {
+            backend.recording().programs[shader.handle()].from_binary
+        };
+
+        let (mut ctx, backend) = context();
+        assert!(ctx.supports_program_binaries());
+        ctx.set_program_cache(cache.clone());
+        let compiled = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        assert!(!from_binary(&backend, &compiled));
+        assert_eq!(cache.0.borrow().len(), 1);
+
+        // a fresh context picks up the binary and reflects the same interface
+        let (mut ctx, backend) = context();
+        ctx.set_program_cache(cache.clone());
+        let loaded = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        assert!(from_binary(&backend, &loaded));
+        assert_eq!(loaded.uniforms().len(), compiled.uniforms().len());
+        assert_eq!(loaded.attributes().len(), compiled.attributes().len());
+
+        // rejected binaries fall back to compiling
+        for binary in cache.0.borrow_mut().values_mut() {
+            binary.data.clear();
+        }
+        let recompiled = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        assert!(!from_binary(&backend, &recompiled));
+        assert!(cache
+            .0
+            .borrow()
+            .values()
+            .all(|binary| !binary.data.is_empty()));
+
+        assert!(ctx.take_program_cache().is_some());
+        let uncached = DynamicShader::new(&mut ctx, &vert, &frag).unwrap();
+        assert!(!from_binary(&backend, &uncached));
+    }
 }
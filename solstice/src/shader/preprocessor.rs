@@ -0,0 +1,214 @@
+use super::{DynamicShader, ShaderError};
+use crate::{Context, GraphicsError};
+use std::collections::{BTreeMap, HashMap};
+
+/// A virtual file system of shader sources for `#include` directives to refer to.
+///
+/// `#include "path"` and `#include <path>` are replaced with the source registered at `path`,
+/// whose own includes are resolved in turn. A file is only included the first time it comes up
+/// so shared code doesn't need include guards. Includes are resolved ahead of the rest of the
+/// preprocessor, which means ones in inactive `#if` branches still have to exist.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShaderLibrary {
+    files: HashMap<String, String>,
+}
+
+impl ShaderLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `source` at `path`, returning the source it replaced.
+    pub fn insert<P, S>(&mut self, path: P, source: S) -> Option<String>
+    where
+        P: Into<String>,
+        S: Into<String>,
+    {
+        self.files.insert(path.into(), source.into())
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<String> {
+        self.files.remove(path)
+    }
+
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.files.get(path).map(String::as_str)
+    }
+
+    /// Replaces the `#include` directives in `source`. `#line` directives are added around the
+    /// included code so that compile errors point at the right line. Each included file gets its
+    /// own source string number, starting at 1 in the order they were first included.
+    pub fn resolve(&self, source: &str) -> Result<String, ShaderError> {
+        let mut included = vec![];
+        let mut output = String::with_capacity(source.len());
+        self.resolve_into(source, 0, &mut included, &mut output)?;
+        Ok(output)
+    }
+
+    fn resolve_into<'a>(
+        &'a self,
+        source: &str,
+        source_number: usize,
+        included: &mut Vec<&'a str>,
+        output: &mut String,
+    ) -> Result<(), ShaderError> {
+        for (index, line) in source.lines().enumerate() {
+            let path = match include_path(line) {
+                Some(path) => path,
+                None => {
+                    output.push_str(line);
+                    output.push('\n');
+                    continue;
+                }
+            };
+            let (path, file) = self
+                .files
+                .get_key_value(path)
+                .ok_or_else(|| ShaderError::IncludeNotFound(path.to_owned()))?;
+            if included.contains(&path.as_str()) {
+                output.push('\n');
+                continue;
+            }
+            included.push(path);
+            let file_number = included.len();
+            output.push_str(&format!("#line 1 {}\n", file_number));
+            self.resolve_into(file, file_number, included, output)?;
+            output.push_str(&format!("#line {} {}\n", index + 2, source_number));
+        }
+        Ok(())
+    }
+}
+
+fn include_path(line: &str) -> Option<&str> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let path = directive.strip_prefix("include")?.trim();
+    let quoted = path.starts_with('"') && path.ends_with('"');
+    let bracketed = path.starts_with('<') && path.ends_with('>');
+    if path.len() >= 2 && (quoted || bracketed) {
+        Some(&path[1..(path.len() - 1)])
+    } else {
+        None
+    }
+}
+
+/// Compiles a shader once for every set of defines it's asked for and hands back the cached
+/// program afterwards. Useful for shaders that switch features on and off with `#ifdef`.
+#[derive(Debug)]
+pub struct ShaderVariants {
+    vertex: String,
+    fragment: String,
+    library: ShaderLibrary,
+    variants: HashMap<BTreeMap<String, String>, DynamicShader>,
+}
+
+impl ShaderVariants {
+    pub fn new<V, F>(vertex: V, fragment: F, library: ShaderLibrary) -> Self
+    where
+        V: Into<String>,
+        F: Into<String>,
+    {
+        Self {
+            vertex: vertex.into(),
+            fragment: fragment.into(),
+            library,
+            variants: HashMap::new(),
+        }
+    }
+
+    /// The variant compiled with `defines`, compiling it if this is the first time it's been
+    /// asked for. The order of the defines doesn't matter and later values replace earlier ones
+    /// for the same name.
+    pub fn get(
+        &mut self,
+        ctx: &mut Context,
+        defines: &[(&str, &str)],
+    ) -> Result<&DynamicShader, GraphicsError> {
+        let key = defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        if !self.variants.contains_key(&key) {
+            let defines = key
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>();
+            let (vertex, fragment) = DynamicShader::create_source_with(
+                &self.vertex,
+                &self.fragment,
+                &self.library,
+                &defines,
+            )
+            .map_err(GraphicsError::ShaderError)?;
+            let shader = DynamicShader::new(ctx, &vertex, &fragment)?;
+            self.variants.insert(key.clone(), shader);
+        }
+        Ok(&self.variants[&key])
+    }
+
+    pub fn library(&self) -> &ShaderLibrary {
+        &self.library
+    }
+
+    /// Changes to the library only affect variants compiled afterwards, see `clear`.
+    pub fn library_mut(&mut self) -> &mut ShaderLibrary {
+        &mut self.library
+    }
+
+    /// The number of variants that have been compiled.
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+
+    /// Drops the compiled variants so they're compiled again the next time they're asked for.
+    pub fn clear(&mut self) {
+        self.variants.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_paths() {
+        assert_eq!(include_path("#include \"a.glsl\""), Some("a.glsl"));
+        assert_eq!(
+            include_path("  #  include <dir/b.glsl> "),
+            Some("dir/b.glsl")
+        );
+        assert_eq!(include_path("#include a.glsl"), None);
+        assert_eq!(include_path("#include \""), None);
+        assert_eq!(include_path("#define include"), None);
+    }
+
+    #[test]
+    fn resolve() {
+        let mut library = ShaderLibrary::new();
+        library.insert("noise.glsl", "uniform float uNoise;");
+        library.insert(
+            "lighting.glsl",
+            "#include \"noise.glsl\"\nuniform vec3 uLight;",
+        );
+        // files are numbered in the order they're first included and only included once
+        assert_eq!(
+            library
+                .resolve("#include <lighting.glsl>\n#include \"noise.glsl\"\nvoid main() {}")
+                .unwrap(),
+            "#line 1 1\n#line 1 2\nuniform float uNoise;\n#line 2 1\nuniform vec3 uLight;\n#line 2 0\n\nvoid main() {}\n"
+        );
+        assert_eq!(
+            library.resolve("void main() {}").unwrap(),
+            "void main() {}\n"
+        );
+
+        library.remove("noise.glsl");
+        match library.resolve("#include \"lighting.glsl\"") {
+            Err(ShaderError::IncludeNotFound(path)) => assert_eq!(path, "noise.glsl"),
+            result => panic!("expected a missing include, got {:?}", result),
+        }
+    }
+}
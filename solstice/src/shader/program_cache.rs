@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+/// A linked program as the driver stores it. Only the driver that produced it can load it and it
+/// may stop accepting it after an update.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramBinary {
+    pub format: u32,
+    pub data: Vec<u8>,
+}
+
+/// Storage for program binaries so that shaders don't have to be compiled every time the app
+/// starts. See `Context::set_program_cache`.
+///
+/// Keys are made up of hex digits and dashes so they can be used as file names. They're derived
+/// from the shader sources and the driver's vendor, renderer and version so a binary is never
+/// handed to a driver that didn't produce it.
+pub trait ProgramCache {
+    fn load(&mut self, key: &str) -> Option<ProgramBinary>;
+    fn store(&mut self, key: &str, binary: &ProgramBinary);
+}
+
+/// Keeps binaries for as long as it lives, which is mostly useful for tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryProgramCache {
+    binaries: HashMap<String, ProgramBinary>,
+}
+
+impl MemoryProgramCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.binaries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.binaries.is_empty()
+    }
+}
+
+impl ProgramCache for MemoryProgramCache {
+    fn load(&mut self, key: &str) -> Option<ProgramBinary> {
+        self.binaries.get(key).cloned()
+    }
+
+    fn store(&mut self, key: &str, binary: &ProgramBinary) {
+        self.binaries.insert(key.to_owned(), binary.clone());
+    }
+}
+
+/// Keeps each binary in its own file in a directory, which is created if it doesn't exist.
+/// Failing to read or write a file only makes for a cache miss.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct DirectoryProgramCache {
+    directory: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirectoryProgramCache {
+    pub fn new<P: Into<std::path::PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.directory.join(key).with_extension("bin")
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ProgramCache for DirectoryProgramCache {
+    fn load(&mut self, key: &str) -> Option<ProgramBinary> {
+        let file = std::fs::read(self.path(key)).ok()?;
+        if file.len() < 4 {
+            return None;
+        }
+        let (format, data) = file.split_at(4);
+        Some(ProgramBinary {
+            format: u32::from_le_bytes([format[0], format[1], format[2], format[3]]),
+            data: data.to_vec(),
+        })
+    }
+
+    fn store(&mut self, key: &str, binary: &ProgramBinary) {
+        let mut file = binary.format.to_le_bytes().to_vec();
+        file.extend_from_slice(&binary.data);
+        let result = std::fs::create_dir_all(&self.directory)
+            .and_then(|()| std::fs::write(self.path(key), file));
+        if let Err(err) = result {
+            log::warn!("failed to store program binary {}: {}", key, err);
+        }
+    }
+}
+
+// the standard library's hasher may change between releases, which would miss every time
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for part in parts {
+        // the length keeps ("ab", "c") and ("a", "bc") apart
+        for byte in (part.len() as u64)
+            .to_le_bytes()
+            .iter()
+            .chain(part.as_bytes())
+        {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+pub(crate) fn cache_key(driver: &[&str], vertex_source: &str, fragment_source: &str) -> String {
+    format!(
+        "{:016x}-{:016x}",
+        fnv1a(driver),
+        fnv1a(&[vertex_source, fragment_source])
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_keys() {
+        let driver = ["vendor", "renderer", "version"];
+        let key = cache_key(&driver, "vertex", "fragment");
+        assert_eq!(key.len(), 33);
+        assert!(key.chars().all(|c| c == '-' || c.is_ascii_hexdigit()));
+        assert_eq!(key, cache_key(&driver, "vertex", "fragment"));
+
+        assert_ne!(key, cache_key(&driver, "vertex", "fragment2"));
+        assert_ne!(
+            key,
+            cache_key(&["vendor", "renderer", "2"], "vertex", "fragment")
+        );
+        // moving text from one source to the other changes the key
+        assert_ne!(key, cache_key(&driver, "vertexf", "ragment"));
+        // the driver and the sources are hashed separately
+        assert_eq!(
+            key.split('-').next(),
+            cache_key(&driver, "other", "sources").split('-').next()
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn directory_cache() {
+        let directory =
+            std::env::temp_dir().join(format!("solstice-program-cache-{}", std::process::id()));
+        let mut cache = DirectoryProgramCache::new(&directory);
+        let binary = ProgramBinary {
+            format: 0x1234_5678,
+            data: vec![1, 2, 3],
+        };
+        assert_eq!(cache.load("key"), None);
+        cache.store("key", &binary);
+        assert_eq!(cache.load("key"), Some(binary));
+
+        // files too short to hold a format are misses
+        std::fs::write(directory.join("short.bin"), [1, 2]).unwrap();
+        assert_eq!(cache.load("short"), None);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -26,12 +26,24 @@ impl TextureType {
         ]
     }
 
+    /// Whether solstice can create textures of this type at all. Driver support can still be
+    /// missing, see `Context::supports_texture_type`.
     pub fn is_supported(self) -> bool {
         match self {
             TextureType::Tex2D => true,
-            TextureType::Volume => false,
-            TextureType::Tex2DArray => false,
-            TextureType::Cube => false,
+            TextureType::Volume => true,
+            TextureType::Tex2DArray => true,
+            TextureType::Cube => true,
+        }
+    }
+
+    /// The number of layers a texture of this type has for the given number of slices. Cube
+    /// maps always have six faces and 2D textures a single layer.
+    pub fn layers(self, slices: u32) -> u32 {
+        match self {
+            TextureType::Tex2D => 1,
+            TextureType::Volume | TextureType::Tex2DArray => slices,
+            TextureType::Cube => 6,
         }
     }
 }
@@ -159,6 +171,7 @@ pub struct TextureInfo {
     format: super::PixelFormat,
     width: u32,
     height: u32,
+    depth: u32,
     filter: Filter,
     wrap: Wrap,
     mipmaps: bool,
@@ -170,6 +183,7 @@ impl Default for TextureInfo {
             format: super::PixelFormat::Unknown,
             width: 0,
             height: 0,
+            depth: 1,
             filter: Default::default(),
             wrap: Default::default(),
             mipmaps: false,
@@ -190,6 +204,7 @@ impl TextureInfo {
             format,
             width,
             height,
+            depth: 1,
             filter,
             wrap,
             mipmaps,
@@ -212,6 +227,16 @@ impl TextureInfo {
         self.height = height
     }
 
+    /// The depth of a volume texture, the layer count of an array texture and six for a cube
+    /// map. Always one for 2D textures.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: u32) {
+        self.depth = depth
+    }
+
     pub fn get_format(&self) -> super::PixelFormat {
         self.format
     }
@@ -252,6 +277,9 @@ pub trait Texture {
 }
 
 pub trait TextureUpdate {
+    /// Uploads a region of level 0. The other mipmap levels are regenerated afterwards if
+    /// `texture` has mipmaps. Clear its `mipmaps` flag to batch several uploads and call
+    /// `generate_mipmaps` once at the end instead.
     fn set_texture_sub_data(
         &mut self,
         texture_key: super::TextureKey,
@@ -261,6 +289,20 @@ pub trait TextureUpdate {
         x_offset: u32,
         y_offset: u32,
     );
+    /// Uploads a `width * height * depth` region of the texture starting at `layer`. For cube
+    /// maps the layer is the face, in the order +X, -X, +Y, -Y, +Z, -Z.
+    #[allow(clippy::too_many_arguments)]
+    fn set_texture_layer_sub_data(
+        &mut self,
+        texture_key: super::TextureKey,
+        texture: TextureInfo,
+        texture_type: TextureType,
+        data: &[u8],
+        x_offset: u32,
+        y_offset: u32,
+        layer: u32,
+    );
+    /// `data` holds every layer, or face, of the texture one after another.
     fn set_texture_data(
         &mut self,
         texture_key: super::TextureKey,
@@ -268,6 +310,16 @@ pub trait TextureUpdate {
         texture_type: TextureType,
         data: Option<&[u8]>,
     );
+    /// Uploads a single mipmap level without generating any others. `texture` describes level
+    /// 0 and `data` is laid out like it is for `set_texture_data`.
+    fn set_texture_level_data(
+        &mut self,
+        texture_key: super::TextureKey,
+        texture: TextureInfo,
+        texture_type: TextureType,
+        level: u32,
+        data: Option<&[u8]>,
+    );
     #[cfg(target_arch = "wasm32")]
     fn set_texture_data_with_html_image<T: Texture>(
         &mut self,
@@ -286,4 +338,34 @@ pub trait TextureUpdate {
         texture_type: TextureType,
         filter: Filter,
     );
+    /// Keeps sampling to levels `0..=max_level` so that a partial mipmap chain is complete.
+    /// GLES 2 and WebGL 1 can't do this and ignore it.
+    fn set_texture_max_level(
+        &mut self,
+        texture_key: super::TextureKey,
+        texture_type: TextureType,
+        max_level: u32,
+    ) {
+        self.set_texture_level_range(texture_key, texture_type, 0, max_level)
+    }
+    /// Keeps sampling to levels `base_level..=max_level`, which also makes a partial mipmap
+    /// chain complete. GLES 2 and WebGL 1 can't do this and ignore it.
+    fn set_texture_level_range(
+        &mut self,
+        texture_key: super::TextureKey,
+        texture_type: TextureType,
+        base_level: u32,
+        max_level: u32,
+    );
+    /// Offsets the level of detail picked when sampling, positive values pick smaller levels.
+    /// Only desktop GL has this, it's ignored elsewhere.
+    fn set_texture_lod_bias(
+        &mut self,
+        texture_key: super::TextureKey,
+        texture_type: TextureType,
+        bias: f32,
+    );
+    /// Regenerates every level past the base level from the base level. Compressed textures
+    /// can't have their mipmaps generated.
+    fn generate_mipmaps(&mut self, texture_key: super::TextureKey, texture_type: TextureType);
 }